@@ -2,6 +2,33 @@ use leptos::prelude::*;
 #[cfg(feature = "ssr")]
 use leptos_meta::*;
 
+pub const CSRF_FORM_FIELD: &str = "csrf_token";
+pub const CSRF_HEADER: &str = "x-csrf-token";
+
+/// CSRF token for the current request, provided as context by the server.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CsrfToken(pub String);
+
+fn csrf_token_from_context() -> String {
+    use_context::<CsrfToken>().map(|t| t.0).unwrap_or_default()
+}
+
+/// Keys of the feature flags that are on for the current visitor, provided as context by the
+/// server. Context does not reach islands once they hydrate, so pass flags to them as props.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EnabledFeatures(pub std::collections::BTreeSet<String>);
+
+/// Whether the feature flag `key` is on for the current visitor.
+pub fn feature_enabled(key: &str) -> bool {
+    use_context::<EnabledFeatures>().is_some_and(|features| features.0.contains(key))
+}
+
+/// Hidden input carrying the request's CSRF token; every form posting to the server needs one.
+#[component]
+pub fn CsrfField() -> impl IntoView {
+    view! { <input type="hidden" name=CSRF_FORM_FIELD value=csrf_token_from_context()/> }
+}
+
 #[component]
 pub fn LandingPage() -> impl IntoView {
     view! {
@@ -88,7 +115,7 @@ pub fn LoginPage(flash_error: Option<String>) -> impl IntoView {
                 </div>
             </div>
         </Show>
-        <LoginFormIsland/>
+        <LoginFormIsland csrf_token=csrf_token_from_context()/>
     }
 }
 
@@ -105,12 +132,28 @@ pub fn RegisterPage(flash_error: Option<String>) -> impl IntoView {
                 </div>
             </div>
         </Show>
-        <RegisterFormIsland/>
+        <RegisterFormIsland csrf_token=csrf_token_from_context()/>
     }
 }
 
 #[component]
-pub fn DashboardPage(email: String) -> impl IntoView {
+pub fn RegistrationPendingPage() -> impl IntoView {
+    view! {
+        <main class="min-h-screen bg-slate-950 text-slate-100 flex items-center justify-center px-6">
+            <div class="w-full max-w-md card p-6 space-y-4 text-center">
+                <p class="text-emerald-300 font-semibold text-sm uppercase tracking-widest">"Almost there"</p>
+                <h1 class="text-3xl font-bold">"Check your inbox"</h1>
+                <p class="text-slate-400 text-sm">
+                    "We sent a message to the address you entered. Follow the link in it to finish creating your account."
+                </p>
+                <a href="/app/login" rel="external" class="text-emerald-300 hover:text-emerald-200 text-sm">"Back to login"</a>
+            </div>
+        </main>
+    }
+}
+
+#[component]
+pub fn DashboardPage(email: String, avatar_url: Option<String>) -> impl IntoView {
     view! {
         <main class="min-h-screen bg-slate-950 text-slate-100">
             <section class="max-w-5xl mx-auto px-6 py-12 space-y-6">
@@ -119,19 +162,638 @@ pub fn DashboardPage(email: String) -> impl IntoView {
                         <p class="text-sm text-emerald-300 uppercase tracking-widest">"Private area"</p>
                         <h1 class="text-3xl font-bold">"App dashboard"</h1>
                     </div>
-                    <a href="/logout" rel="external" class="text-sm text-slate-400 hover:text-slate-200">"Logout"</a>
+                    <div class="flex items-center gap-6">
+                        <NotificationBellIsland csrf_token=csrf_token_from_context()/>
+                        <form action="/logout" method="post">
+                            <CsrfField/>
+                            <button type="submit" class="text-sm text-slate-400 hover:text-slate-200">"Logout"</button>
+                        </form>
+                    </div>
                 </div>
-                <div class="card p-6 space-y-2">
-                    <p class="text-sm text-slate-400">"Authenticated as"</p>
-                    <p class="text-xl font-semibold">{email}</p>
+                <div class="card p-6 flex items-center justify-between gap-6">
+                    <div class="space-y-2">
+                        <p class="text-sm text-slate-400">"Authenticated as"</p>
+                        <p class="text-xl font-semibold">{email}</p>
+                    </div>
+                    <AvatarIsland csrf_token=csrf_token_from_context() avatar_url=avatar_url/>
                 </div>
+                <LiveUpdatesIsland csrf_token=csrf_token_from_context()/>
             </section>
         </main>
     }
 }
 
+/// Image types `/api/me/avatar` accepts; the server checks the content, not the extension.
+const AVATAR_ACCEPT: &str = "image/png,image/jpeg,image/gif,image/webp";
+
+/// The user's avatar with controls to replace or remove it through `/api/me/avatar`.
+#[island(lazy)]
+pub fn AvatarIsland(csrf_token: String, avatar_url: Option<String>) -> impl IntoView {
+    let csrf_token = StoredValue::new(csrf_token);
+    let avatar_url = RwSignal::new(avatar_url);
+    let error = RwSignal::new(None::<String>);
+    let busy = RwSignal::new(false);
+
+    let on_change = move |ev: leptos::ev::Event| {
+        #[cfg(target_arch = "wasm32")]
+        {
+            use wasm_bindgen::JsCast;
+            if let Some(input) = ev
+                .target()
+                .and_then(|target| target.dyn_into::<web_sys::HtmlInputElement>().ok())
+            {
+                avatar::upload(input, csrf_token.get_value(), avatar_url, error, busy);
+            }
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        let _ = (ev, csrf_token);
+    };
+    let remove = move |_| {
+        #[cfg(target_arch = "wasm32")]
+        avatar::remove(csrf_token.get_value(), avatar_url, error, busy);
+    };
+
+    view! {
+        <div class="flex items-center gap-4">
+            <Show
+                when=move || avatar_url.get().is_some()
+                fallback=|| view! {
+                    <div class="h-16 w-16 rounded-full bg-slate-800 flex items-center justify-center text-xs text-slate-500">
+                        "No photo"
+                    </div>
+                }
+            >
+                <img
+                    class="h-16 w-16 rounded-full object-cover"
+                    alt="Your avatar"
+                    src=move || avatar_url.get().unwrap_or_default()
+                />
+            </Show>
+            <div class="space-y-1">
+                <label class="block text-sm text-emerald-300 hover:text-emerald-200 cursor-pointer">
+                    {move || if busy.get() { "Uploading..." } else { "Change photo" }}
+                    <input
+                        type="file"
+                        class="hidden"
+                        accept=AVATAR_ACCEPT
+                        disabled=move || busy.get()
+                        on:change=on_change
+                    />
+                </label>
+                <Show when=move || avatar_url.get().is_some() fallback=|| ()>
+                    <button type="button" class="block text-xs text-slate-400 hover:text-slate-200" on:click=remove>
+                        "Remove"
+                    </button>
+                </Show>
+                <Show when=move || error.get().is_some() fallback=|| ()>
+                    <p class="text-xs text-rose-300">{move || error.get().unwrap_or_default()}</p>
+                </Show>
+            </div>
+        </div>
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod avatar {
+    use super::CSRF_HEADER;
+    use leptos::prelude::*;
+    use wasm_bindgen::{JsCast, JsValue};
+    use wasm_bindgen_futures::JsFuture;
+
+    const URL: &str = "/api/me/avatar";
+
+    pub fn upload(
+        input: web_sys::HtmlInputElement,
+        csrf_token: String,
+        avatar_url: RwSignal<Option<String>>,
+        error: RwSignal<Option<String>>,
+        busy: RwSignal<bool>,
+    ) {
+        let Some(file) = input.files().and_then(|files| files.get(0)) else {
+            return;
+        };
+        // Lets the same file be picked again after a failed upload.
+        input.set_value("");
+        busy.set(true);
+        error.set(None);
+        wasm_bindgen_futures::spawn_local(async move {
+            let result = async {
+                let form = web_sys::FormData::new()?;
+                form.append_with_blob("file", &file)?;
+                send("PUT", Some(&form), &csrf_token).await
+            }
+            .await;
+            match result {
+                Ok((200, body)) => {
+                    let url = serde_json::from_str::<serde_json::Value>(&body)
+                        .ok()
+                        .and_then(|body| body.get("url")?.as_str().map(String::from));
+                    avatar_url.set(url);
+                }
+                other => error.set(Some(failure_message(other))),
+            }
+            busy.set(false);
+        });
+    }
+
+    pub fn remove(
+        csrf_token: String,
+        avatar_url: RwSignal<Option<String>>,
+        error: RwSignal<Option<String>>,
+        busy: RwSignal<bool>,
+    ) {
+        busy.set(true);
+        error.set(None);
+        wasm_bindgen_futures::spawn_local(async move {
+            match send("DELETE", None, &csrf_token).await {
+                Ok((204, _)) => avatar_url.set(None),
+                other => error.set(Some(failure_message(other))),
+            }
+            busy.set(false);
+        });
+    }
+
+    fn failure_message(result: Result<(u16, String), JsValue>) -> String {
+        match result {
+            Ok((401, _)) => "Your session expired. Reload the page to sign in again.".into(),
+            Ok((_, body)) => serde_json::from_str::<serde_json::Value>(&body)
+                .ok()
+                .and_then(|body| body.get("message")?.as_str().map(String::from))
+                .unwrap_or_else(|| "Something went wrong. Please try again.".into()),
+            Err(_) => "Could not reach the server. Please try again.".into(),
+        }
+    }
+
+    async fn send(
+        method: &str,
+        form: Option<&web_sys::FormData>,
+        csrf_token: &str,
+    ) -> Result<(u16, String), JsValue> {
+        let Some(window) = web_sys::window() else {
+            return Err(JsValue::from_str("missing window"));
+        };
+
+        let init = web_sys::RequestInit::new();
+        init.set_method(method);
+        init.set_credentials(web_sys::RequestCredentials::SameOrigin);
+        if let Some(form) = form {
+            // No Content-Type header: the browser adds it with the multipart boundary.
+            init.set_body(form);
+        }
+
+        let request = web_sys::Request::new_with_str_and_init(URL, &init)?;
+        request.headers().set("Accept", "application/json")?;
+        request.headers().set(CSRF_HEADER, csrf_token)?;
+
+        let resp: web_sys::Response = JsFuture::from(window.fetch_with_request(&request))
+            .await?
+            .dyn_into()?;
+        let text = JsFuture::from(resp.text()?)
+            .await?
+            .as_string()
+            .unwrap_or_default();
+        Ok((resp.status(), text))
+    }
+}
+
+/// One entry in the dashboard's live activity list.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LiveItem {
+    pub id: String,
+    pub title: String,
+    pub detail: String,
+}
+
+/// Subscribes to `/api/events` and lists what happens to the account while the page is open.
 #[island(lazy)]
-pub fn LoginFormIsland() -> impl IntoView {
+pub fn LiveUpdatesIsland(csrf_token: String) -> impl IntoView {
+    let connected = RwSignal::new(false);
+    let items = RwSignal::new(Vec::<LiveItem>::new());
+
+    #[cfg(target_arch = "wasm32")]
+    live::connect(
+        live::LiveState {
+            connected,
+            items,
+            csrf_token: StoredValue::new(csrf_token),
+        },
+        None,
+        0,
+    );
+    #[cfg(not(target_arch = "wasm32"))]
+    let _ = csrf_token;
+
+    view! {
+        <div class="card p-6 space-y-3">
+            <div class="flex items-center justify-between">
+                <p class="text-sm text-slate-400">"Live activity"</p>
+                <span class="text-xs text-slate-500">
+                    {move || if connected.get() { "Live" } else { "Connecting..." }}
+                </span>
+            </div>
+            <Show
+                when=move || !items.get().is_empty()
+                fallback=|| view! { <p class="text-sm text-slate-500">"Nothing new since you opened this page."</p> }
+            >
+                <ul class="space-y-2">
+                    <For each=move || items.get() key=|item| item.id.clone() let:item>
+                        <li class="text-sm">
+                            <p class="font-semibold">{item.title}</p>
+                            <p class="text-slate-400">{item.detail}</p>
+                        </li>
+                    </For>
+                </ul>
+            </Show>
+        </div>
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod live {
+    use super::{fetch_json, LiveItem};
+    use leptos::prelude::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use wasm_bindgen::closure::Closure;
+    use wasm_bindgen::JsCast;
+
+    const MAX_ITEMS: usize = 10;
+    const MAX_BACKOFF_MS: i32 = 30_000;
+
+    #[derive(Clone, Copy)]
+    pub struct LiveState {
+        pub connected: RwSignal<bool>,
+        pub items: RwSignal<Vec<LiveItem>>,
+        pub csrf_token: StoredValue<String>,
+    }
+
+    /// Opens the stream. The browser reconnects by itself after network errors; when it
+    /// gives up (usually an expired access token) the session is refreshed and a new
+    /// stream resumes from the last event seen.
+    pub fn connect(state: LiveState, last_event_id: Option<String>, attempt: u32) {
+        let url = match &last_event_id {
+            Some(id) => format!("/api/events?last_event_id={id}"),
+            None => "/api/events".to_string(),
+        };
+        let Ok(source) = web_sys::EventSource::new(&url) else {
+            return;
+        };
+        let last_event_id = Rc::new(RefCell::new(last_event_id));
+        let attempt = Rc::new(RefCell::new(attempt));
+
+        let on_open = {
+            let attempt = attempt.clone();
+            Closure::<dyn FnMut()>::new(move || {
+                *attempt.borrow_mut() = 0;
+                state.connected.set(true);
+            })
+        };
+        source.set_onopen(Some(on_open.as_ref().unchecked_ref()));
+        on_open.forget();
+
+        let on_message = {
+            let last_event_id = last_event_id.clone();
+            let source = source.clone();
+            Closure::<dyn FnMut(web_sys::MessageEvent)>::new(move |ev: web_sys::MessageEvent| {
+                let id = ev.last_event_id();
+                *last_event_id.borrow_mut() = Some(id.clone());
+                let Some(data) = ev.data().as_string() else {
+                    return;
+                };
+                let Ok(event) = serde_json::from_str::<serde_json::Value>(&data) else {
+                    return;
+                };
+                let text = |key: &str| {
+                    event
+                        .get(key)
+                        .and_then(|v| v.as_str())
+                        .unwrap_or_default()
+                        .to_string()
+                };
+                let item = match event.get("type").and_then(|v| v.as_str()) {
+                    Some("new_sign_in") => LiveItem {
+                        id,
+                        title: "New sign-in to your account".into(),
+                        detail: format!(
+                            "{} from {}",
+                            text("user_agent"),
+                            match text("ip") {
+                                ip if ip.is_empty() => "an unknown address".to_string(),
+                                ip => ip,
+                            }
+                        ),
+                    },
+                    Some("notification") => {
+                        super::bell::announce();
+                        LiveItem {
+                            id,
+                            title: text("title"),
+                            detail: text("body"),
+                        }
+                    }
+                    Some("session_revoked") => {
+                        source.close();
+                        go_to_login();
+                        return;
+                    }
+                    _ => return,
+                };
+                state.items.update(|items| {
+                    items.insert(0, item);
+                    items.truncate(MAX_ITEMS);
+                });
+            })
+        };
+        source.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+        on_message.forget();
+
+        let on_error = {
+            let source = source.clone();
+            Closure::<dyn FnMut()>::new(move || {
+                state.connected.set(false);
+                if source.ready_state() != web_sys::EventSource::CLOSED {
+                    return;
+                }
+                let next_attempt = *attempt.borrow() + 1;
+                let last_event_id = last_event_id.borrow().clone();
+                retry_later(state, last_event_id, next_attempt);
+            })
+        };
+        source.set_onerror(Some(on_error.as_ref().unchecked_ref()));
+        on_error.forget();
+    }
+
+    fn retry_later(state: LiveState, last_event_id: Option<String>, attempt: u32) {
+        let Some(window) = web_sys::window() else {
+            return;
+        };
+        let delay = (1_000i32 << attempt.min(5)).min(MAX_BACKOFF_MS);
+        let callback = Closure::once_into_js(move || {
+            wasm_bindgen_futures::spawn_local(async move {
+                let csrf_token = state.csrf_token.get_value();
+                match fetch_json("/api/auth/refresh", String::new(), &csrf_token).await {
+                    Ok((status, body)) if (200..300).contains(&status) => {
+                        // The refreshed session comes with a new CSRF token.
+                        if let Some(token) = serde_json::from_str::<serde_json::Value>(&body)
+                            .ok()
+                            .and_then(|v| v.get("csrf_token")?.as_str().map(str::to_string))
+                        {
+                            state.csrf_token.set_value(token);
+                        }
+                        connect(state, last_event_id, attempt);
+                    }
+                    Ok((401, _)) => go_to_login(),
+                    _ => retry_later(state, last_event_id, attempt + 1),
+                }
+            });
+        });
+        let _ = window.set_timeout_with_callback_and_timeout_and_arguments_0(
+            callback.unchecked_ref(),
+            delay,
+        );
+    }
+
+    fn go_to_login() {
+        if let Some(win) = web_sys::window() {
+            let _ = win.location().set_href("/app/login");
+        }
+    }
+}
+
+/// One entry in the notification bell's list.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NotificationItem {
+    pub id: String,
+    pub title: String,
+    pub body: String,
+    pub link: Option<String>,
+    pub read: bool,
+}
+
+/// Unread notification count with a dropdown of the latest notifications.
+#[island(lazy)]
+pub fn NotificationBellIsland(csrf_token: String) -> impl IntoView {
+    let csrf_token = StoredValue::new(csrf_token);
+    let unread = RwSignal::new(0i64);
+    let open = RwSignal::new(false);
+    let items = RwSignal::new(Vec::<NotificationItem>::new());
+
+    #[cfg(target_arch = "wasm32")]
+    {
+        bell::load_unread_count(unread);
+        // The live updates island announces notifications pushed over `/api/events`.
+        bell::on_announce(move || {
+            unread.update(|n| *n += 1);
+            if open.get_untracked() {
+                bell::load_items(items);
+            }
+        });
+    }
+
+    let toggle = move |_| {
+        open.update(|open| *open = !*open);
+        #[cfg(target_arch = "wasm32")]
+        if open.get_untracked() {
+            bell::load_items(items);
+        }
+    };
+    let mark_all_read = move |_| {
+        #[cfg(target_arch = "wasm32")]
+        bell::mark_all_read(csrf_token.get_value(), unread, items);
+    };
+
+    view! {
+        <div class="relative">
+            <button
+                type="button"
+                class="relative text-sm text-slate-300 hover:text-slate-100"
+                aria-label="Notifications"
+                on:click=toggle
+            >
+                "Notifications"
+                <Show when=move || { unread.get() > 0 } fallback=|| ()>
+                    <span class="ml-2 rounded-full bg-rose-500 text-white text-xs px-2 py-0.5">
+                        {move || unread.get().to_string()}
+                    </span>
+                </Show>
+            </button>
+            <Show when=move || open.get() fallback=|| ()>
+                <div class="absolute right-0 mt-2 w-80 card p-4 space-y-3 z-10">
+                    <div class="flex items-center justify-between">
+                        <p class="text-sm font-semibold">"Notifications"</p>
+                        <button type="button" class="text-xs text-emerald-300 hover:text-emerald-200" on:click=mark_all_read>
+                            "Mark all read"
+                        </button>
+                    </div>
+                    <Show
+                        when=move || !items.get().is_empty()
+                        fallback=|| view! { <p class="text-sm text-slate-500">"No notifications yet."</p> }
+                    >
+                        <ul class="space-y-2 max-h-96 overflow-y-auto">
+                            <For each=move || items.get() key=|item| (item.id.clone(), item.read) let:item>
+                                <NotificationRow item=item csrf_token=csrf_token unread=unread items=items/>
+                            </For>
+                        </ul>
+                    </Show>
+                </div>
+            </Show>
+        </div>
+    }
+}
+
+#[component]
+fn NotificationRow(
+    item: NotificationItem,
+    csrf_token: StoredValue<String>,
+    unread: RwSignal<i64>,
+    items: RwSignal<Vec<NotificationItem>>,
+) -> impl IntoView {
+    let class = if item.read {
+        "text-sm text-slate-400 cursor-pointer"
+    } else {
+        "text-sm font-semibold cursor-pointer"
+    };
+    let on_click = {
+        let item = item.clone();
+        move |_| {
+            #[cfg(target_arch = "wasm32")]
+            bell::open_item(item.clone(), csrf_token.get_value(), unread, items);
+            #[cfg(not(target_arch = "wasm32"))]
+            let _ = (&item, csrf_token, unread, items);
+        }
+    };
+
+    view! {
+        <li class=class on:click=on_click>
+            <p>{item.title}</p>
+            <p class="text-slate-400 font-normal">{item.body}</p>
+        </li>
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod bell {
+    use super::{fetch_api, NotificationItem};
+    use leptos::prelude::*;
+    use wasm_bindgen::closure::Closure;
+    use wasm_bindgen::JsCast;
+
+    const ANNOUNCE_EVENT: &str = "app:notification";
+    const PAGE_SIZE: usize = 10;
+
+    /// Tells the bell a notification just arrived.
+    pub fn announce() {
+        if let (Some(window), Ok(event)) =
+            (web_sys::window(), web_sys::Event::new(ANNOUNCE_EVENT))
+        {
+            let _ = window.dispatch_event(&event);
+        }
+    }
+
+    pub fn on_announce(mut callback: impl FnMut() + 'static) {
+        let Some(window) = web_sys::window() else {
+            return;
+        };
+        let listener = Closure::<dyn FnMut()>::new(move || callback());
+        let _ = window
+            .add_event_listener_with_callback(ANNOUNCE_EVENT, listener.as_ref().unchecked_ref());
+        listener.forget();
+    }
+
+    pub fn load_unread_count(unread: RwSignal<i64>) {
+        wasm_bindgen_futures::spawn_local(async move {
+            if let Some(body) = get("/api/notifications/unread-count").await {
+                if let Some(count) = body.get("unread").and_then(|v| v.as_i64()) {
+                    unread.set(count);
+                }
+            }
+        });
+    }
+
+    pub fn load_items(items: RwSignal<Vec<NotificationItem>>) {
+        wasm_bindgen_futures::spawn_local(async move {
+            let url = format!("/api/notifications?per_page={PAGE_SIZE}");
+            let Some(body) = get(&url).await else {
+                return;
+            };
+            let parsed = body
+                .get("items")
+                .and_then(|v| v.as_array())
+                .map(|list| list.iter().filter_map(parse_item).collect())
+                .unwrap_or_default();
+            items.set(parsed);
+        });
+    }
+
+    pub fn mark_all_read(
+        csrf_token: String,
+        unread: RwSignal<i64>,
+        items: RwSignal<Vec<NotificationItem>>,
+    ) {
+        wasm_bindgen_futures::spawn_local(async move {
+            if let Ok((204, _)) = fetch_api(
+                "POST",
+                "/api/notifications/read-all",
+                Some(String::new()),
+                &csrf_token,
+            )
+            .await
+            {
+                unread.set(0);
+                items.update(|items| items.iter_mut().for_each(|item| item.read = true));
+            }
+        });
+    }
+
+    /// Marks the notification read, then follows its link if it has one.
+    pub fn open_item(
+        item: NotificationItem,
+        csrf_token: String,
+        unread: RwSignal<i64>,
+        items: RwSignal<Vec<NotificationItem>>,
+    ) {
+        wasm_bindgen_futures::spawn_local(async move {
+            if !item.read {
+                let url = format!("/api/notifications/{}/read", item.id);
+                if let Ok((204, _)) =
+                    fetch_api("POST", &url, Some(String::new()), &csrf_token).await
+                {
+                    unread.update(|n| *n = (*n - 1).max(0));
+                    items.update(|items| {
+                        if let Some(entry) = items.iter_mut().find(|i| i.id == item.id) {
+                            entry.read = true;
+                        }
+                    });
+                }
+            }
+            if let (Some(link), Some(window)) = (item.link, web_sys::window()) {
+                let _ = window.location().set_href(&link);
+            }
+        });
+    }
+
+    async fn get(url: &str) -> Option<serde_json::Value> {
+        match fetch_api("GET", url, None, "").await {
+            Ok((200, body)) => serde_json::from_str(&body).ok(),
+            _ => None,
+        }
+    }
+
+    fn parse_item(value: &serde_json::Value) -> Option<NotificationItem> {
+        let text = |key: &str| value.get(key).and_then(|v| v.as_str()).map(str::to_string);
+        Some(NotificationItem {
+            id: text("id")?,
+            title: text("title")?,
+            body: text("body").unwrap_or_default(),
+            link: text("link"),
+            read: value.get("read_at").is_some_and(|v| !v.is_null()),
+        })
+    }
+}
+
+#[island(lazy)]
+pub fn LoginFormIsland(csrf_token: String) -> impl IntoView {
+    let csrf_token = StoredValue::new(csrf_token);
     let email = RwSignal::new(String::new());
     let password = RwSignal::new(String::new());
     let status = RwSignal::new(Option::<String>::None);
@@ -146,9 +808,10 @@ pub fn LoginFormIsland() -> impl IntoView {
 
             let email_val = email.get();
             let password_val = password.get();
+            let csrf_val = csrf_token.get_value();
             let status = status.clone();
             spawn_local(async move {
-                match login_api_request(email_val, password_val).await {
+                match login_api_request(email_val, password_val, csrf_val).await {
                     Ok(()) => {
                         if let Some(win) = web_sys::window() {
                             let _ = win.location().set_href("/app");
@@ -169,6 +832,7 @@ pub fn LoginFormIsland() -> impl IntoView {
                     <p class="text-slate-400 text-sm">"JWT + refresh cookie flow with CSRF token."</p>
                 </div>
                 <form class="card p-6 space-y-4" action="/app/login" method="post" on:submit=on_submit>
+                    <input type="hidden" name=CSRF_FORM_FIELD value=csrf_token.get_value()/>
                     <label class="block space-y-2">
                         <span class="text-sm text-slate-300">"Email"</span>
                         <input
@@ -209,7 +873,8 @@ pub fn LoginFormIsland() -> impl IntoView {
 }
 
 #[island(lazy)]
-pub fn RegisterFormIsland() -> impl IntoView {
+pub fn RegisterFormIsland(csrf_token: String) -> impl IntoView {
+    let csrf_token = StoredValue::new(csrf_token);
     let email = RwSignal::new(String::new());
     let password = RwSignal::new(String::new());
     let status = RwSignal::new(Option::<String>::None);
@@ -224,12 +889,19 @@ pub fn RegisterFormIsland() -> impl IntoView {
 
             let email_val = email.get();
             let password_val = password.get();
+            let csrf_val = csrf_token.get_value();
             let status = status.clone();
             spawn_local(async move {
-                match register_api_request(email_val, password_val).await {
-                    Ok(()) => {
+                match register_api_request(email_val, password_val, csrf_val).await {
+                    Ok(status) => {
+                        // 202 means the sign-up finishes by email instead of logging in directly.
+                        let next = if status == 202 {
+                            "/app/register/pending"
+                        } else {
+                            "/app"
+                        };
                         if let Some(win) = web_sys::window() {
-                            let _ = win.location().set_href("/app");
+                            let _ = win.location().set_href(next);
                         }
                     }
                     Err(msg) => status.set(Some(msg)),
@@ -247,6 +919,7 @@ pub fn RegisterFormIsland() -> impl IntoView {
                     <p class="text-slate-400 text-sm">"JWT + refresh cookie flow with CSRF token."</p>
                 </div>
                 <form class="card p-6 space-y-4" action="/app/register" method="post" on:submit=on_submit>
+                    <input type="hidden" name=CSRF_FORM_FIELD value=csrf_token.get_value()/>
                     <label class="block space-y-2">
                         <span class="text-sm text-slate-300">"Email"</span>
                         <input
@@ -288,9 +961,13 @@ pub fn RegisterFormIsland() -> impl IntoView {
 
 #[cfg(target_arch = "wasm32")]
 #[leptos::prelude::lazy]
-async fn login_api_request(email: String, password: String) -> Result<(), String> {
+async fn login_api_request(
+    email: String,
+    password: String,
+    csrf_token: String,
+) -> Result<(), String> {
     let body = serde_json::json!({ "email": email, "password": password }).to_string();
-    let txt = fetch_json("/api/auth/login", body)
+    let txt = fetch_json("/api/auth/login", body, &csrf_token)
         .await
         .map_err(|_| "Network error".to_string())?;
     let status = txt.0;
@@ -308,16 +985,20 @@ async fn login_api_request(email: String, password: String) -> Result<(), String
 
 #[cfg(target_arch = "wasm32")]
 #[leptos::prelude::lazy]
-async fn register_api_request(email: String, password: String) -> Result<(), String> {
+async fn register_api_request(
+    email: String,
+    password: String,
+    csrf_token: String,
+) -> Result<u16, String> {
     let body = serde_json::json!({ "email": email, "password": password }).to_string();
-    let txt = fetch_json("/api/auth/register", body)
+    let txt = fetch_json("/api/auth/register", body, &csrf_token)
         .await
         .map_err(|_| "Network error".to_string())?;
     let status = txt.0;
     let txt = txt.1;
 
     if status >= 200 && status < 400 {
-        return Ok(());
+        return Ok(status);
     }
     let msg = serde_json::from_str::<serde_json::Value>(&txt)
         .ok()
@@ -327,7 +1008,21 @@ async fn register_api_request(email: String, password: String) -> Result<(), Str
 }
 
 #[cfg(target_arch = "wasm32")]
-async fn fetch_json(url: &str, body: String) -> Result<(u16, String), wasm_bindgen::JsValue> {
+async fn fetch_json(
+    url: &str,
+    body: String,
+    csrf_token: &str,
+) -> Result<(u16, String), wasm_bindgen::JsValue> {
+    fetch_api("POST", url, Some(body), csrf_token).await
+}
+
+#[cfg(target_arch = "wasm32")]
+async fn fetch_api(
+    method: &str,
+    url: &str,
+    body: Option<String>,
+    csrf_token: &str,
+) -> Result<(u16, String), wasm_bindgen::JsValue> {
     use wasm_bindgen::JsCast;
     use wasm_bindgen::JsValue;
     use wasm_bindgen_futures::JsFuture;
@@ -337,13 +1032,16 @@ async fn fetch_json(url: &str, body: String) -> Result<(u16, String), wasm_bindg
     };
 
     let init = web_sys::RequestInit::new();
-    init.set_method("POST");
+    init.set_method(method);
     init.set_credentials(web_sys::RequestCredentials::SameOrigin);
-    init.set_body(&JsValue::from_str(&body));
+    if let Some(body) = &body {
+        init.set_body(&JsValue::from_str(body));
+    }
 
     let request = web_sys::Request::new_with_str_and_init(url, &init)?;
     request.headers().set("Content-Type", "application/json")?;
     request.headers().set("Accept", "application/json")?;
+    request.headers().set(CSRF_HEADER, csrf_token)?;
 
     let resp_value = JsFuture::from(window.fetch_with_request(&request)).await?;
     let resp: web_sys::Response = resp_value.dyn_into()?;
@@ -1,9 +1,15 @@
 use clap::{Parser, Subcommand};
-use domain::AuthService;
+use db::{MigrationState, MigrationStatus};
+use domain::audit_chain::{self, CheckpointSigner};
+use domain::flags::FeatureFlagService;
+use domain::jobs::JobStatus;
+use domain::ports::JobRepository;
+use domain::{AuthService, RefreshTokenHasher};
 use shared::config::AppConfig;
 use shared::dto::RegisterRequest;
-use shared::types::UserRole;
+use shared::types::{RequestContext, UserRole};
 use std::sync::Arc;
+use uuid::Uuid;
 
 #[derive(Parser, Debug)]
 #[command(
@@ -18,8 +24,12 @@ struct Cli {
 
 #[derive(Subcommand, Debug)]
 enum Commands {
-    /// Run database migrations
-    Migrate,
+    /// Apply, inspect or revert database migrations
+    Migrate {
+        /// Defaults to `run`
+        #[command(subcommand)]
+        command: Option<MigrateCommands>,
+    },
     /// Seed development data
     Seed {
         #[arg(long, default_value = "admin@example.com")]
@@ -36,6 +46,79 @@ enum Commands {
         #[arg(long, default_value = "user")]
         role: String,
     },
+    /// Inspect the tamper-evident audit log
+    Audit {
+        #[command(subcommand)]
+        command: AuditCommands,
+    },
+    /// Inspect and manage background jobs
+    Jobs {
+        #[command(subcommand)]
+        command: JobsCommands,
+    },
+    /// Inspect and toggle feature flags
+    Flags {
+        #[command(subcommand)]
+        command: FlagsCommands,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum MigrateCommands {
+    /// Apply pending migrations
+    Run {
+        /// List the migrations that would be applied without applying them
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// List every migration and whether it is applied
+    Status,
+    /// Undo the most recently applied migration
+    Revert,
+    /// Check that applied migrations match this build's checksums
+    Verify,
+}
+
+#[derive(Subcommand, Debug)]
+enum AuditCommands {
+    /// Walk the hash chain and report the first break
+    Verify,
+    /// Sign checkpoints for chain heads that have none yet
+    Checkpoint,
+}
+
+#[derive(Subcommand, Debug)]
+enum JobsCommands {
+    /// List the most recent jobs
+    List {
+        /// pending, running, succeeded, dead or cancelled
+        #[arg(long)]
+        status: Option<String>,
+        #[arg(long, default_value_t = 50)]
+        limit: i64,
+    },
+    /// Requeue a dead or cancelled job
+    Retry { id: Uuid },
+    /// Cancel a job that has not started
+    Cancel { id: Uuid },
+}
+
+#[derive(Subcommand, Debug)]
+enum FlagsCommands {
+    /// List every flag and its targeting
+    List,
+    /// Turn a flag on, creating it if needed
+    Enable { key: String },
+    /// Turn a flag off for everyone
+    Disable { key: String },
+    /// Set the percentage of users a flag is rolled out to
+    Rollout {
+        key: String,
+        #[arg(value_parser = clap::value_parser!(u8).range(0..=100))]
+        percentage: u8,
+    },
+    /// Delete a flag
+    Delete { key: String },
 }
 
 #[tokio::main]
@@ -43,13 +126,22 @@ async fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
     let config = AppConfig::from_env()?;
     let db = db::Database::connect(&config.database).await?;
-    db.migrate().await?;
-    let auth = AuthService::new(Arc::new(db.clone()));
+    if let Commands::Migrate { command } = cli.command {
+        return migrate(
+            &db,
+            command.unwrap_or(MigrateCommands::Run { dry_run: false }),
+        )
+        .await;
+    }
+    // Only `migrate` changes the schema, so other commands cannot roll out a migration early.
+    db.ensure_migrated().await?;
+    let auth = AuthService::new(
+        Arc::new(db.clone()),
+        RefreshTokenHasher::from_config(&config.auth),
+    );
 
     match cli.command {
-        Commands::Migrate => {
-            println!("Migrations applied");
-        }
+        Commands::Migrate { .. } => unreachable!("handled before connecting services"),
         Commands::Seed {
             admin_email,
             admin_password,
@@ -63,11 +155,255 @@ async fn main() -> anyhow::Result<()> {
         } => {
             let role = parse_role(&role)?;
             let req = RegisterRequest { email, password };
-            let user = auth.register(req, Some(role)).await?;
+            let user = auth
+                .register(req, Some(role), &RequestContext::default())
+                .await?;
             println!("Created user {} ({:?})", user.email, user.role);
         }
+        Commands::Audit { command } => {
+            let signer = config
+                .audit
+                .checkpoint_secret
+                .as_deref()
+                .map(CheckpointSigner::new);
+            match command {
+                AuditCommands::Verify => verify_audit_chain(&db, signer.as_ref()).await?,
+                AuditCommands::Checkpoint => {
+                    let signer = signer.ok_or_else(|| {
+                        anyhow::anyhow!("AUDIT_CHECKPOINT_SECRET is required to sign checkpoints")
+                    })?;
+                    let written = audit_chain::write_checkpoints(&db, &signer).await?;
+                    println!("Wrote {written} audit checkpoint(s)");
+                }
+            }
+        }
+        Commands::Jobs { command } => match command {
+            JobsCommands::List { status, limit } => {
+                let status = status
+                    .map(|s| {
+                        JobStatus::parse(&s)
+                            .ok_or_else(|| anyhow::anyhow!("unknown job status: {s}"))
+                    })
+                    .transpose()?;
+                list_jobs(&db, status, limit).await?;
+            }
+            JobsCommands::Retry { id } => {
+                if db.retry_job(id).await? {
+                    println!("Requeued job {id}");
+                } else {
+                    anyhow::bail!("job {id} not found or not dead/cancelled");
+                }
+            }
+            JobsCommands::Cancel { id } => {
+                if db.cancel_job(id).await? {
+                    println!("Cancelled job {id}");
+                } else {
+                    anyhow::bail!("job {id} not found or already started");
+                }
+            }
+        },
+        Commands::Flags { command } => {
+            let flags = FeatureFlagService::new(Arc::new(db.clone()));
+            let ctx = RequestContext::default();
+            match command {
+                FlagsCommands::List => list_flags(&flags).await?,
+                FlagsCommands::Enable { key } => {
+                    flags.update(&key, None, &ctx, |f| f.enabled = true).await?;
+                    println!("Enabled flag {key}");
+                }
+                FlagsCommands::Disable { key } => {
+                    flags
+                        .update(&key, None, &ctx, |f| f.enabled = false)
+                        .await?;
+                    println!("Disabled flag {key}");
+                }
+                FlagsCommands::Rollout { key, percentage } => {
+                    let flag = flags
+                        .update(&key, None, &ctx, |f| f.rollout_percentage = percentage)
+                        .await?;
+                    println!("Rolled flag {key} out to {percentage}% of users");
+                    if !flag.enabled {
+                        println!("  the flag is disabled; run `flags enable {key}` to turn it on");
+                    }
+                }
+                FlagsCommands::Delete { key } => {
+                    if flags.delete(&key, None, &ctx).await? {
+                        println!("Deleted flag {key}");
+                    } else {
+                        anyhow::bail!("flag {key} not found");
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn migrate(db: &db::Database, command: MigrateCommands) -> anyhow::Result<()> {
+    if db.is_memory() {
+        println!("The in-memory store has no migrations");
+        return Ok(());
+    }
+    match command {
+        MigrateCommands::Run { dry_run: false } => {
+            let pending = pending_migrations(db).await?;
+            db.migrate().await?;
+            println!("Applied {} migration(s)", pending.len());
+            for migration in pending {
+                println!("  {}  {}", migration.version, migration.description);
+            }
+        }
+        MigrateCommands::Run { dry_run: true } => {
+            let pending = pending_migrations(db).await?;
+            if pending.is_empty() {
+                println!("No pending migrations");
+            } else {
+                println!("Would apply {} migration(s)", pending.len());
+            }
+            for migration in pending {
+                println!("  {}  {}", migration.version, migration.description);
+            }
+        }
+        MigrateCommands::Status => {
+            for migration in db.migration_status().await? {
+                println!(
+                    "{}  {:<17}  {}",
+                    migration.version,
+                    migration.state.as_str(),
+                    migration.description
+                );
+            }
+        }
+        MigrateCommands::Revert => match db.revert_migration().await? {
+            Some(migration) => {
+                println!("Reverted {}  {}", migration.version, migration.description)
+            }
+            None => println!("No applied migrations to revert"),
+        },
+        MigrateCommands::Verify => {
+            let statuses = db.migration_status().await?;
+            check_applied_migrations(&statuses)?;
+            let applied = statuses
+                .iter()
+                .filter(|m| m.state == MigrationState::Applied)
+                .count();
+            println!("{applied} applied migration(s) match this build");
+        }
+    }
+    Ok(())
+}
+
+/// The migrations `migrate run` would apply, after checking the applied ones.
+async fn pending_migrations(db: &db::Database) -> anyhow::Result<Vec<MigrationStatus>> {
+    let statuses = db.migration_status().await?;
+    check_applied_migrations(&statuses)?;
+    Ok(statuses
+        .into_iter()
+        .filter(|m| m.state == MigrationState::Pending)
+        .collect())
+}
+
+/// Fails on applied migrations that changed, failed or are unknown to this build.
+fn check_applied_migrations(statuses: &[MigrationStatus]) -> anyhow::Result<()> {
+    let mut broken = statuses
+        .iter()
+        .filter(|m| !matches!(m.state, MigrationState::Applied | MigrationState::Pending))
+        .peekable();
+    if broken.peek().is_none() {
+        return Ok(());
+    }
+    for migration in broken {
+        println!(
+            "{}  {}  {}",
+            migration.version,
+            migration.state.as_str(),
+            migration.description
+        );
+    }
+    anyhow::bail!("applied migrations do not match this build")
+}
+
+async fn verify_audit_chain(
+    db: &db::Database,
+    signer: Option<&CheckpointSigner>,
+) -> anyhow::Result<()> {
+    if signer.is_none() {
+        println!("AUDIT_CHECKPOINT_SECRET is not set; checkpoint signatures will not be checked");
+    }
+    let report = audit_chain::verify_chain(db, signer).await?;
+    if report.unchained_events > 0 {
+        println!(
+            "{} event(s) predate chaining and cannot be verified",
+            report.unchained_events
+        );
+    }
+
+    match report.first_break {
+        Some(chain_break) => {
+            println!("Audit chain broken on {}", chain_break.day());
+            println!("  {chain_break}");
+            std::process::exit(1);
+        }
+        None => println!(
+            "Audit chain intact: {} event(s) across {} day(s), {} checkpoint(s)",
+            report.events, report.days, report.checkpoints
+        ),
+    }
+    Ok(())
+}
+
+async fn list_jobs(db: &db::Database, status: Option<JobStatus>, limit: i64) -> anyhow::Result<()> {
+    let jobs = db.list_jobs(status, limit).await?;
+    if jobs.is_empty() {
+        println!("No jobs");
+        return Ok(());
+    }
+    for job in jobs {
+        println!(
+            "{}  {:<9}  {}  attempts {}/{}  run at {}",
+            job.id,
+            job.status.as_str(),
+            job.kind,
+            job.attempts,
+            job.max_attempts,
+            job.run_at.format("%Y-%m-%d %H:%M:%S")
+        );
+        if let Some(error) = job.last_error {
+            println!("    last error: {error}");
+        }
     }
+    Ok(())
+}
 
+async fn list_flags(flags: &FeatureFlagService<db::Database>) -> anyhow::Result<()> {
+    let flags = flags.list().await?;
+    if flags.is_empty() {
+        println!("No feature flags");
+        return Ok(());
+    }
+    for flag in flags {
+        println!(
+            "{:<32}  {:<3}  rollout {:>3}%  updated {}",
+            flag.key,
+            if flag.enabled { "on" } else { "off" },
+            flag.rollout_percentage,
+            flag.updated_at.format("%Y-%m-%d %H:%M:%S")
+        );
+        if !flag.users.is_empty() {
+            println!("    {} targeted user(s)", flag.users.len());
+        }
+        if !flag.roles.is_empty() {
+            let roles: Vec<_> = flag.roles.iter().map(|r| r.as_str()).collect();
+            println!("    roles: {}", roles.join(", "));
+        }
+        if !flag.orgs.is_empty() {
+            println!("    orgs: {}", flag.orgs.join(", "));
+        }
+        if let Some(description) = flag.description {
+            println!("    {description}");
+        }
+    }
     Ok(())
 }
 
@@ -80,7 +416,10 @@ async fn seed(
         email: admin_email.clone(),
         password: admin_password,
     };
-    match auth.register(admin_req, Some(UserRole::Admin)).await {
+    match auth
+        .register(admin_req, Some(UserRole::Admin), &RequestContext::default())
+        .await
+    {
         Ok(user) => println!("Seeded admin user {}", user.email),
         Err(shared::error::AppError::Conflict(_)) => {
             println!("Admin user already exists ({admin_email})")
@@ -92,7 +431,10 @@ async fn seed(
         email: "user@example.com".into(),
         password: "password123".into(),
     };
-    match auth.register(user_req, Some(UserRole::User)).await {
+    match auth
+        .register(user_req, Some(UserRole::User), &RequestContext::default())
+        .await
+    {
         Ok(user) => println!("Seeded demo user {}", user.email),
         Err(shared::error::AppError::Conflict(_)) => println!("Demo user already exists"),
         Err(err) => return Err(err.into()),
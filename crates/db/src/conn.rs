@@ -0,0 +1,71 @@
+//! Connections for the SQL backends, which run each call either on a pooled connection
+//! or, for a store returned by `begin`, on the connection its transaction holds.
+
+use crate::{map_sqlx_error, telemetry};
+use shared::error::{AppError, Result};
+use sqlx::pool::PoolConnection;
+use sqlx::{Database, Pool, Transaction};
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+use tokio::sync::{MappedMutexGuard, Mutex, MutexGuard};
+
+/// An open transaction, or `None` once it has committed.
+pub(crate) type SharedTransaction<DB> = Arc<Mutex<Option<Transaction<'static, DB>>>>;
+
+pub(crate) enum Conn<'a, DB: Database> {
+    Pooled(PoolConnection<DB>),
+    Transaction(MappedMutexGuard<'a, Transaction<'static, DB>>),
+}
+
+impl<DB: Database> Deref for Conn<'_, DB> {
+    type Target = DB::Connection;
+
+    fn deref(&self) -> &DB::Connection {
+        match self {
+            Conn::Pooled(conn) => conn,
+            Conn::Transaction(tx) => tx,
+        }
+    }
+}
+
+impl<DB: Database> DerefMut for Conn<'_, DB> {
+    fn deref_mut(&mut self) -> &mut DB::Connection {
+        match self {
+            Conn::Pooled(conn) => conn,
+            Conn::Transaction(tx) => tx,
+        }
+    }
+}
+
+pub(crate) async fn begin<DB: Database>(pool: &Pool<DB>) -> Result<SharedTransaction<DB>> {
+    let tx = pool.begin().await.map_err(map_sqlx_error)?;
+    Ok(Arc::new(Mutex::new(Some(tx))))
+}
+
+/// A connection from `pool`, named `name` in metrics, or the transaction's connection when
+/// there is one.
+pub(crate) async fn acquire<'a, DB: Database>(
+    pool: &Pool<DB>,
+    name: &'static str,
+    tx: Option<&'a SharedTransaction<DB>>,
+) -> Result<Conn<'a, DB>> {
+    match tx {
+        None => telemetry::acquire(pool, name)
+            .await
+            .map(Conn::Pooled)
+            .map_err(map_sqlx_error),
+        Some(tx) => MutexGuard::try_map(tx.lock().await, Option::as_mut)
+            .map(Conn::Transaction)
+            .map_err(|_| finished()),
+    }
+}
+
+pub(crate) async fn commit<DB: Database>(tx: Option<&SharedTransaction<DB>>) -> Result<()> {
+    let tx = tx.ok_or_else(|| AppError::internal("no transaction to commit"))?;
+    let tx = tx.lock().await.take().ok_or_else(finished)?;
+    tx.commit().await.map_err(map_sqlx_error)
+}
+
+fn finished() -> AppError {
+    AppError::internal("transaction already committed")
+}
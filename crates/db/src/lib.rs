@@ -1,280 +1,488 @@
+mod conn;
+mod memory;
+mod migrations;
+mod postgres;
+mod routing;
+mod rows;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod telemetry;
+
 use async_trait::async_trait;
-use chrono::{DateTime, Utc};
-use domain::models::{NewUser, RefreshToken, User};
-use domain::ports::{AuditLogRepository, RefreshTokenRepository, UserRepository};
+use chrono::{DateTime, NaiveDate, Utc};
+use domain::audit_chain::{AuditChainLink, AuditCheckpoint, ChainedAuditEvent};
+use domain::events::{EventEnvelope, OutboxEntry};
+use domain::files::{NewFile, StoredFile};
+use domain::flags::FeatureFlag;
+use domain::jobs::{Job, JobSchedule, JobStatus, NewJob};
+use domain::models::{
+    AuditQuery, NewUser, PendingRegistration, RefreshToken, User, UserFilter, UserQuery,
+};
+use domain::notifications::{NewNotification, Notification, NotificationPreference};
+use domain::ports::{
+    self, AuditChainRepository, AuditLogRepository, FeatureFlagRepository, FileRepository,
+    JobRepository, NotificationRepository, OutboxRepository, PendingRegistrationRepository,
+    RefreshTokenRepository, UnitOfWork, UserRepository, WebhookRepository,
+};
+use domain::webhooks::{DeliveryAttempt, NewWebhookEndpoint, WebhookDelivery, WebhookEndpoint};
+use memory::MemoryStore;
+use postgres::PgStore;
 use shared::config::DatabaseConfig;
 use shared::error::{AppError, Result};
-use shared::types::AuditEvent;
-use sqlx::postgres::PgPoolOptions;
-use sqlx::{Pool, Postgres};
-use tracing::instrument;
+use shared::types::{AuditEvent, NotificationCategory, NotificationChannel, UserRole};
+#[cfg(feature = "sqlite")]
+use sqlite::SqliteStore;
+use sqlx::postgres::PgListener;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast;
 use uuid::Uuid;
 
-pub type PgPool = Pool<Postgres>;
+pub use migrations::{MigrationState, MigrationStatus};
+pub use postgres::PgPool;
+pub use routing::read_your_writes;
+pub use telemetry::LATENCY_BUCKETS;
+
+/// Notified by a trigger with the key of every inserted, updated or deleted feature flag.
+pub const FEATURE_FLAGS_CHANNEL: &str = "feature_flags_changed";
+
+/// The longest wait between attempts to reach the database in [`Database::connect`].
+const MAX_CONNECT_BACKOFF: Duration = Duration::from_secs(30);
+
+/// How long a dispatcher holds a claimed outbox event before another may take it.
+const OUTBOX_LEASE_SECONDS: i64 = 60;
 
+/// The repository ports, backed by whichever store `DatabaseConfig.url` names:
+/// `postgres://…` for Postgres, `sqlite://path/to/app.db` for a single SQLite file (with the
+/// `sqlite` feature), or `memory://` for a process-local store that starts empty and is lost
+/// on exit.
 #[derive(Clone)]
 pub struct Database {
-    pub pool: PgPool,
+    backend: Backend,
+}
+
+#[derive(Clone)]
+enum Backend {
+    Postgres(PgStore),
+    #[cfg(feature = "sqlite")]
+    Sqlite(Arc<SqliteStore>),
+    Memory(Arc<MemoryStore>),
 }
 
 impl Database {
+    /// Connects and waits until the database answers, retrying `config.connect_retries` times
+    /// with exponential backoff before returning the last error.
     pub async fn connect(config: &DatabaseConfig) -> Result<Self> {
-        let pool = PgPoolOptions::new()
-            .max_connections(config.max_connections)
-            .min_connections(config.min_connections)
-            .connect(&config.url)
-            .await
-            .map_err(map_sqlx_error)?;
-
-        Ok(Self { pool })
+        let db = Self::connect_lazy(config).await?;
+        let mut delay = Duration::from_millis(config.connect_backoff_ms);
+        let mut attempt = 0;
+        loop {
+            match db.ping().await {
+                Ok(()) => return Ok(db),
+                Err(err) if attempt < config.connect_retries => {
+                    attempt += 1;
+                    tracing::warn!(
+                        error = %err,
+                        attempt,
+                        retries = config.connect_retries,
+                        "database unreachable, retrying in {delay:?}"
+                    );
+                    tokio::time::sleep(delay).await;
+                    delay = (delay * 2).min(MAX_CONNECT_BACKOFF);
+                }
+                Err(err) => return Err(err),
+            }
+        }
     }
 
-    pub async fn migrate(&self) -> Result<()> {
-        sqlx::migrate!("../../migrations")
-            .run(&self.pool)
-            .await
-            .map_err(|e| AppError::internal(e.to_string()))?;
-        Ok(())
+    /// Sets up the connection pool without waiting for Postgres, which is connected to on
+    /// first use. SQLite files are still opened straight away.
+    pub async fn connect_lazy(config: &DatabaseConfig) -> Result<Self> {
+        let scheme = config.url.split_once(':').map(|(scheme, _)| scheme);
+        if config
+            .replica_url
+            .as_deref()
+            .is_some_and(|url| !url.is_empty())
+            && !matches!(scheme, Some("postgres" | "postgresql"))
+        {
+            return Err(AppError::config(
+                "a read replica needs a postgres:// database url",
+            ));
+        }
+        let backend = match scheme {
+            Some("postgres" | "postgresql") => Backend::Postgres(PgStore::connect(config)?),
+            #[cfg(feature = "sqlite")]
+            Some("sqlite") => Backend::Sqlite(Arc::new(SqliteStore::connect(config).await?)),
+            #[cfg(not(feature = "sqlite"))]
+            Some("sqlite") => {
+                return Err(AppError::config(
+                    "sqlite:// database urls need a build with the `sqlite` feature",
+                ))
+            }
+            Some("memory") => Backend::Memory(Arc::default()),
+            _ => {
+                return Err(AppError::config(
+                    "database url must start with postgres://, sqlite:// or memory://",
+                ))
+            }
+        };
+        Ok(Self { backend })
     }
 
-    pub async fn ping(&self) -> Result<()> {
-        sqlx::query("SELECT 1")
-            .execute(&self.pool)
-            .await
-            .map_err(map_sqlx_error)?;
-        Ok(())
+    /// A fresh, empty in-memory database.
+    pub fn memory() -> Self {
+        Self {
+            backend: Backend::Memory(Arc::default()),
+        }
     }
-}
 
-#[async_trait]
-impl UserRepository for Database {
-    #[instrument(skip(self, new_user), fields(email = %new_user.email))]
-    async fn create_user(&self, new_user: NewUser) -> Result<User> {
-        let row = sqlx::query_as::<_, UserRow>(
-            r#"
-            INSERT INTO users (id, email, password_hash, role, created_at, updated_at)
-            VALUES ($1, $2, $3, $4, now(), now())
-            RETURNING id, email, password_hash, role, created_at, updated_at
-            "#,
-        )
-        .bind(Uuid::new_v4())
-        .bind(new_user.email)
-        .bind(new_user.password_hash)
-        .bind(UserRoleDb::from(new_user.role))
-        .fetch_one(&self.pool)
-        .await
-        .map_err(map_sqlx_error)?;
-
-        Ok(row.into())
+    /// Whether everything stored is lost when the process exits.
+    pub fn is_memory(&self) -> bool {
+        matches!(self.backend, Backend::Memory(_))
     }
 
-    async fn find_by_email(&self, email: &str) -> Result<Option<User>> {
-        let row = sqlx::query_as::<_, UserRow>(
-            r#"
-            SELECT id, email, password_hash, role, created_at, updated_at
-            FROM users WHERE email = $1
-            "#,
-        )
-        .bind(email)
-        .fetch_optional(&self.pool)
-        .await
-        .map_err(map_sqlx_error)?;
-
-        Ok(row.map(Into::into))
+    /// Brings the schema up to date. The in-memory store has no schema.
+    pub async fn migrate(&self) -> Result<()> {
+        match &self.backend {
+            Backend::Postgres(store) => store.migrate().await,
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite(store) => store.migrate().await,
+            Backend::Memory(_) => Ok(()),
+        }
     }
 
-    async fn find_by_id(&self, id: Uuid) -> Result<Option<User>> {
-        let row = sqlx::query_as::<_, UserRow>(
-            r#"
-            SELECT id, email, password_hash, role, created_at, updated_at
-            FROM users WHERE id = $1
-            "#,
-        )
-        .bind(id)
-        .fetch_optional(&self.pool)
-        .await
-        .map_err(map_sqlx_error)?;
-
-        Ok(row.map(Into::into))
+    /// Every migration and whether it is applied, by version. Empty for the in-memory store.
+    pub async fn migration_status(&self) -> Result<Vec<MigrationStatus>> {
+        match &self.backend {
+            Backend::Postgres(store) => store.migration_status().await,
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite(store) => store.migration_status().await,
+            Backend::Memory(_) => Ok(Vec::new()),
+        }
     }
 
-    async fn list_users(&self, page: i64, per_page: i64) -> Result<(Vec<User>, i64)> {
-        let offset = (page - 1).max(0) * per_page;
-        let rows = sqlx::query_as::<_, UserRow>(
-            r#"
-            SELECT id, email, password_hash, role, created_at, updated_at
-            FROM users
-            ORDER BY created_at DESC
-            LIMIT $1 OFFSET $2
-            "#,
-        )
-        .bind(per_page)
-        .bind(offset)
-        .fetch_all(&self.pool)
-        .await
-        .map_err(map_sqlx_error)?;
-
-        let total: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM users")
-            .fetch_one(&self.pool)
-            .await
-            .map_err(map_sqlx_error)?;
-
-        Ok((rows.into_iter().map(Into::into).collect(), total.0))
+    /// An error unless every migration is applied and unchanged, for processes that must not
+    /// migrate the schema themselves.
+    pub async fn ensure_migrated(&self) -> Result<()> {
+        migrations::ensure_applied(&self.migration_status().await?)
     }
-}
 
-#[async_trait]
-impl RefreshTokenRepository for Database {
-    async fn store_refresh_token(&self, token: &RefreshToken) -> Result<()> {
-        sqlx::query(
-            r#"
-            INSERT INTO refresh_tokens (id, user_id, token_hash, expires_at, created_at)
-            VALUES ($1, $2, $3, $4, $5)
-            ON CONFLICT (token_hash) DO UPDATE SET expires_at = EXCLUDED.expires_at
-            "#,
-        )
-        .bind(token.id)
-        .bind(token.user_id)
-        .bind(&token.token_hash)
-        .bind(token.expires_at)
-        .bind(token.created_at)
-        .execute(&self.pool)
-        .await
-        .map_err(map_sqlx_error)?;
-        Ok(())
+    /// Undoes the most recently applied migration with its down migration, returning it, or
+    /// `None` when none is applied.
+    pub async fn revert_migration(&self) -> Result<Option<MigrationStatus>> {
+        match &self.backend {
+            Backend::Postgres(store) => store.revert_migration().await,
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite(store) => store.revert_migration().await,
+            Backend::Memory(_) => Err(AppError::config("the in-memory store has no migrations")),
+        }
     }
 
-    async fn find_refresh_token(&self, token_hash: &str) -> Result<Option<RefreshToken>> {
-        let row = sqlx::query_as::<_, RefreshTokenRow>(
-            r#"
-            SELECT id, user_id, token_hash, expires_at, created_at
-            FROM refresh_tokens
-            WHERE token_hash = $1
-            "#,
-        )
-        .bind(token_hash)
-        .fetch_optional(&self.pool)
-        .await
-        .map_err(map_sqlx_error)?;
-
-        Ok(row.map(Into::into))
+    /// Feature flag changes made by any process sharing the database.
+    pub async fn listen_for_flag_changes(&self) -> Result<FlagChanges> {
+        let changes = match &self.backend {
+            Backend::Postgres(store) => {
+                FlagChanges::Postgres(Box::new(store.listen_for_flag_changes().await?))
+            }
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite(store) => FlagChanges::Local(store.subscribe_flag_changes()),
+            Backend::Memory(store) => FlagChanges::Local(store.subscribe_flag_changes()),
+        };
+        Ok(changes)
     }
 
-    async fn delete_refresh_token(&self, id: Uuid) -> Result<()> {
-        sqlx::query("DELETE FROM refresh_tokens WHERE id = $1")
-            .bind(id)
-            .execute(&self.pool)
-            .await
-            .map_err(map_sqlx_error)?;
-        Ok(())
+    pub async fn ping(&self) -> Result<()> {
+        match &self.backend {
+            Backend::Postgres(store) => store.ping().await,
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite(store) => store.ping().await,
+            Backend::Memory(_) => Ok(()),
+        }
     }
 
-    async fn delete_tokens_for_user(&self, user_id: Uuid) -> Result<()> {
-        sqlx::query("DELETE FROM refresh_tokens WHERE user_id = $1")
-            .bind(user_id)
-            .execute(&self.pool)
-            .await
-            .map_err(map_sqlx_error)?;
-        Ok(())
+    /// Sets the `db_pool_connections` and `db_pool_idle_connections` gauges, per pool.
+    pub fn record_pool_metrics(&self) {
+        match &self.backend {
+            Backend::Postgres(store) => store.record_pool_metrics(),
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite(store) => store.record_pool_metrics(),
+            Backend::Memory(_) => {}
+        }
     }
-}
 
-#[async_trait]
-impl AuditLogRepository for Database {
-    async fn log_event(&self, event: AuditEvent) -> Result<()> {
-        sqlx::query(
-            r#"
-            INSERT INTO audit_log (id, user_id, event_type, ip, user_agent, created_at)
-            VALUES ($1, $2, $3, $4, $5, $6)
-            "#,
-        )
-        .bind(event.id)
-        .bind(event.user_id)
-        .bind(event.event_type)
-        .bind(event.ip)
-        .bind(event.user_agent)
-        .bind(event.created_at)
-        .execute(&self.pool)
-        .await
-        .map_err(map_sqlx_error)?;
-        Ok(())
+    /// The `db.system` of this backend's spans and metrics.
+    fn system(&self) -> &'static str {
+        match &self.backend {
+            Backend::Postgres(_) => "postgresql",
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite(_) => "sqlite",
+            Backend::Memory(_) => "memory",
+        }
+    }
+
+    /// Whether the read replica answers, or `None` without one. Reads fall back to the
+    /// primary while it does not.
+    pub async fn ping_replica(&self) -> Option<Result<()>> {
+        match &self.backend {
+            Backend::Postgres(store) => store.ping_replica().await,
+            _ => None,
+        }
     }
 }
 
-#[derive(sqlx::FromRow)]
-struct UserRow {
-    id: Uuid,
-    email: String,
-    password_hash: String,
-    role: UserRoleDb,
-    created_at: DateTime<Utc>,
-    updated_at: DateTime<Utc>,
+/// Calls through every repository port that take effect together on `commit`, from
+/// [`UnitOfWork::begin`]. Dropping it uncommitted rolls them back.
+///
+/// Postgres runs it on one pooled connection. SQLite runs it on the writer, so other writes
+/// wait for it, and the in-memory store holds its lock throughout.
+pub struct Transaction {
+    db: Database,
 }
 
-impl From<UserRow> for User {
-    fn from(row: UserRow) -> Self {
-        Self {
-            id: row.id,
-            email: row.email,
-            password_hash: row.password_hash,
-            role: row.role.into(),
-            created_at: row.created_at,
-            updated_at: row.updated_at,
+#[async_trait]
+impl UnitOfWork for Database {
+    type Transaction = Transaction;
+
+    async fn begin(&self) -> Result<Transaction> {
+        let backend = match &self.backend {
+            Backend::Postgres(store) => Backend::Postgres(store.begin().await?),
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite(store) => Backend::Sqlite(Arc::new(store.begin().await?)),
+            Backend::Memory(store) => Backend::Memory(Arc::new(store.begin().await)),
+        };
+        Ok(Transaction {
+            db: Database { backend },
+        })
+    }
+}
+
+#[async_trait]
+impl ports::Transaction for Transaction {
+    async fn commit(self) -> Result<()> {
+        match &self.db.backend {
+            Backend::Postgres(store) => store.commit().await,
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite(store) => store.commit().await,
+            Backend::Memory(store) => store.commit().await,
         }
     }
 }
 
-#[derive(sqlx::Type, Copy, Clone, Debug, PartialEq, Eq)]
-#[sqlx(type_name = "user_role", rename_all = "lowercase")]
-enum UserRoleDb {
-    User,
-    Admin,
+/// A subscription to feature flag changes, from [`Database::listen_for_flag_changes`].
+pub enum FlagChanges {
+    Postgres(Box<PgListener>),
+    /// Changes made through this process, for backends without a notification channel.
+    Local(broadcast::Receiver<String>),
 }
 
-impl From<UserRoleDb> for shared::types::UserRole {
-    fn from(value: UserRoleDb) -> Self {
-        match value {
-            UserRoleDb::User => shared::types::UserRole::User,
-            UserRoleDb::Admin => shared::types::UserRole::Admin,
+impl FlagChanges {
+    /// The key of the next changed flag. `None` means changes may have been missed, so
+    /// anything cached should be reloaded.
+    pub async fn recv(&mut self) -> Result<Option<String>> {
+        match self {
+            FlagChanges::Postgres(listener) => listener
+                .try_recv()
+                .await
+                .map(|notification| notification.map(|n| n.payload().to_string()))
+                .map_err(map_sqlx_error),
+            FlagChanges::Local(receiver) => match receiver.recv().await {
+                Ok(key) => Ok(Some(key)),
+                Err(broadcast::error::RecvError::Lagged(_)) => Ok(None),
+                Err(broadcast::error::RecvError::Closed) => {
+                    Err(AppError::Unavailable("feature flag changes closed".into()))
+                }
+            },
         }
     }
 }
 
-impl From<shared::types::UserRole> for UserRoleDb {
-    fn from(value: shared::types::UserRole) -> Self {
-        match value {
-            shared::types::UserRole::User => UserRoleDb::User,
-            shared::types::UserRole::Admin => UserRoleDb::Admin,
+/// Implements a repository port for [`Database`] by handing each call to the backend, in the
+/// span and metrics of [`telemetry::observe`], and for [`Transaction`] by handing it to the database the transaction runs on.
+macro_rules! forward {
+    ($port:ident { $(async fn $name:ident(&self $(, $arg:ident: $ty:ty)*) -> $ret:ty;)* }) => {
+        #[async_trait]
+        impl $port for Database {
+            $(
+                async fn $name(&self $(, $arg: $ty)*) -> $ret {
+                    telemetry::observe(self.system(), stringify!($name), async {
+                        match &self.backend {
+                            Backend::Postgres(store) => store.$name($($arg),*).await,
+                            #[cfg(feature = "sqlite")]
+                            Backend::Sqlite(store) => store.$name($($arg),*).await,
+                            Backend::Memory(store) => store.$name($($arg),*).await,
+                        }
+                    })
+                    .await
+                }
+            )*
         }
-    }
-}
 
-#[derive(sqlx::FromRow)]
-struct RefreshTokenRow {
-    id: Uuid,
-    user_id: Uuid,
-    token_hash: String,
-    expires_at: DateTime<Utc>,
-    created_at: DateTime<Utc>,
+        #[async_trait]
+        impl $port for Transaction {
+            $(
+                async fn $name(&self $(, $arg: $ty)*) -> $ret {
+                    self.db.$name($($arg),*).await
+                }
+            )*
+        }
+    };
 }
 
-impl From<RefreshTokenRow> for RefreshToken {
-    fn from(row: RefreshTokenRow) -> Self {
-        Self {
-            id: row.id,
-            user_id: row.user_id,
-            token_hash: row.token_hash,
-            expires_at: row.expires_at,
-            created_at: row.created_at,
+forward!(UserRepository {
+    async fn create_user(&self, new_user: NewUser) -> Result<User>;
+    async fn find_by_email(&self, email: &str) -> Result<Option<User>>;
+    async fn find_by_id(&self, id: Uuid) -> Result<Option<User>>;
+    async fn list_users(&self, query: &UserQuery) -> Result<Vec<User>>;
+    async fn count_users(&self, filter: &UserFilter) -> Result<i64>;
+    async fn update_role(&self, id: Uuid, role: UserRole) -> Result<Option<User>>;
+    async fn update_password_hash(&self, id: Uuid, password_hash: &str) -> Result<()>;
+});
+
+forward!(RefreshTokenRepository {
+    async fn store_refresh_token(&self, token: &RefreshToken) -> Result<()>;
+    async fn find_refresh_token(&self, token_hash: &str) -> Result<Option<RefreshToken>>;
+    async fn delete_refresh_token(&self, id: Uuid) -> Result<bool>;
+    async fn delete_tokens_for_user(&self, user_id: Uuid) -> Result<()>;
+    async fn delete_expired_tokens(&self, now: DateTime<Utc>) -> Result<u64>;
+});
+
+forward!(AuditLogRepository {
+    async fn log_event(&self, event: AuditEvent) -> Result<()>;
+    async fn list_events(&self, query: &AuditQuery) -> Result<Vec<AuditEvent>>;
+});
+
+forward!(AuditChainRepository {
+    async fn chain_days(&self) -> Result<Vec<NaiveDate>>;
+    async fn chained_events(&self, day: NaiveDate) -> Result<Vec<ChainedAuditEvent>>;
+    async fn count_unchained_events(&self) -> Result<i64>;
+    async fn unanchored_heads(&self) -> Result<Vec<AuditChainLink>>;
+    async fn store_checkpoint(&self, checkpoint: &AuditCheckpoint) -> Result<()>;
+    async fn checkpoints(&self, day: NaiveDate) -> Result<Vec<AuditCheckpoint>>;
+    async fn purge_days_before(&self, day: NaiveDate) -> Result<u64>;
+});
+
+forward!(PendingRegistrationRepository {
+    async fn store_pending_registration(&self, pending: &PendingRegistration) -> Result<()>;
+    async fn take_pending_registration(
+        &self,
+        token_hash: &str
+    ) -> Result<Option<PendingRegistration>>;
+    async fn delete_expired_pending_registrations(&self, now: DateTime<Utc>) -> Result<u64>;
+});
+
+forward!(JobRepository {
+    async fn enqueue_job(&self, job: NewJob) -> Result<Job>;
+    async fn claim_jobs(&self, worker: &str, kinds: &[String], limit: i64) -> Result<Vec<Job>>;
+    async fn complete_job(&self, id: Uuid) -> Result<()>;
+    async fn fail_job(
+        &self,
+        id: Uuid,
+        error: &str,
+        retry_at: Option<DateTime<Utc>>
+    ) -> Result<()>;
+    async fn release_stale_jobs(&self, locked_before: DateTime<Utc>) -> Result<u64>;
+    async fn list_jobs(&self, status: Option<JobStatus>, limit: i64) -> Result<Vec<Job>>;
+    async fn retry_job(&self, id: Uuid) -> Result<bool>;
+    async fn cancel_job(&self, id: Uuid) -> Result<bool>;
+    async fn upsert_schedule(&self, schedule: &JobSchedule) -> Result<()>;
+    async fn enqueue_due_schedules(&self, now: DateTime<Utc>) -> Result<usize>;
+});
+
+forward!(WebhookRepository {
+    async fn create_webhook_endpoint(
+        &self,
+        endpoint: NewWebhookEndpoint
+    ) -> Result<WebhookEndpoint>;
+    async fn list_webhook_endpoints(&self, owner_id: Uuid) -> Result<Vec<WebhookEndpoint>>;
+    async fn find_webhook_endpoint(&self, id: Uuid) -> Result<Option<WebhookEndpoint>>;
+    async fn delete_webhook_endpoint(&self, id: Uuid, owner_id: Uuid) -> Result<bool>;
+    async fn find_webhook_delivery(&self, id: Uuid) -> Result<Option<WebhookDelivery>>;
+    async fn list_webhook_deliveries(
+        &self,
+        endpoint_id: Uuid,
+        limit: i64
+    ) -> Result<Vec<WebhookDelivery>>;
+    async fn queue_webhook_deliveries(&self, event: &AuditEvent) -> Result<usize>;
+    async fn record_delivery_attempt(&self, id: Uuid, attempt: &DeliveryAttempt) -> Result<()>;
+    async fn redeliver_webhook(&self, id: Uuid) -> Result<bool>;
+});
+
+forward!(FeatureFlagRepository {
+    async fn list_flags(&self) -> Result<Vec<FeatureFlag>>;
+    async fn find_flag(&self, key: &str) -> Result<Option<FeatureFlag>>;
+    async fn upsert_flag(&self, flag: &FeatureFlag) -> Result<FeatureFlag>;
+    async fn delete_flag(&self, key: &str) -> Result<bool>;
+});
+
+forward!(NotificationRepository {
+    async fn create_notification(&self, notification: NewNotification) -> Result<Notification>;
+    async fn list_notifications(
+        &self,
+        user_id: Uuid,
+        page: i64,
+        per_page: i64
+    ) -> Result<(Vec<Notification>, i64)>;
+    async fn count_unread_notifications(&self, user_id: Uuid) -> Result<i64>;
+    async fn mark_notification_read(&self, user_id: Uuid, id: Uuid) -> Result<bool>;
+    async fn mark_all_notifications_read(&self, user_id: Uuid) -> Result<u64>;
+    async fn notification_preferences(&self, user_id: Uuid) -> Result<Vec<NotificationPreference>>;
+    async fn set_notification_preference(
+        &self,
+        user_id: Uuid,
+        category: NotificationCategory,
+        channel: NotificationChannel
+    ) -> Result<()>;
+});
+
+forward!(FileRepository {
+    async fn create_file(&self, file: NewFile) -> Result<StoredFile>;
+    async fn find_file(&self, id: Uuid) -> Result<Option<StoredFile>>;
+    async fn delete_file(&self, id: Uuid) -> Result<bool>;
+    async fn set_avatar(&self, user_id: Uuid, file_id: Option<Uuid>) -> Result<Option<Uuid>>;
+    async fn find_avatar(&self, user_id: Uuid) -> Result<Option<StoredFile>>;
+});
+
+forward!(OutboxRepository {
+    async fn append_outbox(&self, envelope: &EventEnvelope) -> Result<()>;
+    async fn claim_outbox(&self, limit: i64) -> Result<Vec<OutboxEntry>>;
+    async fn mark_outbox_delivered(&self, id: Uuid, subscriber: &str) -> Result<()>;
+    async fn complete_outbox(&self, id: Uuid) -> Result<()>;
+    async fn retry_outbox(
+        &self,
+        id: Uuid,
+        error: &str,
+        retry_at: Option<DateTime<Utc>>
+    ) -> Result<()>;
+});
+
+/// A `LIKE … ESCAPE '\'` pattern matching `needle` anywhere, with its wildcards taken
+/// literally.
+pub(crate) fn contains_pattern(needle: &str) -> String {
+    let mut pattern = String::with_capacity(needle.len() + 2);
+    pattern.push('%');
+    for c in needle.chars() {
+        if matches!(c, '\\' | '%' | '_') {
+            pattern.push('\\');
         }
+        pattern.push(c);
     }
+    pattern.push('%');
+    pattern
+}
+
+/// The `OFFSET` of a 1-based `page`, rejecting pages too far out to address.
+pub(crate) fn page_offset(page: i64, per_page: i64) -> Result<i64> {
+    page.saturating_sub(1)
+        .max(0)
+        .checked_mul(per_page)
+        .ok_or_else(|| AppError::Validation("page is out of range".into()))
 }
 
-fn map_sqlx_error(err: sqlx::Error) -> AppError {
+pub(crate) fn map_sqlx_error(err: sqlx::Error) -> AppError {
     match &err {
         sqlx::Error::RowNotFound => AppError::NotFound,
         sqlx::Error::Database(db_err) => {
-            if db_err.code().as_deref() == Some("23505") {
+            if db_err.is_unique_violation() {
                 AppError::Conflict("duplicate record".into())
             } else {
                 AppError::internal(db_err.to_string())
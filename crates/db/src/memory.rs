@@ -0,0 +1,1194 @@
+use crate::{page_offset, OUTBOX_LEASE_SECONDS};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use domain::audit_chain::{self, AuditChainLink, AuditCheckpoint, ChainedAuditEvent};
+use domain::events::{EventEnvelope, OutboxEntry};
+use domain::files::{NewFile, StoredFile};
+use domain::flags::FeatureFlag;
+use domain::jobs::{CronSchedule, Job, JobSchedule, JobStatus, NewJob};
+use domain::models::{
+    AuditQuery, NewUser, PendingRegistration, RefreshToken, User, UserCursor, UserFilter, UserQuery,
+};
+use domain::notifications::{NewNotification, Notification, NotificationPreference};
+use domain::ports::{
+    AuditChainRepository, AuditLogRepository, FeatureFlagRepository, FileRepository, JobRepository,
+    NotificationRepository, OutboxRepository, PendingRegistrationRepository,
+    RefreshTokenRepository, UserRepository, WebhookRepository,
+};
+use domain::webhooks::{
+    self, DeliverWebhook, DeliveryAttempt, DeliveryStatus, NewWebhookEndpoint, WebhookDelivery,
+    WebhookEndpoint,
+};
+use shared::error::{AppError, Result};
+use shared::types::{AuditEvent, NotificationCategory, NotificationChannel, UserRole};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+use tokio::sync::{broadcast, Mutex, MutexGuard, OwnedMutexGuard};
+use uuid::Uuid;
+
+const FLAG_CHANGES_CAPACITY: usize = 64;
+/// The `jobs.max_attempts` column default, for jobs enqueued by schedules.
+const DEFAULT_MAX_ATTEMPTS: i32 = 5;
+
+/// The in-memory backend of [`crate::Database`]. It keeps every table behind one lock, so
+/// each call is atomic the way a single statement or transaction is in Postgres. It
+/// enforces the schema's unique keys and ordering, but not its foreign keys.
+pub(crate) struct MemoryStore {
+    tables: Arc<Mutex<Tables>>,
+    /// Set on a store from `begin`, which holds the lock for the whole transaction.
+    tx: Option<Mutex<HeldTables>>,
+    flag_changes: broadcast::Sender<String>,
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        Self {
+            tables: Arc::default(),
+            tx: None,
+            flag_changes: broadcast::channel(FLAG_CHANGES_CAPACITY).0,
+        }
+    }
+}
+
+/// The tables a transaction has locked, and their state when it began.
+struct HeldTables {
+    tables: OwnedMutexGuard<Tables>,
+    /// Put back on drop unless the transaction committed.
+    rollback: Option<Tables>,
+}
+
+impl Drop for HeldTables {
+    fn drop(&mut self) {
+        if let Some(snapshot) = self.rollback.take() {
+            *self.tables = snapshot;
+        }
+    }
+}
+
+enum TablesGuard<'a> {
+    Shared(MutexGuard<'a, Tables>),
+    Held(MutexGuard<'a, HeldTables>),
+}
+
+impl Deref for TablesGuard<'_> {
+    type Target = Tables;
+
+    fn deref(&self) -> &Tables {
+        match self {
+            TablesGuard::Shared(tables) => tables,
+            TablesGuard::Held(held) => &held.tables,
+        }
+    }
+}
+
+impl DerefMut for TablesGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Tables {
+        match self {
+            TablesGuard::Shared(tables) => tables,
+            TablesGuard::Held(held) => &mut held.tables,
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+struct Tables {
+    users: Vec<UserRecord>,
+    refresh_tokens: Vec<RefreshToken>,
+    pending_registrations: Vec<PendingRegistration>,
+    audit_log: Vec<ChainedAuditEvent>,
+    audit_checkpoints: Vec<AuditCheckpoint>,
+    jobs: Vec<Job>,
+    job_schedules: Vec<JobSchedule>,
+    webhook_endpoints: Vec<WebhookEndpoint>,
+    webhook_deliveries: Vec<WebhookDelivery>,
+    feature_flags: BTreeMap<String, FeatureFlag>,
+    notifications: Vec<Notification>,
+    notification_preferences: Vec<(Uuid, NotificationPreference)>,
+    files: Vec<StoredFile>,
+    outbox: Vec<OutboxRecord>,
+}
+
+#[derive(Clone)]
+struct UserRecord {
+    user: User,
+    avatar_file_id: Option<Uuid>,
+}
+
+#[derive(Clone)]
+struct OutboxRecord {
+    envelope: EventEnvelope,
+    attempts: i32,
+    available_at: DateTime<Utc>,
+    locked_until: Option<DateTime<Utc>>,
+    processed_at: Option<DateTime<Utc>>,
+    failed_at: Option<DateTime<Utc>>,
+    delivered_to: Vec<String>,
+}
+
+impl MemoryStore {
+    pub(crate) async fn begin(&self) -> Self {
+        let tables = self.tables.clone().lock_owned().await;
+        let rollback = Some(tables.clone());
+        Self {
+            tables: self.tables.clone(),
+            tx: Some(Mutex::new(HeldTables { tables, rollback })),
+            flag_changes: self.flag_changes.clone(),
+        }
+    }
+
+    pub(crate) async fn commit(&self) -> Result<()> {
+        let tx = self
+            .tx
+            .as_ref()
+            .ok_or_else(|| AppError::internal("no transaction to commit"))?;
+        tx.lock().await.rollback = None;
+        Ok(())
+    }
+
+    async fn tables(&self) -> TablesGuard<'_> {
+        match &self.tx {
+            None => TablesGuard::Shared(self.tables.lock().await),
+            Some(held) => TablesGuard::Held(held.lock().await),
+        }
+    }
+
+    pub(crate) fn subscribe_flag_changes(&self) -> broadcast::Receiver<String> {
+        self.flag_changes.subscribe()
+    }
+
+    fn flag_changed(&self, key: &str) {
+        // Nobody listening is fine; the cache loads from the store when it starts.
+        let _ = self.flag_changes.send(key.to_string());
+    }
+}
+
+/// Timestamps are stored with the microsecond precision Postgres keeps.
+fn now() -> DateTime<Utc> {
+    audit_chain::truncate_to_micros(Utc::now())
+}
+
+fn micros(ts: DateTime<Utc>) -> DateTime<Utc> {
+    audit_chain::truncate_to_micros(ts)
+}
+
+fn duplicate() -> AppError {
+    AppError::Conflict("duplicate record".into())
+}
+
+/// `LIMIT` as a slice length, rejecting negatives as Postgres does.
+fn limit_rows(limit: i64) -> Result<usize> {
+    usize::try_from(limit).map_err(|_| AppError::internal("LIMIT must not be negative"))
+}
+
+/// The `OFFSET` and `LIMIT` the Postgres backend derives from a 1-based page.
+fn page_window(page: i64, per_page: i64) -> Result<(usize, usize)> {
+    let offset = usize::try_from(page_offset(page, per_page)?)
+        .map_err(|_| AppError::internal("OFFSET must not be negative"))?;
+    Ok((offset, limit_rows(per_page)?))
+}
+
+fn user_matches(user: &User, filter: &UserFilter) -> bool {
+    filter
+        .email
+        .as_ref()
+        .is_none_or(|needle| user.email.to_lowercase().contains(&needle.to_lowercase()))
+        && filter.role.is_none_or(|role| user.role == role)
+        && filter
+            .created_from
+            .is_none_or(|from| user.created_at >= from)
+        && filter.created_to.is_none_or(|to| user.created_at < to)
+}
+
+fn new_job(job: NewJob) -> Job {
+    let now = now();
+    Job {
+        id: Uuid::new_v4(),
+        kind: job.kind,
+        payload: job.payload,
+        status: JobStatus::Pending,
+        attempts: 0,
+        max_attempts: job.max_attempts,
+        run_at: micros(job.run_at),
+        locked_by: None,
+        locked_at: None,
+        last_error: None,
+        created_at: now,
+        updated_at: now,
+    }
+}
+
+fn queue_webhook_delivery(tables: &mut Tables, delivery_id: Uuid) -> Result<()> {
+    let job = NewJob::of(&DeliverWebhook { delivery_id })?;
+    tables.jobs.push(new_job(job));
+    Ok(())
+}
+
+#[async_trait]
+impl UserRepository for MemoryStore {
+    async fn create_user(&self, new_user: NewUser) -> Result<User> {
+        let mut tables = self.tables().await;
+        if tables.users.iter().any(|r| r.user.email == new_user.email) {
+            return Err(duplicate());
+        }
+        let now = now();
+        let user = User {
+            id: Uuid::new_v4(),
+            email: new_user.email,
+            password_hash: new_user.password_hash,
+            role: new_user.role,
+            created_at: now,
+            updated_at: now,
+        };
+        tables.users.push(UserRecord {
+            user: user.clone(),
+            avatar_file_id: None,
+        });
+        Ok(user)
+    }
+
+    async fn find_by_email(&self, email: &str) -> Result<Option<User>> {
+        let tables = self.tables().await;
+        let user = tables.users.iter().find(|r| r.user.email == email);
+        Ok(user.map(|r| r.user.clone()))
+    }
+
+    async fn find_by_id(&self, id: Uuid) -> Result<Option<User>> {
+        let tables = self.tables().await;
+        let user = tables.users.iter().find(|r| r.user.id == id);
+        Ok(user.map(|r| r.user.clone()))
+    }
+
+    async fn list_users(&self, query: &UserQuery) -> Result<Vec<User>> {
+        let limit = limit_rows(query.limit)?;
+        let tables = self.tables().await;
+        let mut users: Vec<(UserCursor, &User)> = tables
+            .users
+            .iter()
+            .map(|r| &r.user)
+            .filter(|u| user_matches(u, &query.filter))
+            .map(|u| (UserCursor::from_user(u, query.sort), u))
+            .collect();
+        users.sort_by(|(a, _), (b, _)| a.cmp(b));
+        if query.descending {
+            users.reverse();
+        }
+        Ok(users
+            .into_iter()
+            .filter(|(key, _)| {
+                query.after.as_ref().is_none_or(|after| {
+                    if query.descending {
+                        key < after
+                    } else {
+                        key > after
+                    }
+                })
+            })
+            .take(limit)
+            .map(|(_, u)| u.clone())
+            .collect())
+    }
+
+    async fn count_users(&self, filter: &UserFilter) -> Result<i64> {
+        let tables = self.tables().await;
+        let count = tables
+            .users
+            .iter()
+            .filter(|r| user_matches(&r.user, filter))
+            .count();
+        Ok(count as i64)
+    }
+
+    async fn update_role(&self, id: Uuid, role: UserRole) -> Result<Option<User>> {
+        let mut tables = self.tables().await;
+        let user = tables.users.iter_mut().find(|r| r.user.id == id).map(|r| {
+            r.user.role = role;
+            r.user.updated_at = now();
+            r.user.clone()
+        });
+        Ok(user)
+    }
+
+    async fn update_password_hash(&self, id: Uuid, password_hash: &str) -> Result<()> {
+        let mut tables = self.tables().await;
+        if let Some(record) = tables.users.iter_mut().find(|r| r.user.id == id) {
+            record.user.password_hash = password_hash.to_string();
+            record.user.updated_at = now();
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RefreshTokenRepository for MemoryStore {
+    async fn store_refresh_token(&self, token: &RefreshToken) -> Result<()> {
+        let mut tables = self.tables().await;
+        let expires_at = micros(token.expires_at);
+        if let Some(existing) = tables
+            .refresh_tokens
+            .iter_mut()
+            .find(|t| t.token_hash == token.token_hash)
+        {
+            existing.expires_at = expires_at;
+            return Ok(());
+        }
+        if tables.refresh_tokens.iter().any(|t| t.id == token.id) {
+            return Err(duplicate());
+        }
+        tables.refresh_tokens.push(RefreshToken {
+            expires_at,
+            created_at: micros(token.created_at),
+            ..token.clone()
+        });
+        Ok(())
+    }
+
+    async fn find_refresh_token(&self, token_hash: &str) -> Result<Option<RefreshToken>> {
+        let tables = self.tables().await;
+        let token = tables
+            .refresh_tokens
+            .iter()
+            .find(|t| t.token_hash == token_hash);
+        Ok(token.cloned())
+    }
+
+    async fn delete_refresh_token(&self, id: Uuid) -> Result<bool> {
+        let mut tables = self.tables().await;
+        let before = tables.refresh_tokens.len();
+        tables.refresh_tokens.retain(|t| t.id != id);
+        Ok(tables.refresh_tokens.len() < before)
+    }
+
+    async fn delete_tokens_for_user(&self, user_id: Uuid) -> Result<()> {
+        self.tables()
+            .await
+            .refresh_tokens
+            .retain(|t| t.user_id != user_id);
+        Ok(())
+    }
+
+    async fn delete_expired_tokens(&self, now: DateTime<Utc>) -> Result<u64> {
+        let mut tables = self.tables().await;
+        let before = tables.refresh_tokens.len();
+        tables.refresh_tokens.retain(|t| t.expires_at >= now);
+        Ok((before - tables.refresh_tokens.len()) as u64)
+    }
+}
+
+#[async_trait]
+impl AuditLogRepository for MemoryStore {
+    async fn log_event(&self, mut event: AuditEvent) -> Result<()> {
+        event.created_at = micros(event.created_at);
+        let day = audit_chain::chain_day(event.created_at);
+
+        let mut tables = self.tables().await;
+        if tables.audit_log.iter().any(|e| e.event.id == event.id) {
+            return Err(duplicate());
+        }
+        let head = tables
+            .audit_log
+            .iter()
+            .filter(|e| e.link.day == day)
+            .max_by_key(|e| e.link.seq);
+        let (seq, prev_hash) = match head {
+            Some(head) => (head.link.seq + 1, head.link.hash.clone()),
+            None => (1, audit_chain::genesis_hash(day)),
+        };
+        let hash = audit_chain::event_hash(&prev_hash, seq, &event);
+        tables.audit_log.push(ChainedAuditEvent {
+            event,
+            link: AuditChainLink {
+                day,
+                seq,
+                prev_hash,
+                hash,
+            },
+        });
+        Ok(())
+    }
+
+    async fn list_events(&self, query: &AuditQuery) -> Result<Vec<AuditEvent>> {
+        let limit = limit_rows(query.limit)?;
+        let event_type = query.event_type.map(|t| t.as_str());
+        let tables = self.tables().await;
+        let mut events: Vec<&AuditEvent> = tables
+            .audit_log
+            .iter()
+            .map(|e| &e.event)
+            .filter(|e| {
+                query
+                    .user_id
+                    .is_none_or(|id| e.actor_id == Some(id) || e.subject_id == Some(id))
+            })
+            .filter(|e| event_type.is_none_or(|t| e.event_type == t))
+            .filter(|e| query.from.is_none_or(|from| e.created_at >= from))
+            .filter(|e| query.to.is_none_or(|to| e.created_at < to))
+            .filter(|e| {
+                query
+                    .after
+                    .is_none_or(|c| (e.created_at, e.id) < (c.created_at, c.id))
+            })
+            .collect();
+        events.sort_by_key(|e| Reverse((e.created_at, e.id)));
+        Ok(events.into_iter().take(limit).cloned().collect())
+    }
+}
+
+#[async_trait]
+impl AuditChainRepository for MemoryStore {
+    async fn chain_days(&self) -> Result<Vec<NaiveDate>> {
+        let tables = self.tables().await;
+        let mut days: Vec<NaiveDate> = tables
+            .audit_log
+            .iter()
+            .map(|e| e.link.day)
+            .chain(tables.audit_checkpoints.iter().map(|c| c.day))
+            .collect();
+        days.sort();
+        days.dedup();
+        Ok(days)
+    }
+
+    async fn chained_events(&self, day: NaiveDate) -> Result<Vec<ChainedAuditEvent>> {
+        let tables = self.tables().await;
+        let mut events: Vec<ChainedAuditEvent> = tables
+            .audit_log
+            .iter()
+            .filter(|e| e.link.day == day)
+            .cloned()
+            .collect();
+        events.sort_by_key(|e| e.link.seq);
+        Ok(events)
+    }
+
+    async fn count_unchained_events(&self) -> Result<i64> {
+        // Every event is chained as it is logged; only rows from before chaining existed
+        // in Postgres can be unchained.
+        Ok(0)
+    }
+
+    async fn unanchored_heads(&self) -> Result<Vec<AuditChainLink>> {
+        let tables = self.tables().await;
+        let mut heads: BTreeMap<NaiveDate, &AuditChainLink> = BTreeMap::new();
+        for link in tables.audit_log.iter().map(|e| &e.link) {
+            let head = heads.entry(link.day).or_insert(link);
+            if link.seq > head.seq {
+                *head = link;
+            }
+        }
+        Ok(heads
+            .into_values()
+            .filter(|head| {
+                !tables
+                    .audit_checkpoints
+                    .iter()
+                    .any(|c| c.day == head.day && c.seq == head.seq)
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn store_checkpoint(&self, checkpoint: &AuditCheckpoint) -> Result<()> {
+        let mut tables = self.tables().await;
+        let checkpoints = &mut tables.audit_checkpoints;
+        if checkpoints
+            .iter()
+            .any(|c| c.day == checkpoint.day && c.seq == checkpoint.seq)
+        {
+            return Ok(());
+        }
+        if checkpoints.iter().any(|c| c.id == checkpoint.id) {
+            return Err(duplicate());
+        }
+        checkpoints.push(AuditCheckpoint {
+            created_at: micros(checkpoint.created_at),
+            ..checkpoint.clone()
+        });
+        Ok(())
+    }
+
+    async fn checkpoints(&self, day: NaiveDate) -> Result<Vec<AuditCheckpoint>> {
+        let tables = self.tables().await;
+        let mut checkpoints: Vec<AuditCheckpoint> = tables
+            .audit_checkpoints
+            .iter()
+            .filter(|c| c.day == day)
+            .cloned()
+            .collect();
+        checkpoints.sort_by_key(|c| c.seq);
+        Ok(checkpoints)
+    }
+
+    async fn purge_days_before(&self, day: NaiveDate) -> Result<u64> {
+        let mut tables = self.tables().await;
+        let before = tables.audit_log.len();
+        tables.audit_log.retain(|e| e.link.day >= day);
+        let deleted = before - tables.audit_log.len();
+        tables.audit_checkpoints.retain(|c| c.day >= day);
+        Ok(deleted as u64)
+    }
+}
+
+#[async_trait]
+impl PendingRegistrationRepository for MemoryStore {
+    async fn store_pending_registration(&self, pending: &PendingRegistration) -> Result<()> {
+        let mut tables = self.tables().await;
+        if tables
+            .pending_registrations
+            .iter()
+            .any(|p| p.id == pending.id || p.token_hash == pending.token_hash)
+        {
+            return Err(duplicate());
+        }
+        tables.pending_registrations.push(PendingRegistration {
+            expires_at: micros(pending.expires_at),
+            created_at: micros(pending.created_at),
+            ..pending.clone()
+        });
+        Ok(())
+    }
+
+    async fn take_pending_registration(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<PendingRegistration>> {
+        let mut tables = self.tables().await;
+        let index = tables
+            .pending_registrations
+            .iter()
+            .position(|p| p.token_hash == token_hash);
+        Ok(index.map(|i| tables.pending_registrations.remove(i)))
+    }
+
+    async fn delete_expired_pending_registrations(&self, now: DateTime<Utc>) -> Result<u64> {
+        let mut tables = self.tables().await;
+        let before = tables.pending_registrations.len();
+        tables.pending_registrations.retain(|p| p.expires_at >= now);
+        Ok((before - tables.pending_registrations.len()) as u64)
+    }
+}
+
+#[async_trait]
+impl JobRepository for MemoryStore {
+    async fn enqueue_job(&self, job: NewJob) -> Result<Job> {
+        let job = new_job(job);
+        self.tables().await.jobs.push(job.clone());
+        Ok(job)
+    }
+
+    async fn claim_jobs(&self, worker: &str, kinds: &[String], limit: i64) -> Result<Vec<Job>> {
+        let limit = limit_rows(limit)?;
+        let now = now();
+        let mut tables = self.tables().await;
+        let mut due: Vec<&mut Job> = tables
+            .jobs
+            .iter_mut()
+            .filter(|j| j.status == JobStatus::Pending && j.run_at <= now)
+            .filter(|j| kinds.contains(&j.kind))
+            .collect();
+        due.sort_by_key(|j| j.run_at);
+        Ok(due
+            .into_iter()
+            .take(limit)
+            .map(|job| {
+                job.status = JobStatus::Running;
+                job.attempts += 1;
+                job.locked_by = Some(worker.to_string());
+                job.locked_at = Some(now);
+                job.updated_at = now;
+                job.clone()
+            })
+            .collect())
+    }
+
+    async fn complete_job(&self, id: Uuid) -> Result<()> {
+        let mut tables = self.tables().await;
+        if let Some(job) = tables.jobs.iter_mut().find(|j| j.id == id) {
+            job.status = JobStatus::Succeeded;
+            job.locked_by = None;
+            job.locked_at = None;
+            job.updated_at = now();
+        }
+        Ok(())
+    }
+
+    async fn fail_job(&self, id: Uuid, error: &str, retry_at: Option<DateTime<Utc>>) -> Result<()> {
+        let mut tables = self.tables().await;
+        if let Some(job) = tables.jobs.iter_mut().find(|j| j.id == id) {
+            match retry_at {
+                Some(retry_at) => {
+                    job.status = JobStatus::Pending;
+                    job.run_at = micros(retry_at);
+                }
+                None => job.status = JobStatus::Dead,
+            }
+            job.last_error = Some(error.to_string());
+            job.locked_by = None;
+            job.locked_at = None;
+            job.updated_at = now();
+        }
+        Ok(())
+    }
+
+    async fn release_stale_jobs(&self, locked_before: DateTime<Utc>) -> Result<u64> {
+        let now = now();
+        let mut tables = self.tables().await;
+        let mut released = 0;
+        for job in tables.jobs.iter_mut().filter(|j| {
+            j.status == JobStatus::Running && j.locked_at.is_some_and(|at| at < locked_before)
+        }) {
+            job.status = JobStatus::Pending;
+            job.locked_by = None;
+            job.locked_at = None;
+            job.updated_at = now;
+            job.last_error = Some("lease expired before the job finished".into());
+            released += 1;
+        }
+        Ok(released)
+    }
+
+    async fn list_jobs(&self, status: Option<JobStatus>, limit: i64) -> Result<Vec<Job>> {
+        let limit = limit_rows(limit)?;
+        let tables = self.tables().await;
+        let mut jobs: Vec<&Job> = tables
+            .jobs
+            .iter()
+            .rev()
+            .filter(|j| status.is_none_or(|status| j.status == status))
+            .collect();
+        jobs.sort_by_key(|j| Reverse(j.updated_at));
+        Ok(jobs.into_iter().take(limit).cloned().collect())
+    }
+
+    async fn retry_job(&self, id: Uuid) -> Result<bool> {
+        let mut tables = self.tables().await;
+        let job = tables
+            .jobs
+            .iter_mut()
+            .find(|j| j.id == id && matches!(j.status, JobStatus::Dead | JobStatus::Cancelled));
+        let Some(job) = job else {
+            return Ok(false);
+        };
+        let now = now();
+        job.status = JobStatus::Pending;
+        job.attempts = 0;
+        job.run_at = now;
+        job.updated_at = now;
+        Ok(true)
+    }
+
+    async fn cancel_job(&self, id: Uuid) -> Result<bool> {
+        let mut tables = self.tables().await;
+        let job = tables
+            .jobs
+            .iter_mut()
+            .find(|j| j.id == id && j.status == JobStatus::Pending);
+        let Some(job) = job else {
+            return Ok(false);
+        };
+        job.status = JobStatus::Cancelled;
+        job.updated_at = now();
+        Ok(true)
+    }
+
+    async fn upsert_schedule(&self, schedule: &JobSchedule) -> Result<()> {
+        let mut tables = self.tables().await;
+        let next_run_at = micros(schedule.next_run_at);
+        match tables
+            .job_schedules
+            .iter_mut()
+            .find(|s| s.name == schedule.name)
+        {
+            Some(existing) => {
+                existing.kind = schedule.kind.clone();
+                existing.payload = schedule.payload.clone();
+                if existing.cron != schedule.cron {
+                    existing.next_run_at = next_run_at;
+                }
+                existing.cron = schedule.cron.clone();
+            }
+            None => tables.job_schedules.push(JobSchedule {
+                next_run_at,
+                ..schedule.clone()
+            }),
+        }
+        Ok(())
+    }
+
+    async fn enqueue_due_schedules(&self, now: DateTime<Utc>) -> Result<usize> {
+        let mut tables = self.tables().await;
+        let mut jobs = Vec::new();
+        for schedule in tables
+            .job_schedules
+            .iter_mut()
+            .filter(|s| s.enabled && s.next_run_at <= now)
+        {
+            let next_run_at = CronSchedule::parse(&schedule.cron)
+                .ok()
+                .and_then(|cron| cron.next_after(now));
+            jobs.push(new_job(NewJob {
+                kind: schedule.kind.clone(),
+                payload: schedule.payload.clone(),
+                run_at: now,
+                max_attempts: DEFAULT_MAX_ATTEMPTS,
+            }));
+            // A cron that no longer parses disables its schedule instead of firing every poll.
+            schedule.enabled = next_run_at.is_some();
+            if let Some(next_run_at) = next_run_at {
+                schedule.next_run_at = micros(next_run_at);
+            }
+        }
+        let due = jobs.len();
+        tables.jobs.extend(jobs);
+        Ok(due)
+    }
+}
+
+#[async_trait]
+impl WebhookRepository for MemoryStore {
+    async fn create_webhook_endpoint(
+        &self,
+        endpoint: NewWebhookEndpoint,
+    ) -> Result<WebhookEndpoint> {
+        let endpoint = WebhookEndpoint {
+            id: Uuid::new_v4(),
+            owner_id: endpoint.owner_id,
+            url: endpoint.url,
+            description: endpoint.description,
+            events: endpoint.events,
+            secret: endpoint.secret,
+            enabled: true,
+            created_at: now(),
+        };
+        self.tables().await.webhook_endpoints.push(endpoint.clone());
+        Ok(endpoint)
+    }
+
+    async fn list_webhook_endpoints(&self, owner_id: Uuid) -> Result<Vec<WebhookEndpoint>> {
+        let tables = self.tables().await;
+        let mut endpoints: Vec<WebhookEndpoint> = tables
+            .webhook_endpoints
+            .iter()
+            .rev()
+            .filter(|e| e.owner_id == owner_id)
+            .cloned()
+            .collect();
+        endpoints.sort_by_key(|e| Reverse(e.created_at));
+        Ok(endpoints)
+    }
+
+    async fn find_webhook_endpoint(&self, id: Uuid) -> Result<Option<WebhookEndpoint>> {
+        let tables = self.tables().await;
+        Ok(tables
+            .webhook_endpoints
+            .iter()
+            .find(|e| e.id == id)
+            .cloned())
+    }
+
+    async fn delete_webhook_endpoint(&self, id: Uuid, owner_id: Uuid) -> Result<bool> {
+        let mut tables = self.tables().await;
+        let before = tables.webhook_endpoints.len();
+        tables
+            .webhook_endpoints
+            .retain(|e| !(e.id == id && e.owner_id == owner_id));
+        if tables.webhook_endpoints.len() == before {
+            return Ok(false);
+        }
+        tables.webhook_deliveries.retain(|d| d.endpoint_id != id);
+        Ok(true)
+    }
+
+    async fn find_webhook_delivery(&self, id: Uuid) -> Result<Option<WebhookDelivery>> {
+        let tables = self.tables().await;
+        Ok(tables
+            .webhook_deliveries
+            .iter()
+            .find(|d| d.id == id)
+            .cloned())
+    }
+
+    async fn list_webhook_deliveries(
+        &self,
+        endpoint_id: Uuid,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>> {
+        let limit = limit_rows(limit)?;
+        let tables = self.tables().await;
+        let mut deliveries: Vec<&WebhookDelivery> = tables
+            .webhook_deliveries
+            .iter()
+            .rev()
+            .filter(|d| d.endpoint_id == endpoint_id)
+            .collect();
+        deliveries.sort_by_key(|d| Reverse(d.created_at));
+        Ok(deliveries.into_iter().take(limit).cloned().collect())
+    }
+
+    async fn queue_webhook_deliveries(&self, event: &AuditEvent) -> Result<usize> {
+        let payload = webhooks::webhook_payload(event);
+        let mut tables = self.tables().await;
+        let endpoints: Vec<Uuid> = tables
+            .webhook_endpoints
+            .iter()
+            .filter(|e| e.enabled && e.events.contains(&event.event_type))
+            .map(|e| e.id)
+            .collect();
+        for endpoint_id in &endpoints {
+            let delivery_id = Uuid::new_v4();
+            tables.webhook_deliveries.push(WebhookDelivery {
+                id: delivery_id,
+                endpoint_id: *endpoint_id,
+                event_id: event.id,
+                event_type: event.event_type.clone(),
+                payload: payload.clone(),
+                status: DeliveryStatus::Pending,
+                attempts: 0,
+                response_status: None,
+                last_error: None,
+                created_at: now(),
+                delivered_at: None,
+            });
+            queue_webhook_delivery(&mut tables, delivery_id)?;
+        }
+        Ok(endpoints.len())
+    }
+
+    async fn record_delivery_attempt(&self, id: Uuid, attempt: &DeliveryAttempt) -> Result<()> {
+        let mut tables = self.tables().await;
+        if let Some(delivery) = tables.webhook_deliveries.iter_mut().find(|d| d.id == id) {
+            delivery.status = attempt.status;
+            delivery.attempts += 1;
+            delivery.response_status = attempt.response_status;
+            delivery.last_error = attempt.error.clone();
+            if attempt.status == DeliveryStatus::Succeeded {
+                delivery.delivered_at = Some(now());
+            }
+        }
+        Ok(())
+    }
+
+    async fn redeliver_webhook(&self, id: Uuid) -> Result<bool> {
+        let mut tables = self.tables().await;
+        let Some(delivery) = tables.webhook_deliveries.iter_mut().find(|d| d.id == id) else {
+            return Ok(false);
+        };
+        delivery.status = DeliveryStatus::Pending;
+        delivery.last_error = None;
+        queue_webhook_delivery(&mut tables, id)?;
+        Ok(true)
+    }
+}
+
+#[async_trait]
+impl FeatureFlagRepository for MemoryStore {
+    async fn list_flags(&self) -> Result<Vec<FeatureFlag>> {
+        Ok(self
+            .tables()
+            .await
+            .feature_flags
+            .values()
+            .cloned()
+            .collect())
+    }
+
+    async fn find_flag(&self, key: &str) -> Result<Option<FeatureFlag>> {
+        Ok(self.tables().await.feature_flags.get(key).cloned())
+    }
+
+    async fn upsert_flag(&self, flag: &FeatureFlag) -> Result<FeatureFlag> {
+        let stored = FeatureFlag {
+            updated_at: now(),
+            ..flag.clone()
+        };
+        self.tables()
+            .await
+            .feature_flags
+            .insert(flag.key.clone(), stored.clone());
+        self.flag_changed(&flag.key);
+        Ok(stored)
+    }
+
+    async fn delete_flag(&self, key: &str) -> Result<bool> {
+        let deleted = self.tables().await.feature_flags.remove(key).is_some();
+        if deleted {
+            self.flag_changed(key);
+        }
+        Ok(deleted)
+    }
+}
+
+#[async_trait]
+impl NotificationRepository for MemoryStore {
+    async fn create_notification(&self, notification: NewNotification) -> Result<Notification> {
+        let mut tables = self.tables().await;
+        if tables.notifications.iter().any(|n| n.id == notification.id) {
+            return Err(duplicate());
+        }
+        let notification = Notification {
+            id: notification.id,
+            user_id: notification.user_id,
+            category: notification.category,
+            title: notification.title,
+            body: notification.body,
+            link: notification.link,
+            read_at: None,
+            created_at: now(),
+        };
+        tables.notifications.push(notification.clone());
+        Ok(notification)
+    }
+
+    async fn list_notifications(
+        &self,
+        user_id: Uuid,
+        page: i64,
+        per_page: i64,
+    ) -> Result<(Vec<Notification>, i64)> {
+        let (offset, limit) = page_window(page, per_page)?;
+        let tables = self.tables().await;
+        let mut notifications: Vec<&Notification> = tables
+            .notifications
+            .iter()
+            .filter(|n| n.user_id == user_id)
+            .collect();
+        let total = notifications.len() as i64;
+        // Unread first, then newest first.
+        notifications.sort_by_key(|n| (n.read_at.is_some(), Reverse((n.created_at, n.id))));
+        let page = notifications
+            .into_iter()
+            .skip(offset)
+            .take(limit)
+            .cloned()
+            .collect();
+        Ok((page, total))
+    }
+
+    async fn count_unread_notifications(&self, user_id: Uuid) -> Result<i64> {
+        let tables = self.tables().await;
+        let unread = tables
+            .notifications
+            .iter()
+            .filter(|n| n.user_id == user_id && n.read_at.is_none())
+            .count();
+        Ok(unread as i64)
+    }
+
+    async fn mark_notification_read(&self, user_id: Uuid, id: Uuid) -> Result<bool> {
+        let mut tables = self.tables().await;
+        let notification = tables
+            .notifications
+            .iter_mut()
+            .find(|n| n.id == id && n.user_id == user_id);
+        let Some(notification) = notification else {
+            return Ok(false);
+        };
+        notification.read_at.get_or_insert_with(now);
+        Ok(true)
+    }
+
+    async fn mark_all_notifications_read(&self, user_id: Uuid) -> Result<u64> {
+        let now = now();
+        let mut tables = self.tables().await;
+        let mut marked = 0;
+        for notification in tables
+            .notifications
+            .iter_mut()
+            .filter(|n| n.user_id == user_id && n.read_at.is_none())
+        {
+            notification.read_at = Some(now);
+            marked += 1;
+        }
+        Ok(marked)
+    }
+
+    async fn notification_preferences(&self, user_id: Uuid) -> Result<Vec<NotificationPreference>> {
+        let tables = self.tables().await;
+        Ok(tables
+            .notification_preferences
+            .iter()
+            .filter(|(owner, _)| *owner == user_id)
+            .map(|(_, preference)| *preference)
+            .collect())
+    }
+
+    async fn set_notification_preference(
+        &self,
+        user_id: Uuid,
+        category: NotificationCategory,
+        channel: NotificationChannel,
+    ) -> Result<()> {
+        let mut tables = self.tables().await;
+        let preferences = &mut tables.notification_preferences;
+        match preferences
+            .iter_mut()
+            .find(|(owner, p)| *owner == user_id && p.category == category)
+        {
+            Some((_, preference)) => preference.channel = channel,
+            None => preferences.push((user_id, NotificationPreference { category, channel })),
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl FileRepository for MemoryStore {
+    async fn create_file(&self, file: NewFile) -> Result<StoredFile> {
+        let mut tables = self.tables().await;
+        if tables
+            .files
+            .iter()
+            .any(|f| f.id == file.id || f.storage_key == file.storage_key)
+        {
+            return Err(duplicate());
+        }
+        let file = StoredFile {
+            id: file.id,
+            owner_id: file.owner_id,
+            storage_key: file.storage_key,
+            content_type: file.content_type,
+            size_bytes: file.size_bytes,
+            filename: file.filename,
+            created_at: now(),
+        };
+        tables.files.push(file.clone());
+        Ok(file)
+    }
+
+    async fn find_file(&self, id: Uuid) -> Result<Option<StoredFile>> {
+        Ok(self
+            .tables()
+            .await
+            .files
+            .iter()
+            .find(|f| f.id == id)
+            .cloned())
+    }
+
+    async fn delete_file(&self, id: Uuid) -> Result<bool> {
+        let mut tables = self.tables().await;
+        let before = tables.files.len();
+        tables.files.retain(|f| f.id != id);
+        if tables.files.len() == before {
+            return Ok(false);
+        }
+        for record in tables
+            .users
+            .iter_mut()
+            .filter(|r| r.avatar_file_id == Some(id))
+        {
+            record.avatar_file_id = None;
+        }
+        Ok(true)
+    }
+
+    async fn set_avatar(&self, user_id: Uuid, file_id: Option<Uuid>) -> Result<Option<Uuid>> {
+        let mut tables = self.tables().await;
+        let record = tables
+            .users
+            .iter_mut()
+            .find(|r| r.user.id == user_id)
+            .ok_or(AppError::NotFound)?;
+        record.user.updated_at = now();
+        Ok(std::mem::replace(&mut record.avatar_file_id, file_id))
+    }
+
+    async fn find_avatar(&self, user_id: Uuid) -> Result<Option<StoredFile>> {
+        let tables = self.tables().await;
+        let avatar = tables
+            .users
+            .iter()
+            .find(|r| r.user.id == user_id)
+            .and_then(|r| r.avatar_file_id);
+        Ok(avatar.and_then(|id| tables.files.iter().find(|f| f.id == id).cloned()))
+    }
+}
+
+#[async_trait]
+impl OutboxRepository for MemoryStore {
+    async fn append_outbox(&self, envelope: &EventEnvelope) -> Result<()> {
+        let mut tables = self.tables().await;
+        if tables.outbox.iter().any(|r| r.envelope.id == envelope.id) {
+            return Err(duplicate());
+        }
+        tables.outbox.push(OutboxRecord {
+            envelope: EventEnvelope {
+                occurred_at: micros(envelope.occurred_at),
+                ..envelope.clone()
+            },
+            attempts: 0,
+            available_at: now(),
+            locked_until: None,
+            processed_at: None,
+            failed_at: None,
+            delivered_to: Vec::new(),
+        });
+        Ok(())
+    }
+
+    async fn claim_outbox(&self, limit: i64) -> Result<Vec<OutboxEntry>> {
+        let limit = limit_rows(limit)?;
+        let now = now();
+        let mut tables = self.tables().await;
+        let mut ready: Vec<&mut OutboxRecord> = tables
+            .outbox
+            .iter_mut()
+            .filter(|r| r.processed_at.is_none() && r.failed_at.is_none())
+            .filter(|r| r.available_at <= now && r.locked_until.is_none_or(|at| at < now))
+            .collect();
+        ready.sort_by_key(|r| r.envelope.occurred_at);
+        Ok(ready
+            .into_iter()
+            .take(limit)
+            .map(|record| {
+                record.attempts += 1;
+                record.locked_until = Some(now + Duration::seconds(OUTBOX_LEASE_SECONDS));
+                OutboxEntry {
+                    envelope: record.envelope.clone(),
+                    attempts: record.attempts,
+                    delivered_to: record.delivered_to.clone(),
+                }
+            })
+            .collect())
+    }
+
+    async fn mark_outbox_delivered(&self, id: Uuid, subscriber: &str) -> Result<()> {
+        let mut tables = self.tables().await;
+        if let Some(record) = tables.outbox.iter_mut().find(|r| r.envelope.id == id) {
+            if !record.delivered_to.iter().any(|s| s == subscriber) {
+                record.delivered_to.push(subscriber.to_string());
+            }
+        }
+        Ok(())
+    }
+
+    async fn complete_outbox(&self, id: Uuid) -> Result<()> {
+        let mut tables = self.tables().await;
+        if let Some(record) = tables.outbox.iter_mut().find(|r| r.envelope.id == id) {
+            record.processed_at = Some(now());
+            record.locked_until = None;
+        }
+        Ok(())
+    }
+
+    async fn retry_outbox(
+        &self,
+        id: Uuid,
+        _error: &str,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> Result<()> {
+        let mut tables = self.tables().await;
+        if let Some(record) = tables.outbox.iter_mut().find(|r| r.envelope.id == id) {
+            record.locked_until = None;
+            match retry_at {
+                Some(retry_at) => {
+                    record.available_at = micros(retry_at);
+                    record.failed_at = None;
+                }
+                None => record.failed_at = Some(now()),
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,155 @@
+//! Inspecting and reverting the schema migrations of the SQL backends.
+//!
+//! Every migration is reversible: `<version>_<name>.up.sql` applies it and `.down.sql`
+//! undoes it. The database records the checksum of each migration it applied, which must
+//! still match the file this build embeds.
+
+use shared::error::{AppError, Result};
+use sqlx::migrate::{Migrate, MigrateError, Migration, Migrator};
+use std::collections::HashMap;
+
+/// Where a migration stands in the database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MigrationState {
+    Applied,
+    Pending,
+    /// Applied, but its file has changed since.
+    ChecksumMismatch,
+    /// Started but did not finish; the database needs repair by hand.
+    Failed,
+    /// Recorded as applied but not part of this build, as after deploying an older build.
+    Unknown,
+}
+
+impl MigrationState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MigrationState::Applied => "applied",
+            MigrationState::Pending => "pending",
+            MigrationState::ChecksumMismatch => "checksum mismatch",
+            MigrationState::Failed => "failed",
+            MigrationState::Unknown => "unknown",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MigrationStatus {
+    pub version: i64,
+    /// Empty for an [`MigrationState::Unknown`] migration.
+    pub description: String,
+    pub state: MigrationState,
+}
+
+/// Every migration of `migrator` and every one the database recorded, by version.
+pub(crate) async fn status<C: Migrate>(
+    migrator: &Migrator,
+    conn: &mut C,
+) -> Result<Vec<MigrationStatus>> {
+    conn.ensure_migrations_table()
+        .await
+        .map_err(migrate_error)?;
+    let dirty = conn.dirty_version().await.map_err(migrate_error)?;
+    let mut applied: HashMap<i64, _> = conn
+        .list_applied_migrations()
+        .await
+        .map_err(migrate_error)?
+        .into_iter()
+        .map(|migration| (migration.version, migration.checksum))
+        .collect();
+
+    let mut statuses: Vec<_> = up_migrations(migrator)
+        .map(|migration| {
+            let state = match applied.remove(&migration.version) {
+                _ if dirty == Some(migration.version) => MigrationState::Failed,
+                None => MigrationState::Pending,
+                Some(checksum) if checksum == migration.checksum => MigrationState::Applied,
+                Some(_) => MigrationState::ChecksumMismatch,
+            };
+            MigrationStatus {
+                version: migration.version,
+                description: migration.description.to_string(),
+                state,
+            }
+        })
+        .collect();
+    statuses.extend(applied.into_keys().map(|version| MigrationStatus {
+        version,
+        description: String::new(),
+        state: if dirty == Some(version) {
+            MigrationState::Failed
+        } else {
+            MigrationState::Unknown
+        },
+    }));
+    statuses.sort_by_key(|status| status.version);
+    Ok(statuses)
+}
+
+/// Undoes the most recently applied migration, returning it, or `None` when none is applied.
+pub(crate) async fn revert_latest<C: Migrate>(
+    migrator: &Migrator,
+    conn: &mut C,
+) -> Result<Option<MigrationStatus>> {
+    let statuses = status(migrator, conn).await?;
+    if let Some(broken) = statuses.iter().find(|status| {
+        !matches!(
+            status.state,
+            MigrationState::Applied | MigrationState::Pending
+        )
+    }) {
+        return Err(AppError::internal(format!(
+            "cannot revert while migration {} is {}",
+            broken.version,
+            broken.state.as_str()
+        )));
+    }
+    let Some(latest) = statuses
+        .into_iter()
+        .rev()
+        .find(|status| status.state == MigrationState::Applied)
+    else {
+        return Ok(None);
+    };
+    let down = migrator
+        .iter()
+        .find(|m| m.version == latest.version && m.migration_type.is_down_migration())
+        .ok_or_else(|| {
+            AppError::internal(format!(
+                "migration {} has no down migration",
+                latest.version
+            ))
+        })?;
+
+    conn.lock().await.map_err(migrate_error)?;
+    let reverted = conn.revert(down).await.map_err(migrate_error);
+    conn.unlock().await.map_err(migrate_error)?;
+    reverted?;
+    Ok(Some(latest))
+}
+
+/// An error when any migration is not applied, naming them.
+pub(crate) fn ensure_applied(statuses: &[MigrationStatus]) -> Result<()> {
+    let outstanding: Vec<_> = statuses
+        .iter()
+        .filter(|status| status.state != MigrationState::Applied)
+        .map(|status| format!("{} ({})", status.version, status.state.as_str()))
+        .collect();
+    if outstanding.is_empty() {
+        return Ok(());
+    }
+    Err(AppError::config(format!(
+        "database schema is not up to date: {}; run `cli migrate run` or `cli migrate status`",
+        outstanding.join(", ")
+    )))
+}
+
+fn up_migrations(migrator: &Migrator) -> impl Iterator<Item = &Migration> {
+    migrator
+        .iter()
+        .filter(|migration| migration.migration_type.is_up_migration())
+}
+
+pub(crate) fn migrate_error(err: MigrateError) -> AppError {
+    AppError::internal(err.to_string())
+}
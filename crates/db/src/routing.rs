@@ -0,0 +1,29 @@
+//! Whether a read may go to the read replica.
+//!
+//! Reads are only routed to the replica inside [`read_your_writes`], which the server runs
+//! each request in. Everything else, such as jobs and the CLI, reads from the primary.
+
+use std::cell::Cell;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+tokio::task_local! {
+    static LAST_WRITE: Cell<Option<Instant>>;
+}
+
+/// Runs `request` with its reads allowed on the replica, except for a while after it
+/// writes, so that it sees its own writes despite replication lag.
+pub async fn read_your_writes<F: Future>(request: F) -> F::Output {
+    LAST_WRITE.scope(Cell::new(None), request).await
+}
+
+pub(crate) fn record_write() {
+    let _ = LAST_WRITE.try_with(|last| last.set(Some(Instant::now())));
+}
+
+/// Whether the current task runs in [`read_your_writes`] and has not written within `window`.
+pub(crate) fn replica_allowed(window: Duration) -> bool {
+    LAST_WRITE
+        .try_with(|last| last.get().is_none_or(|at| at.elapsed() >= window))
+        .unwrap_or(false)
+}
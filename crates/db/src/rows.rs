@@ -0,0 +1,283 @@
+//! Rows that decode the same way from every SQL backend.
+
+use chrono::{DateTime, NaiveDate, Utc};
+use domain::audit_chain::{AuditChainLink, ChainedAuditEvent};
+use domain::files::StoredFile;
+use domain::jobs::{Job, JobStatus};
+use domain::models::{PendingRegistration, RefreshToken, User};
+use domain::notifications::Notification;
+use domain::webhooks::{DeliveryStatus, WebhookDelivery};
+use shared::types::{AuditEvent, AuditSeverity, NotificationCategory};
+use uuid::Uuid;
+
+#[derive(sqlx::FromRow)]
+pub(crate) struct UserRow {
+    id: Uuid,
+    email: String,
+    password_hash: String,
+    role: UserRoleDb,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+impl From<UserRow> for User {
+    fn from(row: UserRow) -> Self {
+        Self {
+            id: row.id,
+            email: row.email,
+            password_hash: row.password_hash,
+            role: row.role.into(),
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        }
+    }
+}
+
+#[derive(sqlx::Type, Copy, Clone, Debug, PartialEq, Eq)]
+#[sqlx(type_name = "user_role", rename_all = "lowercase")]
+pub(crate) enum UserRoleDb {
+    User,
+    Admin,
+}
+
+impl From<UserRoleDb> for shared::types::UserRole {
+    fn from(value: UserRoleDb) -> Self {
+        match value {
+            UserRoleDb::User => shared::types::UserRole::User,
+            UserRoleDb::Admin => shared::types::UserRole::Admin,
+        }
+    }
+}
+
+impl From<shared::types::UserRole> for UserRoleDb {
+    fn from(value: shared::types::UserRole) -> Self {
+        match value {
+            shared::types::UserRole::User => UserRoleDb::User,
+            shared::types::UserRole::Admin => UserRoleDb::Admin,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub(crate) struct RefreshTokenRow {
+    id: Uuid,
+    user_id: Uuid,
+    session_id: Uuid,
+    token_hash: String,
+    expires_at: DateTime<Utc>,
+    created_at: DateTime<Utc>,
+}
+
+impl From<RefreshTokenRow> for RefreshToken {
+    fn from(row: RefreshTokenRow) -> Self {
+        Self {
+            id: row.id,
+            user_id: row.user_id,
+            session_id: row.session_id,
+            token_hash: row.token_hash,
+            expires_at: row.expires_at,
+            created_at: row.created_at,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub(crate) struct PendingRegistrationRow {
+    id: Uuid,
+    email: String,
+    password_hash: String,
+    role: UserRoleDb,
+    token_hash: String,
+    expires_at: DateTime<Utc>,
+    created_at: DateTime<Utc>,
+}
+
+impl From<PendingRegistrationRow> for PendingRegistration {
+    fn from(row: PendingRegistrationRow) -> Self {
+        Self {
+            id: row.id,
+            email: row.email,
+            password_hash: row.password_hash,
+            role: row.role.into(),
+            token_hash: row.token_hash,
+            expires_at: row.expires_at,
+            created_at: row.created_at,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub(crate) struct AuditEventRow {
+    id: Uuid,
+    actor_id: Option<Uuid>,
+    subject_id: Option<Uuid>,
+    event_type: String,
+    severity: String,
+    metadata: serde_json::Value,
+    ip: Option<String>,
+    user_agent: Option<String>,
+    request_id: Option<String>,
+    created_at: DateTime<Utc>,
+}
+
+impl From<AuditEventRow> for AuditEvent {
+    fn from(row: AuditEventRow) -> Self {
+        Self {
+            id: row.id,
+            actor_id: row.actor_id,
+            subject_id: row.subject_id,
+            event_type: row.event_type,
+            severity: AuditSeverity::parse(&row.severity).unwrap_or_default(),
+            metadata: row.metadata,
+            ip: row.ip,
+            user_agent: row.user_agent,
+            request_id: row.request_id,
+            created_at: row.created_at,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub(crate) struct ChainedAuditEventRow {
+    #[sqlx(flatten)]
+    event: AuditEventRow,
+    chain_day: NaiveDate,
+    seq: i64,
+    prev_hash: String,
+    hash: String,
+}
+
+impl From<ChainedAuditEventRow> for ChainedAuditEvent {
+    fn from(row: ChainedAuditEventRow) -> Self {
+        Self {
+            event: row.event.into(),
+            link: AuditChainLink {
+                day: row.chain_day,
+                seq: row.seq,
+                prev_hash: row.prev_hash,
+                hash: row.hash,
+            },
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub(crate) struct JobRow {
+    id: Uuid,
+    kind: String,
+    payload: serde_json::Value,
+    status: String,
+    attempts: i32,
+    max_attempts: i32,
+    run_at: DateTime<Utc>,
+    locked_by: Option<String>,
+    locked_at: Option<DateTime<Utc>>,
+    last_error: Option<String>,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+impl From<JobRow> for Job {
+    fn from(row: JobRow) -> Self {
+        Self {
+            id: row.id,
+            kind: row.kind,
+            payload: row.payload,
+            status: JobStatus::parse(&row.status).unwrap_or(JobStatus::Dead),
+            attempts: row.attempts,
+            max_attempts: row.max_attempts,
+            run_at: row.run_at,
+            locked_by: row.locked_by,
+            locked_at: row.locked_at,
+            last_error: row.last_error,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub(crate) struct WebhookDeliveryRow {
+    id: Uuid,
+    endpoint_id: Uuid,
+    event_id: Uuid,
+    event_type: String,
+    payload: serde_json::Value,
+    status: String,
+    attempts: i32,
+    response_status: Option<i32>,
+    last_error: Option<String>,
+    created_at: DateTime<Utc>,
+    delivered_at: Option<DateTime<Utc>>,
+}
+
+impl From<WebhookDeliveryRow> for WebhookDelivery {
+    fn from(row: WebhookDeliveryRow) -> Self {
+        Self {
+            id: row.id,
+            endpoint_id: row.endpoint_id,
+            event_id: row.event_id,
+            event_type: row.event_type,
+            payload: row.payload,
+            status: DeliveryStatus::parse(&row.status).unwrap_or(DeliveryStatus::Failed),
+            attempts: row.attempts,
+            response_status: row.response_status,
+            last_error: row.last_error,
+            created_at: row.created_at,
+            delivered_at: row.delivered_at,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub(crate) struct NotificationRow {
+    id: Uuid,
+    user_id: Uuid,
+    category: String,
+    title: String,
+    body: String,
+    link: Option<String>,
+    read_at: Option<DateTime<Utc>>,
+    created_at: DateTime<Utc>,
+}
+
+impl From<NotificationRow> for Notification {
+    fn from(row: NotificationRow) -> Self {
+        Self {
+            id: row.id,
+            user_id: row.user_id,
+            category: NotificationCategory::parse(&row.category)
+                .unwrap_or(NotificationCategory::General),
+            title: row.title,
+            body: row.body,
+            link: row.link,
+            read_at: row.read_at,
+            created_at: row.created_at,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub(crate) struct FileRow {
+    id: Uuid,
+    owner_id: Uuid,
+    storage_key: String,
+    content_type: String,
+    size_bytes: i64,
+    filename: Option<String>,
+    created_at: DateTime<Utc>,
+}
+
+impl From<FileRow> for StoredFile {
+    fn from(row: FileRow) -> Self {
+        Self {
+            id: row.id,
+            owner_id: row.owner_id,
+            storage_key: row.storage_key,
+            content_type: row.content_type,
+            size_bytes: row.size_bytes,
+            filename: row.filename,
+            created_at: row.created_at,
+        }
+    }
+}
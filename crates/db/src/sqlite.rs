@@ -0,0 +1,1617 @@
+use crate::conn::{self, Conn, SharedTransaction};
+use crate::migrations::{self, MigrationStatus};
+use crate::rows::{
+    AuditEventRow, ChainedAuditEventRow, FileRow, JobRow, NotificationRow, PendingRegistrationRow,
+    RefreshTokenRow, UserRoleDb, UserRow, WebhookDeliveryRow,
+};
+use crate::{contains_pattern, map_sqlx_error, page_offset, telemetry, OUTBOX_LEASE_SECONDS};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use domain::audit_chain::{self, AuditChainLink, AuditCheckpoint, ChainedAuditEvent};
+use domain::events::{EventEnvelope, OutboxEntry};
+use domain::files::{NewFile, StoredFile};
+use domain::flags::FeatureFlag;
+use domain::jobs::{CronSchedule, Job, JobSchedule, JobStatus, NewJob};
+use domain::models::{
+    AuditQuery, NewUser, PendingRegistration, RefreshToken, User, UserCursor, UserFilter, UserQuery,
+};
+use domain::notifications::{NewNotification, Notification, NotificationPreference};
+use domain::ports::{
+    AuditChainRepository, AuditLogRepository, FeatureFlagRepository, FileRepository, JobRepository,
+    NotificationRepository, OutboxRepository, PendingRegistrationRepository,
+    RefreshTokenRepository, UserRepository, WebhookRepository,
+};
+use domain::webhooks::{
+    self, DeliverWebhook, DeliveryAttempt, NewWebhookEndpoint, WebhookDelivery, WebhookEndpoint,
+};
+use shared::config::DatabaseConfig;
+use shared::error::{AppError, Result};
+use shared::types::{AuditEvent, NotificationCategory, NotificationChannel, UserRole};
+use sqlx::migrate::Migrator;
+use sqlx::query::QueryAs;
+use sqlx::sqlite::{SqliteArguments, SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions};
+use sqlx::types::Json;
+use sqlx::{Connection, Sqlite, SqliteConnection, SqlitePool};
+use std::str::FromStr;
+use std::sync::Mutex;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+const FLAG_CHANGES_CAPACITY: usize = 64;
+
+static MIGRATOR: Migrator = sqlx::migrate!("../../migrations/sqlite");
+
+/// The SQLite backend of [`crate::Database`], for single-file deployments. UUIDs are stored
+/// as BLOBs, timestamps as RFC 3339 text and arrays as JSON; see `migrations/sqlite`.
+///
+/// Writes with `RETURNING` are read with `fetch_all`. Stopping at the first row leaves the
+/// statement open, and its write uncommitted, until the connection next gets to reset it.
+pub(crate) struct SqliteStore {
+    /// Reads, from as many connections as the config allows.
+    pool: SqlitePool,
+    /// Every write, over a single connection. SQLite allows one writer at a time, so writes
+    /// queue here instead of failing with `SQLITE_BUSY`. It also keeps a statement that failed
+    /// from being retried by the driver after another connection has changed the data.
+    writer: SqlitePool,
+    /// SQLite has no `LISTEN`, so only flag changes made by this process are announced.
+    flag_changes: broadcast::Sender<String>,
+    /// Flag changes made in this transaction. They are announced once it commits, as a
+    /// listener reloading the flag before then would read the old row.
+    unannounced_flag_changes: Mutex<Vec<String>>,
+    /// Set on a store from `begin`, which runs every call, reads included, in this
+    /// transaction on the writer connection.
+    tx: Option<SharedTransaction<Sqlite>>,
+}
+
+impl SqliteStore {
+    pub(crate) async fn connect(config: &DatabaseConfig) -> Result<Self> {
+        let options = SqliteConnectOptions::from_str(&config.url)
+            .map_err(|e| AppError::config(format!("invalid sqlite url: {e}")))?
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal)
+            .foreign_keys(true);
+        let options = telemetry::slow_statements(options, config);
+        let pool = SqlitePoolOptions::new()
+            .max_connections(config.max_connections)
+            .min_connections(config.min_connections)
+            .connect_with(options.clone())
+            .await
+            .map_err(map_sqlx_error)?;
+        let writer = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(options)
+            .await
+            .map_err(map_sqlx_error)?;
+
+        Ok(Self {
+            pool,
+            writer,
+            flag_changes: broadcast::channel(FLAG_CHANGES_CAPACITY).0,
+            unannounced_flag_changes: Mutex::default(),
+            tx: None,
+        })
+    }
+
+    pub(crate) async fn migrate(&self) -> Result<()> {
+        MIGRATOR
+            .run(&self.writer)
+            .await
+            .map_err(migrations::migrate_error)
+    }
+
+    pub(crate) async fn migration_status(&self) -> Result<Vec<MigrationStatus>> {
+        let mut conn = self.writer.acquire().await.map_err(map_sqlx_error)?;
+        migrations::status(&MIGRATOR, &mut *conn).await
+    }
+
+    pub(crate) async fn revert_migration(&self) -> Result<Option<MigrationStatus>> {
+        let mut conn = self.writer.acquire().await.map_err(map_sqlx_error)?;
+        migrations::revert_latest(&MIGRATOR, &mut *conn).await
+    }
+
+    pub(crate) fn subscribe_flag_changes(&self) -> broadcast::Receiver<String> {
+        self.flag_changes.subscribe()
+    }
+
+    pub(crate) async fn begin(&self) -> Result<Self> {
+        Ok(Self {
+            pool: self.pool.clone(),
+            writer: self.writer.clone(),
+            flag_changes: self.flag_changes.clone(),
+            unannounced_flag_changes: Mutex::default(),
+            tx: Some(conn::begin(&self.writer).await?),
+        })
+    }
+
+    pub(crate) async fn commit(&self) -> Result<()> {
+        conn::commit(self.tx.as_ref()).await?;
+        let mut unannounced = self
+            .unannounced_flag_changes
+            .lock()
+            .expect("flag changes poisoned");
+        for key in unannounced.drain(..) {
+            let _ = self.flag_changes.send(key);
+        }
+        Ok(())
+    }
+
+    async fn read(&self) -> Result<Conn<'_, Sqlite>> {
+        conn::acquire(&self.pool, "reader", self.tx.as_ref()).await
+    }
+
+    async fn write(&self) -> Result<Conn<'_, Sqlite>> {
+        conn::acquire(&self.writer, "writer", self.tx.as_ref()).await
+    }
+
+    pub(crate) async fn ping(&self) -> Result<()> {
+        sqlx::query("SELECT 1")
+            .execute(&self.pool)
+            .await
+            .map_err(map_sqlx_error)?;
+        Ok(())
+    }
+
+    pub(crate) fn record_pool_metrics(&self) {
+        telemetry::record_pool(&self.pool, "reader");
+        telemetry::record_pool(&self.writer, "writer");
+    }
+
+    fn flag_changed(&self, key: &str) {
+        if self.tx.is_some() {
+            self.unannounced_flag_changes
+                .lock()
+                .expect("flag changes poisoned")
+                .push(key.to_string());
+            return;
+        }
+        // Nobody listening is fine; the cache loads from the store when it starts.
+        let _ = self.flag_changes.send(key.to_string());
+    }
+}
+
+/// Timestamps are stored with the microsecond precision Postgres keeps.
+fn now() -> DateTime<Utc> {
+    audit_chain::truncate_to_micros(Utc::now())
+}
+
+fn micros(ts: DateTime<Utc>) -> DateTime<Utc> {
+    audit_chain::truncate_to_micros(ts)
+}
+
+/// The conditions of a [`UserFilter`], bound by `bind_user_filter` to `$1`–`$4`. `LIKE`
+/// ignores ASCII case.
+const USER_FILTER: &str = r#"($1 IS NULL OR email LIKE $1 ESCAPE '\')
+              AND ($2 IS NULL OR role = $2)
+              AND ($3 IS NULL OR created_at >= $3)
+              AND ($4 IS NULL OR created_at < $4)"#;
+
+fn bind_user_filter<'q, O>(
+    query: QueryAs<'q, Sqlite, O, SqliteArguments<'q>>,
+    filter: &UserFilter,
+) -> QueryAs<'q, Sqlite, O, SqliteArguments<'q>> {
+    query
+        .bind(filter.email.as_deref().map(contains_pattern))
+        .bind(filter.role.map(UserRoleDb::from))
+        .bind(filter.created_from)
+        .bind(filter.created_to)
+}
+
+#[async_trait]
+impl UserRepository for SqliteStore {
+    async fn create_user(&self, new_user: NewUser) -> Result<User> {
+        let now = now();
+        let row = sqlx::query_as::<_, UserRow>(
+            r#"
+            INSERT INTO users (id, email, password_hash, role, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $5)
+            RETURNING id, email, password_hash, role, created_at, updated_at
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(new_user.email)
+        .bind(new_user.password_hash)
+        .bind(UserRoleDb::from(new_user.role))
+        .bind(now)
+        .fetch_all(&mut *self.write().await?)
+        .await
+        .map_err(map_sqlx_error)?
+        .pop()
+        .ok_or(AppError::NotFound)?;
+
+        Ok(row.into())
+    }
+
+    async fn find_by_email(&self, email: &str) -> Result<Option<User>> {
+        let row = sqlx::query_as::<_, UserRow>(
+            r#"
+            SELECT id, email, password_hash, role, created_at, updated_at
+            FROM users WHERE email = $1
+            "#,
+        )
+        .bind(email)
+        .fetch_optional(&mut *self.read().await?)
+        .await
+        .map_err(map_sqlx_error)?;
+
+        Ok(row.map(Into::into))
+    }
+
+    async fn find_by_id(&self, id: Uuid) -> Result<Option<User>> {
+        let row = sqlx::query_as::<_, UserRow>(
+            r#"
+            SELECT id, email, password_hash, role, created_at, updated_at
+            FROM users WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&mut *self.read().await?)
+        .await
+        .map_err(map_sqlx_error)?;
+
+        Ok(row.map(Into::into))
+    }
+
+    async fn list_users(&self, query: &UserQuery) -> Result<Vec<User>> {
+        let column = query.sort.as_str();
+        let (direction, comparison) = if query.descending {
+            ("DESC", "<")
+        } else {
+            ("ASC", ">")
+        };
+        let after = match &query.after {
+            Some(_) => format!("AND ({column}, id) {comparison} ($6, $7)"),
+            None => String::new(),
+        };
+        let sql = format!(
+            r#"
+            SELECT id, email, password_hash, role, created_at, updated_at
+            FROM users
+            WHERE {USER_FILTER} {after}
+            ORDER BY {column} {direction}, id {direction}
+            LIMIT $5
+            "#
+        );
+        let mut rows =
+            bind_user_filter(sqlx::query_as::<_, UserRow>(&sql), &query.filter).bind(query.limit);
+        if let Some(after) = &query.after {
+            rows = match after {
+                UserCursor::CreatedAt(created_at, _) => rows.bind(*created_at),
+                UserCursor::Email(email, _) => rows.bind(email.as_str()),
+            }
+            .bind(after.id());
+        }
+        let rows = rows
+            .fetch_all(&mut *self.read().await?)
+            .await
+            .map_err(map_sqlx_error)?;
+
+        Ok(rows.into_iter().map(Into::into).collect())
+    }
+
+    async fn count_users(&self, filter: &UserFilter) -> Result<i64> {
+        let sql = format!("SELECT COUNT(*) FROM users WHERE {USER_FILTER}");
+        let (count,): (i64,) = bind_user_filter(sqlx::query_as(&sql), filter)
+            .fetch_one(&mut *self.read().await?)
+            .await
+            .map_err(map_sqlx_error)?;
+        Ok(count)
+    }
+
+    async fn update_role(&self, id: Uuid, role: UserRole) -> Result<Option<User>> {
+        let row = sqlx::query_as::<_, UserRow>(
+            r#"
+            UPDATE users SET role = $2, updated_at = $3
+            WHERE id = $1
+            RETURNING id, email, password_hash, role, created_at, updated_at
+            "#,
+        )
+        .bind(id)
+        .bind(UserRoleDb::from(role))
+        .bind(now())
+        .fetch_all(&mut *self.write().await?)
+        .await
+        .map_err(map_sqlx_error)?
+        .pop();
+
+        Ok(row.map(Into::into))
+    }
+
+    async fn update_password_hash(&self, id: Uuid, password_hash: &str) -> Result<()> {
+        sqlx::query("UPDATE users SET password_hash = $2, updated_at = $3 WHERE id = $1")
+            .bind(id)
+            .bind(password_hash)
+            .bind(now())
+            .execute(&mut *self.write().await?)
+            .await
+            .map_err(map_sqlx_error)?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RefreshTokenRepository for SqliteStore {
+    async fn store_refresh_token(&self, token: &RefreshToken) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO refresh_tokens (id, user_id, session_id, token_hash, expires_at, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (token_hash) DO UPDATE SET expires_at = excluded.expires_at
+            "#,
+        )
+        .bind(token.id)
+        .bind(token.user_id)
+        .bind(token.session_id)
+        .bind(&token.token_hash)
+        .bind(micros(token.expires_at))
+        .bind(micros(token.created_at))
+        .execute(&mut *self.write().await?)
+        .await
+        .map_err(map_sqlx_error)?;
+        Ok(())
+    }
+
+    async fn find_refresh_token(&self, token_hash: &str) -> Result<Option<RefreshToken>> {
+        let row = sqlx::query_as::<_, RefreshTokenRow>(
+            r#"
+            SELECT id, user_id, session_id, token_hash, expires_at, created_at
+            FROM refresh_tokens
+            WHERE token_hash = $1
+            "#,
+        )
+        .bind(token_hash)
+        .fetch_optional(&mut *self.read().await?)
+        .await
+        .map_err(map_sqlx_error)?;
+
+        Ok(row.map(Into::into))
+    }
+
+    async fn delete_refresh_token(&self, id: Uuid) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM refresh_tokens WHERE id = $1")
+            .bind(id)
+            .execute(&mut *self.write().await?)
+            .await
+            .map_err(map_sqlx_error)?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_tokens_for_user(&self, user_id: Uuid) -> Result<()> {
+        sqlx::query("DELETE FROM refresh_tokens WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&mut *self.write().await?)
+            .await
+            .map_err(map_sqlx_error)?;
+        Ok(())
+    }
+
+    async fn delete_expired_tokens(&self, now: DateTime<Utc>) -> Result<u64> {
+        let result = sqlx::query("DELETE FROM refresh_tokens WHERE expires_at < $1")
+            .bind(now)
+            .execute(&mut *self.write().await?)
+            .await
+            .map_err(map_sqlx_error)?;
+        Ok(result.rows_affected())
+    }
+}
+
+#[async_trait]
+impl AuditLogRepository for SqliteStore {
+    async fn log_event(&self, mut event: AuditEvent) -> Result<()> {
+        event.created_at = audit_chain::truncate_to_micros(event.created_at);
+        let day = audit_chain::chain_day(event.created_at);
+
+        // The single writer connection keeps the sequence gap-free.
+        let mut conn = self.write().await?;
+        let mut tx = conn.begin().await.map_err(map_sqlx_error)?;
+        let head: Option<(i64, String)> = sqlx::query_as(
+            "SELECT seq, hash FROM audit_log WHERE chain_day = $1 ORDER BY seq DESC LIMIT 1",
+        )
+        .bind(day)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(map_sqlx_error)?;
+        let (seq, prev_hash) = match head {
+            Some((seq, hash)) => (seq + 1, hash),
+            None => (1, audit_chain::genesis_hash(day)),
+        };
+        let hash = audit_chain::event_hash(&prev_hash, seq, &event);
+
+        sqlx::query(
+            r#"
+            INSERT INTO audit_log
+                (id, actor_id, subject_id, event_type, severity, metadata, ip, user_agent,
+                 request_id, created_at, chain_day, seq, prev_hash, hash)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
+            "#,
+        )
+        .bind(event.id)
+        .bind(event.actor_id)
+        .bind(event.subject_id)
+        .bind(event.event_type)
+        .bind(event.severity.as_str())
+        .bind(event.metadata)
+        .bind(event.ip)
+        .bind(event.user_agent)
+        .bind(event.request_id)
+        .bind(event.created_at)
+        .bind(day)
+        .bind(seq)
+        .bind(prev_hash)
+        .bind(hash)
+        .execute(&mut *tx)
+        .await
+        .map_err(map_sqlx_error)?;
+
+        tx.commit().await.map_err(map_sqlx_error)?;
+        Ok(())
+    }
+
+    async fn list_events(&self, query: &AuditQuery) -> Result<Vec<AuditEvent>> {
+        let rows = sqlx::query_as::<_, AuditEventRow>(
+            r#"
+            SELECT id, actor_id, subject_id, event_type, severity, metadata, ip, user_agent,
+                   request_id, created_at
+            FROM audit_log
+            WHERE ($1 IS NULL OR actor_id = $1 OR subject_id = $1)
+              AND ($2 IS NULL OR event_type = $2)
+              AND ($3 IS NULL OR created_at >= $3)
+              AND ($4 IS NULL OR created_at < $4)
+              AND ($5 IS NULL OR (created_at, id) < ($5, $6))
+            ORDER BY created_at DESC, id DESC
+            LIMIT $7
+            "#,
+        )
+        .bind(query.user_id)
+        .bind(query.event_type.map(|t| t.as_str()))
+        .bind(query.from)
+        .bind(query.to)
+        .bind(query.after.map(|c| c.created_at))
+        .bind(query.after.map(|c| c.id))
+        .bind(query.limit)
+        .fetch_all(&mut *self.read().await?)
+        .await
+        .map_err(map_sqlx_error)?;
+
+        Ok(rows.into_iter().map(Into::into).collect())
+    }
+}
+
+#[async_trait]
+impl AuditChainRepository for SqliteStore {
+    async fn chain_days(&self) -> Result<Vec<NaiveDate>> {
+        let rows: Vec<(NaiveDate,)> = sqlx::query_as(
+            r#"
+            SELECT DISTINCT chain_day FROM audit_log WHERE chain_day IS NOT NULL
+            UNION
+            SELECT DISTINCT chain_day FROM audit_checkpoints
+            ORDER BY chain_day
+            "#,
+        )
+        .fetch_all(&mut *self.read().await?)
+        .await
+        .map_err(map_sqlx_error)?;
+
+        Ok(rows.into_iter().map(|(day,)| day).collect())
+    }
+
+    async fn chained_events(&self, day: NaiveDate) -> Result<Vec<ChainedAuditEvent>> {
+        let rows = sqlx::query_as::<_, ChainedAuditEventRow>(
+            r#"
+            SELECT id, actor_id, subject_id, event_type, severity, metadata, ip, user_agent,
+                   request_id, created_at, chain_day, seq, prev_hash, hash
+            FROM audit_log
+            WHERE chain_day = $1
+            ORDER BY seq
+            "#,
+        )
+        .bind(day)
+        .fetch_all(&mut *self.read().await?)
+        .await
+        .map_err(map_sqlx_error)?;
+
+        Ok(rows.into_iter().map(Into::into).collect())
+    }
+
+    async fn count_unchained_events(&self) -> Result<i64> {
+        let count: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM audit_log WHERE hash IS NULL")
+            .fetch_one(&mut *self.read().await?)
+            .await
+            .map_err(map_sqlx_error)?;
+        Ok(count.0)
+    }
+
+    async fn unanchored_heads(&self) -> Result<Vec<AuditChainLink>> {
+        let rows: Vec<(NaiveDate, i64, String, String)> = sqlx::query_as(
+            r#"
+            SELECT head.chain_day, head.seq, head.prev_hash, head.hash
+            FROM audit_log head
+            WHERE head.chain_day IS NOT NULL
+              AND head.seq = (
+                  SELECT MAX(seq) FROM audit_log l WHERE l.chain_day = head.chain_day
+              )
+              AND NOT EXISTS (
+                  SELECT 1 FROM audit_checkpoints c
+                  WHERE c.chain_day = head.chain_day AND c.seq = head.seq
+              )
+            ORDER BY head.chain_day
+            "#,
+        )
+        .fetch_all(&mut *self.read().await?)
+        .await
+        .map_err(map_sqlx_error)?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(day, seq, prev_hash, hash)| AuditChainLink {
+                day,
+                seq,
+                prev_hash,
+                hash,
+            })
+            .collect())
+    }
+
+    async fn store_checkpoint(&self, checkpoint: &AuditCheckpoint) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO audit_checkpoints (id, chain_day, seq, hash, signature, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (chain_day, seq) DO NOTHING
+            "#,
+        )
+        .bind(checkpoint.id)
+        .bind(checkpoint.day)
+        .bind(checkpoint.seq)
+        .bind(&checkpoint.hash)
+        .bind(&checkpoint.signature)
+        .bind(micros(checkpoint.created_at))
+        .execute(&mut *self.write().await?)
+        .await
+        .map_err(map_sqlx_error)?;
+        Ok(())
+    }
+
+    async fn checkpoints(&self, day: NaiveDate) -> Result<Vec<AuditCheckpoint>> {
+        let rows: Vec<(Uuid, NaiveDate, i64, String, String, DateTime<Utc>)> = sqlx::query_as(
+            r#"
+            SELECT id, chain_day, seq, hash, signature, created_at
+            FROM audit_checkpoints
+            WHERE chain_day = $1
+            ORDER BY seq
+            "#,
+        )
+        .bind(day)
+        .fetch_all(&mut *self.read().await?)
+        .await
+        .map_err(map_sqlx_error)?;
+
+        Ok(rows
+            .into_iter()
+            .map(
+                |(id, day, seq, hash, signature, created_at)| AuditCheckpoint {
+                    id,
+                    day,
+                    seq,
+                    hash,
+                    signature,
+                    created_at,
+                },
+            )
+            .collect())
+    }
+
+    async fn purge_days_before(&self, day: NaiveDate) -> Result<u64> {
+        let cutoff = day.and_time(chrono::NaiveTime::MIN).and_utc();
+        let mut conn = self.write().await?;
+        let mut tx = conn.begin().await.map_err(map_sqlx_error)?;
+        let deleted = sqlx::query(
+            r#"
+            DELETE FROM audit_log
+            WHERE chain_day < $1 OR (chain_day IS NULL AND created_at < $2)
+            "#,
+        )
+        .bind(day)
+        .bind(cutoff)
+        .execute(&mut *tx)
+        .await
+        .map_err(map_sqlx_error)?;
+        sqlx::query("DELETE FROM audit_checkpoints WHERE chain_day < $1")
+            .bind(day)
+            .execute(&mut *tx)
+            .await
+            .map_err(map_sqlx_error)?;
+        tx.commit().await.map_err(map_sqlx_error)?;
+
+        Ok(deleted.rows_affected())
+    }
+}
+
+#[async_trait]
+impl PendingRegistrationRepository for SqliteStore {
+    async fn store_pending_registration(&self, pending: &PendingRegistration) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO pending_registrations
+                (id, email, password_hash, role, token_hash, expires_at, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+        )
+        .bind(pending.id)
+        .bind(&pending.email)
+        .bind(&pending.password_hash)
+        .bind(UserRoleDb::from(pending.role))
+        .bind(&pending.token_hash)
+        .bind(micros(pending.expires_at))
+        .bind(micros(pending.created_at))
+        .execute(&mut *self.write().await?)
+        .await
+        .map_err(map_sqlx_error)?;
+        Ok(())
+    }
+
+    async fn take_pending_registration(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<PendingRegistration>> {
+        let row = sqlx::query_as::<_, PendingRegistrationRow>(
+            r#"
+            DELETE FROM pending_registrations
+            WHERE token_hash = $1
+            RETURNING id, email, password_hash, role, token_hash, expires_at, created_at
+            "#,
+        )
+        .bind(token_hash)
+        .fetch_all(&mut *self.write().await?)
+        .await
+        .map_err(map_sqlx_error)?
+        .pop();
+
+        Ok(row.map(Into::into))
+    }
+
+    async fn delete_expired_pending_registrations(&self, now: DateTime<Utc>) -> Result<u64> {
+        let result = sqlx::query("DELETE FROM pending_registrations WHERE expires_at < $1")
+            .bind(now)
+            .execute(&mut *self.write().await?)
+            .await
+            .map_err(map_sqlx_error)?;
+        Ok(result.rows_affected())
+    }
+}
+
+const JOB_COLUMNS: &str = "id, kind, payload, status, attempts, max_attempts, run_at, \
+     locked_by, locked_at, last_error, created_at, updated_at";
+
+#[async_trait]
+impl JobRepository for SqliteStore {
+    async fn enqueue_job(&self, job: NewJob) -> Result<Job> {
+        let row = sqlx::query_as::<_, JobRow>(&format!(
+            r#"
+            INSERT INTO jobs (id, kind, payload, run_at, max_attempts, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $6)
+            RETURNING {JOB_COLUMNS}
+            "#
+        ))
+        .bind(Uuid::new_v4())
+        .bind(job.kind)
+        .bind(job.payload)
+        .bind(micros(job.run_at))
+        .bind(job.max_attempts)
+        .bind(now())
+        .fetch_all(&mut *self.write().await?)
+        .await
+        .map_err(map_sqlx_error)?
+        .pop()
+        .ok_or(AppError::NotFound)?;
+
+        Ok(row.into())
+    }
+
+    async fn claim_jobs(&self, worker: &str, kinds: &[String], limit: i64) -> Result<Vec<Job>> {
+        let rows = sqlx::query_as::<_, JobRow>(&format!(
+            r#"
+            UPDATE jobs
+            SET status = 'running', attempts = attempts + 1, locked_by = $1,
+                locked_at = $4, updated_at = $4
+            WHERE id IN (
+                SELECT id FROM jobs
+                WHERE status = 'pending' AND run_at <= $4
+                  AND kind IN (SELECT value FROM json_each($2))
+                ORDER BY run_at
+                LIMIT $3
+            )
+            RETURNING {JOB_COLUMNS}
+            "#
+        ))
+        .bind(worker)
+        .bind(Json(kinds))
+        .bind(limit)
+        .bind(now())
+        .fetch_all(&mut *self.write().await?)
+        .await
+        .map_err(map_sqlx_error)?;
+
+        Ok(rows.into_iter().map(Into::into).collect())
+    }
+
+    async fn complete_job(&self, id: Uuid) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE jobs
+            SET status = 'succeeded', locked_by = NULL, locked_at = NULL, updated_at = $2
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(now())
+        .execute(&mut *self.write().await?)
+        .await
+        .map_err(map_sqlx_error)?;
+        Ok(())
+    }
+
+    async fn fail_job(&self, id: Uuid, error: &str, retry_at: Option<DateTime<Utc>>) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE jobs
+            SET status = CASE WHEN $3 IS NULL THEN 'dead' ELSE 'pending' END,
+                run_at = COALESCE($3, run_at),
+                last_error = $2, locked_by = NULL, locked_at = NULL, updated_at = $4
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(error)
+        .bind(retry_at.map(micros))
+        .bind(now())
+        .execute(&mut *self.write().await?)
+        .await
+        .map_err(map_sqlx_error)?;
+        Ok(())
+    }
+
+    async fn release_stale_jobs(&self, locked_before: DateTime<Utc>) -> Result<u64> {
+        let result = sqlx::query(
+            r#"
+            UPDATE jobs
+            SET status = 'pending', locked_by = NULL, locked_at = NULL, updated_at = $2,
+                last_error = 'lease expired before the job finished'
+            WHERE status = 'running' AND locked_at < $1
+            "#,
+        )
+        .bind(locked_before)
+        .bind(now())
+        .execute(&mut *self.write().await?)
+        .await
+        .map_err(map_sqlx_error)?;
+        Ok(result.rows_affected())
+    }
+
+    async fn list_jobs(&self, status: Option<JobStatus>, limit: i64) -> Result<Vec<Job>> {
+        let rows = sqlx::query_as::<_, JobRow>(&format!(
+            r#"
+            SELECT {JOB_COLUMNS}
+            FROM jobs
+            WHERE ($1 IS NULL OR status = $1)
+            ORDER BY updated_at DESC
+            LIMIT $2
+            "#
+        ))
+        .bind(status.map(|s| s.as_str()))
+        .bind(limit)
+        .fetch_all(&mut *self.read().await?)
+        .await
+        .map_err(map_sqlx_error)?;
+
+        Ok(rows.into_iter().map(Into::into).collect())
+    }
+
+    async fn retry_job(&self, id: Uuid) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE jobs
+            SET status = 'pending', attempts = 0, run_at = $2, updated_at = $2
+            WHERE id = $1 AND status IN ('dead', 'cancelled')
+            "#,
+        )
+        .bind(id)
+        .bind(now())
+        .execute(&mut *self.write().await?)
+        .await
+        .map_err(map_sqlx_error)?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn cancel_job(&self, id: Uuid) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE jobs
+            SET status = 'cancelled', updated_at = $2
+            WHERE id = $1 AND status = 'pending'
+            "#,
+        )
+        .bind(id)
+        .bind(now())
+        .execute(&mut *self.write().await?)
+        .await
+        .map_err(map_sqlx_error)?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn upsert_schedule(&self, schedule: &JobSchedule) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO job_schedules (name, kind, payload, cron, next_run_at, enabled)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (name) DO UPDATE
+            SET kind = excluded.kind,
+                payload = excluded.payload,
+                next_run_at = CASE
+                    WHEN job_schedules.cron = excluded.cron THEN job_schedules.next_run_at
+                    ELSE excluded.next_run_at
+                END,
+                cron = excluded.cron
+            "#,
+        )
+        .bind(&schedule.name)
+        .bind(&schedule.kind)
+        .bind(&schedule.payload)
+        .bind(&schedule.cron)
+        .bind(micros(schedule.next_run_at))
+        .bind(schedule.enabled)
+        .execute(&mut *self.write().await?)
+        .await
+        .map_err(map_sqlx_error)?;
+        Ok(())
+    }
+
+    async fn enqueue_due_schedules(&self, now: DateTime<Utc>) -> Result<usize> {
+        let now = micros(now);
+        let mut conn = self.write().await?;
+        let mut tx = conn.begin().await.map_err(map_sqlx_error)?;
+        let due: Vec<(String, String, serde_json::Value, String)> = sqlx::query_as(
+            r#"
+            SELECT name, kind, payload, cron
+            FROM job_schedules
+            WHERE enabled AND next_run_at <= $1
+            "#,
+        )
+        .bind(now)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(map_sqlx_error)?;
+
+        for (name, kind, payload, cron) in &due {
+            let next_run_at = CronSchedule::parse(cron)
+                .ok()
+                .and_then(|schedule| schedule.next_after(now));
+            sqlx::query(
+                r#"
+                INSERT INTO jobs (id, kind, payload, run_at, created_at, updated_at)
+                VALUES ($1, $2, $3, $4, $5, $5)
+                "#,
+            )
+            .bind(Uuid::new_v4())
+            .bind(kind)
+            .bind(payload)
+            .bind(now)
+            .bind(self::now())
+            .execute(&mut *tx)
+            .await
+            .map_err(map_sqlx_error)?;
+            // A cron that no longer parses disables its schedule instead of firing every poll.
+            sqlx::query(
+                r#"
+                UPDATE job_schedules
+                SET next_run_at = COALESCE($2, next_run_at), enabled = $2 IS NOT NULL,
+                    last_enqueued_at = $3
+                WHERE name = $1
+                "#,
+            )
+            .bind(name)
+            .bind(next_run_at.map(micros))
+            .bind(now)
+            .execute(&mut *tx)
+            .await
+            .map_err(map_sqlx_error)?;
+        }
+
+        tx.commit().await.map_err(map_sqlx_error)?;
+        Ok(due.len())
+    }
+}
+
+const WEBHOOK_ENDPOINT_COLUMNS: &str =
+    "id, owner_id, url, description, events, secret, enabled, created_at";
+
+const WEBHOOK_DELIVERY_COLUMNS: &str = "id, endpoint_id, event_id, event_type, payload, status, \
+     attempts, response_status, last_error, created_at, delivered_at";
+
+#[async_trait]
+impl WebhookRepository for SqliteStore {
+    async fn create_webhook_endpoint(
+        &self,
+        endpoint: NewWebhookEndpoint,
+    ) -> Result<WebhookEndpoint> {
+        let row = sqlx::query_as::<_, WebhookEndpointRow>(&format!(
+            r#"
+            INSERT INTO webhook_endpoints
+                (id, owner_id, url, description, events, secret, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING {WEBHOOK_ENDPOINT_COLUMNS}
+            "#
+        ))
+        .bind(Uuid::new_v4())
+        .bind(endpoint.owner_id)
+        .bind(endpoint.url)
+        .bind(endpoint.description)
+        .bind(Json(endpoint.events))
+        .bind(endpoint.secret)
+        .bind(now())
+        .fetch_all(&mut *self.write().await?)
+        .await
+        .map_err(map_sqlx_error)?
+        .pop()
+        .ok_or(AppError::NotFound)?;
+
+        Ok(row.into())
+    }
+
+    async fn list_webhook_endpoints(&self, owner_id: Uuid) -> Result<Vec<WebhookEndpoint>> {
+        let rows = sqlx::query_as::<_, WebhookEndpointRow>(&format!(
+            "SELECT {WEBHOOK_ENDPOINT_COLUMNS} FROM webhook_endpoints \
+             WHERE owner_id = $1 ORDER BY created_at DESC"
+        ))
+        .bind(owner_id)
+        .fetch_all(&mut *self.read().await?)
+        .await
+        .map_err(map_sqlx_error)?;
+
+        Ok(rows.into_iter().map(Into::into).collect())
+    }
+
+    async fn find_webhook_endpoint(&self, id: Uuid) -> Result<Option<WebhookEndpoint>> {
+        let row = sqlx::query_as::<_, WebhookEndpointRow>(&format!(
+            "SELECT {WEBHOOK_ENDPOINT_COLUMNS} FROM webhook_endpoints WHERE id = $1"
+        ))
+        .bind(id)
+        .fetch_optional(&mut *self.read().await?)
+        .await
+        .map_err(map_sqlx_error)?;
+
+        Ok(row.map(Into::into))
+    }
+
+    async fn delete_webhook_endpoint(&self, id: Uuid, owner_id: Uuid) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM webhook_endpoints WHERE id = $1 AND owner_id = $2")
+            .bind(id)
+            .bind(owner_id)
+            .execute(&mut *self.write().await?)
+            .await
+            .map_err(map_sqlx_error)?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn find_webhook_delivery(&self, id: Uuid) -> Result<Option<WebhookDelivery>> {
+        let row = sqlx::query_as::<_, WebhookDeliveryRow>(&format!(
+            "SELECT {WEBHOOK_DELIVERY_COLUMNS} FROM webhook_deliveries WHERE id = $1"
+        ))
+        .bind(id)
+        .fetch_optional(&mut *self.read().await?)
+        .await
+        .map_err(map_sqlx_error)?;
+
+        Ok(row.map(Into::into))
+    }
+
+    async fn list_webhook_deliveries(
+        &self,
+        endpoint_id: Uuid,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>> {
+        let rows = sqlx::query_as::<_, WebhookDeliveryRow>(&format!(
+            "SELECT {WEBHOOK_DELIVERY_COLUMNS} FROM webhook_deliveries \
+             WHERE endpoint_id = $1 ORDER BY created_at DESC LIMIT $2"
+        ))
+        .bind(endpoint_id)
+        .bind(limit)
+        .fetch_all(&mut *self.read().await?)
+        .await
+        .map_err(map_sqlx_error)?;
+
+        Ok(rows.into_iter().map(Into::into).collect())
+    }
+
+    async fn queue_webhook_deliveries(&self, event: &AuditEvent) -> Result<usize> {
+        let payload = webhooks::webhook_payload(event);
+        let mut conn = self.write().await?;
+        let mut tx = conn.begin().await.map_err(map_sqlx_error)?;
+        let endpoints: Vec<(Uuid,)> = sqlx::query_as(
+            r#"
+            SELECT id FROM webhook_endpoints
+            WHERE enabled AND EXISTS (SELECT 1 FROM json_each(events) WHERE value = $1)
+            "#,
+        )
+        .bind(&event.event_type)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(map_sqlx_error)?;
+        for (endpoint_id,) in &endpoints {
+            let delivery_id = Uuid::new_v4();
+            sqlx::query(
+                r#"
+                INSERT INTO webhook_deliveries
+                    (id, endpoint_id, event_id, event_type, payload, created_at)
+                VALUES ($1, $2, $3, $4, $5, $6)
+                "#,
+            )
+            .bind(delivery_id)
+            .bind(endpoint_id)
+            .bind(event.id)
+            .bind(&event.event_type)
+            .bind(&payload)
+            .bind(now())
+            .execute(&mut *tx)
+            .await
+            .map_err(map_sqlx_error)?;
+            queue_webhook_delivery(&mut tx, delivery_id).await?;
+        }
+        tx.commit().await.map_err(map_sqlx_error)?;
+        Ok(endpoints.len())
+    }
+
+    async fn record_delivery_attempt(&self, id: Uuid, attempt: &DeliveryAttempt) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE webhook_deliveries
+            SET status = $2, attempts = attempts + 1, response_status = $3, last_error = $4,
+                delivered_at = CASE WHEN $2 = 'succeeded' THEN $5 ELSE delivered_at END
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(attempt.status.as_str())
+        .bind(attempt.response_status)
+        .bind(&attempt.error)
+        .bind(now())
+        .execute(&mut *self.write().await?)
+        .await
+        .map_err(map_sqlx_error)?;
+        Ok(())
+    }
+
+    async fn redeliver_webhook(&self, id: Uuid) -> Result<bool> {
+        let mut conn = self.write().await?;
+        let mut tx = conn.begin().await.map_err(map_sqlx_error)?;
+        let result = sqlx::query(
+            "UPDATE webhook_deliveries SET status = 'pending', last_error = NULL WHERE id = $1",
+        )
+        .bind(id)
+        .execute(&mut *tx)
+        .await
+        .map_err(map_sqlx_error)?;
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+        queue_webhook_delivery(&mut tx, id).await?;
+        tx.commit().await.map_err(map_sqlx_error)?;
+        Ok(true)
+    }
+}
+
+const FEATURE_FLAG_COLUMNS: &str =
+    "key, description, enabled, rollout_percentage, users, roles, orgs, updated_by, updated_at";
+
+#[async_trait]
+impl FeatureFlagRepository for SqliteStore {
+    async fn list_flags(&self) -> Result<Vec<FeatureFlag>> {
+        let rows = sqlx::query_as::<_, FeatureFlagRow>(&format!(
+            "SELECT {FEATURE_FLAG_COLUMNS} FROM feature_flags ORDER BY key"
+        ))
+        .fetch_all(&mut *self.read().await?)
+        .await
+        .map_err(map_sqlx_error)?;
+
+        Ok(rows.into_iter().map(Into::into).collect())
+    }
+
+    async fn find_flag(&self, key: &str) -> Result<Option<FeatureFlag>> {
+        let row = sqlx::query_as::<_, FeatureFlagRow>(&format!(
+            "SELECT {FEATURE_FLAG_COLUMNS} FROM feature_flags WHERE key = $1"
+        ))
+        .bind(key)
+        .fetch_optional(&mut *self.read().await?)
+        .await
+        .map_err(map_sqlx_error)?;
+
+        Ok(row.map(Into::into))
+    }
+
+    async fn upsert_flag(&self, flag: &FeatureFlag) -> Result<FeatureFlag> {
+        let roles: Vec<&str> = flag.roles.iter().map(|r| r.as_str()).collect();
+        let row = sqlx::query_as::<_, FeatureFlagRow>(&format!(
+            r#"
+            INSERT INTO feature_flags
+                (key, description, enabled, rollout_percentage, users, roles, orgs, updated_by,
+                 updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            ON CONFLICT (key) DO UPDATE SET
+                description = excluded.description,
+                enabled = excluded.enabled,
+                rollout_percentage = excluded.rollout_percentage,
+                users = excluded.users,
+                roles = excluded.roles,
+                orgs = excluded.orgs,
+                updated_by = excluded.updated_by,
+                updated_at = excluded.updated_at
+            RETURNING {FEATURE_FLAG_COLUMNS}
+            "#
+        ))
+        .bind(&flag.key)
+        .bind(&flag.description)
+        .bind(flag.enabled)
+        .bind(i16::from(flag.rollout_percentage))
+        .bind(Json(&flag.users))
+        .bind(Json(roles))
+        .bind(Json(&flag.orgs))
+        .bind(flag.updated_by)
+        .bind(now())
+        .fetch_all(&mut *self.write().await?)
+        .await
+        .map_err(map_sqlx_error)?
+        .pop()
+        .ok_or(AppError::NotFound)?;
+
+        self.flag_changed(&flag.key);
+        Ok(row.into())
+    }
+
+    async fn delete_flag(&self, key: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM feature_flags WHERE key = $1")
+            .bind(key)
+            .execute(&mut *self.write().await?)
+            .await
+            .map_err(map_sqlx_error)?;
+        let deleted = result.rows_affected() > 0;
+        if deleted {
+            self.flag_changed(key);
+        }
+        Ok(deleted)
+    }
+}
+
+const NOTIFICATION_COLUMNS: &str = "id, user_id, category, title, body, link, read_at, created_at";
+
+#[async_trait]
+impl NotificationRepository for SqliteStore {
+    async fn create_notification(&self, notification: NewNotification) -> Result<Notification> {
+        let row = sqlx::query_as::<_, NotificationRow>(&format!(
+            r#"
+            INSERT INTO notifications (id, user_id, category, title, body, link, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING {NOTIFICATION_COLUMNS}
+            "#
+        ))
+        .bind(notification.id)
+        .bind(notification.user_id)
+        .bind(notification.category.as_str())
+        .bind(notification.title)
+        .bind(notification.body)
+        .bind(notification.link)
+        .bind(now())
+        .fetch_all(&mut *self.write().await?)
+        .await
+        .map_err(map_sqlx_error)?
+        .pop()
+        .ok_or(AppError::NotFound)?;
+
+        Ok(row.into())
+    }
+
+    async fn list_notifications(
+        &self,
+        user_id: Uuid,
+        page: i64,
+        per_page: i64,
+    ) -> Result<(Vec<Notification>, i64)> {
+        let offset = page_offset(page, per_page)?;
+        let rows = sqlx::query_as::<_, NotificationRow>(&format!(
+            r#"
+            SELECT {NOTIFICATION_COLUMNS}
+            FROM notifications
+            WHERE user_id = $1
+            ORDER BY (read_at IS NOT NULL), created_at DESC, id DESC
+            LIMIT $2 OFFSET $3
+            "#
+        ))
+        .bind(user_id)
+        .bind(per_page)
+        .bind(offset)
+        .fetch_all(&mut *self.read().await?)
+        .await
+        .map_err(map_sqlx_error)?;
+
+        let total: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM notifications WHERE user_id = $1")
+            .bind(user_id)
+            .fetch_one(&mut *self.read().await?)
+            .await
+            .map_err(map_sqlx_error)?;
+
+        Ok((rows.into_iter().map(Into::into).collect(), total.0))
+    }
+
+    async fn count_unread_notifications(&self, user_id: Uuid) -> Result<i64> {
+        let count: (i64,) = sqlx::query_as(
+            "SELECT COUNT(*) FROM notifications WHERE user_id = $1 AND read_at IS NULL",
+        )
+        .bind(user_id)
+        .fetch_one(&mut *self.read().await?)
+        .await
+        .map_err(map_sqlx_error)?;
+        Ok(count.0)
+    }
+
+    async fn mark_notification_read(&self, user_id: Uuid, id: Uuid) -> Result<bool> {
+        let result = sqlx::query(
+            "UPDATE notifications SET read_at = COALESCE(read_at, $3) \
+             WHERE id = $1 AND user_id = $2",
+        )
+        .bind(id)
+        .bind(user_id)
+        .bind(now())
+        .execute(&mut *self.write().await?)
+        .await
+        .map_err(map_sqlx_error)?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn mark_all_notifications_read(&self, user_id: Uuid) -> Result<u64> {
+        let result = sqlx::query(
+            "UPDATE notifications SET read_at = $2 WHERE user_id = $1 AND read_at IS NULL",
+        )
+        .bind(user_id)
+        .bind(now())
+        .execute(&mut *self.write().await?)
+        .await
+        .map_err(map_sqlx_error)?;
+        Ok(result.rows_affected())
+    }
+
+    async fn notification_preferences(&self, user_id: Uuid) -> Result<Vec<NotificationPreference>> {
+        let rows: Vec<(String, String)> = sqlx::query_as(
+            "SELECT category, channel FROM notification_preferences WHERE user_id = $1",
+        )
+        .bind(user_id)
+        .fetch_all(&mut *self.read().await?)
+        .await
+        .map_err(map_sqlx_error)?;
+
+        Ok(rows
+            .into_iter()
+            .filter_map(|(category, channel)| {
+                Some(NotificationPreference {
+                    category: NotificationCategory::parse(&category)?,
+                    channel: NotificationChannel::parse(&channel)?,
+                })
+            })
+            .collect())
+    }
+
+    async fn set_notification_preference(
+        &self,
+        user_id: Uuid,
+        category: NotificationCategory,
+        channel: NotificationChannel,
+    ) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO notification_preferences (user_id, category, channel, updated_at)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (user_id, category)
+            DO UPDATE SET channel = excluded.channel, updated_at = excluded.updated_at
+            "#,
+        )
+        .bind(user_id)
+        .bind(category.as_str())
+        .bind(channel.as_str())
+        .bind(now())
+        .execute(&mut *self.write().await?)
+        .await
+        .map_err(map_sqlx_error)?;
+        Ok(())
+    }
+}
+
+const FILE_COLUMNS: &str =
+    "id, owner_id, storage_key, content_type, size_bytes, filename, created_at";
+
+#[async_trait]
+impl FileRepository for SqliteStore {
+    async fn create_file(&self, file: NewFile) -> Result<StoredFile> {
+        let row = sqlx::query_as::<_, FileRow>(&format!(
+            r#"
+            INSERT INTO files
+                (id, owner_id, storage_key, content_type, size_bytes, filename, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING {FILE_COLUMNS}
+            "#
+        ))
+        .bind(file.id)
+        .bind(file.owner_id)
+        .bind(file.storage_key)
+        .bind(file.content_type)
+        .bind(file.size_bytes)
+        .bind(file.filename)
+        .bind(now())
+        .fetch_all(&mut *self.write().await?)
+        .await
+        .map_err(map_sqlx_error)?
+        .pop()
+        .ok_or(AppError::NotFound)?;
+
+        Ok(row.into())
+    }
+
+    async fn find_file(&self, id: Uuid) -> Result<Option<StoredFile>> {
+        let row = sqlx::query_as::<_, FileRow>(&format!(
+            "SELECT {FILE_COLUMNS} FROM files WHERE id = $1"
+        ))
+        .bind(id)
+        .fetch_optional(&mut *self.read().await?)
+        .await
+        .map_err(map_sqlx_error)?;
+
+        Ok(row.map(Into::into))
+    }
+
+    async fn delete_file(&self, id: Uuid) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM files WHERE id = $1")
+            .bind(id)
+            .execute(&mut *self.write().await?)
+            .await
+            .map_err(map_sqlx_error)?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_avatar(&self, user_id: Uuid, file_id: Option<Uuid>) -> Result<Option<Uuid>> {
+        let mut conn = self.write().await?;
+        let mut tx = conn.begin().await.map_err(map_sqlx_error)?;
+        let (previous,): (Option<Uuid>,) =
+            sqlx::query_as("SELECT avatar_file_id FROM users WHERE id = $1")
+                .bind(user_id)
+                .fetch_optional(&mut *tx)
+                .await
+                .map_err(map_sqlx_error)?
+                .ok_or(AppError::NotFound)?;
+        sqlx::query("UPDATE users SET avatar_file_id = $2, updated_at = $3 WHERE id = $1")
+            .bind(user_id)
+            .bind(file_id)
+            .bind(now())
+            .execute(&mut *tx)
+            .await
+            .map_err(map_sqlx_error)?;
+        tx.commit().await.map_err(map_sqlx_error)?;
+
+        Ok(previous)
+    }
+
+    async fn find_avatar(&self, user_id: Uuid) -> Result<Option<StoredFile>> {
+        let row = sqlx::query_as::<_, FileRow>(&format!(
+            r#"
+            SELECT {FILE_COLUMNS}
+            FROM files
+            WHERE id = (SELECT avatar_file_id FROM users WHERE id = $1)
+            "#
+        ))
+        .bind(user_id)
+        .fetch_optional(&mut *self.read().await?)
+        .await
+        .map_err(map_sqlx_error)?;
+
+        Ok(row.map(Into::into))
+    }
+}
+
+#[async_trait]
+impl OutboxRepository for SqliteStore {
+    async fn append_outbox(&self, envelope: &EventEnvelope) -> Result<()> {
+        let json = serde_json::to_value(envelope)
+            .map_err(|e| AppError::internal(format!("failed to encode event: {e}")))?;
+        sqlx::query(
+            r#"
+            INSERT INTO outbox_events (id, event_type, envelope, available_at, created_at)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+        )
+        .bind(envelope.id)
+        .bind(envelope.event.audit_type().as_str())
+        .bind(json)
+        .bind(now())
+        .bind(micros(envelope.occurred_at))
+        .execute(&mut *self.write().await?)
+        .await
+        .map_err(map_sqlx_error)?;
+        Ok(())
+    }
+
+    async fn claim_outbox(&self, limit: i64) -> Result<Vec<OutboxEntry>> {
+        let now = now();
+        let mut conn = self.write().await?;
+        let mut tx = conn.begin().await.map_err(map_sqlx_error)?;
+        let rows: Vec<(Uuid, serde_json::Value, i32)> = sqlx::query_as(
+            r#"
+            UPDATE outbox_events
+            SET attempts = attempts + 1, locked_until = $2
+            WHERE id IN (
+                SELECT id FROM outbox_events
+                WHERE processed_at IS NULL AND failed_at IS NULL AND available_at <= $3
+                  AND (locked_until IS NULL OR locked_until < $3)
+                ORDER BY created_at
+                LIMIT $1
+            )
+            RETURNING id, envelope, attempts
+            "#,
+        )
+        .bind(limit)
+        .bind(now + Duration::seconds(OUTBOX_LEASE_SECONDS))
+        .bind(now)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(map_sqlx_error)?;
+
+        let mut entries = Vec::with_capacity(rows.len());
+        for (id, envelope, attempts) in rows {
+            let delivered_to: Vec<(String,)> =
+                sqlx::query_as("SELECT subscriber FROM outbox_deliveries WHERE event_id = $1")
+                    .bind(id)
+                    .fetch_all(&mut *tx)
+                    .await
+                    .map_err(map_sqlx_error)?;
+            let envelope = serde_json::from_value(envelope)
+                .map_err(|e| AppError::internal(format!("invalid outbox event: {e}")))?;
+            entries.push(OutboxEntry {
+                envelope,
+                attempts,
+                delivered_to: delivered_to
+                    .into_iter()
+                    .map(|(subscriber,)| subscriber)
+                    .collect(),
+            });
+        }
+        tx.commit().await.map_err(map_sqlx_error)?;
+
+        Ok(entries)
+    }
+
+    async fn mark_outbox_delivered(&self, id: Uuid, subscriber: &str) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO outbox_deliveries (event_id, subscriber, delivered_at) \
+             VALUES ($1, $2, $3) ON CONFLICT DO NOTHING",
+        )
+        .bind(id)
+        .bind(subscriber)
+        .bind(now())
+        .execute(&mut *self.write().await?)
+        .await
+        .map_err(map_sqlx_error)?;
+        Ok(())
+    }
+
+    async fn complete_outbox(&self, id: Uuid) -> Result<()> {
+        sqlx::query(
+            "UPDATE outbox_events SET processed_at = $2, locked_until = NULL, last_error = NULL \
+             WHERE id = $1",
+        )
+        .bind(id)
+        .bind(now())
+        .execute(&mut *self.write().await?)
+        .await
+        .map_err(map_sqlx_error)?;
+        Ok(())
+    }
+
+    async fn retry_outbox(
+        &self,
+        id: Uuid,
+        error: &str,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE outbox_events
+            SET locked_until = NULL, last_error = $2,
+                available_at = COALESCE($3, available_at),
+                failed_at = CASE WHEN $3 IS NULL THEN $4 END
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(error)
+        .bind(retry_at.map(micros))
+        .bind(now())
+        .execute(&mut *self.write().await?)
+        .await
+        .map_err(map_sqlx_error)?;
+        Ok(())
+    }
+}
+
+async fn queue_webhook_delivery(conn: &mut SqliteConnection, delivery_id: Uuid) -> Result<()> {
+    let job = NewJob::of(&DeliverWebhook { delivery_id })?;
+    sqlx::query(
+        r#"
+        INSERT INTO jobs (id, kind, payload, run_at, max_attempts, created_at, updated_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $6)
+        "#,
+    )
+    .bind(Uuid::new_v4())
+    .bind(job.kind)
+    .bind(job.payload)
+    .bind(micros(job.run_at))
+    .bind(job.max_attempts)
+    .bind(now())
+    .execute(conn)
+    .await
+    .map_err(map_sqlx_error)?;
+    Ok(())
+}
+
+#[derive(sqlx::FromRow)]
+struct WebhookEndpointRow {
+    id: Uuid,
+    owner_id: Uuid,
+    url: String,
+    description: Option<String>,
+    events: Json<Vec<String>>,
+    secret: String,
+    enabled: bool,
+    created_at: DateTime<Utc>,
+}
+
+impl From<WebhookEndpointRow> for WebhookEndpoint {
+    fn from(row: WebhookEndpointRow) -> Self {
+        Self {
+            id: row.id,
+            owner_id: row.owner_id,
+            url: row.url,
+            description: row.description,
+            events: row.events.0,
+            secret: row.secret,
+            enabled: row.enabled,
+            created_at: row.created_at,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct FeatureFlagRow {
+    key: String,
+    description: Option<String>,
+    enabled: bool,
+    rollout_percentage: i16,
+    users: Json<Vec<Uuid>>,
+    roles: Json<Vec<String>>,
+    orgs: Json<Vec<String>>,
+    updated_by: Option<Uuid>,
+    updated_at: DateTime<Utc>,
+}
+
+impl From<FeatureFlagRow> for FeatureFlag {
+    fn from(row: FeatureFlagRow) -> Self {
+        Self {
+            key: row.key,
+            description: row.description,
+            enabled: row.enabled,
+            rollout_percentage: row.rollout_percentage.clamp(0, 100) as u8,
+            users: row.users.0,
+            roles: row
+                .roles
+                .iter()
+                .filter_map(|r| UserRole::parse(r))
+                .collect(),
+            orgs: row.orgs.0,
+            updated_by: row.updated_by,
+            updated_at: row.updated_at,
+        }
+    }
+}
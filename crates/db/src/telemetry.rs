@@ -0,0 +1,168 @@
+//! Spans and metrics for database calls.
+//!
+//! Every repository call through [`crate::Database`] runs in a `db.query` span with the
+//! operation and how many rows it returned or changed, never its arguments, and records its
+//! latency in `db_query_duration_seconds`. Acquiring a pooled connection is timed in
+//! `db_pool_acquire_duration_seconds`. Statements slower than `DatabaseConfig.slow_query_ms`
+//! are logged by SQLx, with their SQL but not their bound values.
+
+use domain::files::StoredFile;
+use domain::flags::FeatureFlag;
+use domain::jobs::Job;
+use domain::models::User;
+use domain::notifications::Notification;
+use domain::webhooks::WebhookEndpoint;
+use shared::config::DatabaseConfig;
+use shared::error::Result;
+use sqlx::pool::PoolConnection;
+use sqlx::{ConnectOptions, Database, Pool};
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tracing::field::Empty;
+use tracing::Instrument;
+
+/// Histogram buckets, in seconds, for the `db_` latency metrics.
+pub const LATENCY_BUCKETS: &[f64] = &[
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0,
+];
+
+/// How many rows a call returned or changed, if that is known from its result.
+pub(crate) trait Rows {
+    fn rows(&self) -> Option<u64>;
+}
+
+impl Rows for () {
+    fn rows(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// Whether the call found or changed its row.
+impl Rows for bool {
+    fn rows(&self) -> Option<u64> {
+        Some(u64::from(*self))
+    }
+}
+
+/// Rows changed.
+impl Rows for u64 {
+    fn rows(&self) -> Option<u64> {
+        Some(*self)
+    }
+}
+
+impl Rows for usize {
+    fn rows(&self) -> Option<u64> {
+        Some(*self as u64)
+    }
+}
+
+/// A count, which says nothing about the rows the query touched.
+impl Rows for i64 {
+    fn rows(&self) -> Option<u64> {
+        None
+    }
+}
+
+impl<T> Rows for Option<T> {
+    fn rows(&self) -> Option<u64> {
+        Some(u64::from(self.is_some()))
+    }
+}
+
+impl<T> Rows for Vec<T> {
+    fn rows(&self) -> Option<u64> {
+        Some(self.len() as u64)
+    }
+}
+
+/// A page and the total it was taken from.
+impl<T> Rows for (Vec<T>, i64) {
+    fn rows(&self) -> Option<u64> {
+        self.0.rows()
+    }
+}
+
+macro_rules! one_row {
+    ($($ty:ty),*) => {
+        $(
+            impl Rows for $ty {
+                fn rows(&self) -> Option<u64> {
+                    Some(1)
+                }
+            }
+        )*
+    };
+}
+
+one_row!(
+    FeatureFlag,
+    Job,
+    Notification,
+    StoredFile,
+    User,
+    WebhookEndpoint
+);
+
+/// Runs `call`, the repository operation `operation` on `backend`, in its span and records
+/// its latency.
+pub(crate) async fn observe<T: Rows>(
+    backend: &'static str,
+    operation: &'static str,
+    call: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    let span = tracing::info_span!(
+        "db.query",
+        db.system = backend,
+        db.operation = operation,
+        db.rows = Empty,
+    );
+    let started = Instant::now();
+    let result = call.instrument(span.clone()).await;
+    let outcome = match &result {
+        Ok(value) => {
+            if let Some(rows) = value.rows() {
+                span.record("db.rows", rows);
+            }
+            "ok"
+        }
+        Err(_) => "error",
+    };
+    metrics::histogram!(
+        "db_query_duration_seconds",
+        started.elapsed().as_secs_f64(),
+        "backend" => backend,
+        "operation" => operation,
+        "outcome" => outcome,
+    );
+    result
+}
+
+/// A connection from `pool`, named `name` in the acquire metric.
+pub(crate) async fn acquire<DB: Database>(
+    pool: &Pool<DB>,
+    name: &'static str,
+) -> std::result::Result<PoolConnection<DB>, sqlx::Error> {
+    let started = Instant::now();
+    let conn = pool.acquire().await;
+    metrics::histogram!(
+        "db_pool_acquire_duration_seconds",
+        started.elapsed().as_secs_f64(),
+        "pool" => name,
+    );
+    conn
+}
+
+/// Sets the `db_pool_connections` and `db_pool_idle_connections` gauges for `pool`.
+pub(crate) fn record_pool<DB: Database>(pool: &Pool<DB>, name: &'static str) {
+    metrics::gauge!("db_pool_connections", f64::from(pool.size()), "pool" => name);
+    metrics::gauge!("db_pool_idle_connections", pool.num_idle() as f64, "pool" => name);
+}
+
+/// Logs statements slower than `config.slow_query_ms` as warnings.
+pub(crate) fn slow_statements<O: ConnectOptions>(options: O, config: &DatabaseConfig) -> O {
+    options.log_slow_statements(
+        log::LevelFilter::Warn,
+        Duration::from_millis(config.slow_query_ms),
+    )
+}
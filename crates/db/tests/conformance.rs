@@ -0,0 +1,1289 @@
+//! Behaviour every `Database` backend must share. Each scenario runs against the in-memory
+//! store, against a fresh SQLite file with the `sqlite` feature, and against Postgres when
+//! `TEST_DATABASE_URL` is set, in a schema of its own.
+
+use chrono::{DateTime, Duration, NaiveDate, TimeZone, Utc};
+use db::{Database, FlagChanges, MigrationState};
+use domain::audit_chain::CheckpointSigner;
+use domain::events::{DomainEvent, EventEnvelope};
+use domain::files::NewFile;
+use domain::flags::{FeatureFlag, FeatureFlagService};
+use domain::jobs::{JobKind, JobSchedule, JobStatus, NewJob};
+use domain::models::{
+    AuditCursor, AuditEventType, AuditQuery, NewUser, PendingRegistration, RefreshToken, User,
+    UserCursor, UserFilter, UserQuery, UserSort,
+};
+use domain::notifications::{NewNotification, NotificationPreference};
+use domain::ports::{
+    AuditChainRepository, AuditLogRepository, FeatureFlagRepository, FileRepository, JobRepository,
+    NotificationRepository, OutboxRepository, PendingRegistrationRepository,
+    RefreshTokenRepository, Transaction, UnitOfWork, UserRepository, WebhookRepository,
+};
+use domain::webhooks::{DeliverWebhook, DeliveryAttempt, DeliveryStatus, NewWebhookEndpoint};
+use shared::config::DatabaseConfig;
+use shared::error::AppError;
+use shared::types::{
+    AuditEvent, AuditSeverity, NotificationCategory, NotificationChannel, RequestContext, UserRole,
+};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// A Postgres schema that lives for one scenario.
+struct PgSchema {
+    admin: sqlx::PgPool,
+    name: String,
+    /// Connects to this schema.
+    url: String,
+    database: Database,
+}
+
+impl PgSchema {
+    async fn create() -> Option<Self> {
+        Self::create_with(|_| {}).await
+    }
+
+    async fn create_with(configure: impl FnOnce(&mut DatabaseConfig)) -> Option<Self> {
+        let Ok(url) = std::env::var("TEST_DATABASE_URL") else {
+            eprintln!("TEST_DATABASE_URL is not set; skipping the Postgres backend");
+            return None;
+        };
+        let admin = sqlx::PgPool::connect(&url).await.unwrap();
+        let name = format!("conformance_{}", Uuid::new_v4().simple());
+        sqlx::query(&format!("CREATE SCHEMA {name}"))
+            .execute(&admin)
+            .await
+            .unwrap();
+        let separator = if url.contains('?') { '&' } else { '?' };
+        let mut config = DatabaseConfig {
+            url: format!("{url}{separator}options=-c%20search_path%3D{name}"),
+            max_connections: 2,
+            min_connections: 0,
+            ..DatabaseConfig::default()
+        };
+        configure(&mut config);
+        let database = Database::connect(&config).await.unwrap();
+        database.migrate().await.unwrap();
+        Some(Self {
+            admin,
+            name,
+            url: config.url,
+            database,
+        })
+    }
+
+    async fn drop(self) {
+        sqlx::query(&format!("DROP SCHEMA {} CASCADE", self.name))
+            .execute(&self.admin)
+            .await
+            .unwrap();
+    }
+}
+
+/// A SQLite file that lives for one scenario.
+#[cfg(feature = "sqlite")]
+struct SqliteFile {
+    path: std::path::PathBuf,
+    database: Database,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteFile {
+    async fn create() -> Self {
+        let path = std::env::temp_dir().join(format!("conformance_{}.db", Uuid::new_v4().simple()));
+        let config = DatabaseConfig {
+            url: format!("sqlite://{}", path.display()),
+            max_connections: 2,
+            min_connections: 0,
+            ..DatabaseConfig::default()
+        };
+        let database = Database::connect(&config).await.unwrap();
+        database.migrate().await.unwrap();
+        Self { path, database }
+    }
+
+    fn drop(self) {
+        drop(self.database);
+        for suffix in ["", "-wal", "-shm"] {
+            let mut path = self.path.clone().into_os_string();
+            path.push(suffix);
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+macro_rules! conformance {
+    ($($scenario:ident),* $(,)?) => {
+        mod memory {
+            $(
+                #[tokio::test]
+                async fn $scenario() {
+                    super::$scenario(&db::Database::memory()).await;
+                }
+            )*
+        }
+
+        #[cfg(feature = "sqlite")]
+        mod sqlite {
+            $(
+                #[tokio::test]
+                async fn $scenario() {
+                    let file = super::SqliteFile::create().await;
+                    super::$scenario(&file.database).await;
+                    file.drop();
+                }
+            )*
+        }
+
+        mod postgres {
+            $(
+                #[tokio::test]
+                async fn $scenario() {
+                    if let Some(schema) = super::PgSchema::create().await {
+                        super::$scenario(&schema.database).await;
+                        schema.drop().await;
+                    }
+                }
+            )*
+        }
+    };
+}
+
+conformance!(
+    users,
+    user_listing,
+    refresh_tokens,
+    pending_registrations,
+    audit_log,
+    audit_chain,
+    jobs,
+    job_schedules,
+    webhooks,
+    feature_flags,
+    notifications,
+    files,
+    outbox,
+    transactions,
+    migrations,
+);
+
+/// Users created one after another get distinct `created_at`, so listings by it do not
+/// fall back to ordering by id.
+async fn create_user(db: &Database, email: &str) -> User {
+    tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+    db.create_user(NewUser {
+        email: email.to_string(),
+        password_hash: "hash".into(),
+        role: UserRole::User,
+    })
+    .await
+    .unwrap()
+}
+
+fn at(day: NaiveDate, second: u32) -> DateTime<Utc> {
+    Utc.from_utc_datetime(&day.and_hms_opt(12, 0, second).unwrap())
+}
+
+fn audit_event(
+    event_type: AuditEventType,
+    actor: Option<Uuid>,
+    created_at: DateTime<Utc>,
+) -> AuditEvent {
+    AuditEvent {
+        id: Uuid::new_v4(),
+        actor_id: actor,
+        subject_id: actor,
+        event_type: event_type.as_str().to_string(),
+        severity: AuditSeverity::Info,
+        metadata: serde_json::json!({ "source": "conformance" }),
+        ip: Some("127.0.0.1".into()),
+        user_agent: None,
+        request_id: None,
+        created_at,
+    }
+}
+
+async fn users(db: &Database) {
+    let ada = create_user(db, "ada@example.com").await;
+    let err = db
+        .create_user(NewUser {
+            email: "ada@example.com".into(),
+            password_hash: "other".into(),
+            role: UserRole::Admin,
+        })
+        .await
+        .unwrap_err();
+    assert!(matches!(err, AppError::Conflict(_)), "{err:?}");
+
+    let bob = create_user(db, "bob@example.com").await;
+    let cy = create_user(db, "cy@example.com").await;
+    assert_eq!(
+        db.find_by_email("bob@example.com")
+            .await
+            .unwrap()
+            .unwrap()
+            .id,
+        bob.id
+    );
+    assert_eq!(
+        db.find_by_id(ada.id).await.unwrap().unwrap().email,
+        ada.email
+    );
+    assert!(db
+        .find_by_email("nobody@example.com")
+        .await
+        .unwrap()
+        .is_none());
+
+    // Newest first, continuing after the last user seen.
+    let newest = |after| UserQuery {
+        filter: UserFilter::default(),
+        sort: UserSort::CreatedAt,
+        descending: true,
+        after,
+        limit: 2,
+    };
+    let first = db.list_users(&newest(None)).await.unwrap();
+    let ids: Vec<Uuid> = first.iter().map(|u| u.id).collect();
+    assert_eq!(ids, [cy.id, bob.id]);
+    let after = UserCursor::from_user(&first[1], UserSort::CreatedAt);
+    let second = db.list_users(&newest(Some(after))).await.unwrap();
+    assert_eq!(second.len(), 1);
+    assert_eq!(second[0].id, ada.id);
+    let after = UserCursor::from_user(&second[0], UserSort::CreatedAt);
+    assert!(db
+        .list_users(&newest(Some(after)))
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(db.count_users(&UserFilter::default()).await.unwrap(), 3);
+
+    let promoted = db
+        .update_role(bob.id, UserRole::Admin)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(promoted.role, UserRole::Admin);
+    assert!(promoted.updated_at >= bob.updated_at);
+    assert!(db
+        .update_role(Uuid::new_v4(), UserRole::Admin)
+        .await
+        .unwrap()
+        .is_none());
+
+    db.update_password_hash(ada.id, "new-hash").await.unwrap();
+    let ada = db.find_by_id(ada.id).await.unwrap().unwrap();
+    assert_eq!(ada.password_hash, "new-hash");
+}
+
+async fn user_listing(db: &Database) {
+    let mut users = Vec::new();
+    for email in [
+        "carol@example.com",
+        "Alice@Example.com",
+        "dan_1@example.com",
+        "dan11@example.com",
+        "bob@example.org",
+    ] {
+        users.push(create_user(db, email).await);
+    }
+    let admin = db
+        .update_role(users[4].id, UserRole::Admin)
+        .await
+        .unwrap()
+        .unwrap();
+    let emails = |users: Vec<User>| users.into_iter().map(|u| u.email).collect::<Vec<_>>();
+    let query = |filter: UserFilter, sort, descending, after, limit| UserQuery {
+        filter,
+        sort,
+        descending,
+        after,
+        limit,
+    };
+    let filtered = |filter: UserFilter| query(filter, UserSort::Email, false, None, 10);
+
+    // Email filters match a substring regardless of ASCII case, and wildcards literally.
+    let found = db
+        .list_users(&filtered(UserFilter {
+            email: Some("EXAMPLE.COM".into()),
+            ..UserFilter::default()
+        }))
+        .await
+        .unwrap();
+    assert_eq!(found.len(), 4);
+    let found = db
+        .list_users(&filtered(UserFilter {
+            email: Some("dan_".into()),
+            ..UserFilter::default()
+        }))
+        .await
+        .unwrap();
+    assert_eq!(emails(found), ["dan_1@example.com"]);
+    let found = db
+        .list_users(&filtered(UserFilter {
+            email: Some("%".into()),
+            ..UserFilter::default()
+        }))
+        .await
+        .unwrap();
+    assert!(found.is_empty());
+
+    let admins = UserFilter {
+        role: Some(UserRole::Admin),
+        ..UserFilter::default()
+    };
+    let found = db.list_users(&filtered(admins.clone())).await.unwrap();
+    assert_eq!(emails(found), [admin.email]);
+    assert_eq!(db.count_users(&admins).await.unwrap(), 1);
+
+    // Created ranges include their start and exclude their end.
+    let range = UserFilter {
+        created_from: Some(users[1].created_at),
+        created_to: Some(users[3].created_at),
+        ..UserFilter::default()
+    };
+    let found = db.list_users(&filtered(range.clone())).await.unwrap();
+    assert_eq!(emails(found), ["Alice@Example.com", "dan_1@example.com"]);
+    assert_eq!(db.count_users(&range).await.unwrap(), 2);
+
+    // Emails sort bytewise, and pages pick up after the cursor in either direction.
+    let mut ascending = Vec::new();
+    let mut after = None;
+    loop {
+        let page = db
+            .list_users(&query(
+                UserFilter::default(),
+                UserSort::Email,
+                false,
+                after,
+                2,
+            ))
+            .await
+            .unwrap();
+        let Some(last) = page.last() else { break };
+        after = Some(UserCursor::from_user(last, UserSort::Email));
+        ascending.extend(emails(page));
+    }
+    assert_eq!(
+        ascending,
+        [
+            "Alice@Example.com",
+            "bob@example.org",
+            "carol@example.com",
+            "dan11@example.com",
+            "dan_1@example.com",
+        ]
+    );
+    let after = UserCursor::from_user(&users[0], UserSort::Email);
+    let page = db
+        .list_users(&query(
+            UserFilter::default(),
+            UserSort::Email,
+            true,
+            Some(after),
+            10,
+        ))
+        .await
+        .unwrap();
+    assert_eq!(emails(page), ["bob@example.org", "Alice@Example.com"]);
+}
+
+async fn refresh_tokens(db: &Database) {
+    let user = create_user(db, "tokens@example.com").await;
+    let now = Utc::now();
+    let token = RefreshToken::new(user.id, "hash-1".into(), now + Duration::days(7));
+    db.store_refresh_token(&token).await.unwrap();
+    let stored = db.find_refresh_token("hash-1").await.unwrap().unwrap();
+    assert_eq!(stored.id, token.id);
+    assert_eq!(stored.user_id, user.id);
+    assert_eq!(stored.session_id, token.id);
+
+    // A rotated token carries on the session of the one it replaced.
+    let successor = RefreshToken::new(user.id, "hash-5".into(), now + Duration::days(7))
+        .in_session(token.session_id);
+    db.store_refresh_token(&successor).await.unwrap();
+    let stored = db.find_refresh_token("hash-5").await.unwrap().unwrap();
+    assert_eq!(stored.id, successor.id);
+    assert_eq!(stored.session_id, token.id);
+
+    // Storing the same hash again only moves its expiry.
+    let renewed = RefreshToken::new(user.id, "hash-1".into(), now + Duration::days(30));
+    db.store_refresh_token(&renewed).await.unwrap();
+    let stored = db.find_refresh_token("hash-1").await.unwrap().unwrap();
+    assert_eq!(stored.id, token.id);
+    assert!(stored.expires_at > now + Duration::days(29));
+
+    let expired = RefreshToken::new(user.id, "hash-2".into(), now - Duration::minutes(1));
+    db.store_refresh_token(&expired).await.unwrap();
+    assert_eq!(db.delete_expired_tokens(now).await.unwrap(), 1);
+    assert!(db.find_refresh_token("hash-2").await.unwrap().is_none());
+
+    assert!(db.delete_refresh_token(token.id).await.unwrap());
+    assert!(db.find_refresh_token("hash-1").await.unwrap().is_none());
+    assert!(!db.delete_refresh_token(token.id).await.unwrap());
+
+    for hash in ["hash-3", "hash-4"] {
+        let token = RefreshToken::new(user.id, hash.into(), now + Duration::days(1));
+        db.store_refresh_token(&token).await.unwrap();
+    }
+    db.delete_tokens_for_user(user.id).await.unwrap();
+    assert!(db.find_refresh_token("hash-3").await.unwrap().is_none());
+    assert!(db.find_refresh_token("hash-4").await.unwrap().is_none());
+}
+
+async fn pending_registrations(db: &Database) {
+    let now = Utc::now();
+    let pending = PendingRegistration {
+        id: Uuid::new_v4(),
+        email: "pending@example.com".into(),
+        password_hash: "hash".into(),
+        role: UserRole::User,
+        token_hash: "token-1".into(),
+        expires_at: now + Duration::hours(1),
+        created_at: now,
+    };
+    db.store_pending_registration(&pending).await.unwrap();
+    let duplicate = PendingRegistration {
+        id: Uuid::new_v4(),
+        ..pending.clone()
+    };
+    let err = db.store_pending_registration(&duplicate).await.unwrap_err();
+    assert!(matches!(err, AppError::Conflict(_)), "{err:?}");
+
+    // A token can only be taken once.
+    let taken = db
+        .take_pending_registration("token-1")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(taken.id, pending.id);
+    assert_eq!(taken.email, pending.email);
+    assert!(db
+        .take_pending_registration("token-1")
+        .await
+        .unwrap()
+        .is_none());
+
+    let expired = PendingRegistration {
+        id: Uuid::new_v4(),
+        token_hash: "token-2".into(),
+        expires_at: now - Duration::minutes(1),
+        ..pending
+    };
+    db.store_pending_registration(&expired).await.unwrap();
+    assert_eq!(
+        db.delete_expired_pending_registrations(now).await.unwrap(),
+        1
+    );
+    assert!(db
+        .take_pending_registration("token-2")
+        .await
+        .unwrap()
+        .is_none());
+}
+
+async fn audit_log(db: &Database) {
+    let ada = create_user(db, "audit-ada@example.com").await;
+    let bob = create_user(db, "audit-bob@example.com").await;
+    let day = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+    let events = [
+        audit_event(AuditEventType::AuthLogin, Some(ada.id), at(day, 1)),
+        audit_event(AuditEventType::AuthLogin, Some(bob.id), at(day, 2)),
+        audit_event(AuditEventType::AuthLogout, Some(ada.id), at(day, 3)),
+        audit_event(AuditEventType::AuthLogin, Some(ada.id), at(day, 4)),
+    ];
+    for event in &events {
+        db.log_event(event.clone()).await.unwrap();
+    }
+    let err = db.log_event(events[0].clone()).await.unwrap_err();
+    assert!(matches!(err, AppError::Conflict(_)), "{err:?}");
+
+    let query = AuditQuery {
+        user_id: None,
+        event_type: None,
+        from: None,
+        to: None,
+        after: None,
+        limit: 10,
+    };
+    let ids = |events: Vec<AuditEvent>| events.into_iter().map(|e| e.id).collect::<Vec<_>>();
+
+    // Newest first.
+    let all = db.list_events(&query).await.unwrap();
+    assert_eq!(all[0].metadata, events[3].metadata);
+    assert_eq!(
+        ids(all),
+        [events[3].id, events[2].id, events[1].id, events[0].id]
+    );
+
+    let for_ada = AuditQuery {
+        user_id: Some(ada.id),
+        ..query.clone()
+    };
+    assert_eq!(
+        ids(db.list_events(&for_ada).await.unwrap()),
+        [events[3].id, events[2].id, events[0].id]
+    );
+
+    let logins_in_range = AuditQuery {
+        event_type: Some(AuditEventType::AuthLogin),
+        from: Some(at(day, 2)),
+        to: Some(at(day, 4)),
+        ..query.clone()
+    };
+    assert_eq!(
+        ids(db.list_events(&logins_in_range).await.unwrap()),
+        [events[1].id]
+    );
+
+    // Paging with a cursor picks up after the last event seen.
+    let first_page = db
+        .list_events(&AuditQuery {
+            limit: 2,
+            ..query.clone()
+        })
+        .await
+        .unwrap();
+    let next_page = AuditQuery {
+        after: first_page.last().map(AuditCursor::from_event),
+        limit: 2,
+        ..query
+    };
+    assert_eq!(
+        ids(db.list_events(&next_page).await.unwrap()),
+        [events[1].id, events[0].id]
+    );
+}
+
+async fn audit_chain(db: &Database) {
+    let first = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+    let second = NaiveDate::from_ymd_opt(2024, 3, 2).unwrap();
+    for (day, second_of_day) in [(first, 1), (first, 2), (second, 1)] {
+        let event = audit_event(AuditEventType::AuthLogin, None, at(day, second_of_day));
+        db.log_event(event).await.unwrap();
+    }
+
+    assert_eq!(db.chain_days().await.unwrap(), [first, second]);
+    assert_eq!(db.count_unchained_events().await.unwrap(), 0);
+    let chain = db.chained_events(first).await.unwrap();
+    let seqs: Vec<i64> = chain.iter().map(|e| e.link.seq).collect();
+    assert_eq!(seqs, [1, 2]);
+    assert_eq!(chain[1].link.prev_hash, chain[0].link.hash);
+
+    let heads = db.unanchored_heads().await.unwrap();
+    assert_eq!(heads.len(), 2);
+    assert_eq!((heads[0].day, heads[0].seq), (first, 2));
+
+    let signer = CheckpointSigner::new("conformance-checkpoint-secret-0123456789");
+    let checkpoint = signer.checkpoint(&heads[0]);
+    db.store_checkpoint(&checkpoint).await.unwrap();
+    // A second checkpoint of the same head is ignored.
+    db.store_checkpoint(&signer.checkpoint(&heads[0]))
+        .await
+        .unwrap();
+    let checkpoints = db.checkpoints(first).await.unwrap();
+    assert_eq!(checkpoints.len(), 1);
+    assert_eq!(checkpoints[0].id, checkpoint.id);
+    let heads = db.unanchored_heads().await.unwrap();
+    assert_eq!(heads.len(), 1);
+    assert_eq!(heads[0].day, second);
+
+    assert_eq!(db.purge_days_before(second).await.unwrap(), 2);
+    assert_eq!(db.chain_days().await.unwrap(), [second]);
+    assert!(db.checkpoints(first).await.unwrap().is_empty());
+}
+
+fn job(kind: &str, run_at: DateTime<Utc>) -> NewJob {
+    NewJob {
+        kind: kind.to_string(),
+        payload: serde_json::json!({ "kind": kind }),
+        run_at,
+        max_attempts: 3,
+    }
+}
+
+async fn jobs(db: &Database) {
+    let now = Utc::now();
+    let later = db
+        .enqueue_job(job("mail", now - Duration::seconds(10)))
+        .await
+        .unwrap();
+    let earlier = db
+        .enqueue_job(job("mail", now - Duration::seconds(20)))
+        .await
+        .unwrap();
+    let other = db
+        .enqueue_job(job("report", now - Duration::seconds(30)))
+        .await
+        .unwrap();
+    let future = db
+        .enqueue_job(job("mail", now + Duration::hours(1)))
+        .await
+        .unwrap();
+    assert_eq!(later.status, JobStatus::Pending);
+    assert_eq!(later.attempts, 0);
+
+    // Due jobs of the requested kinds, oldest first.
+    let kinds = ["mail".to_string()];
+    let claimed = db.claim_jobs("worker-1", &kinds, 1).await.unwrap();
+    assert_eq!(claimed.len(), 1);
+    assert_eq!(claimed[0].id, earlier.id);
+    assert_eq!(claimed[0].status, JobStatus::Running);
+    assert_eq!(claimed[0].attempts, 1);
+    assert_eq!(claimed[0].locked_by.as_deref(), Some("worker-1"));
+    let claimed = db.claim_jobs("worker-2", &kinds, 10).await.unwrap();
+    assert_eq!(claimed.len(), 1);
+    assert_eq!(claimed[0].id, later.id);
+    assert!(db
+        .claim_jobs("worker-2", &kinds, 10)
+        .await
+        .unwrap()
+        .is_empty());
+
+    db.complete_job(earlier.id).await.unwrap();
+    db.fail_job(later.id, "smtp down", Some(now - Duration::seconds(1)))
+        .await
+        .unwrap();
+    let retried = db.claim_jobs("worker-1", &kinds, 10).await.unwrap();
+    assert_eq!(retried.len(), 1);
+    assert_eq!(retried[0].attempts, 2);
+    assert_eq!(retried[0].last_error.as_deref(), Some("smtp down"));
+    db.fail_job(later.id, "gave up", None).await.unwrap();
+
+    let dead = db.list_jobs(Some(JobStatus::Dead), 10).await.unwrap();
+    assert_eq!(dead.len(), 1);
+    assert_eq!(dead[0].id, later.id);
+    assert!(db.retry_job(later.id).await.unwrap());
+    assert!(!db.retry_job(later.id).await.unwrap());
+    let pending = db.list_jobs(Some(JobStatus::Pending), 10).await.unwrap();
+    assert_eq!(pending.len(), 3);
+    assert_eq!(pending[0].id, later.id);
+    assert_eq!(pending[0].attempts, 0);
+    assert_eq!(db.list_jobs(None, 2).await.unwrap().len(), 2);
+
+    assert!(db.cancel_job(future.id).await.unwrap());
+    assert!(!db.cancel_job(future.id).await.unwrap());
+    assert!(!db.cancel_job(earlier.id).await.unwrap());
+
+    let kinds = ["report".to_string()];
+    let claimed = db.claim_jobs("worker-1", &kinds, 10).await.unwrap();
+    assert_eq!(claimed[0].id, other.id);
+    assert_eq!(
+        db.release_stale_jobs(now - Duration::minutes(1))
+            .await
+            .unwrap(),
+        0
+    );
+    assert_eq!(
+        db.release_stale_jobs(Utc::now() + Duration::seconds(1))
+            .await
+            .unwrap(),
+        1
+    );
+    let released = db.list_jobs(Some(JobStatus::Pending), 10).await.unwrap();
+    assert!(released
+        .iter()
+        .any(|j| j.id == other.id && j.locked_by.is_none()));
+}
+
+async fn job_schedules(db: &Database) {
+    let now = Utc::now();
+    let schedule = JobSchedule {
+        name: "nightly".into(),
+        kind: "report".into(),
+        payload: serde_json::json!({}),
+        cron: "0 3 * * *".into(),
+        next_run_at: now - Duration::minutes(1),
+        enabled: true,
+    };
+    db.upsert_schedule(&schedule).await.unwrap();
+    // Re-registering with the same cron keeps the schedule's place.
+    db.upsert_schedule(&JobSchedule {
+        next_run_at: now + Duration::days(1),
+        ..schedule.clone()
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(db.enqueue_due_schedules(now).await.unwrap(), 1);
+    assert_eq!(db.enqueue_due_schedules(now).await.unwrap(), 0);
+    let jobs = db.list_jobs(Some(JobStatus::Pending), 10).await.unwrap();
+    assert_eq!(jobs.len(), 1);
+    assert_eq!(jobs[0].kind, "report");
+    assert_eq!(jobs[0].max_attempts, 5);
+
+    // A cron that no longer parses disables the schedule once it comes due.
+    db.upsert_schedule(&JobSchedule {
+        name: "broken".into(),
+        cron: "not a cron".into(),
+        ..schedule
+    })
+    .await
+    .unwrap();
+    assert_eq!(db.enqueue_due_schedules(now).await.unwrap(), 1);
+    assert_eq!(
+        db.enqueue_due_schedules(now + Duration::days(2))
+            .await
+            .unwrap(),
+        1
+    );
+}
+
+async fn webhooks(db: &Database) {
+    let owner = create_user(db, "hooks@example.com").await;
+    let other = create_user(db, "other-hooks@example.com").await;
+    let endpoint = |url: &str, events: &[&str]| NewWebhookEndpoint {
+        owner_id: owner.id,
+        url: url.to_string(),
+        description: None,
+        events: events.iter().map(|e| e.to_string()).collect(),
+        secret: NewWebhookEndpoint::generate_secret(),
+    };
+    let login = AuditEventType::AuthLogin.as_str();
+    let logins = db
+        .create_webhook_endpoint(endpoint("https://one.example.com", &[login]))
+        .await
+        .unwrap();
+    let roles = db
+        .create_webhook_endpoint(endpoint(
+            "https://two.example.com",
+            &[AuditEventType::UserRoleChanged.as_str()],
+        ))
+        .await
+        .unwrap();
+    assert!(logins.enabled);
+    let listed = db.list_webhook_endpoints(owner.id).await.unwrap();
+    let ids: Vec<Uuid> = listed.iter().map(|e| e.id).collect();
+    assert_eq!(ids, [roles.id, logins.id]);
+    assert!(db
+        .list_webhook_endpoints(other.id)
+        .await
+        .unwrap()
+        .is_empty());
+
+    let event = audit_event(AuditEventType::AuthLogin, Some(owner.id), Utc::now());
+    assert_eq!(db.queue_webhook_deliveries(&event).await.unwrap(), 1);
+    let deliveries = db.list_webhook_deliveries(logins.id, 10).await.unwrap();
+    assert_eq!(deliveries.len(), 1);
+    let delivery = &deliveries[0];
+    assert_eq!(delivery.event_id, event.id);
+    assert_eq!(delivery.status, DeliveryStatus::Pending);
+    assert_eq!(delivery.payload["type"], login);
+    let jobs = db.list_jobs(Some(JobStatus::Pending), 10).await.unwrap();
+    assert_eq!(jobs.len(), 1);
+    assert_eq!(jobs[0].kind, DeliverWebhook::KIND);
+    assert_eq!(jobs[0].payload["delivery_id"], delivery.id.to_string());
+
+    let failed = DeliveryAttempt {
+        status: DeliveryStatus::Failed,
+        response_status: Some(500),
+        error: Some("server error".into()),
+    };
+    db.record_delivery_attempt(delivery.id, &failed)
+        .await
+        .unwrap();
+    let stored = db
+        .find_webhook_delivery(delivery.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored.status, DeliveryStatus::Failed);
+    assert_eq!(stored.attempts, 1);
+    assert_eq!(stored.response_status, Some(500));
+    assert!(stored.delivered_at.is_none());
+
+    assert!(db.redeliver_webhook(delivery.id).await.unwrap());
+    assert!(!db.redeliver_webhook(Uuid::new_v4()).await.unwrap());
+    let stored = db
+        .find_webhook_delivery(delivery.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored.status, DeliveryStatus::Pending);
+    assert!(stored.last_error.is_none());
+    assert_eq!(
+        db.list_jobs(Some(JobStatus::Pending), 10)
+            .await
+            .unwrap()
+            .len(),
+        2
+    );
+
+    let succeeded = DeliveryAttempt {
+        status: DeliveryStatus::Succeeded,
+        response_status: Some(200),
+        error: None,
+    };
+    db.record_delivery_attempt(delivery.id, &succeeded)
+        .await
+        .unwrap();
+    let stored = db
+        .find_webhook_delivery(delivery.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored.attempts, 2);
+    assert!(stored.delivered_at.is_some());
+
+    // Only the owner can delete an endpoint, and its deliveries go with it.
+    assert!(!db
+        .delete_webhook_endpoint(logins.id, other.id)
+        .await
+        .unwrap());
+    assert!(db
+        .delete_webhook_endpoint(logins.id, owner.id)
+        .await
+        .unwrap());
+    assert!(db.find_webhook_endpoint(logins.id).await.unwrap().is_none());
+    assert!(db
+        .find_webhook_delivery(delivery.id)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+async fn feature_flags(db: &Database) {
+    let admin = create_user(db, "flags@example.com").await;
+    let mut changes = db.listen_for_flag_changes().await.unwrap();
+
+    let mut beta = FeatureFlag::new("beta").unwrap();
+    beta.enabled = true;
+    beta.rollout_percentage = 25;
+    beta.roles = vec![UserRole::Admin];
+    beta.orgs = vec!["acme".into()];
+    beta.updated_by = Some(admin.id);
+    let stored = db.upsert_flag(&beta).await.unwrap();
+    assert_eq!(stored.rollout_percentage, 25);
+    assert_eq!(stored.roles, [UserRole::Admin]);
+    assert_eq!(stored.updated_by, Some(admin.id));
+
+    changed_flag(&mut changes, "beta").await;
+
+    db.upsert_flag(&FeatureFlag::new("alpha").unwrap())
+        .await
+        .unwrap();
+    beta.enabled = false;
+    let updated = db.upsert_flag(&beta).await.unwrap();
+    assert!(!updated.enabled);
+    assert!(updated.updated_at >= stored.updated_at);
+
+    let keys: Vec<String> = db
+        .list_flags()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|f| f.key)
+        .collect();
+    assert_eq!(keys, ["alpha", "beta"]);
+    assert!(!db.find_flag("beta").await.unwrap().unwrap().enabled);
+
+    assert!(db.delete_flag("alpha").await.unwrap());
+    assert!(!db.delete_flag("alpha").await.unwrap());
+    assert!(db.find_flag("alpha").await.unwrap().is_none());
+
+    let tx = db.begin().await.unwrap();
+    tx.upsert_flag(&FeatureFlag::new("gamma").unwrap())
+        .await
+        .unwrap();
+    drop(tx);
+    assert!(db.find_flag("gamma").await.unwrap().is_none());
+
+    // The service writes each change together with the event announcing it.
+    let service = FeatureFlagService::new(Arc::new(db.clone()));
+    let ctx = RequestContext::default();
+    service
+        .save(FeatureFlag::new("gamma").unwrap(), Some(admin.id), &ctx)
+        .await
+        .unwrap();
+    changed_flag(&mut changes, "gamma").await;
+    assert!(service.delete("gamma", Some(admin.id), &ctx).await.unwrap());
+    assert!(!service.delete("gamma", Some(admin.id), &ctx).await.unwrap());
+    let mut flags: Vec<Option<bool>> = db
+        .claim_outbox(10)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|entry| match entry.envelope.event {
+            DomainEvent::FeatureFlagChanged { key, flag, .. } => {
+                assert_eq!(key, "gamma");
+                flag.map(|flag| flag.enabled)
+            }
+            other => panic!("unexpected event {other:?}"),
+        })
+        .collect();
+    flags.sort();
+    assert_eq!(flags, [None, Some(false)]);
+}
+
+/// Waits for the change notification of `key`. Other processes' schemas share the
+/// notification channel, so their changes are skipped.
+async fn changed_flag(changes: &mut FlagChanges, key: &str) {
+    tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        loop {
+            if changes.recv().await.unwrap().as_deref() == Some(key) {
+                return;
+            }
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("no change notification for {key}"));
+}
+
+async fn notifications(db: &Database) {
+    let user = create_user(db, "notify@example.com").await;
+    let other = create_user(db, "notify-other@example.com").await;
+    let mut created = Vec::new();
+    for title in ["first", "second", "third"] {
+        let notification =
+            NewNotification::new(user.id, NotificationCategory::General, title, "body");
+        created.push(db.create_notification(notification).await.unwrap());
+    }
+    db.create_notification(NewNotification::new(
+        other.id,
+        NotificationCategory::Security,
+        "theirs",
+        "body",
+    ))
+    .await
+    .unwrap();
+    let again = NewNotification::new(user.id, NotificationCategory::General, "again", "body")
+        .id(created[0].id);
+    let err = db.create_notification(again).await.unwrap_err();
+    assert!(matches!(err, AppError::Conflict(_)), "{err:?}");
+
+    assert_eq!(db.count_unread_notifications(user.id).await.unwrap(), 3);
+    assert!(!db
+        .mark_notification_read(other.id, created[2].id)
+        .await
+        .unwrap());
+    assert!(db
+        .mark_notification_read(user.id, created[2].id)
+        .await
+        .unwrap());
+    let read_at = db.list_notifications(user.id, 1, 10).await.unwrap().0[2].read_at;
+    assert!(db
+        .mark_notification_read(user.id, created[2].id)
+        .await
+        .unwrap());
+    assert_eq!(
+        db.list_notifications(user.id, 1, 10).await.unwrap().0[2].read_at,
+        read_at
+    );
+
+    // Unread first, newest first within each group.
+    let (page, total) = db.list_notifications(user.id, 1, 2).await.unwrap();
+    assert_eq!(total, 3);
+    let titles: Vec<&str> = page.iter().map(|n| n.title.as_str()).collect();
+    assert_eq!(titles, ["second", "first"]);
+    let (page, _) = db.list_notifications(user.id, 2, 2).await.unwrap();
+    assert_eq!(page[0].title, "third");
+    assert!(page[0].read_at.is_some());
+    let err = db
+        .list_notifications(user.id, i64::MAX, 2)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, AppError::Validation(_)), "{err:?}");
+
+    assert_eq!(db.mark_all_notifications_read(user.id).await.unwrap(), 2);
+    assert_eq!(db.count_unread_notifications(user.id).await.unwrap(), 0);
+    assert_eq!(db.count_unread_notifications(other.id).await.unwrap(), 1);
+
+    assert!(db
+        .notification_preferences(user.id)
+        .await
+        .unwrap()
+        .is_empty());
+    for channel in [
+        NotificationChannel::InAppAndEmail,
+        NotificationChannel::InApp,
+    ] {
+        db.set_notification_preference(user.id, NotificationCategory::General, channel)
+            .await
+            .unwrap();
+    }
+    assert_eq!(
+        db.notification_preferences(user.id).await.unwrap(),
+        [NotificationPreference {
+            category: NotificationCategory::General,
+            channel: NotificationChannel::InApp,
+        }]
+    );
+    assert!(db
+        .notification_preferences(other.id)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+fn new_file(owner_id: Uuid, key: &str) -> NewFile {
+    NewFile {
+        id: Uuid::new_v4(),
+        owner_id,
+        storage_key: key.to_string(),
+        content_type: "image/png".into(),
+        size_bytes: 42,
+        filename: Some("me.png".into()),
+    }
+}
+
+async fn files(db: &Database) {
+    let user = create_user(db, "files@example.com").await;
+    let first = db
+        .create_file(new_file(user.id, "avatars/1"))
+        .await
+        .unwrap();
+    assert_eq!(first.size_bytes, 42);
+    let err = db
+        .create_file(new_file(user.id, "avatars/1"))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, AppError::Conflict(_)), "{err:?}");
+    let second = db
+        .create_file(new_file(user.id, "avatars/2"))
+        .await
+        .unwrap();
+    assert_eq!(
+        db.find_file(first.id).await.unwrap().unwrap().storage_key,
+        "avatars/1"
+    );
+
+    assert!(db.find_avatar(user.id).await.unwrap().is_none());
+    assert_eq!(db.set_avatar(user.id, Some(first.id)).await.unwrap(), None);
+    assert_eq!(
+        db.set_avatar(user.id, Some(second.id)).await.unwrap(),
+        Some(first.id)
+    );
+    assert_eq!(
+        db.find_avatar(user.id).await.unwrap().unwrap().id,
+        second.id
+    );
+    let err = db.set_avatar(Uuid::new_v4(), None).await.unwrap_err();
+    assert!(matches!(err, AppError::NotFound), "{err:?}");
+
+    // Deleting the file clears the avatar that points at it.
+    assert!(db.delete_file(second.id).await.unwrap());
+    assert!(!db.delete_file(second.id).await.unwrap());
+    assert!(db.find_avatar(user.id).await.unwrap().is_none());
+    assert_eq!(db.set_avatar(user.id, None).await.unwrap(), None);
+}
+
+async fn outbox(db: &Database) {
+    let envelope = |user_id| {
+        EventEnvelope::new(
+            DomainEvent::LoggedIn { user_id },
+            &RequestContext::default(),
+        )
+    };
+    let first = envelope(Uuid::new_v4());
+    let second = envelope(Uuid::new_v4());
+    db.append_outbox(&first).await.unwrap();
+    db.append_outbox(&second).await.unwrap();
+    let err = db.append_outbox(&first).await.unwrap_err();
+    assert!(matches!(err, AppError::Conflict(_)), "{err:?}");
+
+    // Oldest first; claimed events are leased, so they are not handed out twice.
+    let claimed = db.claim_outbox(1).await.unwrap();
+    assert_eq!(claimed.len(), 1);
+    assert_eq!(claimed[0].envelope.id, first.id);
+    assert_eq!(claimed[0].attempts, 1);
+    assert!(claimed[0].delivered_to.is_empty());
+    let claimed = db.claim_outbox(10).await.unwrap();
+    assert_eq!(claimed.len(), 1);
+    assert_eq!(claimed[0].envelope.id, second.id);
+    assert!(db.claim_outbox(10).await.unwrap().is_empty());
+
+    db.mark_outbox_delivered(first.id, "audit").await.unwrap();
+    db.mark_outbox_delivered(first.id, "audit").await.unwrap();
+    db.retry_outbox(
+        first.id,
+        "webhooks failed",
+        Some(Utc::now() - Duration::seconds(1)),
+    )
+    .await
+    .unwrap();
+    let retried = db.claim_outbox(10).await.unwrap();
+    assert_eq!(retried.len(), 1);
+    assert_eq!(retried[0].envelope.id, first.id);
+    assert_eq!(retried[0].envelope.event, first.event);
+    assert_eq!(retried[0].attempts, 2);
+    assert_eq!(retried[0].delivered_to, ["audit"]);
+
+    db.complete_outbox(first.id).await.unwrap();
+    db.retry_outbox(second.id, "gave up", None).await.unwrap();
+    assert!(db.claim_outbox(10).await.unwrap().is_empty());
+}
+
+async fn transactions(db: &Database) {
+    let new_user = |email: &str| NewUser {
+        email: email.to_string(),
+        password_hash: "hash".into(),
+        role: UserRole::User,
+    };
+    let envelope = EventEnvelope::new(
+        DomainEvent::LoggedIn {
+            user_id: Uuid::new_v4(),
+        },
+        &RequestContext::default(),
+    );
+
+    // Reads inside see the transaction's own writes; everything lands on commit, including
+    // calls that open a transaction of their own.
+    let tx = db.begin().await.unwrap();
+    let ada = tx
+        .create_user(new_user("tx-ada@example.com"))
+        .await
+        .unwrap();
+    assert_eq!(
+        tx.find_by_id(ada.id).await.unwrap().unwrap().email,
+        ada.email
+    );
+    let token = RefreshToken::new(ada.id, "tx-hash".into(), Utc::now() + Duration::days(1));
+    tx.store_refresh_token(&token).await.unwrap();
+    let event = audit_event(AuditEventType::AuthLogin, Some(ada.id), Utc::now());
+    tx.log_event(event.clone()).await.unwrap();
+    tx.commit().await.unwrap();
+    assert!(db.find_by_id(ada.id).await.unwrap().is_some());
+    assert!(db.find_refresh_token("tx-hash").await.unwrap().is_some());
+    let err = db.log_event(event).await.unwrap_err();
+    assert!(matches!(err, AppError::Conflict(_)), "{err:?}");
+
+    // Dropping a transaction rolls back every call made through it.
+    let tx = db.begin().await.unwrap();
+    assert!(tx.delete_refresh_token(token.id).await.unwrap());
+    assert!(!tx.delete_refresh_token(token.id).await.unwrap());
+    let bob = tx
+        .create_user(new_user("tx-bob@example.com"))
+        .await
+        .unwrap();
+    tx.append_outbox(&envelope).await.unwrap();
+    drop(tx);
+    assert!(db.find_refresh_token("tx-hash").await.unwrap().is_some());
+    assert!(db.find_by_id(bob.id).await.unwrap().is_none());
+    assert!(db.claim_outbox(10).await.unwrap().is_empty());
+
+    // So does a failed call, once the transaction is dropped.
+    let tx = db.begin().await.unwrap();
+    tx.append_outbox(&envelope).await.unwrap();
+    let err = tx
+        .create_user(new_user("tx-ada@example.com"))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, AppError::Conflict(_)), "{err:?}");
+    drop(tx);
+    assert!(db.claim_outbox(10).await.unwrap().is_empty());
+}
+
+/// Reads inside `read_your_writes` go to the replica, here an empty schema of its own, except
+/// for the read-your-writes window after a write.
+#[tokio::test]
+async fn postgres_replica_routing() {
+    let Some(replica) = PgSchema::create().await else {
+        return;
+    };
+    for window in [0, 60] {
+        let primary = PgSchema::create_with(|config| {
+            config.replica_url = Some(replica.url.clone());
+            config.read_your_writes_seconds = window;
+        })
+        .await
+        .unwrap();
+        let db = &primary.database;
+        assert!(matches!(db.ping_replica().await, Some(Ok(()))));
+
+        let ada = create_user(db, "ada@example.com").await;
+        assert!(db.find_by_id(ada.id).await.unwrap().is_some());
+        let read = db::read_your_writes(async {
+            let before = db.find_by_id(ada.id).await.unwrap();
+            create_user(db, "bob@example.com").await;
+            let after = db.find_by_id(ada.id).await.unwrap();
+            (before, after)
+        })
+        .await;
+        assert!(read.0.is_none());
+        assert_eq!(read.1.is_some(), window > 0);
+        // Lookups that must see every write stay on the primary.
+        let token = RefreshToken::new(
+            ada.id,
+            "replica-hash".into(),
+            Utc::now() + Duration::days(1),
+        );
+        db.store_refresh_token(&token).await.unwrap();
+        let found = db::read_your_writes(db.find_refresh_token("replica-hash")).await;
+        assert_eq!(found.unwrap().map(|found| found.id), Some(token.id));
+
+        primary.drop().await;
+    }
+    replica.drop().await;
+}
+
+/// An unreachable replica is reported by `ping_replica`, and reads fall back to the primary.
+#[tokio::test]
+async fn postgres_replica_fallback() {
+    let Some(primary) = PgSchema::create_with(|config| {
+        config.replica_url = Some("postgres://postgres@127.0.0.1:1/unreachable".into());
+    })
+    .await
+    else {
+        return;
+    };
+    let db = &primary.database;
+    let ada = create_user(db, "ada@example.com").await;
+    let found = db::read_your_writes(db.find_by_id(ada.id)).await.unwrap();
+    assert_eq!(found.map(|found| found.id), Some(ada.id));
+    assert!(matches!(db.ping_replica().await, Some(Err(_))));
+    primary.drop().await;
+}
+
+/// An unreachable Postgres fails `connect` once its retries run out, while `connect_lazy`
+/// starts without it.
+#[tokio::test]
+async fn postgres_connect_retries() {
+    let config = DatabaseConfig {
+        url: "postgres://postgres@127.0.0.1:1/unreachable".into(),
+        connect_retries: 2,
+        connect_backoff_ms: 10,
+        acquire_timeout_seconds: 1,
+        ..DatabaseConfig::default()
+    };
+    let db = Database::connect_lazy(&config).await.unwrap();
+    assert!(db.ping().await.is_err());
+    assert!(Database::connect(&config).await.is_err());
+}
+
+async fn migrations(db: &Database) {
+    let statuses = db.migration_status().await.unwrap();
+    if db.is_memory() {
+        assert!(statuses.is_empty());
+        assert!(db.ensure_migrated().await.is_ok());
+        assert!(db.revert_migration().await.is_err());
+        return;
+    }
+    assert!(!statuses.is_empty());
+    assert!(statuses.iter().all(|m| m.state == MigrationState::Applied));
+    db.ensure_migrated().await.unwrap();
+
+    // Reverting the latest migration leaves it pending until the next run.
+    let latest = statuses.last().unwrap();
+    let reverted = db.revert_migration().await.unwrap().unwrap();
+    assert_eq!(reverted.version, latest.version);
+    let status = db.migration_status().await.unwrap();
+    assert_eq!(status.last().unwrap().state, MigrationState::Pending);
+    let err = db.ensure_migrated().await.unwrap_err();
+    assert!(matches!(err, AppError::Config(_)), "{err:?}");
+
+    // Every down migration undoes its up migration, so the schema can be rebuilt from scratch.
+    let mut remaining = statuses.len() - 1;
+    while let Some(reverted) = db.revert_migration().await.unwrap() {
+        assert_eq!(reverted.version, statuses[remaining - 1].version);
+        remaining -= 1;
+    }
+    assert_eq!(remaining, 0);
+    assert!(db
+        .migration_status()
+        .await
+        .unwrap()
+        .iter()
+        .all(|m| m.state == MigrationState::Pending));
+    db.migrate().await.unwrap();
+    db.ensure_migrated().await.unwrap();
+    create_user(db, "migrated@example.com").await;
+}
@@ -0,0 +1,353 @@
+//! Tamper evidence for the audit log. Events form one hash chain per UTC day: each event
+//! stores its sequence number, the previous event's hash and a hash over its own contents
+//! and that previous hash, so editing or deleting a row breaks every later link. Signed
+//! checkpoints pin chain heads, which also catches truncating the end of a day.
+
+use crate::ports::AuditChainRepository;
+use chrono::{DateTime, NaiveDate, Timelike, Utc};
+use hmac::{Hmac, Mac};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use shared::error::Result;
+use shared::types::AuditEvent;
+use std::fmt;
+use uuid::Uuid;
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditChainLink {
+    pub day: NaiveDate,
+    pub seq: i64,
+    pub prev_hash: String,
+    pub hash: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChainedAuditEvent {
+    pub event: AuditEvent,
+    pub link: AuditChainLink,
+}
+
+#[derive(Debug, Clone)]
+pub struct AuditCheckpoint {
+    pub id: Uuid,
+    pub day: NaiveDate,
+    pub seq: i64,
+    pub hash: String,
+    pub signature: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Postgres keeps microseconds, so events are truncated before hashing to hash what is stored.
+pub fn truncate_to_micros(ts: DateTime<Utc>) -> DateTime<Utc> {
+    let nanos = ts.nanosecond() / 1_000 * 1_000;
+    ts.with_nanosecond(nanos).unwrap_or(ts)
+}
+
+pub fn chain_day(created_at: DateTime<Utc>) -> NaiveDate {
+    created_at.date_naive()
+}
+
+/// The `prev_hash` of the first event of a day.
+pub fn genesis_hash(day: NaiveDate) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(format!("audit-chain:{day}").as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+pub fn event_hash(prev_hash: &str, seq: i64, event: &AuditEvent) -> String {
+    let mut hasher = Sha256::new();
+    let uuid = |id: Option<Uuid>| id.map(|id| id.to_string());
+    let fields = [
+        Some(prev_hash.to_string()),
+        Some(seq.to_string()),
+        Some(event.id.to_string()),
+        Some(
+            truncate_to_micros(event.created_at)
+                .timestamp_micros()
+                .to_string(),
+        ),
+        uuid(event.actor_id),
+        uuid(event.subject_id),
+        Some(event.event_type.clone()),
+        Some(event.severity.as_str().to_string()),
+        Some(canonical_json(&event.metadata)),
+        event.ip.clone(),
+        event.user_agent.clone(),
+        event.request_id.clone(),
+    ];
+    // Length-prefix every field so values cannot bleed into their neighbours.
+    for field in fields {
+        match field {
+            Some(value) => hasher.update(format!("{}:{value};", value.len()).as_bytes()),
+            None => hasher.update(b"-;"),
+        }
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// JSON with object keys sorted, so the hash survives a round trip through JSONB.
+fn canonical_json(value: &Value) -> String {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            let body = entries
+                .into_iter()
+                .map(|(key, value)| {
+                    format!("{}:{}", Value::String(key.clone()), canonical_json(value))
+                })
+                .collect::<Vec<_>>()
+                .join(",");
+            format!("{{{body}}}")
+        }
+        Value::Array(items) => {
+            let body = items
+                .iter()
+                .map(canonical_json)
+                .collect::<Vec<_>>()
+                .join(",");
+            format!("[{body}]")
+        }
+        other => other.to_string(),
+    }
+}
+
+/// Signs checkpoints with a secret kept outside the database, so whoever can rewrite
+/// `audit_log` cannot also forge the checkpoints that pin it.
+#[derive(Clone)]
+pub struct CheckpointSigner {
+    key: Vec<u8>,
+}
+
+impl CheckpointSigner {
+    pub fn new(secret: impl AsRef<[u8]>) -> Self {
+        Self {
+            key: secret.as_ref().to_vec(),
+        }
+    }
+
+    pub fn checkpoint(&self, head: &AuditChainLink) -> AuditCheckpoint {
+        AuditCheckpoint {
+            id: Uuid::new_v4(),
+            day: head.day,
+            seq: head.seq,
+            hash: head.hash.clone(),
+            signature: self.sign(head.day, head.seq, &head.hash),
+            created_at: Utc::now(),
+        }
+    }
+
+    pub fn verify(&self, checkpoint: &AuditCheckpoint) -> bool {
+        let Some(signature) = hex_decode(&checkpoint.signature) else {
+            return false;
+        };
+        self.mac(checkpoint.day, checkpoint.seq, &checkpoint.hash)
+            .verify_slice(&signature)
+            .is_ok()
+    }
+
+    fn sign(&self, day: NaiveDate, seq: i64, hash: &str) -> String {
+        format!("{:x}", self.mac(day, seq, hash).finalize().into_bytes())
+    }
+
+    fn mac(&self, day: NaiveDate, seq: i64, hash: &str) -> HmacSha256 {
+        let mut mac =
+            HmacSha256::new_from_slice(&self.key).expect("hmac accepts keys of any length");
+        mac.update(format!("audit-checkpoint:{day}:{seq}:{hash}").as_bytes());
+        mac
+    }
+}
+
+fn hex_decode(value: &str) -> Option<Vec<u8>> {
+    value
+        .as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            let pair = std::str::from_utf8(pair).ok().filter(|p| p.len() == 2)?;
+            u8::from_str_radix(pair, 16).ok()
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChainBreak {
+    /// Events are missing or duplicated before `found`.
+    SequenceGap {
+        day: NaiveDate,
+        expected: i64,
+        found: i64,
+    },
+    /// The event does not point at the hash of the event before it.
+    BrokenLink {
+        day: NaiveDate,
+        seq: i64,
+        event_id: Uuid,
+    },
+    /// The event's contents no longer match its stored hash.
+    ContentMismatch {
+        day: NaiveDate,
+        seq: i64,
+        event_id: Uuid,
+    },
+    /// A checkpoint's signature does not verify with the configured secret.
+    ForgedCheckpoint { day: NaiveDate, seq: i64 },
+    /// A checkpointed event is gone or no longer has the checkpointed hash.
+    CheckpointMismatch { day: NaiveDate, seq: i64 },
+}
+
+impl ChainBreak {
+    pub fn day(&self) -> NaiveDate {
+        match self {
+            ChainBreak::SequenceGap { day, .. }
+            | ChainBreak::BrokenLink { day, .. }
+            | ChainBreak::ContentMismatch { day, .. }
+            | ChainBreak::ForgedCheckpoint { day, .. }
+            | ChainBreak::CheckpointMismatch { day, .. } => *day,
+        }
+    }
+}
+
+impl fmt::Display for ChainBreak {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChainBreak::SequenceGap {
+                day,
+                expected,
+                found,
+            } => {
+                write!(
+                    f,
+                    "{day}: expected seq {expected} but found {found} (events deleted or inserted)"
+                )
+            }
+            ChainBreak::BrokenLink { day, seq, event_id } => {
+                write!(
+                    f,
+                    "{day}: seq {seq} (event {event_id}) does not link to the previous event"
+                )
+            }
+            ChainBreak::ContentMismatch { day, seq, event_id } => {
+                write!(
+                    f,
+                    "{day}: seq {seq} (event {event_id}) was modified after it was logged"
+                )
+            }
+            ChainBreak::ForgedCheckpoint { day, seq } => {
+                write!(f, "{day}: checkpoint at seq {seq} has an invalid signature")
+            }
+            ChainBreak::CheckpointMismatch { day, seq } => {
+                write!(
+                    f,
+                    "{day}: checkpointed event at seq {seq} is missing or altered"
+                )
+            }
+        }
+    }
+}
+
+/// Checks one day's chain, with `events` in `seq` order. Checkpoint signatures are only
+/// checked when a signer is given.
+pub fn verify_day(
+    day: NaiveDate,
+    events: &[ChainedAuditEvent],
+    checkpoints: &[AuditCheckpoint],
+    signer: Option<&CheckpointSigner>,
+) -> std::result::Result<(), ChainBreak> {
+    let mut prev_hash = genesis_hash(day);
+    for (index, chained) in events.iter().enumerate() {
+        let link = &chained.link;
+        let expected = index as i64 + 1;
+        if link.seq != expected {
+            return Err(ChainBreak::SequenceGap {
+                day,
+                expected,
+                found: link.seq,
+            });
+        }
+        if link.prev_hash != prev_hash {
+            return Err(ChainBreak::BrokenLink {
+                day,
+                seq: link.seq,
+                event_id: chained.event.id,
+            });
+        }
+        if event_hash(&link.prev_hash, link.seq, &chained.event) != link.hash {
+            return Err(ChainBreak::ContentMismatch {
+                day,
+                seq: link.seq,
+                event_id: chained.event.id,
+            });
+        }
+        prev_hash = link.hash.clone();
+    }
+
+    for checkpoint in checkpoints {
+        if signer.is_some_and(|signer| !signer.verify(checkpoint)) {
+            return Err(ChainBreak::ForgedCheckpoint {
+                day,
+                seq: checkpoint.seq,
+            });
+        }
+        let anchored = usize::try_from(checkpoint.seq - 1)
+            .ok()
+            .and_then(|index| events.get(index))
+            .is_some_and(|chained| chained.link.hash == checkpoint.hash);
+        if !anchored {
+            return Err(ChainBreak::CheckpointMismatch {
+                day,
+                seq: checkpoint.seq,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ChainReport {
+    pub days: usize,
+    pub events: usize,
+    pub checkpoints: usize,
+    pub unchained_events: i64,
+    pub first_break: Option<ChainBreak>,
+}
+
+/// Walks every day's chain, oldest first, and stops at the first break.
+pub async fn verify_chain<R>(repo: &R, signer: Option<&CheckpointSigner>) -> Result<ChainReport>
+where
+    R: AuditChainRepository + ?Sized,
+{
+    let mut report = ChainReport {
+        unchained_events: repo.count_unchained_events().await?,
+        ..ChainReport::default()
+    };
+
+    for day in repo.chain_days().await? {
+        let events = repo.chained_events(day).await?;
+        let checkpoints = repo.checkpoints(day).await?;
+        report.days += 1;
+        report.events += events.len();
+        report.checkpoints += checkpoints.len();
+
+        if let Err(chain_break) = verify_day(day, &events, &checkpoints, signer) {
+            report.first_break = Some(chain_break);
+            break;
+        }
+    }
+
+    Ok(report)
+}
+
+/// Signs and stores a checkpoint for every chain head that does not have one yet.
+pub async fn write_checkpoints<R>(repo: &R, signer: &CheckpointSigner) -> Result<usize>
+where
+    R: AuditChainRepository + ?Sized,
+{
+    let heads = repo.unanchored_heads().await?;
+    for head in &heads {
+        repo.store_checkpoint(&signer.checkpoint(head)).await?;
+    }
+    Ok(heads.len())
+}
@@ -1,12 +1,20 @@
-use crate::models::{AuditEventBuilder, NewUser, RefreshToken, User};
-use crate::ports::AuthRepo;
+use crate::events::{DomainEvent, EventEnvelope, OutboxPublisher};
+use crate::models::{
+    NewUser, PasswordService, PendingRegistration, RefreshToken, RefreshTokenHasher, User,
+};
+use crate::ports::{
+    AuthRepo, EventPublisher, PendingRegistrationRepository, RefreshTokenRepository,
+    RegistrationNotifier, Transaction, UserRepository,
+};
 use base64::Engine;
 use chrono::{Duration, Utc};
 use rand::RngCore;
-use shared::dto::{LoginRequest, RegisterRequest};
+use shared::dto::{ChangePasswordRequest, LoginRequest, RegisterRequest};
 use shared::error::{AppError, Result};
-use shared::types::UserRole;
-use std::sync::Arc;
+use shared::types::{RequestContext, UserRole};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 use validator::Validate;
 
 #[derive(Clone)]
@@ -15,17 +23,80 @@ where
     R: AuthRepo,
 {
     repo: Arc<R>,
+    refresh_hasher: RefreshTokenHasher,
+    notifier: Option<Arc<dyn RegistrationNotifier>>,
+    events: Arc<dyn EventPublisher>,
+    rotations: Arc<RecentRotations>,
+    rotation_grace: std::time::Duration,
+}
+
+/// Successors of recently rotated refresh tokens, by the hash of the token they replaced.
+/// Held in memory, so only requests that reach the same process share a successor.
+#[derive(Default)]
+struct RecentRotations {
+    successors: Mutex<HashMap<String, (String, Instant)>>,
+}
+
+impl RecentRotations {
+    fn remember(&self, replaced_hash: String, successor: &str, grace: std::time::Duration) {
+        let now = Instant::now();
+        let mut successors = self.successors.lock().expect("rotation cache poisoned");
+        successors.retain(|_, (_, until)| *until > now);
+        successors.insert(replaced_hash, (successor.to_string(), now + grace));
+    }
+
+    fn successor(&self, replaced_hash: &str) -> Option<String> {
+        self.successors
+            .lock()
+            .expect("rotation cache poisoned")
+            .get(replaced_hash)
+            .filter(|(_, until)| *until > Instant::now())
+            .map(|(successor, _)| successor.clone())
+    }
 }
 
 impl<R> AuthService<R>
 where
     R: AuthRepo,
 {
-    pub fn new(repo: Arc<R>) -> Self {
-        Self { repo }
+    pub fn new(repo: Arc<R>, refresh_hasher: RefreshTokenHasher) -> Self {
+        // Pay for the dummy hash up front so the first unknown-email login is not slower.
+        let _ = PasswordService::dummy_hash();
+        Self {
+            events: Arc::new(OutboxPublisher::new(repo.clone())),
+            repo,
+            refresh_hasher,
+            notifier: None,
+            rotations: Arc::default(),
+            rotation_grace: std::time::Duration::ZERO,
+        }
     }
 
-    pub async fn register(&self, input: RegisterRequest, role: Option<UserRole>) -> Result<User> {
+    /// Lets a rotated refresh token yield its successor for `grace`, so that parallel
+    /// requests carrying it, such as page loads renewing an expired session, end up with
+    /// the same new session instead of all but one failing. Off by default.
+    pub fn with_rotation_grace(mut self, grace: std::time::Duration) -> Self {
+        self.rotation_grace = grace;
+        self
+    }
+
+    /// Replaces the default publisher, which only appends events to the outbox.
+    pub fn with_publisher(mut self, events: Arc<dyn EventPublisher>) -> Self {
+        self.events = events;
+        self
+    }
+
+    pub fn with_notifier(mut self, notifier: Arc<dyn RegistrationNotifier>) -> Self {
+        self.notifier = Some(notifier);
+        self
+    }
+
+    pub async fn register(
+        &self,
+        input: RegisterRequest,
+        role: Option<UserRole>,
+        ctx: &RequestContext,
+    ) -> Result<User> {
         input.validate()?;
 
         if let Some(existing) = self.repo.find_by_email(&input.email).await? {
@@ -35,39 +106,131 @@ where
             )));
         }
 
-        let password_hash = crate::models::PasswordService::hash(&input.password)?;
+        let password_hash = PasswordService::hash(&input.password)?;
         let new_user = NewUser {
             email: input.email,
             password_hash,
             role: role.unwrap_or_default(),
         };
 
-        let user = self.repo.create_user(new_user).await?;
-        let event = AuditEventBuilder::new("auth.register")
-            .user_id(Some(user.id))
-            .build();
-        let _ = self.repo.log_event(event).await;
+        let tx = self.repo.begin().await?;
+        let user = tx.create_user(new_user).await?;
+        self.publish_in(
+            &tx,
+            DomainEvent::UserRegistered {
+                user_id: user.id,
+                email: user.email.clone(),
+            },
+            ctx,
+        )
+        .await?;
+        self.commit(tx).await?;
 
         Ok(user)
     }
 
-    pub async fn login(&self, input: LoginRequest) -> Result<User> {
+    pub async fn login(&self, input: LoginRequest, ctx: &RequestContext) -> Result<User> {
         input.validate()?;
 
-        let user = self
-            .repo
-            .find_by_email(&input.email)
+        let Some(user) = self.repo.find_by_email(&input.email).await? else {
+            PasswordService::verify_dummy(&input.password);
+            self.log_login_failure(None, "unknown_email", ctx).await;
+            return Err(AppError::Unauthorized);
+        };
+
+        if !PasswordService::verify(&user.password_hash, &input.password)? {
+            self.log_login_failure(Some(user.id), "bad_password", ctx)
+                .await;
+            return Err(AppError::Unauthorized);
+        }
+
+        self.publish(DomainEvent::LoggedIn { user_id: user.id }, ctx)
+            .await;
+
+        Ok(user)
+    }
+
+    /// Starts an `EmailConfirmation` sign-up. The result is the same whether or not the
+    /// email is taken: the password is always hashed, the same rows are written and the
+    /// outcome goes out by email.
+    pub async fn request_registration(
+        &self,
+        input: RegisterRequest,
+        ttl_minutes: i64,
+        ctx: &RequestContext,
+    ) -> Result<()> {
+        input.validate()?;
+
+        let notifier = self
+            .notifier
+            .as_ref()
+            .ok_or_else(|| AppError::Unavailable("registration email is not configured".into()))?;
+        let password_hash = PasswordService::hash(&input.password)?;
+        let taken = self.repo.find_by_email(&input.email).await?.is_some();
+
+        // A taken email still gets a pending registration, whose token is never sent and so
+        // can never be confirmed, so that its timing does not give it away.
+        let raw_token = generate_signup_token();
+        let now = Utc::now();
+        let expires_at = now
+            .checked_add_signed(Duration::minutes(ttl_minutes))
+            .ok_or_else(|| AppError::Internal("failed to compute signup token expiry".into()))?;
+        let pending = PendingRegistration {
+            id: uuid::Uuid::new_v4(),
+            email: input.email,
+            password_hash,
+            role: UserRole::default(),
+            token_hash: PendingRegistration::hash_token(&raw_token),
+            expires_at,
+            created_at: now,
+        };
+        self.repo.store_pending_registration(&pending).await?;
+
+        self.publish(DomainEvent::RegistrationRequested, ctx).await;
+
+        if taken {
+            if let Err(err) = notifier.send_existing_account_notice(&pending.email).await {
+                tracing::warn!(error = %err, "failed to send existing account notice");
+            }
+        } else if let Err(err) = notifier.send_signup_link(&pending.email, &raw_token).await {
+            tracing::warn!(error = %err, "failed to send signup link");
+        }
+        Ok(())
+    }
+
+    pub async fn confirm_registration(
+        &self,
+        raw_token: &str,
+        ctx: &RequestContext,
+    ) -> Result<User> {
+        let token_hash = PendingRegistration::hash_token(raw_token);
+        let tx = self.repo.begin().await?;
+        let pending = tx
+            .take_pending_registration(&token_hash)
             .await?
             .ok_or(AppError::Unauthorized)?;
 
-        if !crate::models::PasswordService::verify(&user.password_hash, &input.password)? {
+        if pending.expires_at < Utc::now() {
             return Err(AppError::Unauthorized);
         }
 
-        let event = AuditEventBuilder::new("auth.login")
-            .user_id(Some(user.id))
-            .build();
-        let _ = self.repo.log_event(event).await;
+        let user = tx
+            .create_user(NewUser {
+                email: pending.email,
+                password_hash: pending.password_hash,
+                role: pending.role,
+            })
+            .await?;
+        self.publish_in(
+            &tx,
+            DomainEvent::UserRegistered {
+                user_id: user.id,
+                email: user.email.clone(),
+            },
+            ctx,
+        )
+        .await?;
+        self.commit(tx).await?;
 
         Ok(user)
     }
@@ -77,20 +240,31 @@ where
         user_id: uuid::Uuid,
         refresh_token: &str,
         ttl_days: i64,
+    ) -> Result<RefreshToken> {
+        let token = self.new_refresh_token(user_id, refresh_token, ttl_days)?;
+        self.repo.store_refresh_token(&token).await?;
+        Ok(token)
+    }
+
+    fn new_refresh_token(
+        &self,
+        user_id: uuid::Uuid,
+        refresh_token: &str,
+        ttl_days: i64,
     ) -> Result<RefreshToken> {
         let expires_at = Utc::now()
             .checked_add_signed(Duration::days(ttl_days))
             .ok_or_else(|| AppError::Internal("failed to compute refresh token expiry".into()))?;
-        let token = RefreshToken::from_raw(user_id, refresh_token, expires_at);
-        self.repo.store_refresh_token(&token).await?;
-        Ok(token)
+        Ok(RefreshToken::new(
+            user_id,
+            self.refresh_hasher.hash(refresh_token),
+            expires_at,
+        ))
     }
 
     pub async fn validate_refresh_token(&self, raw_token: &str) -> Result<RefreshToken> {
-        let token_hash = RefreshToken::hash(raw_token);
         let token = self
-            .repo
-            .find_refresh_token(&token_hash)
+            .find_refresh_token(raw_token)
             .await?
             .ok_or(AppError::Unauthorized)?;
 
@@ -102,20 +276,235 @@ where
         Ok(token)
     }
 
-    pub async fn revoke_all(&self, user_id: uuid::Uuid) -> Result<()> {
-        self.repo.delete_tokens_for_user(user_id).await
+    /// Swaps a valid refresh token for a freshly stored one; the old token stops working.
+    /// Both happen in one transaction, and of two requests racing with the same token
+    /// only the first to delete it gets a new one. Within the rotation grace the others
+    /// get that same one, as does any replay of the old token. Returns the new raw token
+    /// and the session it carries on.
+    pub async fn rotate_refresh_token(
+        &self,
+        raw_token: &str,
+        ttl_days: i64,
+        ctx: &RequestContext,
+    ) -> Result<(User, String, uuid::Uuid)> {
+        let token = match self.validate_refresh_token(raw_token).await {
+            Ok(token) => token,
+            Err(AppError::Unauthorized) => return self.successor_of(raw_token).await,
+            Err(err) => return Err(err),
+        };
+
+        let tx = self.repo.begin().await?;
+        if !tx.delete_refresh_token(token.id).await? {
+            drop(tx);
+            return self.successor_of(raw_token).await;
+        }
+
+        let user = tx
+            .find_by_id(token.user_id)
+            .await?
+            .ok_or(AppError::Unauthorized)?;
+
+        let refresh_raw = generate_refresh_token();
+        let refresh = self
+            .new_refresh_token(user.id, &refresh_raw, ttl_days)?
+            .in_session(token.session_id);
+        tx.store_refresh_token(&refresh).await?;
+
+        self.publish_in(&tx, DomainEvent::TokenRefreshed { user_id: user.id }, ctx)
+            .await?;
+        // Before committing, so that a request which loses the race just after finds it.
+        if !self.rotation_grace.is_zero() {
+            self.rotations.remember(
+                self.refresh_hasher.hash(raw_token),
+                &refresh_raw,
+                self.rotation_grace,
+            );
+        }
+        self.commit(tx).await?;
+
+        Ok((user, refresh_raw, refresh.session_id))
+    }
+
+    /// The token `raw_token` was recently rotated to, while that one is still valid.
+    async fn successor_of(&self, raw_token: &str) -> Result<(User, String, uuid::Uuid)> {
+        let successor = self
+            .rotations
+            .successor(&self.refresh_hasher.hash(raw_token))
+            .ok_or(AppError::Unauthorized)?;
+        let token = self.validate_refresh_token(&successor).await?;
+        let user = self
+            .repo
+            .find_by_id(token.user_id)
+            .await?
+            .ok_or(AppError::Unauthorized)?;
+        Ok((user, successor, token.session_id))
     }
 
-    pub async fn logout(&self, raw_token: &str) -> Result<()> {
-        let token_hash = RefreshToken::hash(raw_token);
-        if let Some(token) = self.repo.find_refresh_token(&token_hash).await? {
-            let _ = self.repo.delete_refresh_token(token.id).await?;
+    /// Ends every session of the user, as logging out everywhere does.
+    pub async fn revoke_all(&self, user_id: uuid::Uuid, ctx: &RequestContext) -> Result<()> {
+        let tx = self.repo.begin().await?;
+        tx.delete_tokens_for_user(user_id).await?;
+        self.publish_in(&tx, DomainEvent::SessionsRevoked { user_id }, ctx)
+            .await?;
+        self.commit(tx).await
+    }
+
+    pub async fn logout(&self, raw_token: &str, ctx: &RequestContext) -> Result<()> {
+        if let Some(token) = self.find_refresh_token(raw_token).await? {
+            let tx = self.repo.begin().await?;
+            tx.delete_refresh_token(token.id).await?;
+
+            self.publish_in(
+                &tx,
+                DomainEvent::LoggedOut {
+                    user_id: token.user_id,
+                    session_id: Some(token.session_id),
+                },
+                ctx,
+            )
+            .await?;
+            self.commit(tx).await?;
         }
         Ok(())
     }
+
+    /// Changes another user's role and ends their sessions so the new role applies on
+    /// their next login.
+    pub async fn change_role(
+        &self,
+        admin: &User,
+        user_id: uuid::Uuid,
+        role: UserRole,
+        ctx: &RequestContext,
+    ) -> Result<User> {
+        if admin.id == user_id {
+            return Err(AppError::Validation(
+                "admins cannot change their own role".into(),
+            ));
+        }
+        let user = self
+            .repo
+            .find_by_id(user_id)
+            .await?
+            .ok_or(AppError::NotFound)?;
+        if user.role == role {
+            return Ok(user);
+        }
+        let tx = self.repo.begin().await?;
+        let updated = tx
+            .update_role(user_id, role)
+            .await?
+            .ok_or(AppError::NotFound)?;
+        tx.delete_tokens_for_user(user_id).await?;
+
+        self.publish_in(
+            &tx,
+            DomainEvent::RoleChanged {
+                actor_id: admin.id,
+                user_id,
+                email: updated.email.clone(),
+                from: user.role,
+                to: role,
+            },
+            ctx,
+        )
+        .await?;
+        self.commit(tx).await?;
+        Ok(updated)
+    }
+
+    /// Changes the user's password after checking the current one, and ends all of their
+    /// sessions.
+    pub async fn change_password(
+        &self,
+        user: &User,
+        input: ChangePasswordRequest,
+        ctx: &RequestContext,
+    ) -> Result<()> {
+        input.validate()?;
+        if !PasswordService::verify(&user.password_hash, &input.current_password)? {
+            return Err(AppError::Unauthorized);
+        }
+
+        let password_hash = PasswordService::hash(&input.new_password)?;
+        let tx = self.repo.begin().await?;
+        tx.update_password_hash(user.id, &password_hash).await?;
+        tx.delete_tokens_for_user(user.id).await?;
+
+        self.publish_in(
+            &tx,
+            DomainEvent::PasswordChanged {
+                user_id: user.id,
+                email: user.email.clone(),
+            },
+            ctx,
+        )
+        .await?;
+        self.commit(tx).await
+    }
+
+    /// For events outside a transaction, which report something that has already happened,
+    /// so failing to record one is logged instead of failing the request.
+    async fn publish(&self, event: DomainEvent, ctx: &RequestContext) {
+        let event_type = event.audit_type();
+        if let Err(err) = self.events.publish(EventEnvelope::new(event, ctx)).await {
+            tracing::warn!(
+                error = %err,
+                event = event_type.as_str(),
+                "failed to publish domain event"
+            );
+        }
+    }
+
+    async fn publish_in(
+        &self,
+        tx: &R::Transaction,
+        event: DomainEvent,
+        ctx: &RequestContext,
+    ) -> Result<()> {
+        self.events
+            .publish_in(tx, EventEnvelope::new(event, ctx))
+            .await
+    }
+
+    async fn commit(&self, tx: R::Transaction) -> Result<()> {
+        tx.commit().await?;
+        self.events.committed();
+        Ok(())
+    }
+
+    async fn log_login_failure(
+        &self,
+        user_id: Option<uuid::Uuid>,
+        reason: &str,
+        ctx: &RequestContext,
+    ) {
+        let event = DomainEvent::LoginFailed {
+            user_id,
+            reason: reason.to_string(),
+        };
+        self.publish(event, ctx).await
+    }
+
+    async fn find_refresh_token(&self, raw_token: &str) -> Result<Option<RefreshToken>> {
+        for token_hash in self.refresh_hasher.candidates(raw_token) {
+            if let Some(token) = self.repo.find_refresh_token(&token_hash).await? {
+                return Ok(Some(token));
+            }
+        }
+        Ok(None)
+    }
 }
 
 pub fn generate_refresh_token() -> String {
+    random_token()
+}
+
+pub fn generate_signup_token() -> String {
+    random_token()
+}
+
+fn random_token() -> String {
     let mut buf = [0u8; 32];
     rand::thread_rng().fill_bytes(&mut buf);
     base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(buf)
@@ -0,0 +1,277 @@
+use crate::flags::FeatureFlag;
+use crate::jobs::retry_backoff;
+use crate::models::{AuditEventBuilder, AuditEventType};
+use crate::ports::{AuditLogRepository, EventPublisher, OutboxRepository};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use shared::error::{AppError, Result};
+use shared::types::{AuditEvent, RequestContext, UserRole};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Something that happened in the domain. Subscribers turn these into audit entries,
+/// metrics, emails and webhook deliveries.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DomainEvent {
+    UserRegistered {
+        user_id: Uuid,
+        email: String,
+    },
+    /// An `EmailConfirmation` sign-up started. Carries nothing that would reveal whether
+    /// the address was already taken.
+    RegistrationRequested,
+    LoggedIn {
+        user_id: Uuid,
+    },
+    LoginFailed {
+        user_id: Option<Uuid>,
+        reason: String,
+    },
+    LoggedOut {
+        user_id: Uuid,
+        /// The session that ended. Missing from events recorded before sessions had ids.
+        #[serde(default)]
+        session_id: Option<Uuid>,
+    },
+    TokenRefreshed {
+        user_id: Uuid,
+    },
+    PasswordChanged {
+        user_id: Uuid,
+        email: String,
+    },
+    /// Every session of the user was ended, without changing their password or role.
+    SessionsRevoked {
+        user_id: Uuid,
+    },
+    RoleChanged {
+        actor_id: Uuid,
+        user_id: Uuid,
+        email: String,
+        from: UserRole,
+        to: UserRole,
+    },
+    /// `flag` is the flag as saved, or `None` when it was deleted.
+    FeatureFlagChanged {
+        actor_id: Option<Uuid>,
+        key: String,
+        flag: Option<FeatureFlag>,
+    },
+}
+
+impl DomainEvent {
+    pub fn audit_type(&self) -> AuditEventType {
+        match self {
+            DomainEvent::UserRegistered { .. } => AuditEventType::AuthRegister,
+            DomainEvent::RegistrationRequested => AuditEventType::AuthRegisterRequested,
+            DomainEvent::LoggedIn { .. } => AuditEventType::AuthLogin,
+            DomainEvent::LoginFailed { .. } => AuditEventType::AuthLoginFailed,
+            DomainEvent::LoggedOut { .. } => AuditEventType::AuthLogout,
+            DomainEvent::TokenRefreshed { .. } => AuditEventType::TokenRefresh,
+            DomainEvent::PasswordChanged { .. } => AuditEventType::AuthPasswordChanged,
+            DomainEvent::SessionsRevoked { .. } => AuditEventType::AuthSessionsRevoked,
+            DomainEvent::RoleChanged { .. } => AuditEventType::UserRoleChanged,
+            DomainEvent::FeatureFlagChanged { .. } => AuditEventType::FeatureFlagChanged,
+        }
+    }
+}
+
+/// A domain event with the identity, time and request it happened in.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventEnvelope {
+    pub id: Uuid,
+    pub occurred_at: DateTime<Utc>,
+    pub context: RequestContext,
+    pub event: DomainEvent,
+}
+
+impl EventEnvelope {
+    pub fn new(event: DomainEvent, context: &RequestContext) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            occurred_at: Utc::now(),
+            context: context.clone(),
+            event,
+        }
+    }
+
+    /// The audit entry for this event. It shares the envelope's id, so recording it twice
+    /// is detectable.
+    pub fn audit_event(&self) -> AuditEvent {
+        let builder = AuditEventBuilder::new(self.event.audit_type()).context(&self.context);
+        let builder = match &self.event {
+            DomainEvent::UserRegistered { user_id, .. }
+            | DomainEvent::LoggedIn { user_id }
+            | DomainEvent::LoggedOut { user_id, .. }
+            | DomainEvent::TokenRefreshed { user_id }
+            | DomainEvent::PasswordChanged { user_id, .. }
+            | DomainEvent::SessionsRevoked { user_id } => builder.user(*user_id),
+            DomainEvent::RegistrationRequested => builder,
+            DomainEvent::LoginFailed { user_id, reason } => builder
+                .subject(*user_id)
+                .metadata(serde_json::json!({ "reason": reason })),
+            DomainEvent::RoleChanged {
+                actor_id,
+                user_id,
+                from,
+                to,
+                ..
+            } => builder
+                .actor(Some(*actor_id))
+                .subject(Some(*user_id))
+                .metadata(serde_json::json!({ "from": from, "to": to })),
+            DomainEvent::FeatureFlagChanged {
+                actor_id,
+                key,
+                flag,
+            } => builder
+                .actor(*actor_id)
+                .metadata(serde_json::json!({ "key": key, "flag": flag })),
+        };
+        let mut event = builder.build();
+        event.id = self.id;
+        event.created_at = self.occurred_at;
+        event
+    }
+}
+
+/// Handles published events after they are safely in the outbox. Each subscriber sees
+/// each event at least once; a subscriber that fails is retried without re-running the
+/// ones that succeeded.
+#[async_trait]
+pub trait EventSubscriber: Send + Sync {
+    /// Stable name used to record which subscribers have handled an event.
+    fn name(&self) -> &'static str;
+    async fn handle(&self, envelope: &EventEnvelope) -> Result<()>;
+}
+
+/// An outbox row claimed for dispatch.
+#[derive(Debug, Clone)]
+pub struct OutboxEntry {
+    pub envelope: EventEnvelope,
+    pub attempts: i32,
+    /// Subscribers that already handled the event on an earlier attempt.
+    pub delivered_to: Vec<String>,
+}
+
+/// Publishes by appending to the outbox; a dispatcher hands the events to subscribers.
+pub struct OutboxPublisher<R> {
+    repo: Arc<R>,
+}
+
+impl<R> OutboxPublisher<R> {
+    pub fn new(repo: Arc<R>) -> Self {
+        Self { repo }
+    }
+}
+
+#[async_trait]
+impl<R> EventPublisher for OutboxPublisher<R>
+where
+    R: OutboxRepository + 'static,
+{
+    async fn publish(&self, envelope: EventEnvelope) -> Result<()> {
+        self.repo.append_outbox(&envelope).await
+    }
+}
+
+/// Outbox events are given up on after this many dispatch attempts.
+pub const MAX_DISPATCH_ATTEMPTS: i32 = 10;
+
+/// The subscribers registered at startup.
+#[derive(Clone, Default)]
+pub struct EventBus {
+    subscribers: Vec<Arc<dyn EventSubscriber>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn subscribe(mut self, subscriber: Arc<dyn EventSubscriber>) -> Self {
+        self.subscribers.push(subscriber);
+        self
+    }
+
+    /// Claims up to `limit` due outbox events and runs the subscribers that have not
+    /// handled each one yet. Returns how many events were claimed.
+    pub async fn dispatch_batch<R>(&self, repo: &R, limit: i64) -> Result<usize>
+    where
+        R: OutboxRepository + ?Sized,
+    {
+        let mut entries = repo.claim_outbox(limit).await?;
+        entries.sort_by_key(|entry| entry.envelope.occurred_at);
+        let claimed = entries.len();
+
+        for entry in entries {
+            let id = entry.envelope.id;
+            let mut failure = None;
+            for subscriber in &self.subscribers {
+                if entry
+                    .delivered_to
+                    .iter()
+                    .any(|name| name == subscriber.name())
+                {
+                    continue;
+                }
+                match subscriber.handle(&entry.envelope).await {
+                    Ok(()) => repo.mark_outbox_delivered(id, subscriber.name()).await?,
+                    Err(err) => {
+                        tracing::warn!(
+                            event_id = %id,
+                            subscriber = subscriber.name(),
+                            error = %err,
+                            "event subscriber failed"
+                        );
+                        failure.get_or_insert_with(|| format!("{}: {err}", subscriber.name()));
+                    }
+                }
+            }
+
+            match failure {
+                None => repo.complete_outbox(id).await?,
+                Some(error) => {
+                    let retry_at = (entry.attempts < MAX_DISPATCH_ATTEMPTS)
+                        .then(|| Utc::now() + retry_backoff(entry.attempts));
+                    if retry_at.is_none() {
+                        tracing::error!(event_id = %id, error = %error, "giving up on outbox event");
+                    }
+                    repo.retry_outbox(id, &error, retry_at).await?;
+                }
+            }
+        }
+        Ok(claimed)
+    }
+}
+
+/// Records every event in the audit log.
+pub struct AuditSubscriber<R> {
+    repo: Arc<R>,
+}
+
+impl<R> AuditSubscriber<R> {
+    pub fn new(repo: Arc<R>) -> Self {
+        Self { repo }
+    }
+}
+
+#[async_trait]
+impl<R> EventSubscriber for AuditSubscriber<R>
+where
+    R: AuditLogRepository + 'static,
+{
+    fn name(&self) -> &'static str {
+        "audit"
+    }
+
+    async fn handle(&self, envelope: &EventEnvelope) -> Result<()> {
+        match self.repo.log_event(envelope.audit_event()).await {
+            // Already recorded by an attempt that failed before it was marked delivered.
+            Err(AppError::Conflict(_)) => Ok(()),
+            other => other,
+        }
+    }
+}
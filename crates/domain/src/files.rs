@@ -0,0 +1,33 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// An uploaded file. The bytes are in object storage under `storage_key`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredFile {
+    pub id: Uuid,
+    pub owner_id: Uuid,
+    pub storage_key: String,
+    /// Sniffed from the content when it was uploaded, not taken from the client.
+    pub content_type: String,
+    pub size_bytes: i64,
+    /// Name the client gave the file, for `Content-Disposition`.
+    pub filename: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl StoredFile {
+    pub fn is_image(&self) -> bool {
+        self.content_type.starts_with("image/")
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NewFile {
+    pub id: Uuid,
+    pub owner_id: Uuid,
+    pub storage_key: String,
+    pub content_type: String,
+    pub size_bytes: i64,
+    pub filename: Option<String>,
+}
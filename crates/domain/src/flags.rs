@@ -0,0 +1,242 @@
+use crate::events::{DomainEvent, EventEnvelope, OutboxPublisher};
+use crate::models::User;
+use crate::ports::{
+    EventPublisher, FeatureFlagRepository, OutboxRepository, Transaction, UnitOfWork,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use shared::error::{AppError, Result};
+use shared::types::{RequestContext, UserRole};
+use std::sync::Arc;
+use uuid::Uuid;
+
+const MAX_KEY_LEN: usize = 64;
+
+/// A feature switch. `enabled` is the kill switch; when it is on, the flag is on for
+/// targeted users, roles and orgs, and for `rollout_percentage` percent of everyone else.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FeatureFlag {
+    pub key: String,
+    pub description: Option<String>,
+    pub enabled: bool,
+    pub rollout_percentage: u8,
+    pub users: Vec<Uuid>,
+    pub roles: Vec<UserRole>,
+    pub orgs: Vec<String>,
+    pub updated_by: Option<Uuid>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl FeatureFlag {
+    /// A new flag: off, with nobody targeted.
+    pub fn new(key: &str) -> Result<Self> {
+        validate_key(key)?;
+        Ok(Self {
+            key: key.to_string(),
+            description: None,
+            enabled: false,
+            rollout_percentage: 0,
+            users: Vec::new(),
+            roles: Vec::new(),
+            orgs: Vec::new(),
+            updated_by: None,
+            updated_at: Utc::now(),
+        })
+    }
+
+    pub fn evaluate(&self, ctx: &FlagContext) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        let targeted = ctx.user_id.is_some_and(|id| self.users.contains(&id))
+            || ctx.role.is_some_and(|role| self.roles.contains(&role))
+            || ctx.org.as_ref().is_some_and(|org| self.orgs.contains(org));
+        if targeted || self.rollout_percentage >= 100 {
+            return true;
+        }
+        // Anonymous visitors have no stable bucket, so partial rollouts skip them.
+        match ctx.user_id {
+            Some(user_id) => {
+                rollout_bucket(&self.key, user_id) < u32::from(self.rollout_percentage)
+            }
+            None => false,
+        }
+    }
+}
+
+/// Who a flag is evaluated for.
+#[derive(Debug, Clone, Default)]
+pub struct FlagContext {
+    pub user_id: Option<Uuid>,
+    pub role: Option<UserRole>,
+    /// Organisation the user acts for, for deployments that have them. Users carry no org
+    /// yet, so callers set it themselves.
+    pub org: Option<String>,
+}
+
+impl FlagContext {
+    pub fn anonymous() -> Self {
+        Self::default()
+    }
+
+    pub fn for_user(user: &User) -> Self {
+        Self {
+            user_id: Some(user.id),
+            role: Some(user.role),
+            org: None,
+        }
+    }
+
+    pub fn org(mut self, org: impl Into<String>) -> Self {
+        self.org = Some(org.into());
+        self
+    }
+}
+
+/// The user's rollout bucket for `key`, 0-99. Stable for a user and flag, and independent
+/// between flags, so raising the percentage only ever adds users.
+pub fn rollout_bucket(key: &str, user_id: Uuid) -> u32 {
+    let digest = Sha256::new()
+        .chain_update(key.as_bytes())
+        .chain_update(b":")
+        .chain_update(user_id.as_bytes())
+        .finalize();
+    let prefix = u64::from_be_bytes(digest[..8].try_into().expect("digest has 32 bytes"));
+    (prefix % 100) as u32
+}
+
+pub fn validate_key(key: &str) -> Result<()> {
+    let valid = !key.is_empty()
+        && key.len() <= MAX_KEY_LEN
+        && key
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '_' | '-' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(AppError::Validation(
+            "flag keys are 1-64 lowercase letters, digits, `_`, `-` or `.`".into(),
+        ))
+    }
+}
+
+/// Changes flags and publishes a `FeatureFlagChanged` event for each change, in the same
+/// transaction, which the audit subscriber records.
+pub struct FeatureFlagService<R> {
+    repo: Arc<R>,
+    events: Arc<dyn EventPublisher>,
+}
+
+impl<R> FeatureFlagService<R>
+where
+    R: FeatureFlagRepository + OutboxRepository + UnitOfWork + Send + Sync + 'static,
+    R::Transaction: FeatureFlagRepository,
+{
+    pub fn new(repo: Arc<R>) -> Self {
+        Self {
+            events: Arc::new(OutboxPublisher::new(repo.clone())),
+            repo,
+        }
+    }
+
+    pub fn with_publisher(mut self, events: Arc<dyn EventPublisher>) -> Self {
+        self.events = events;
+        self
+    }
+
+    pub async fn list(&self) -> Result<Vec<FeatureFlag>> {
+        self.repo.list_flags().await
+    }
+
+    pub async fn find(&self, key: &str) -> Result<Option<FeatureFlag>> {
+        self.repo.find_flag(key).await
+    }
+
+    /// Creates or replaces the flag. `actor_id` is `None` for changes made from the CLI.
+    pub async fn save(
+        &self,
+        mut flag: FeatureFlag,
+        actor_id: Option<Uuid>,
+        ctx: &RequestContext,
+    ) -> Result<FeatureFlag> {
+        validate_key(&flag.key)?;
+        if flag.rollout_percentage > 100 {
+            return Err(AppError::Validation(
+                "rollout_percentage must be between 0 and 100".into(),
+            ));
+        }
+        flag.updated_by = actor_id;
+        let tx = self.repo.begin().await?;
+        let saved = tx.upsert_flag(&flag).await?;
+        self.publish_in(
+            &tx,
+            DomainEvent::FeatureFlagChanged {
+                actor_id,
+                key: saved.key.clone(),
+                flag: Some(saved.clone()),
+            },
+            ctx,
+        )
+        .await?;
+        self.commit(tx).await?;
+        Ok(saved)
+    }
+
+    /// Applies `change` to the existing flag, or to a new one that starts off.
+    pub async fn update(
+        &self,
+        key: &str,
+        actor_id: Option<Uuid>,
+        ctx: &RequestContext,
+        change: impl FnOnce(&mut FeatureFlag),
+    ) -> Result<FeatureFlag> {
+        let mut flag = match self.repo.find_flag(key).await? {
+            Some(flag) => flag,
+            None => FeatureFlag::new(key)?,
+        };
+        change(&mut flag);
+        self.save(flag, actor_id, ctx).await
+    }
+
+    pub async fn delete(
+        &self,
+        key: &str,
+        actor_id: Option<Uuid>,
+        ctx: &RequestContext,
+    ) -> Result<bool> {
+        let tx = self.repo.begin().await?;
+        if !tx.delete_flag(key).await? {
+            return Ok(false);
+        }
+        self.publish_in(
+            &tx,
+            DomainEvent::FeatureFlagChanged {
+                actor_id,
+                key: key.to_string(),
+                flag: None,
+            },
+            ctx,
+        )
+        .await?;
+        self.commit(tx).await?;
+        Ok(true)
+    }
+
+    async fn publish_in(
+        &self,
+        tx: &R::Transaction,
+        event: DomainEvent,
+        ctx: &RequestContext,
+    ) -> Result<()> {
+        self.events
+            .publish_in(tx, EventEnvelope::new(event, ctx))
+            .await
+    }
+
+    async fn commit(&self, tx: R::Transaction) -> Result<()> {
+        tx.commit().await?;
+        self.events.committed();
+        Ok(())
+    }
+}
@@ -0,0 +1,241 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, Timelike, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use shared::error::{AppError, Result};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Pending,
+    Running,
+    Succeeded,
+    /// Out of attempts, or no handler knows the job; only `retry` brings it back.
+    Dead,
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobStatus::Pending => "pending",
+            JobStatus::Running => "running",
+            JobStatus::Succeeded => "succeeded",
+            JobStatus::Dead => "dead",
+            JobStatus::Cancelled => "cancelled",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "pending" => Some(JobStatus::Pending),
+            "running" => Some(JobStatus::Running),
+            "succeeded" => Some(JobStatus::Succeeded),
+            "dead" => Some(JobStatus::Dead),
+            "cancelled" => Some(JobStatus::Cancelled),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Job {
+    pub id: Uuid,
+    pub kind: String,
+    pub payload: serde_json::Value,
+    pub status: JobStatus,
+    pub attempts: i32,
+    pub max_attempts: i32,
+    pub run_at: DateTime<Utc>,
+    pub locked_by: Option<String>,
+    pub locked_at: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+pub struct NewJob {
+    pub kind: String,
+    pub payload: serde_json::Value,
+    pub run_at: DateTime<Utc>,
+    pub max_attempts: i32,
+}
+
+impl NewJob {
+    pub fn of<J: JobKind>(job: &J) -> Result<Self> {
+        let payload = serde_json::to_value(job)
+            .map_err(|e| AppError::internal(format!("failed to encode {} job: {e}", J::KIND)))?;
+        Ok(Self {
+            kind: J::KIND.to_string(),
+            payload,
+            run_at: Utc::now(),
+            max_attempts: J::MAX_ATTEMPTS,
+        })
+    }
+
+    pub fn run_at(mut self, run_at: DateTime<Utc>) -> Self {
+        self.run_at = run_at;
+        self
+    }
+}
+
+/// A typed job payload. `KIND` is stored with the job and picks its handler.
+pub trait JobKind: Serialize + DeserializeOwned + Send + Sync + 'static {
+    const KIND: &'static str;
+    const MAX_ATTEMPTS: i32 = 5;
+}
+
+/// Delay before the next attempt: 30s doubling per failed attempt, capped at one hour.
+pub fn retry_backoff(attempts: i32) -> Duration {
+    let exponent = attempts.clamp(1, 8) - 1;
+    Duration::seconds((30i64 << exponent).min(3600))
+}
+
+/// Enqueues a job of `kind` whenever `cron` comes due. Schedules are keyed by `name`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobSchedule {
+    pub name: String,
+    pub kind: String,
+    pub payload: serde_json::Value,
+    pub cron: String,
+    pub next_run_at: DateTime<Utc>,
+    pub enabled: bool,
+}
+
+impl JobSchedule {
+    pub fn new<J: JobKind>(name: &str, cron: &str, job: &J) -> Result<Self> {
+        let next_run_at = CronSchedule::parse(cron)?
+            .next_after(Utc::now())
+            .ok_or_else(|| AppError::Validation(format!("cron `{cron}` never fires")))?;
+        Ok(Self {
+            name: name.to_string(),
+            kind: J::KIND.to_string(),
+            payload: NewJob::of(job)?.payload,
+            cron: cron.to_string(),
+            next_run_at,
+            enabled: true,
+        })
+    }
+}
+
+/// Standard five-field cron (`minute hour day-of-month month day-of-week`, UTC) with
+/// `*`, lists, ranges and steps, plus `@hourly`, `@daily`, `@weekly`, `@monthly` and `@yearly`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl CronSchedule {
+    pub fn parse(expr: &str) -> Result<Self> {
+        let expr = match expr.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            other => other,
+        };
+        let invalid = || AppError::Validation(format!("invalid cron expression `{expr}`"));
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(invalid());
+        };
+
+        let mut weekdays = parse_field(weekday, 0, 7).ok_or_else(invalid)?;
+        // Both 0 and 7 mean Sunday.
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays | 1) & !(1 << 7);
+        }
+        Ok(Self {
+            minutes: parse_field(minute, 0, 59).ok_or_else(invalid)?,
+            hours: parse_field(hour, 0, 23).ok_or_else(invalid)?,
+            days: parse_field(day, 1, 31).ok_or_else(invalid)?,
+            months: parse_field(month, 1, 12).ok_or_else(invalid)?,
+            weekdays,
+            any_day: day == "*",
+            any_weekday: weekday == "*",
+        })
+    }
+
+    /// The first matching minute strictly after `after`.
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let start = after.naive_utc().with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let mut t = start;
+        // Bounded so impossible dates such as `0 0 30 2 *` give up instead of spinning.
+        for _ in 0..100_000 {
+            if !bit(self.months, t.month()) {
+                t = first_of_next_month(t.date())?.and_hms_opt(0, 0, 0)?;
+            } else if !self.day_matches(t.date()) {
+                t = t.date().succ_opt()?.and_hms_opt(0, 0, 0)?;
+            } else if !bit(self.hours, t.hour()) {
+                t = truncate_hour(t)? + Duration::hours(1);
+            } else if !bit(self.minutes, t.minute()) {
+                t += Duration::minutes(1);
+            } else {
+                return Some(t.and_utc());
+            }
+        }
+        None
+    }
+
+    fn day_matches(&self, date: NaiveDate) -> bool {
+        let day = bit(self.days, date.day());
+        let weekday = bit(self.weekdays, date.weekday().num_days_from_sunday());
+        // Cron quirk: when both day fields are restricted, either one matching is enough.
+        match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (true, false) => weekday,
+            (false, true) => day,
+            (false, false) => day || weekday,
+        }
+    }
+}
+
+fn parse_field(field: &str, min: u32, max: u32) -> Option<u64> {
+    let mut mask = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().ok().filter(|s| *s > 0)?),
+            None => (part, 1),
+        };
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((a, b)) = range.split_once('-') {
+            (a.parse().ok()?, b.parse().ok()?)
+        } else {
+            let value = range.parse().ok()?;
+            // `5/15` means every 15 starting at 5.
+            (value, if step > 1 { max } else { value })
+        };
+        if start < min || end > max || start > end {
+            return None;
+        }
+        for value in (start..=end).step_by(step as usize) {
+            mask |= 1 << value;
+        }
+    }
+    Some(mask)
+}
+
+fn bit(mask: u64, value: u32) -> bool {
+    mask & (1 << value) != 0
+}
+
+fn first_of_next_month(date: NaiveDate) -> Option<NaiveDate> {
+    if date.month() == 12 {
+        NaiveDate::from_ymd_opt(date.year() + 1, 1, 1)
+    } else {
+        NaiveDate::from_ymd_opt(date.year(), date.month() + 1, 1)
+    }
+}
+
+fn truncate_hour(t: NaiveDateTime) -> Option<NaiveDateTime> {
+    t.with_minute(0)
+}
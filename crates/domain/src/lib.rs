@@ -1,6 +1,13 @@
+pub mod audit_chain;
 pub mod auth;
+pub mod events;
+pub mod files;
+pub mod flags;
+pub mod jobs;
 pub mod models;
+pub mod notifications;
 pub mod ports;
+pub mod webhooks;
 
 pub use auth::AuthService;
 pub use models::*;
@@ -1,10 +1,14 @@
 use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use base64::Engine;
 use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
 use rand::RngCore;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use shared::config::AuthConfig;
 use shared::error::{AppError, Result};
-use shared::types::{AuditEvent, UserRole};
+use shared::types::{AuditEvent, AuditSeverity, RequestContext, UserRole};
+use std::sync::OnceLock;
 use uuid::Uuid;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -28,92 +32,386 @@ pub struct NewUser {
 pub struct RefreshToken {
     pub id: Uuid,
     pub user_id: Uuid,
+    /// The sign-in this token descends from, kept when it is rotated.
+    pub session_id: Uuid,
     pub token_hash: String,
     pub expires_at: DateTime<Utc>,
     pub created_at: DateTime<Utc>,
 }
 
 impl RefreshToken {
-    pub fn from_raw(user_id: Uuid, raw: &str, expires_at: DateTime<Utc>) -> Self {
+    /// A token starting a new session.
+    pub fn new(user_id: Uuid, token_hash: String, expires_at: DateTime<Utc>) -> Self {
+        let id = Uuid::new_v4();
         Self {
-            id: Uuid::new_v4(),
+            id,
             user_id,
-            token_hash: Self::hash(raw),
+            session_id: id,
+            token_hash,
             expires_at,
             created_at: Utc::now(),
         }
     }
 
-    pub fn hash(raw: &str) -> String {
+    /// Moves the token into an existing session, as when it replaces a rotated one.
+    pub fn in_session(mut self, session_id: Uuid) -> Self {
+        self.session_id = session_id;
+        self
+    }
+}
+
+/// Hashes refresh tokens with HMAC-SHA256 keyed by the refresh secret, so the stored
+/// hashes are useless for checking stolen tokens without the secret as well.
+///
+/// New tokens always use the current secret. Lookups also try retired secrets and,
+/// while `accept_legacy` is on, the unkeyed SHA-256 hashes written before peppering.
+#[derive(Clone)]
+pub struct RefreshTokenHasher {
+    current: Vec<u8>,
+    previous: Vec<Vec<u8>>,
+    accept_legacy: bool,
+}
+
+impl RefreshTokenHasher {
+    pub fn new(secret: impl AsRef<[u8]>) -> Self {
+        Self {
+            current: secret.as_ref().to_vec(),
+            previous: Vec::new(),
+            accept_legacy: false,
+        }
+    }
+
+    pub fn from_config(config: &AuthConfig) -> Self {
+        Self::new(&config.refresh_secret)
+            .with_previous(&config.previous_refresh_secrets)
+            .accept_legacy(config.accept_legacy_refresh_hashes)
+    }
+
+    pub fn with_previous<I, S>(mut self, secrets: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<[u8]>,
+    {
+        self.previous = secrets.into_iter().map(|s| s.as_ref().to_vec()).collect();
+        self
+    }
+
+    pub fn accept_legacy(mut self, accept: bool) -> Self {
+        self.accept_legacy = accept;
+        self
+    }
+
+    pub fn hash(&self, raw: &str) -> String {
+        keyed_hash(&self.current, raw)
+    }
+
+    /// Every stored form `raw` may have, current secret first.
+    pub fn candidates(&self, raw: &str) -> Vec<String> {
+        let mut hashes = vec![self.hash(raw)];
+        hashes.extend(self.previous.iter().map(|key| keyed_hash(key, raw)));
+        if self.accept_legacy {
+            let mut hasher = Sha256::new();
+            hasher.update(raw.as_bytes());
+            hashes.push(format!("{:x}", hasher.finalize()));
+        }
+        hashes
+    }
+}
+
+fn keyed_hash(key: &[u8], raw: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("hmac accepts keys of any length");
+    mac.update(raw.as_bytes());
+    format!("{:x}", mac.finalize().into_bytes())
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingRegistration {
+    pub id: Uuid,
+    pub email: String,
+    pub password_hash: String,
+    pub role: UserRole,
+    pub token_hash: String,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl PendingRegistration {
+    pub fn hash_token(raw: &str) -> String {
         let mut hasher = Sha256::new();
         hasher.update(raw.as_bytes());
         format!("{:x}", hasher.finalize())
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 pub enum AuditEventType {
     AuthLogin,
+    AuthLoginFailed,
     AuthRegister,
+    AuthRegisterRequested,
     AuthLogout,
     TokenRefresh,
+    AuthPasswordChanged,
+    AuthSessionsRevoked,
+    AuditPurged,
+    UserRoleChanged,
+    FeatureFlagChanged,
 }
 
 impl AuditEventType {
+    pub const ALL: [AuditEventType; 11] = [
+        AuditEventType::AuthLogin,
+        AuditEventType::AuthLoginFailed,
+        AuditEventType::AuthRegister,
+        AuditEventType::AuthRegisterRequested,
+        AuditEventType::AuthLogout,
+        AuditEventType::TokenRefresh,
+        AuditEventType::AuthPasswordChanged,
+        AuditEventType::AuthSessionsRevoked,
+        AuditEventType::AuditPurged,
+        AuditEventType::UserRoleChanged,
+        AuditEventType::FeatureFlagChanged,
+    ];
+
     pub fn as_str(&self) -> &'static str {
         match self {
             AuditEventType::AuthLogin => "auth.login",
+            AuditEventType::AuthLoginFailed => "auth.login_failed",
             AuditEventType::AuthRegister => "auth.register",
+            AuditEventType::AuthRegisterRequested => "auth.register_requested",
             AuditEventType::AuthLogout => "auth.logout",
             AuditEventType::TokenRefresh => "auth.refresh",
+            AuditEventType::AuthPasswordChanged => "auth.password_changed",
+            AuditEventType::AuthSessionsRevoked => "auth.sessions_revoked",
+            AuditEventType::AuditPurged => "audit.purged",
+            AuditEventType::UserRoleChanged => "user.role_changed",
+            AuditEventType::FeatureFlagChanged => "feature_flag.changed",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|t| t.as_str() == value)
+    }
+
+    pub fn severity(&self) -> AuditSeverity {
+        match self {
+            AuditEventType::AuthLoginFailed
+            | AuditEventType::AuthPasswordChanged
+            | AuditEventType::AuthSessionsRevoked
+            | AuditEventType::AuditPurged
+            | AuditEventType::UserRoleChanged => AuditSeverity::Warning,
+            _ => AuditSeverity::Info,
         }
     }
+
+    /// Whether integrators can subscribe to this event type with a webhook.
+    pub fn is_webhook_event(&self) -> bool {
+        !matches!(self, AuditEventType::AuditPurged)
+    }
 }
 
 pub struct AuditEventBuilder {
-    event_type: String,
-    user_id: Option<Uuid>,
-    ip: Option<String>,
-    user_agent: Option<String>,
+    event_type: AuditEventType,
+    severity: AuditSeverity,
+    actor_id: Option<Uuid>,
+    subject_id: Option<Uuid>,
+    metadata: serde_json::Value,
+    context: RequestContext,
 }
 
 impl AuditEventBuilder {
-    pub fn new(event_type: impl Into<String>) -> Self {
+    pub fn new(event_type: AuditEventType) -> Self {
         Self {
-            event_type: event_type.into(),
-            user_id: None,
-            ip: None,
-            user_agent: None,
+            event_type,
+            severity: event_type.severity(),
+            actor_id: None,
+            subject_id: None,
+            metadata: serde_json::Value::Object(Default::default()),
+            context: RequestContext::default(),
         }
     }
 
-    pub fn user_id(mut self, user_id: Option<Uuid>) -> Self {
-        self.user_id = user_id;
+    pub fn actor(mut self, actor_id: Option<Uuid>) -> Self {
+        self.actor_id = actor_id;
+        self
+    }
+
+    pub fn subject(mut self, subject_id: Option<Uuid>) -> Self {
+        self.subject_id = subject_id;
         self
     }
 
-    pub fn ip(mut self, ip: Option<String>) -> Self {
-        self.ip = ip;
+    /// Sets actor and subject to the same user, for actions people take on their own account.
+    pub fn user(self, user_id: Uuid) -> Self {
+        self.actor(Some(user_id)).subject(Some(user_id))
+    }
+
+    pub fn severity(mut self, severity: AuditSeverity) -> Self {
+        self.severity = severity;
         self
     }
 
-    pub fn user_agent(mut self, ua: Option<String>) -> Self {
-        self.user_agent = ua;
+    pub fn metadata(mut self, metadata: serde_json::Value) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    pub fn context(mut self, context: &RequestContext) -> Self {
+        self.context = context.clone();
         self
     }
 
     pub fn build(self) -> AuditEvent {
         AuditEvent {
             id: Uuid::new_v4(),
-            user_id: self.user_id,
-            event_type: self.event_type,
-            ip: self.ip,
-            user_agent: self.user_agent,
+            actor_id: self.actor_id,
+            subject_id: self.subject_id,
+            event_type: self.event_type.as_str().to_string(),
+            severity: self.severity,
+            metadata: self.metadata,
+            ip: self.context.ip,
+            user_agent: self.context.user_agent,
+            request_id: self.context.request_id,
             created_at: Utc::now(),
         }
     }
 }
 
+/// Filters for reading the audit log, newest first. `user_id` matches actor or subject.
+#[derive(Debug, Clone, Default)]
+pub struct AuditQuery {
+    pub user_id: Option<Uuid>,
+    pub event_type: Option<AuditEventType>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub after: Option<AuditCursor>,
+    pub limit: i64,
+}
+
+/// Position in the audit log, as the `(created_at, id)` of the last event already seen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AuditCursor {
+    pub created_at: DateTime<Utc>,
+    pub id: Uuid,
+}
+
+impl AuditCursor {
+    pub fn from_event(event: &AuditEvent) -> Self {
+        Self {
+            created_at: event.created_at,
+            id: event.id,
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        let raw = format!("{}:{}", self.created_at.timestamp_micros(), self.id);
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(raw)
+    }
+
+    pub fn decode(value: &str) -> Result<Self> {
+        let invalid = || AppError::Validation("invalid cursor".into());
+        let raw = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(value)
+            .map_err(|_| invalid())?;
+        let raw = String::from_utf8(raw).map_err(|_| invalid())?;
+        let (micros, id) = raw.split_once(':').ok_or_else(invalid)?;
+        let micros = micros.parse::<i64>().map_err(|_| invalid())?;
+        Ok(Self {
+            created_at: DateTime::from_timestamp_micros(micros).ok_or_else(invalid)?,
+            id: Uuid::parse_str(id).map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// Narrows a user listing. Every filter that is set must match.
+#[derive(Debug, Clone, Default)]
+pub struct UserFilter {
+    /// Case-insensitive substring of the email.
+    pub email: Option<String>,
+    pub role: Option<UserRole>,
+    pub created_from: Option<DateTime<Utc>>,
+    pub created_to: Option<DateTime<Utc>>,
+}
+
+/// The fields a user listing can be sorted by. Ties are broken by id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UserSort {
+    #[default]
+    CreatedAt,
+    Email,
+}
+
+impl UserSort {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UserSort::CreatedAt => "created_at",
+            UserSort::Email => "email",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "created_at" => Some(UserSort::CreatedAt),
+            "email" => Some(UserSort::Email),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct UserQuery {
+    pub filter: UserFilter,
+    pub sort: UserSort,
+    pub descending: bool,
+    /// Only users that come after this one in the requested order.
+    pub after: Option<UserCursor>,
+    pub limit: i64,
+}
+
+/// Position in a user listing, as the sort key and id of the last user already seen.
+/// Cursors of the same sort compare in the listing's ascending order.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum UserCursor {
+    CreatedAt(DateTime<Utc>, Uuid),
+    Email(String, Uuid),
+}
+
+impl UserCursor {
+    pub fn from_user(user: &User, sort: UserSort) -> Self {
+        match sort {
+            UserSort::CreatedAt => UserCursor::CreatedAt(user.created_at, user.id),
+            UserSort::Email => UserCursor::Email(user.email.clone(), user.id),
+        }
+    }
+
+    pub fn sort(&self) -> UserSort {
+        match self {
+            UserCursor::CreatedAt(..) => UserSort::CreatedAt,
+            UserCursor::Email(..) => UserSort::Email,
+        }
+    }
+
+    pub fn id(&self) -> Uuid {
+        match self {
+            UserCursor::CreatedAt(_, id) | UserCursor::Email(_, id) => *id,
+        }
+    }
+
+    pub fn created_at(&self) -> Option<DateTime<Utc>> {
+        match self {
+            UserCursor::CreatedAt(created_at, _) => Some(*created_at),
+            UserCursor::Email(..) => None,
+        }
+    }
+
+    pub fn email(&self) -> Option<&str> {
+        match self {
+            UserCursor::Email(email, _) => Some(email),
+            UserCursor::CreatedAt(..) => None,
+        }
+    }
+}
+
 pub struct PasswordService;
 
 impl PasswordService {
@@ -134,4 +432,18 @@ impl PasswordService {
             .verify_password(candidate.as_bytes(), &parsed)
             .is_ok())
     }
+
+    /// Spends the same Argon2 work as a real verification when there is no account
+    /// to check against, so unknown emails cannot be told apart by response time.
+    pub fn verify_dummy(candidate: &str) {
+        let _ = Self::verify(Self::dummy_hash(), candidate);
+    }
+
+    pub fn dummy_hash() -> &'static str {
+        static DUMMY_HASH: OnceLock<String> = OnceLock::new();
+        DUMMY_HASH.get_or_init(|| {
+            Self::hash("dummy-password-for-timing-equalization")
+                .expect("argon2 hashing with default params cannot fail")
+        })
+    }
 }
@@ -0,0 +1,76 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use shared::types::{NotificationCategory, NotificationChannel};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Notification {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub category: NotificationCategory,
+    pub title: String,
+    pub body: String,
+    /// Where the notification leads in the app, if anywhere.
+    pub link: Option<String>,
+    pub read_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+pub struct NewNotification {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub category: NotificationCategory,
+    pub title: String,
+    pub body: String,
+    pub link: Option<String>,
+}
+
+impl NewNotification {
+    pub fn new(
+        user_id: Uuid,
+        category: NotificationCategory,
+        title: impl Into<String>,
+        body: impl Into<String>,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            user_id,
+            category,
+            title: title.into(),
+            body: body.into(),
+            link: None,
+        }
+    }
+
+    /// Reusing an id makes creating the same notification twice a conflict.
+    pub fn id(mut self, id: Uuid) -> Self {
+        self.id = id;
+        self
+    }
+
+    pub fn link(mut self, link: impl Into<String>) -> Self {
+        self.link = Some(link.into());
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct NotificationPreference {
+    pub category: NotificationCategory,
+    pub channel: NotificationChannel,
+}
+
+/// One preference per category: the user's choice where they made one, else the default.
+pub fn effective_preferences(stored: &[NotificationPreference]) -> Vec<NotificationPreference> {
+    NotificationCategory::ALL
+        .into_iter()
+        .map(|category| NotificationPreference {
+            category,
+            channel: stored
+                .iter()
+                .find(|p| p.category == category)
+                .map_or_else(|| category.default_channel(), |p| p.channel),
+        })
+        .collect()
+}
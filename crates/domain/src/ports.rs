@@ -1,7 +1,17 @@
-use crate::models::{NewUser, RefreshToken, User};
+use crate::audit_chain::{AuditChainLink, AuditCheckpoint, ChainedAuditEvent};
+use crate::events::{EventEnvelope, OutboxEntry};
+use crate::files::{NewFile, StoredFile};
+use crate::flags::FeatureFlag;
+use crate::jobs::{Job, JobSchedule, JobStatus, NewJob};
+use crate::models::{
+    AuditQuery, NewUser, PendingRegistration, RefreshToken, User, UserFilter, UserQuery,
+};
+use crate::notifications::{NewNotification, Notification, NotificationPreference};
+use crate::webhooks::{DeliveryAttempt, NewWebhookEndpoint, WebhookDelivery, WebhookEndpoint};
 use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
 use shared::error::Result;
-use shared::types::AuditEvent;
+use shared::types::{AuditEvent, NotificationCategory, NotificationChannel, UserRole};
 use uuid::Uuid;
 
 #[async_trait]
@@ -9,28 +19,229 @@ pub trait UserRepository: Send + Sync {
     async fn create_user(&self, new_user: NewUser) -> Result<User>;
     async fn find_by_email(&self, email: &str) -> Result<Option<User>>;
     async fn find_by_id(&self, id: Uuid) -> Result<Option<User>>;
-    async fn list_users(&self, page: i64, per_page: i64) -> Result<(Vec<User>, i64)>;
+    async fn list_users(&self, query: &UserQuery) -> Result<Vec<User>>;
+    async fn count_users(&self, filter: &UserFilter) -> Result<i64>;
+    async fn update_role(&self, id: Uuid, role: UserRole) -> Result<Option<User>>;
+    async fn update_password_hash(&self, id: Uuid, password_hash: &str) -> Result<()>;
 }
 
 #[async_trait]
 pub trait RefreshTokenRepository: Send + Sync {
     async fn store_refresh_token(&self, token: &RefreshToken) -> Result<()>;
     async fn find_refresh_token(&self, token_hash: &str) -> Result<Option<RefreshToken>>;
-    async fn delete_refresh_token(&self, id: Uuid) -> Result<()>;
+    /// Whether the token existed, so only one of two concurrent rotations wins.
+    async fn delete_refresh_token(&self, id: Uuid) -> Result<bool>;
     async fn delete_tokens_for_user(&self, user_id: Uuid) -> Result<()>;
+    async fn delete_expired_tokens(&self, now: DateTime<Utc>) -> Result<u64>;
 }
 
 #[async_trait]
 pub trait AuditLogRepository: Send + Sync {
     async fn log_event(&self, event: AuditEvent) -> Result<()>;
+    async fn list_events(&self, query: &AuditQuery) -> Result<Vec<AuditEvent>>;
+}
+
+/// Reads the hash chain that `log_event` maintains, for verification and checkpointing.
+#[async_trait]
+pub trait AuditChainRepository: Send + Sync {
+    /// Days with chained events or checkpoints, oldest first.
+    async fn chain_days(&self) -> Result<Vec<NaiveDate>>;
+    async fn chained_events(&self, day: NaiveDate) -> Result<Vec<ChainedAuditEvent>>;
+    /// Events logged before chaining was introduced, which cannot be verified.
+    async fn count_unchained_events(&self) -> Result<i64>;
+    /// The last link of every day whose current head has no checkpoint yet.
+    async fn unanchored_heads(&self) -> Result<Vec<AuditChainLink>>;
+    async fn store_checkpoint(&self, checkpoint: &AuditCheckpoint) -> Result<()>;
+    async fn checkpoints(&self, day: NaiveDate) -> Result<Vec<AuditCheckpoint>>;
+    /// Drops whole days older than `day`, with their checkpoints, so the days that
+    /// remain still verify end to end.
+    async fn purge_days_before(&self, day: NaiveDate) -> Result<u64>;
+}
+
+#[async_trait]
+pub trait PendingRegistrationRepository: Send + Sync {
+    async fn store_pending_registration(&self, pending: &PendingRegistration) -> Result<()>;
+    /// Removes and returns the pending registration, so each link works once.
+    async fn take_pending_registration(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<PendingRegistration>>;
+    async fn delete_expired_pending_registrations(&self, now: DateTime<Utc>) -> Result<u64>;
+}
+
+#[async_trait]
+pub trait JobRepository: Send + Sync {
+    async fn enqueue_job(&self, job: NewJob) -> Result<Job>;
+    /// Locks up to `limit` due jobs of the given kinds for `worker`, skipping rows other
+    /// workers hold, and marks them running.
+    async fn claim_jobs(&self, worker: &str, kinds: &[String], limit: i64) -> Result<Vec<Job>>;
+    async fn complete_job(&self, id: Uuid) -> Result<()>;
+    /// Puts the job back to run at `retry_at`, or dead-letters it when that is `None`.
+    async fn fail_job(&self, id: Uuid, error: &str, retry_at: Option<DateTime<Utc>>) -> Result<()>;
+    /// Returns running jobs whose lease started before `locked_before` to the queue.
+    async fn release_stale_jobs(&self, locked_before: DateTime<Utc>) -> Result<u64>;
+    async fn list_jobs(&self, status: Option<JobStatus>, limit: i64) -> Result<Vec<Job>>;
+    /// Requeues a dead or cancelled job with a fresh set of attempts.
+    async fn retry_job(&self, id: Uuid) -> Result<bool>;
+    /// Cancels a job that has not started yet.
+    async fn cancel_job(&self, id: Uuid) -> Result<bool>;
+    /// Creates the schedule, or updates it and keeps its next run if the cron is unchanged.
+    async fn upsert_schedule(&self, schedule: &JobSchedule) -> Result<()>;
+    /// Enqueues a job for every enabled schedule that is due and moves it to its next run.
+    async fn enqueue_due_schedules(&self, now: DateTime<Utc>) -> Result<usize>;
+}
+
+#[async_trait]
+pub trait WebhookRepository: Send + Sync {
+    async fn create_webhook_endpoint(
+        &self,
+        endpoint: NewWebhookEndpoint,
+    ) -> Result<WebhookEndpoint>;
+    async fn list_webhook_endpoints(&self, owner_id: Uuid) -> Result<Vec<WebhookEndpoint>>;
+    async fn find_webhook_endpoint(&self, id: Uuid) -> Result<Option<WebhookEndpoint>>;
+    async fn delete_webhook_endpoint(&self, id: Uuid, owner_id: Uuid) -> Result<bool>;
+    async fn find_webhook_delivery(&self, id: Uuid) -> Result<Option<WebhookDelivery>>;
+    /// Most recent deliveries to the endpoint first.
+    async fn list_webhook_deliveries(
+        &self,
+        endpoint_id: Uuid,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>>;
+    /// Queues a delivery for every enabled endpoint subscribed to the event's type.
+    async fn queue_webhook_deliveries(&self, event: &AuditEvent) -> Result<usize>;
+    async fn record_delivery_attempt(&self, id: Uuid, attempt: &DeliveryAttempt) -> Result<()>;
+    /// Resets the delivery to pending and queues a fresh delivery job for it.
+    async fn redeliver_webhook(&self, id: Uuid) -> Result<bool>;
+}
+
+#[async_trait]
+pub trait FeatureFlagRepository: Send + Sync {
+    async fn list_flags(&self) -> Result<Vec<FeatureFlag>>;
+    async fn find_flag(&self, key: &str) -> Result<Option<FeatureFlag>>;
+    async fn upsert_flag(&self, flag: &FeatureFlag) -> Result<FeatureFlag>;
+    async fn delete_flag(&self, key: &str) -> Result<bool>;
+}
+
+#[async_trait]
+pub trait NotificationRepository: Send + Sync {
+    async fn create_notification(&self, notification: NewNotification) -> Result<Notification>;
+    /// Unread notifications first, newest first within each group.
+    async fn list_notifications(
+        &self,
+        user_id: Uuid,
+        page: i64,
+        per_page: i64,
+    ) -> Result<(Vec<Notification>, i64)>;
+    async fn count_unread_notifications(&self, user_id: Uuid) -> Result<i64>;
+    async fn mark_notification_read(&self, user_id: Uuid, id: Uuid) -> Result<bool>;
+    async fn mark_all_notifications_read(&self, user_id: Uuid) -> Result<u64>;
+    /// Only the categories the user has set; see `effective_preferences` for the rest.
+    async fn notification_preferences(&self, user_id: Uuid) -> Result<Vec<NotificationPreference>>;
+    async fn set_notification_preference(
+        &self,
+        user_id: Uuid,
+        category: NotificationCategory,
+        channel: NotificationChannel,
+    ) -> Result<()>;
+}
+
+#[async_trait]
+pub trait FileRepository: Send + Sync {
+    async fn create_file(&self, file: NewFile) -> Result<StoredFile>;
+    async fn find_file(&self, id: Uuid) -> Result<Option<StoredFile>>;
+    async fn delete_file(&self, id: Uuid) -> Result<bool>;
+    /// Points the user's avatar at `file_id` and returns the file it replaced.
+    async fn set_avatar(&self, user_id: Uuid, file_id: Option<Uuid>) -> Result<Option<Uuid>>;
+    async fn find_avatar(&self, user_id: Uuid) -> Result<Option<StoredFile>>;
+}
+
+/// Where `AuthService` sends its domain events.
+#[async_trait]
+pub trait EventPublisher: Send + Sync {
+    async fn publish(&self, envelope: EventEnvelope) -> Result<()>;
+
+    /// Appends the event to the outbox through `tx`, so it is kept only if the rest of
+    /// the transaction is.
+    async fn publish_in(&self, tx: &dyn OutboxRepository, envelope: EventEnvelope) -> Result<()> {
+        tx.append_outbox(&envelope).await
+    }
+
+    /// Runs after a transaction that `publish_in` wrote to has committed.
+    fn committed(&self) {}
+}
+
+/// Durable queue of published events, so an event survives a failing subscriber or a
+/// restart until every subscriber has handled it.
+#[async_trait]
+pub trait OutboxRepository: Send + Sync {
+    async fn append_outbox(&self, envelope: &EventEnvelope) -> Result<()>;
+    /// Leases up to `limit` due events, skipping rows another dispatcher holds.
+    async fn claim_outbox(&self, limit: i64) -> Result<Vec<OutboxEntry>>;
+    async fn mark_outbox_delivered(&self, id: Uuid, subscriber: &str) -> Result<()>;
+    async fn complete_outbox(&self, id: Uuid) -> Result<()>;
+    /// Makes the event due again at `retry_at`, or gives up on it when that is `None`.
+    async fn retry_outbox(
+        &self,
+        id: Uuid,
+        error: &str,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> Result<()>;
+}
+
+/// Starts transactions over the repository ports.
+#[async_trait]
+pub trait UnitOfWork: Send + Sync {
+    type Transaction: Transaction;
+
+    async fn begin(&self) -> Result<Self::Transaction>;
+}
+
+/// Repository calls that take effect together when `commit` succeeds. Dropping the
+/// transaction first rolls all of them back. Until then it may hold the store's only
+/// writer, so make every call of the operation through it.
+#[async_trait]
+pub trait Transaction:
+    UserRepository
+    + RefreshTokenRepository
+    + PendingRegistrationRepository
+    + OutboxRepository
+    + Sized
+    + Send
+    + Sync
+    + 'static
+{
+    async fn commit(self) -> Result<()>;
+}
+
+/// Delivers the emails that finish an `EmailConfirmation` sign-up.
+#[async_trait]
+pub trait RegistrationNotifier: Send + Sync {
+    async fn send_signup_link(&self, email: &str, token: &str) -> Result<()>;
+    async fn send_existing_account_notice(&self, email: &str) -> Result<()>;
 }
 
 pub trait AuthRepo:
-    UserRepository + RefreshTokenRepository + AuditLogRepository + Send + Sync + 'static
+    UserRepository
+    + RefreshTokenRepository
+    + AuditLogRepository
+    + PendingRegistrationRepository
+    + OutboxRepository
+    + UnitOfWork
+    + Send
+    + Sync
+    + 'static
 {
 }
 
 impl<T> AuthRepo for T where
-    T: UserRepository + RefreshTokenRepository + AuditLogRepository + Send + Sync + 'static
+    T: UserRepository
+        + RefreshTokenRepository
+        + AuditLogRepository
+        + PendingRegistrationRepository
+        + OutboxRepository
+        + UnitOfWork
+        + Send
+        + Sync
+        + 'static
 {
 }
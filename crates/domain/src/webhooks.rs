@@ -0,0 +1,130 @@
+use crate::jobs::JobKind;
+use crate::models::AuditEventType;
+use chrono::{DateTime, Utc};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use shared::types::AuditEvent;
+use uuid::Uuid;
+
+/// Event types integrators can subscribe to. Each audit event of a subscribed type is
+/// delivered to the endpoint.
+pub fn event_catalogue() -> Vec<&'static str> {
+    AuditEventType::ALL
+        .into_iter()
+        .filter(|t| t.is_webhook_event())
+        .map(|t| t.as_str())
+        .collect()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookEndpoint {
+    pub id: Uuid,
+    /// The admin who registered the endpoint; endpoints are listed and managed per owner.
+    pub owner_id: Uuid,
+    pub url: String,
+    pub description: Option<String>,
+    pub events: Vec<String>,
+    /// Signs every delivery. Only shown to the owner when the endpoint is created.
+    #[serde(skip_serializing)]
+    pub secret: String,
+    pub enabled: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+pub struct NewWebhookEndpoint {
+    pub owner_id: Uuid,
+    pub url: String,
+    pub description: Option<String>,
+    pub events: Vec<String>,
+    pub secret: String,
+}
+
+impl NewWebhookEndpoint {
+    pub fn generate_secret() -> String {
+        let mut bytes = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let hex: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+        format!("whsec_{hex}")
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryStatus {
+    Pending,
+    /// The last attempt failed and another is scheduled.
+    Retrying,
+    Succeeded,
+    /// Out of attempts. `redeliver` starts over.
+    Failed,
+}
+
+impl DeliveryStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Pending => "pending",
+            DeliveryStatus::Retrying => "retrying",
+            DeliveryStatus::Succeeded => "succeeded",
+            DeliveryStatus::Failed => "failed",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "pending" => Some(DeliveryStatus::Pending),
+            "retrying" => Some(DeliveryStatus::Retrying),
+            "succeeded" => Some(DeliveryStatus::Succeeded),
+            "failed" => Some(DeliveryStatus::Failed),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookDelivery {
+    pub id: Uuid,
+    pub endpoint_id: Uuid,
+    pub event_id: Uuid,
+    pub event_type: String,
+    pub payload: serde_json::Value,
+    pub status: DeliveryStatus,
+    pub attempts: i32,
+    pub response_status: Option<i32>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+/// Outcome of one delivery attempt, as recorded in the delivery log.
+#[derive(Debug, Clone)]
+pub struct DeliveryAttempt {
+    pub status: DeliveryStatus,
+    pub response_status: Option<i32>,
+    pub error: Option<String>,
+}
+
+/// The JSON body sent to endpoints. Client IP and user agent stay in the audit log.
+pub fn webhook_payload(event: &AuditEvent) -> serde_json::Value {
+    serde_json::json!({
+        "id": event.id,
+        "type": event.event_type,
+        "created_at": event.created_at,
+        "data": {
+            "actor_id": event.actor_id,
+            "subject_id": event.subject_id,
+            "metadata": event.metadata,
+        },
+    })
+}
+
+/// Delivers one webhook delivery and records the attempt.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeliverWebhook {
+    pub delivery_id: Uuid,
+}
+
+impl JobKind for DeliverWebhook {
+    const KIND: &'static str = "webhooks.deliver";
+    const MAX_ATTEMPTS: i32 = 8;
+}
@@ -0,0 +1,231 @@
+use chrono::{NaiveDate, TimeZone, Utc};
+use domain::audit_chain::{
+    event_hash, genesis_hash, verify_day, AuditChainLink, ChainBreak, ChainedAuditEvent,
+    CheckpointSigner,
+};
+use serde_json::json;
+use shared::types::{AuditEvent, AuditSeverity};
+use uuid::Uuid;
+
+const SECRET: &str = "audit-checkpoint-secret-0123456789";
+
+fn day() -> NaiveDate {
+    NaiveDate::from_ymd_opt(2024, 3, 1).unwrap()
+}
+
+fn event(second: u32) -> AuditEvent {
+    let actor = Uuid::new_v4();
+    AuditEvent {
+        id: Uuid::new_v4(),
+        actor_id: Some(actor),
+        subject_id: Some(actor),
+        event_type: "auth.login".into(),
+        severity: AuditSeverity::Info,
+        metadata: json!({ "method": "password", "attempt": 1 }),
+        ip: Some("127.0.0.1".into()),
+        user_agent: Some("test".into()),
+        request_id: None,
+        created_at: Utc.from_utc_datetime(&day().and_hms_opt(12, 0, second).unwrap()),
+    }
+}
+
+/// Links `events` the way the store does when appending them.
+fn chain(events: Vec<AuditEvent>) -> Vec<ChainedAuditEvent> {
+    let mut prev_hash = genesis_hash(day());
+    let mut chained = Vec::new();
+    for (index, event) in events.into_iter().enumerate() {
+        let seq = index as i64 + 1;
+        let hash = event_hash(&prev_hash, seq, &event);
+        chained.push(ChainedAuditEvent {
+            event,
+            link: AuditChainLink {
+                day: day(),
+                seq,
+                prev_hash: std::mem::replace(&mut prev_hash, hash.clone()),
+                hash,
+            },
+        });
+    }
+    chained
+}
+
+fn sample() -> Vec<ChainedAuditEvent> {
+    chain((1..=4).map(event).collect())
+}
+
+#[test]
+fn untouched_chain_verifies() {
+    let events = sample();
+    let signer = CheckpointSigner::new(SECRET);
+    let checkpoints = [
+        signer.checkpoint(&events[1].link),
+        signer.checkpoint(&events[3].link),
+    ];
+
+    assert_eq!(
+        verify_day(day(), &events, &checkpoints, Some(&signer)),
+        Ok(())
+    );
+    assert_eq!(verify_day(day(), &[], &[], Some(&signer)), Ok(()));
+}
+
+#[test]
+fn modified_fields_are_detected() {
+    let edits: [fn(&mut AuditEvent); 5] = [
+        |event| event.actor_id = None,
+        |event| event.event_type = "auth.logout".into(),
+        |event| event.severity = AuditSeverity::Critical,
+        |event| event.metadata["attempt"] = json!(2),
+        |event| event.created_at += chrono::Duration::seconds(1),
+    ];
+    for edit in edits {
+        let mut events = sample();
+        edit(&mut events[1].event);
+
+        assert_eq!(
+            verify_day(day(), &events, &[], None),
+            Err(ChainBreak::ContentMismatch {
+                day: day(),
+                seq: 2,
+                event_id: events[1].event.id,
+            })
+        );
+    }
+}
+
+#[test]
+fn rehashing_a_modified_row_breaks_the_next_link() {
+    let mut events = sample();
+    events[1].event.ip = Some("10.0.0.1".into());
+    events[1].link.hash = event_hash(&events[1].link.prev_hash, 2, &events[1].event);
+
+    assert_eq!(
+        verify_day(day(), &events, &[], None),
+        Err(ChainBreak::BrokenLink {
+            day: day(),
+            seq: 3,
+            event_id: events[2].event.id,
+        })
+    );
+}
+
+#[test]
+fn metadata_hashes_survive_a_round_trip() {
+    let mut original = event(1);
+    original.metadata = json!({ "b": [1, { "y": null, "x": "2" }], "a": { "d": true, "c": 1.5 } });
+    let stored: serde_json::Value =
+        serde_json::from_str(r#"{"a":{"c":1.5,"d":true},"b":[1,{"x":"2","y":null}]}"#).unwrap();
+    let mut reloaded = original.clone();
+    reloaded.metadata = stored;
+    let prev_hash = genesis_hash(day());
+    assert_eq!(
+        event_hash(&prev_hash, 1, &reloaded),
+        event_hash(&prev_hash, 1, &original)
+    );
+
+    // Moving a value to another object changes the hash, though the keys are the same.
+    reloaded.metadata = json!({ "b": [1, { "y": null }], "a": { "d": true, "c": 1.5, "x": "2" } });
+    assert_ne!(
+        event_hash(&prev_hash, 1, &reloaded),
+        event_hash(&prev_hash, 1, &original)
+    );
+}
+
+#[test]
+fn deleted_rows_are_detected() {
+    let mut events = sample();
+    events.remove(1);
+    assert_eq!(
+        verify_day(day(), &events, &[], None),
+        Err(ChainBreak::SequenceGap {
+            day: day(),
+            expected: 2,
+            found: 3,
+        })
+    );
+
+    // Renumbering the rows after the gap leaves them pointing at the deleted row.
+    for (index, chained) in events.iter_mut().enumerate() {
+        chained.link.seq = index as i64 + 1;
+    }
+    assert_eq!(
+        verify_day(day(), &events, &[], None),
+        Err(ChainBreak::BrokenLink {
+            day: day(),
+            seq: 2,
+            event_id: events[1].event.id,
+        })
+    );
+}
+
+#[test]
+fn truncating_a_day_is_caught_by_its_checkpoint() {
+    let mut events = sample();
+    let signer = CheckpointSigner::new(SECRET);
+    let checkpoint = signer.checkpoint(&events[3].link);
+    events.truncate(3);
+
+    assert_eq!(verify_day(day(), &events, &[], Some(&signer)), Ok(()));
+    assert_eq!(
+        verify_day(day(), &events, &[checkpoint], Some(&signer)),
+        Err(ChainBreak::CheckpointMismatch { day: day(), seq: 4 })
+    );
+}
+
+#[test]
+fn reordered_rows_are_detected() {
+    let mut events = sample();
+    events.swap(1, 2);
+    assert_eq!(
+        verify_day(day(), &events, &[], None),
+        Err(ChainBreak::SequenceGap {
+            day: day(),
+            expected: 2,
+            found: 3,
+        })
+    );
+
+    // Swapping the sequence numbers too leaves the links pointing the wrong way.
+    let (first, second) = (events[1].link.seq, events[2].link.seq);
+    events[1].link.seq = second;
+    events[2].link.seq = first;
+    assert_eq!(
+        verify_day(day(), &events, &[], None),
+        Err(ChainBreak::BrokenLink {
+            day: day(),
+            seq: 2,
+            event_id: events[1].event.id,
+        })
+    );
+}
+
+#[test]
+fn checkpoints_with_bad_signatures_are_detected() {
+    let events = sample();
+    let signer = CheckpointSigner::new(SECRET);
+    let forged =
+        CheckpointSigner::new("someone-elses-secret-0123456789").checkpoint(&events[3].link);
+    assert_eq!(
+        verify_day(day(), &events, std::slice::from_ref(&forged), Some(&signer)),
+        Err(ChainBreak::ForgedCheckpoint { day: day(), seq: 4 })
+    );
+    // Without the secret, only whether the checkpoint matches the chain is checked.
+    assert_eq!(verify_day(day(), &events, &[forged], None), Ok(()));
+
+    let mut tampered = signer.checkpoint(&events[3].link);
+    tampered.signature.replace_range(..2, "zz");
+    assert!(!signer.verify(&tampered));
+    assert_eq!(
+        verify_day(day(), &events, &[tampered], Some(&signer)),
+        Err(ChainBreak::ForgedCheckpoint { day: day(), seq: 4 })
+    );
+
+    // Moving a valid checkpoint to another head invalidates its signature.
+    let mut moved = signer.checkpoint(&events[3].link);
+    moved.seq = 2;
+    moved.hash = events[1].link.hash.clone();
+    assert_eq!(
+        verify_day(day(), &events, &[moved], Some(&signer)),
+        Err(ChainBreak::ForgedCheckpoint { day: day(), seq: 2 })
+    );
+}
@@ -0,0 +1,447 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use domain::events::{EventEnvelope, OutboxEntry};
+use domain::models::{
+    AuditQuery, NewUser, PendingRegistration, RefreshToken, User, UserFilter, UserQuery,
+};
+use domain::ports::{
+    AuditLogRepository, EventPublisher, OutboxRepository, PendingRegistrationRepository,
+    RefreshTokenRepository, RegistrationNotifier, Transaction, UnitOfWork, UserRepository,
+};
+use domain::{AuthService, RefreshTokenHasher};
+use shared::dto::{LoginRequest, RegisterRequest};
+use shared::error::{AppError, Result};
+use shared::types::{AuditEvent, RequestContext, UserRole};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Clones share the same state, so a clone serves as a transaction that never rolls back.
+#[derive(Clone, Default)]
+struct FakeRepo {
+    users: Arc<Mutex<Vec<User>>>,
+    pending: Arc<Mutex<Vec<PendingRegistration>>>,
+    outbox: Arc<Mutex<Vec<EventEnvelope>>>,
+}
+
+#[async_trait]
+impl UnitOfWork for FakeRepo {
+    type Transaction = FakeRepo;
+
+    async fn begin(&self) -> Result<FakeRepo> {
+        Ok(self.clone())
+    }
+}
+
+#[async_trait]
+impl Transaction for FakeRepo {
+    async fn commit(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl UserRepository for FakeRepo {
+    async fn create_user(&self, new_user: NewUser) -> Result<User> {
+        let mut users = self.users.lock().unwrap();
+        if users.iter().any(|u| u.email == new_user.email) {
+            return Err(AppError::Conflict("duplicate record".into()));
+        }
+        let user = User {
+            id: Uuid::new_v4(),
+            email: new_user.email,
+            password_hash: new_user.password_hash,
+            role: new_user.role,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        };
+        users.push(user.clone());
+        Ok(user)
+    }
+
+    async fn find_by_email(&self, email: &str) -> Result<Option<User>> {
+        Ok(self
+            .users
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|u| u.email == email)
+            .cloned())
+    }
+
+    async fn find_by_id(&self, id: Uuid) -> Result<Option<User>> {
+        Ok(self
+            .users
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|u| u.id == id)
+            .cloned())
+    }
+
+    async fn list_users(&self, _query: &UserQuery) -> Result<Vec<User>> {
+        Ok(self.users.lock().unwrap().clone())
+    }
+
+    async fn count_users(&self, _filter: &UserFilter) -> Result<i64> {
+        Ok(self.users.lock().unwrap().len() as i64)
+    }
+
+    async fn update_role(&self, id: Uuid, role: UserRole) -> Result<Option<User>> {
+        let mut users = self.users.lock().unwrap();
+        let user = users.iter_mut().find(|u| u.id == id).map(|u| {
+            u.role = role;
+            u.clone()
+        });
+        Ok(user)
+    }
+
+    async fn update_password_hash(&self, id: Uuid, password_hash: &str) -> Result<()> {
+        let mut users = self.users.lock().unwrap();
+        if let Some(user) = users.iter_mut().find(|u| u.id == id) {
+            user.password_hash = password_hash.to_string();
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RefreshTokenRepository for FakeRepo {
+    async fn store_refresh_token(&self, _token: &RefreshToken) -> Result<()> {
+        Ok(())
+    }
+
+    async fn find_refresh_token(&self, _token_hash: &str) -> Result<Option<RefreshToken>> {
+        Ok(None)
+    }
+
+    async fn delete_refresh_token(&self, _id: Uuid) -> Result<bool> {
+        Ok(false)
+    }
+
+    async fn delete_tokens_for_user(&self, _user_id: Uuid) -> Result<()> {
+        Ok(())
+    }
+
+    async fn delete_expired_tokens(&self, _now: DateTime<Utc>) -> Result<u64> {
+        Ok(0)
+    }
+}
+
+#[async_trait]
+impl AuditLogRepository for FakeRepo {
+    async fn log_event(&self, _event: AuditEvent) -> Result<()> {
+        Ok(())
+    }
+
+    async fn list_events(&self, _query: &AuditQuery) -> Result<Vec<AuditEvent>> {
+        Ok(Vec::new())
+    }
+}
+
+#[async_trait]
+impl OutboxRepository for FakeRepo {
+    async fn append_outbox(&self, envelope: &EventEnvelope) -> Result<()> {
+        self.outbox.lock().unwrap().push(envelope.clone());
+        Ok(())
+    }
+
+    async fn claim_outbox(&self, _limit: i64) -> Result<Vec<OutboxEntry>> {
+        Ok(Vec::new())
+    }
+
+    async fn mark_outbox_delivered(&self, _id: Uuid, _subscriber: &str) -> Result<()> {
+        Ok(())
+    }
+
+    async fn complete_outbox(&self, _id: Uuid) -> Result<()> {
+        Ok(())
+    }
+
+    async fn retry_outbox(
+        &self,
+        _id: Uuid,
+        _error: &str,
+        _retry_at: Option<DateTime<Utc>>,
+    ) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl PendingRegistrationRepository for FakeRepo {
+    async fn store_pending_registration(&self, pending: &PendingRegistration) -> Result<()> {
+        self.pending.lock().unwrap().push(pending.clone());
+        Ok(())
+    }
+
+    async fn take_pending_registration(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<PendingRegistration>> {
+        let mut pending = self.pending.lock().unwrap();
+        let idx = pending.iter().position(|p| p.token_hash == token_hash);
+        Ok(idx.map(|i| pending.remove(i)))
+    }
+
+    async fn delete_expired_pending_registrations(&self, now: DateTime<Utc>) -> Result<u64> {
+        let mut pending = self.pending.lock().unwrap();
+        let before = pending.len();
+        pending.retain(|p| p.expires_at >= now);
+        Ok((before - pending.len()) as u64)
+    }
+}
+
+#[derive(Default)]
+struct RecordingNotifier {
+    links: Mutex<Vec<(String, String)>>,
+    notices: Mutex<Vec<String>>,
+}
+
+#[async_trait]
+impl RegistrationNotifier for RecordingNotifier {
+    async fn send_signup_link(&self, email: &str, token: &str) -> Result<()> {
+        self.links
+            .lock()
+            .unwrap()
+            .push((email.to_string(), token.to_string()));
+        Ok(())
+    }
+
+    async fn send_existing_account_notice(&self, email: &str) -> Result<()> {
+        self.notices.lock().unwrap().push(email.to_string());
+        Ok(())
+    }
+}
+
+/// Fails events published outside a transaction, as when the outbox cannot be reached.
+struct UnavailablePublisher;
+
+#[async_trait]
+impl EventPublisher for UnavailablePublisher {
+    async fn publish(&self, _envelope: EventEnvelope) -> Result<()> {
+        Err(AppError::Unavailable("outbox is down".into()))
+    }
+}
+
+const EXISTING_EMAIL: &str = "taken@example.com";
+const PASSWORD: &str = "correct-horse-battery";
+
+fn ctx() -> RequestContext {
+    RequestContext::default()
+}
+
+async fn setup() -> (AuthService<FakeRepo>, Arc<RecordingNotifier>) {
+    setup_with(FakeRepo::default()).await
+}
+
+async fn setup_with(repo: FakeRepo) -> (AuthService<FakeRepo>, Arc<RecordingNotifier>) {
+    let notifier = Arc::new(RecordingNotifier::default());
+    let hasher = RefreshTokenHasher::new("test-refresh-secret-that-is-32-chars!");
+    let auth = AuthService::new(Arc::new(repo), hasher).with_notifier(notifier.clone());
+    auth.register(
+        RegisterRequest {
+            email: EXISTING_EMAIL.into(),
+            password: PASSWORD.into(),
+        },
+        None,
+        &ctx(),
+    )
+    .await
+    .expect("seed user");
+    (auth, notifier)
+}
+
+fn login(email: &str, password: &str) -> LoginRequest {
+    LoginRequest {
+        email: email.into(),
+        password: password.into(),
+    }
+}
+
+fn signup(email: &str) -> RegisterRequest {
+    RegisterRequest {
+        email: email.into(),
+        password: PASSWORD.into(),
+    }
+}
+
+fn describe(err: &AppError) -> (u16, &'static str, String) {
+    (err.status().as_u16(), err.code(), err.to_string())
+}
+
+fn median(mut samples: Vec<Duration>) -> Duration {
+    samples.sort();
+    samples[samples.len() / 2]
+}
+
+/// Both medians must be within a factor of two of each other. Without the dummy
+/// verification an unknown email skips Argon2 entirely and is orders of magnitude faster.
+fn assert_similar(a: Duration, b: Duration) {
+    let (fast, slow) = if a < b { (a, b) } else { (b, a) };
+    assert!(
+        slow.as_secs_f64() <= fast.as_secs_f64() * 2.0,
+        "timings differ too much: {a:?} vs {b:?}"
+    );
+}
+
+#[tokio::test]
+async fn login_failures_are_indistinguishable() {
+    let (auth, _) = setup().await;
+
+    let unknown = auth
+        .login(login("nobody@example.com", PASSWORD), &ctx())
+        .await
+        .unwrap_err();
+    let wrong_password = auth
+        .login(login(EXISTING_EMAIL, "wrong-password"), &ctx())
+        .await
+        .unwrap_err();
+
+    assert_eq!(describe(&unknown), describe(&wrong_password));
+}
+
+#[tokio::test]
+async fn logins_do_not_fail_on_publishing() {
+    let (auth, _) = setup().await;
+    let auth = auth.with_publisher(Arc::new(UnavailablePublisher));
+
+    let user = auth
+        .login(login(EXISTING_EMAIL, PASSWORD), &ctx())
+        .await
+        .unwrap();
+    assert_eq!(user.email, EXISTING_EMAIL);
+    for (email, password) in [
+        ("nobody@example.com", PASSWORD),
+        (EXISTING_EMAIL, "wrong-password"),
+    ] {
+        let err = auth
+            .login(login(email, password), &ctx())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AppError::Unauthorized), "{err:?}");
+    }
+}
+
+#[tokio::test]
+async fn login_timing_does_not_reveal_unknown_emails() {
+    let (auth, _) = setup().await;
+
+    let mut unknown = Vec::new();
+    let mut wrong_password = Vec::new();
+    for _ in 0..5 {
+        let start = Instant::now();
+        let _ = auth
+            .login(login("nobody@example.com", PASSWORD), &ctx())
+            .await;
+        unknown.push(start.elapsed());
+
+        let start = Instant::now();
+        let _ = auth
+            .login(login(EXISTING_EMAIL, "wrong-password"), &ctx())
+            .await;
+        wrong_password.push(start.elapsed());
+    }
+
+    assert_similar(median(unknown), median(wrong_password));
+}
+
+#[tokio::test]
+async fn registration_request_does_not_reveal_taken_emails() {
+    let (auth, notifier) = setup().await;
+
+    let taken = auth
+        .request_registration(signup(EXISTING_EMAIL), 60, &ctx())
+        .await;
+    let fresh = auth
+        .request_registration(signup("fresh@example.com"), 60, &ctx())
+        .await;
+
+    assert!(taken.is_ok());
+    assert!(fresh.is_ok());
+    assert_eq!(*notifier.notices.lock().unwrap(), vec![EXISTING_EMAIL]);
+    let links = notifier.links.lock().unwrap();
+    assert_eq!(links.len(), 1);
+    assert_eq!(links[0].0, "fresh@example.com");
+}
+
+#[tokio::test]
+async fn registration_request_timing_does_not_reveal_taken_emails() {
+    let (auth, _) = setup().await;
+
+    let mut taken = Vec::new();
+    let mut fresh = Vec::new();
+    for i in 0..5 {
+        let start = Instant::now();
+        let _ = auth
+            .request_registration(signup(EXISTING_EMAIL), 60, &ctx())
+            .await;
+        taken.push(start.elapsed());
+
+        let start = Instant::now();
+        let _ = auth
+            .request_registration(signup(&format!("fresh{i}@example.com")), 60, &ctx())
+            .await;
+        fresh.push(start.elapsed());
+    }
+
+    assert_similar(median(taken), median(fresh));
+}
+
+/// The in-memory fake cannot show a timing gap that writes would cause against a real
+/// database, so check that both cases make the same writes.
+#[tokio::test]
+async fn registration_request_writes_the_same_for_taken_emails() {
+    let repo = FakeRepo::default();
+    let (auth, _) = setup_with(repo.clone()).await;
+    let writes = || {
+        (
+            repo.pending.lock().unwrap().len(),
+            repo.outbox.lock().unwrap().len(),
+        )
+    };
+
+    let before = writes();
+    auth.request_registration(signup(EXISTING_EMAIL), 60, &ctx())
+        .await
+        .unwrap();
+    let after_taken = writes();
+    auth.request_registration(signup("fresh@example.com"), 60, &ctx())
+        .await
+        .unwrap();
+    let after_fresh = writes();
+
+    assert_eq!(after_taken, (before.0 + 1, before.1 + 1));
+    assert_eq!(after_fresh, (after_taken.0 + 1, after_taken.1 + 1));
+}
+
+#[tokio::test]
+async fn signup_link_creates_the_account_once() {
+    let (auth, notifier) = setup().await;
+    auth.request_registration(signup("fresh@example.com"), 60, &ctx())
+        .await
+        .unwrap();
+    let token = notifier.links.lock().unwrap()[0].1.clone();
+
+    let user = auth.confirm_registration(&token, &ctx()).await.unwrap();
+    assert_eq!(user.email, "fresh@example.com");
+    assert!(auth
+        .login(login("fresh@example.com", PASSWORD), &ctx())
+        .await
+        .is_ok());
+
+    let reused = auth.confirm_registration(&token, &ctx()).await.unwrap_err();
+    assert!(matches!(reused, AppError::Unauthorized));
+}
+
+#[tokio::test]
+async fn expired_signup_link_is_rejected() {
+    let (auth, notifier) = setup().await;
+    auth.request_registration(signup("late@example.com"), -1, &ctx())
+        .await
+        .unwrap();
+    let token = notifier.links.lock().unwrap()[0].1.clone();
+
+    let err = auth.confirm_registration(&token, &ctx()).await.unwrap_err();
+    assert!(matches!(err, AppError::Unauthorized));
+}
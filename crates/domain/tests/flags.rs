@@ -0,0 +1,97 @@
+use domain::flags::{rollout_bucket, FeatureFlag, FlagContext};
+use shared::types::UserRole;
+use uuid::Uuid;
+
+fn flag(rollout_percentage: u8) -> FeatureFlag {
+    let mut flag = FeatureFlag::new("new-dashboard").unwrap();
+    flag.enabled = true;
+    flag.rollout_percentage = rollout_percentage;
+    flag
+}
+
+/// A fixed population, so bucket counts do not vary between runs.
+fn users() -> impl Iterator<Item = Uuid> {
+    (0..1000u128).map(Uuid::from_u128)
+}
+
+fn user(user_id: Uuid) -> FlagContext {
+    FlagContext {
+        user_id: Some(user_id),
+        role: Some(UserRole::User),
+        org: None,
+    }
+}
+
+fn enabled_for(flag: &FeatureFlag) -> Vec<Uuid> {
+    users().filter(|id| flag.evaluate(&user(*id))).collect()
+}
+
+#[test]
+fn targeting_turns_the_flag_on_regardless_of_rollout() {
+    let targeted = Uuid::new_v4();
+    let mut flag = flag(0);
+    flag.users = vec![targeted];
+    flag.roles = vec![UserRole::Admin];
+    flag.orgs = vec!["acme".into()];
+
+    assert!(flag.evaluate(&user(targeted)));
+    let admin = FlagContext {
+        role: Some(UserRole::Admin),
+        ..user(Uuid::new_v4())
+    };
+    assert!(flag.evaluate(&admin));
+    assert!(flag.evaluate(&user(Uuid::new_v4()).org("acme")));
+    assert!(!flag.evaluate(&user(Uuid::new_v4()).org("globex")));
+    assert!(!flag.evaluate(&FlagContext::anonymous()));
+}
+
+#[test]
+fn disabled_flags_are_off_even_for_targets() {
+    let targeted = Uuid::new_v4();
+    let mut flag = flag(100);
+    flag.users = vec![targeted];
+    flag.enabled = false;
+
+    assert!(!flag.evaluate(&user(targeted)));
+    assert!(!flag.evaluate(&user(Uuid::new_v4())));
+    assert!(!flag.evaluate(&FlagContext::anonymous()));
+}
+
+#[test]
+fn rollout_bounds() {
+    assert!(enabled_for(&flag(0)).is_empty());
+    assert_eq!(enabled_for(&flag(100)).len(), 1000);
+    // Anonymous visitors get full rollouts only.
+    assert!(flag(100).evaluate(&FlagContext::anonymous()));
+    assert!(!flag(99).evaluate(&FlagContext::anonymous()));
+
+    let partial = enabled_for(&flag(30)).len();
+    assert!((250..=350).contains(&partial), "{partial} of 1000 users");
+}
+
+#[test]
+fn buckets_are_stable_and_independent_between_flags() {
+    for user_id in users() {
+        let bucket = rollout_bucket("new-dashboard", user_id);
+        assert!(bucket < 100);
+        assert_eq!(rollout_bucket("new-dashboard", user_id), bucket);
+    }
+    let differing = users()
+        .filter(|id| rollout_bucket("new-dashboard", *id) != rollout_bucket("dark-mode", *id))
+        .count();
+    assert!(differing > 900, "only {differing} of 1000 buckets differ");
+}
+
+#[test]
+fn raising_the_percentage_only_adds_users() {
+    let mut previous = Vec::new();
+    for percentage in 0..=100 {
+        let enabled = enabled_for(&flag(percentage));
+        assert!(
+            previous.iter().all(|id| enabled.contains(id)),
+            "{percentage}% dropped users"
+        );
+        assert!(enabled.len() >= previous.len());
+        previous = enabled;
+    }
+}
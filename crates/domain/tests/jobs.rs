@@ -0,0 +1,179 @@
+use chrono::{DateTime, Duration, Utc};
+use domain::jobs::{retry_backoff, CronSchedule};
+
+fn at(timestamp: &str) -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339(timestamp).unwrap().into()
+}
+
+/// The next `count` times `expr` fires after `after`.
+fn runs(expr: &str, after: &str, count: usize) -> Vec<DateTime<Utc>> {
+    let schedule = CronSchedule::parse(expr).unwrap();
+    let mut t = at(after);
+    (0..count)
+        .map(|_| {
+            t = schedule.next_after(t).unwrap();
+            t
+        })
+        .collect()
+}
+
+fn times(timestamps: &[&str]) -> Vec<DateTime<Utc>> {
+    timestamps.iter().map(|t| at(t)).collect()
+}
+
+#[test]
+fn fires_strictly_after_the_given_time() {
+    assert_eq!(
+        runs("*/15 * * * *", "2024-05-01T10:07:30Z", 3),
+        times(&[
+            "2024-05-01T10:15:00Z",
+            "2024-05-01T10:30:00Z",
+            "2024-05-01T10:45:00Z",
+        ])
+    );
+    assert_eq!(
+        runs("*/15 * * * *", "2024-05-01T10:45:00Z", 1),
+        times(&["2024-05-01T11:00:00Z"])
+    );
+    assert_eq!(
+        runs("@yearly", "2024-12-31T23:59:59Z", 1),
+        times(&["2025-01-01T00:00:00Z"])
+    );
+}
+
+#[test]
+fn steps_ranges_and_lists() {
+    // A step from a single value runs to the end of the field.
+    assert_eq!(
+        runs("5/20 * * * *", "2024-05-01T10:00:00Z", 4),
+        times(&[
+            "2024-05-01T10:05:00Z",
+            "2024-05-01T10:25:00Z",
+            "2024-05-01T10:45:00Z",
+            "2024-05-01T11:05:00Z",
+        ])
+    );
+    assert_eq!(
+        runs("30 9-17/4 * * *", "2024-05-01T00:00:00Z", 4),
+        times(&[
+            "2024-05-01T09:30:00Z",
+            "2024-05-01T13:30:00Z",
+            "2024-05-01T17:30:00Z",
+            "2024-05-02T09:30:00Z",
+        ])
+    );
+    assert_eq!(
+        runs("0 0 1,15 6-7 *", "2024-01-10T00:00:00Z", 5),
+        times(&[
+            "2024-06-01T00:00:00Z",
+            "2024-06-15T00:00:00Z",
+            "2024-07-01T00:00:00Z",
+            "2024-07-15T00:00:00Z",
+            "2025-06-01T00:00:00Z",
+        ])
+    );
+    // Months without a 31st are skipped.
+    assert_eq!(
+        runs("0 0 31 * *", "2024-04-01T00:00:00Z", 2),
+        times(&["2024-05-31T00:00:00Z", "2024-07-31T00:00:00Z"])
+    );
+}
+
+#[test]
+fn restricted_day_fields_match_either_one() {
+    // 2024-04-29 and 2024-05-06 are Mondays.
+    assert_eq!(
+        runs("0 0 1 * 1", "2024-04-28T00:00:00Z", 3),
+        times(&[
+            "2024-04-29T00:00:00Z",
+            "2024-05-01T00:00:00Z",
+            "2024-05-06T00:00:00Z",
+        ])
+    );
+    // With one of them left as `*`, only the other counts.
+    assert_eq!(
+        runs("0 0 * * 1", "2024-04-28T00:00:00Z", 2),
+        times(&["2024-04-29T00:00:00Z", "2024-05-06T00:00:00Z"])
+    );
+    assert_eq!(
+        runs("0 0 1 * *", "2024-04-28T00:00:00Z", 2),
+        times(&["2024-05-01T00:00:00Z", "2024-06-01T00:00:00Z"])
+    );
+}
+
+#[test]
+fn sunday_is_both_zero_and_seven() {
+    let sunday = times(&["2024-05-05T00:00:00Z", "2024-05-12T00:00:00Z"]);
+    assert_eq!(runs("0 0 * * 0", "2024-05-01T00:00:00Z", 2), sunday);
+    assert_eq!(runs("0 0 * * 7", "2024-05-01T00:00:00Z", 2), sunday);
+    assert_eq!(runs("@weekly", "2024-05-01T00:00:00Z", 2), sunday);
+    assert_eq!(
+        CronSchedule::parse("0 0 * * 7").unwrap(),
+        CronSchedule::parse("0 0 * * 0").unwrap()
+    );
+
+    // 2024-05-03 is a Friday.
+    assert_eq!(
+        runs("0 0 * * 5-7", "2024-05-01T00:00:00Z", 4),
+        times(&[
+            "2024-05-03T00:00:00Z",
+            "2024-05-04T00:00:00Z",
+            "2024-05-05T00:00:00Z",
+            "2024-05-10T00:00:00Z",
+        ])
+    );
+}
+
+#[test]
+fn impossible_dates_never_fire() {
+    for expr in ["0 0 31 2 *", "0 0 30 2 *", "0 0 31 4,6,9,11 *"] {
+        let schedule = CronSchedule::parse(expr).unwrap();
+        assert_eq!(
+            schedule.next_after(at("2024-01-01T00:00:00Z")),
+            None,
+            "{expr}"
+        );
+    }
+    // Leap days do come round.
+    assert_eq!(
+        runs("0 0 29 2 *", "2024-03-01T00:00:00Z", 1),
+        times(&["2028-02-29T00:00:00Z"])
+    );
+}
+
+#[test]
+fn invalid_expressions_are_rejected() {
+    for expr in [
+        "",
+        "* * * *",
+        "* * * * * *",
+        "60 * * * *",
+        "* 24 * * *",
+        "* * 0 * *",
+        "* * 32 * *",
+        "* * * 0 *",
+        "* * * 13 *",
+        "* * * * 8",
+        "*/0 * * * *",
+        "5-1 * * * *",
+        "1,,2 * * * *",
+        "a * * * *",
+        "@often",
+    ] {
+        assert!(CronSchedule::parse(expr).is_err(), "{expr:?}");
+    }
+}
+
+#[test]
+fn retry_backoff_doubles_up_to_an_hour() {
+    let seconds = |attempts| retry_backoff(attempts).num_seconds();
+    assert_eq!(seconds(1), 30);
+    assert_eq!(seconds(2), 60);
+    assert_eq!(seconds(3), 120);
+    assert_eq!(seconds(7), 1920);
+    assert_eq!(seconds(8), 3600);
+    assert_eq!(retry_backoff(i32::MAX), Duration::hours(1));
+    // Jobs that have not run yet back off like a first failure.
+    assert_eq!(seconds(0), 30);
+    assert_eq!(seconds(-1), 30);
+}
@@ -0,0 +1,57 @@
+use domain::RefreshTokenHasher;
+use sha2::{Digest, Sha256};
+use shared::config::AuthConfig;
+
+const CURRENT: &str = "current-refresh-secret-that-is-32-chars";
+const PREVIOUS: &str = "previous-refresh-secret-that-is-32-chars";
+const RAW: &str = "raw-refresh-token";
+
+fn legacy_hash(raw: &str) -> String {
+    format!("{:x}", Sha256::digest(raw.as_bytes()))
+}
+
+#[test]
+fn new_tokens_use_the_current_secret() {
+    let hasher = RefreshTokenHasher::new(CURRENT).with_previous([PREVIOUS]);
+    let hash = hasher.hash(RAW);
+
+    assert_eq!(hash, RefreshTokenHasher::new(CURRENT).hash(RAW));
+    assert_ne!(hash, RefreshTokenHasher::new(PREVIOUS).hash(RAW));
+    assert_ne!(hash, legacy_hash(RAW));
+    assert_eq!(hasher.candidates(RAW)[0], hash);
+}
+
+#[test]
+fn tokens_hashed_with_a_previous_secret_are_found_after_rotation() {
+    let before = RefreshTokenHasher::new(PREVIOUS).hash(RAW);
+
+    let rotated = RefreshTokenHasher::new(CURRENT).with_previous([PREVIOUS]);
+    assert!(rotated.candidates(RAW).contains(&before));
+
+    let retired = RefreshTokenHasher::new(CURRENT);
+    assert!(!retired.candidates(RAW).contains(&before));
+}
+
+#[test]
+fn legacy_hashes_are_found_only_while_accepted() {
+    let legacy = legacy_hash(RAW);
+
+    let accepting = RefreshTokenHasher::new(CURRENT).accept_legacy(true);
+    assert!(accepting.candidates(RAW).contains(&legacy));
+
+    let rejecting = RefreshTokenHasher::new(CURRENT).accept_legacy(false);
+    assert!(!rejecting.candidates(RAW).contains(&legacy));
+}
+
+#[test]
+fn legacy_hashes_are_rejected_by_default() {
+    let config = AuthConfig {
+        refresh_secret: CURRENT.into(),
+        previous_refresh_secrets: vec![PREVIOUS.into()],
+        ..AuthConfig::default()
+    };
+    let candidates = RefreshTokenHasher::from_config(&config).candidates(RAW);
+
+    assert_eq!(candidates.len(), 2);
+    assert!(!candidates.contains(&legacy_hash(RAW)));
+}
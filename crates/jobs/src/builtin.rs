@@ -0,0 +1,104 @@
+//! Housekeeping jobs every deployment runs.
+
+use crate::registry::{JobHandler, JobRegistry};
+use async_trait::async_trait;
+use chrono::{Duration, Utc};
+use domain::jobs::{Job, JobKind};
+use domain::models::{AuditEventBuilder, AuditEventType};
+use domain::ports::{
+    AuditChainRepository, AuditLogRepository, PendingRegistrationRepository, RefreshTokenRepository,
+};
+use serde::{Deserialize, Serialize};
+use shared::config::AppConfig;
+use shared::error::Result;
+use std::sync::Arc;
+use tracing::info;
+
+/// Deletes expired refresh tokens and unconfirmed registrations.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CleanupExpiredTokens {}
+
+impl JobKind for CleanupExpiredTokens {
+    const KIND: &'static str = "auth.cleanup_expired_tokens";
+}
+
+/// Drops whole audit-log days older than `retention_days`, with their checkpoints.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditRetention {
+    pub retention_days: u32,
+}
+
+impl JobKind for AuditRetention {
+    const KIND: &'static str = "audit.retention";
+}
+
+pub struct CleanupExpiredTokensHandler<R> {
+    repo: Arc<R>,
+}
+
+#[async_trait]
+impl<R> JobHandler<CleanupExpiredTokens> for CleanupExpiredTokensHandler<R>
+where
+    R: RefreshTokenRepository + PendingRegistrationRepository + 'static,
+{
+    async fn handle(&self, _payload: CleanupExpiredTokens, _job: &Job) -> Result<()> {
+        let now = Utc::now();
+        let tokens = self.repo.delete_expired_tokens(now).await?;
+        let registrations = self.repo.delete_expired_pending_registrations(now).await?;
+        info!(tokens, registrations, "deleted expired tokens");
+        Ok(())
+    }
+}
+
+pub struct AuditRetentionHandler<R> {
+    repo: Arc<R>,
+}
+
+#[async_trait]
+impl<R> JobHandler<AuditRetention> for AuditRetentionHandler<R>
+where
+    R: AuditChainRepository + AuditLogRepository + 'static,
+{
+    async fn handle(&self, payload: AuditRetention, _job: &Job) -> Result<()> {
+        let before = Utc::now().date_naive() - Duration::days(payload.retention_days.into());
+        let deleted = self.repo.purge_days_before(before).await?;
+        if deleted > 0 {
+            info!(deleted, %before, "purged audit events past retention");
+            let event = AuditEventBuilder::new(AuditEventType::AuditPurged)
+                .metadata(serde_json::json!({
+                    "before": before.to_string(),
+                    "deleted": deleted,
+                }))
+                .build();
+            self.repo.log_event(event).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Registers the built-in handlers on `registry` along with their schedules: token
+/// cleanup at the top of every hour and audit retention daily at 03:30 UTC.
+pub fn register<R>(registry: JobRegistry, repo: Arc<R>, config: &AppConfig) -> Result<JobRegistry>
+where
+    R: RefreshTokenRepository
+        + PendingRegistrationRepository
+        + AuditChainRepository
+        + AuditLogRepository
+        + 'static,
+{
+    registry
+        .register(CleanupExpiredTokensHandler { repo: repo.clone() })
+        .register(AuditRetentionHandler { repo })
+        .schedule(
+            "cleanup-expired-tokens",
+            "0 * * * *",
+            &CleanupExpiredTokens {},
+        )?
+        .schedule(
+            "audit-retention",
+            "30 3 * * *",
+            &AuditRetention {
+                retention_days: config.audit.retention_days,
+            },
+        )
+}
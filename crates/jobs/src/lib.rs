@@ -0,0 +1,6 @@
+pub mod builtin;
+mod registry;
+mod worker;
+
+pub use registry::{JobHandler, JobRegistry};
+pub use worker::Worker;
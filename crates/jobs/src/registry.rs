@@ -0,0 +1,90 @@
+use async_trait::async_trait;
+use domain::jobs::{Job, JobKind, JobSchedule};
+use shared::error::Result;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+/// Runs jobs of kind `J`. Returning an error retries the job with backoff until it runs
+/// out of attempts, after which it is dead-lettered.
+#[async_trait]
+pub trait JobHandler<J: JobKind>: Send + Sync + 'static {
+    async fn handle(&self, payload: J, job: &Job) -> Result<()>;
+}
+
+pub(crate) enum JobFailure {
+    /// Worth another attempt.
+    Retry(String),
+    /// Will fail the same way every time, so it goes straight to the dead letters.
+    Fatal(String),
+}
+
+#[async_trait]
+pub(crate) trait ErasedHandler: Send + Sync {
+    async fn run(&self, job: &Job) -> std::result::Result<(), JobFailure>;
+}
+
+struct Typed<J, H> {
+    handler: H,
+    _kind: PhantomData<fn() -> J>,
+}
+
+#[async_trait]
+impl<J, H> ErasedHandler for Typed<J, H>
+where
+    J: JobKind,
+    H: JobHandler<J>,
+{
+    async fn run(&self, job: &Job) -> std::result::Result<(), JobFailure> {
+        let payload: J = serde_json::from_value(job.payload.clone())
+            .map_err(|e| JobFailure::Fatal(format!("invalid {} payload: {e}", J::KIND)))?;
+        self.handler
+            .handle(payload, job)
+            .await
+            .map_err(|e| JobFailure::Retry(e.to_string()))
+    }
+}
+
+/// Maps job kinds to their handlers and collects the recurring schedules to install.
+#[derive(Clone, Default)]
+pub struct JobRegistry {
+    handlers: HashMap<&'static str, Arc<dyn ErasedHandler>>,
+    schedules: Vec<JobSchedule>,
+}
+
+impl JobRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register<J, H>(mut self, handler: H) -> Self
+    where
+        J: JobKind,
+        H: JobHandler<J>,
+    {
+        let typed = Typed {
+            handler,
+            _kind: PhantomData,
+        };
+        self.handlers.insert(J::KIND, Arc::new(typed));
+        self
+    }
+
+    /// Enqueues `job` every time `cron` comes due.
+    pub fn schedule<J: JobKind>(mut self, name: &str, cron: &str, job: &J) -> Result<Self> {
+        self.schedules.push(JobSchedule::new(name, cron, job)?);
+        Ok(self)
+    }
+
+    pub fn kinds(&self) -> Vec<String> {
+        self.handlers.keys().map(|kind| kind.to_string()).collect()
+    }
+
+    pub fn schedules(&self) -> &[JobSchedule] {
+        &self.schedules
+    }
+
+    pub(crate) fn handler(&self, kind: &str) -> Option<Arc<dyn ErasedHandler>> {
+        self.handlers.get(kind).cloned()
+    }
+}
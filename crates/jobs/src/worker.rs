@@ -0,0 +1,168 @@
+use crate::registry::{JobFailure, JobRegistry};
+use chrono::Utc;
+use domain::jobs::{retry_backoff, Job};
+use domain::ports::JobRepository;
+use shared::config::JobsConfig;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+use tracing::{debug, error, info, warn};
+
+/// Claims and runs jobs, and enqueues recurring schedules as they come due. Any number of
+/// workers can share the queue; `FOR UPDATE SKIP LOCKED` keeps them off each other's jobs.
+pub struct Worker {
+    repo: Arc<dyn JobRepository>,
+    registry: Arc<JobRegistry>,
+    config: JobsConfig,
+    id: String,
+}
+
+impl Worker {
+    pub fn new(repo: Arc<dyn JobRepository>, registry: JobRegistry, config: JobsConfig) -> Self {
+        let host = std::env::var("HOSTNAME").unwrap_or_else(|_| "worker".into());
+        let suffix = uuid::Uuid::new_v4().simple().to_string();
+        Self {
+            repo,
+            registry: Arc::new(registry),
+            config,
+            id: format!("{host}-{}-{}", std::process::id(), &suffix[..8]),
+        }
+    }
+
+    /// Runs until `shutdown` turns true, then stops claiming and gives running jobs
+    /// `shutdown_grace_seconds` to finish. Jobs still running after that are released
+    /// once their lease expires.
+    pub async fn run(self, mut shutdown: watch::Receiver<bool>) {
+        for schedule in self.registry.schedules() {
+            if let Err(err) = self.repo.upsert_schedule(schedule).await {
+                warn!(error = %err, schedule = %schedule.name, "failed to install job schedule");
+            }
+        }
+
+        let kinds = self.registry.kinds();
+        let poll = Duration::from_millis(self.config.poll_interval_ms.max(50));
+        let lease = Duration::from_secs(self.config.lease_seconds);
+        let timeout = handler_timeout(lease);
+        let mut running = JoinSet::new();
+        info!(worker = %self.id, concurrency = self.config.concurrency, "job worker started");
+
+        while !*shutdown.borrow() {
+            while let Some(result) = running.try_join_next() {
+                if let Err(err) = result {
+                    error!(error = %err, "job task panicked");
+                }
+            }
+            self.housekeeping(lease).await;
+
+            let free = self.config.concurrency.saturating_sub(running.len());
+            let mut claimed = 0;
+            if free > 0 {
+                match self.repo.claim_jobs(&self.id, &kinds, free as i64).await {
+                    Ok(jobs) => {
+                        claimed = jobs.len();
+                        for job in jobs {
+                            running.spawn(run_job(
+                                self.repo.clone(),
+                                self.registry.clone(),
+                                job,
+                                timeout,
+                            ));
+                        }
+                    }
+                    Err(err) => warn!(error = %err, "failed to claim jobs"),
+                }
+            }
+            // A full batch suggests more work is waiting, so poll again right away.
+            if free > 0 && claimed == free {
+                continue;
+            }
+
+            tokio::select! {
+                _ = shutdown.changed() => {}
+                _ = tokio::time::sleep(poll) => {}
+                Some(_) = running.join_next(), if !running.is_empty() => {}
+            }
+        }
+
+        info!(running = running.len(), "job worker stopping");
+        let grace = Duration::from_secs(self.config.shutdown_grace_seconds);
+        let drained = tokio::time::timeout(grace, async {
+            while running.join_next().await.is_some() {}
+        })
+        .await;
+        if drained.is_err() {
+            warn!(
+                abandoned = running.len(),
+                "jobs still running at shutdown; they will be retried after their lease expires"
+            );
+            running.abort_all();
+        }
+    }
+
+    async fn housekeeping(&self, lease: Duration) {
+        let lease = chrono::Duration::from_std(lease).unwrap_or(chrono::Duration::minutes(5));
+        match self.repo.release_stale_jobs(Utc::now() - lease).await {
+            Ok(0) => {}
+            Ok(released) => warn!(released, "released jobs whose lease expired"),
+            Err(err) => warn!(error = %err, "failed to release stale jobs"),
+        }
+        match self.repo.enqueue_due_schedules(Utc::now()).await {
+            Ok(0) => {}
+            Ok(enqueued) => debug!(enqueued, "enqueued scheduled jobs"),
+            Err(err) => warn!(error = %err, "failed to enqueue scheduled jobs"),
+        }
+    }
+}
+
+/// Cuts handlers off before their lease expires, leaving time to record the outcome before
+/// another worker's `release_stale_jobs` would hand the job out again.
+fn handler_timeout(lease: Duration) -> Duration {
+    let margin = (lease / 10).clamp(Duration::from_secs(1), Duration::from_secs(30));
+    lease.saturating_sub(margin).max(lease / 2)
+}
+
+async fn run_job(
+    repo: Arc<dyn JobRepository>,
+    registry: Arc<JobRegistry>,
+    job: Job,
+    timeout: Duration,
+) {
+    let outcome = match registry.handler(&job.kind) {
+        Some(handler) => match tokio::time::timeout(timeout, handler.run(&job)).await {
+            Ok(outcome) => outcome,
+            Err(_) => Err(JobFailure::Retry(format!("timed out after {timeout:?}"))),
+        },
+        None => Err(JobFailure::Fatal(format!(
+            "no handler for job kind {}",
+            job.kind
+        ))),
+    };
+
+    let recorded = match outcome {
+        Ok(()) => {
+            debug!(job_id = %job.id, kind = %job.kind, "job succeeded");
+            repo.complete_job(job.id).await
+        }
+        Err(JobFailure::Fatal(message)) => {
+            error!(job_id = %job.id, kind = %job.kind, error = %message, "job dead-lettered");
+            repo.fail_job(job.id, &message, None).await
+        }
+        Err(JobFailure::Retry(message)) => {
+            let retry_at =
+                (job.attempts < job.max_attempts).then(|| Utc::now() + retry_backoff(job.attempts));
+            match retry_at {
+                Some(at) => {
+                    warn!(job_id = %job.id, kind = %job.kind, error = %message, retry_at = %at, "job failed")
+                }
+                None => {
+                    error!(job_id = %job.id, kind = %job.kind, error = %message, "job out of attempts; dead-lettered")
+                }
+            }
+            repo.fail_job(job.id, &message, retry_at).await
+        }
+    };
+    if let Err(err) = recorded {
+        error!(job_id = %job.id, error = %err, "failed to record job outcome");
+    }
+}
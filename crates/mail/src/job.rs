@@ -0,0 +1,100 @@
+use crate::templates::MailTemplate;
+use crate::transport::{Mail, MailTransport};
+use async_trait::async_trait;
+use chrono::Utc;
+use domain::jobs::{Job, JobKind, NewJob};
+use domain::ports::JobRepository;
+use jobs::JobHandler;
+use serde::{Deserialize, Serialize};
+use shared::error::Result;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Renders `template` and delivers it to `to`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SendMail {
+    pub to: String,
+    pub template: MailTemplate,
+}
+
+impl JobKind for SendMail {
+    const KIND: &'static str = "mail.send";
+    const MAX_ATTEMPTS: i32 = 8;
+}
+
+pub struct SendMailHandler {
+    transport: Arc<dyn MailTransport>,
+    from: String,
+    app_name: String,
+}
+
+impl SendMailHandler {
+    pub fn new(
+        transport: Arc<dyn MailTransport>,
+        from: impl Into<String>,
+        app_name: impl Into<String>,
+    ) -> Self {
+        Self {
+            transport,
+            from: from.into(),
+            app_name: app_name.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl JobHandler<SendMail> for SendMailHandler {
+    async fn handle(&self, payload: SendMail, job: &Job) -> Result<()> {
+        let rendered = payload.template.render(&self.app_name);
+        let mail = Mail {
+            // Stable across retries, so a captured or logged message maps back to its job.
+            id: job.id,
+            from: self.from.clone(),
+            to: payload.to,
+            subject: rendered.subject,
+            html: rendered.html,
+            text: rendered.text,
+            created_at: Utc::now(),
+        };
+        self.transport.send(&mail).await?;
+        tracing::info!(
+            template = payload.template.name(),
+            job_id = %job.id,
+            "email sent"
+        );
+        Ok(())
+    }
+}
+
+/// Queues email for background delivery, so a slow or failing mail server never holds
+/// up a request and failed sends are retried.
+#[derive(Clone)]
+pub struct Mailer {
+    jobs: Arc<dyn JobRepository>,
+}
+
+impl Mailer {
+    pub fn new(jobs: Arc<dyn JobRepository>) -> Self {
+        Self { jobs }
+    }
+
+    pub async fn send(&self, to: &str, template: MailTemplate) -> Result<Uuid> {
+        self.send_in(self.jobs.as_ref(), to, template).await
+    }
+
+    /// Queues the email through `jobs`, such as a transaction, so it is only sent if the
+    /// rest of the transaction is kept.
+    pub async fn send_in(
+        &self,
+        jobs: &dyn JobRepository,
+        to: &str,
+        template: MailTemplate,
+    ) -> Result<Uuid> {
+        let job = NewJob::of(&SendMail {
+            to: to.to_string(),
+            template,
+        })?;
+        let job = jobs.enqueue_job(job).await?;
+        Ok(job.id)
+    }
+}
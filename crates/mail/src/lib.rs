@@ -0,0 +1,7 @@
+mod job;
+mod templates;
+mod transport;
+
+pub use job::{Mailer, SendMail, SendMailHandler};
+pub use templates::{escape_html, MailTemplate, RenderedMail};
+pub use transport::{transport_from_config, Mail, MailTransport, MemoryTransport, SmtpTransport};
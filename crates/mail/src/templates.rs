@@ -0,0 +1,176 @@
+use serde::{Deserialize, Serialize};
+
+/// Every email the app sends. Jobs store these in their payload and render them at
+/// delivery time, so renamed variants need a serde alias until old jobs have drained.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "template", rename_all = "snake_case")]
+pub enum MailTemplate {
+    ConfirmRegistration {
+        confirm_url: String,
+        expires_in_minutes: u64,
+    },
+    /// Sent instead of a confirmation link when someone signs up with a registered address.
+    ExistingAccount {
+        login_url: String,
+    },
+    PasswordChanged {
+        login_url: String,
+    },
+    RoleChanged {
+        role: String,
+        login_url: String,
+    },
+    /// The email copy of an in-app notification.
+    Notification {
+        title: String,
+        body: String,
+        url: String,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct RenderedMail {
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+}
+
+impl MailTemplate {
+    pub fn name(&self) -> &'static str {
+        match self {
+            MailTemplate::ConfirmRegistration { .. } => "confirm_registration",
+            MailTemplate::ExistingAccount { .. } => "existing_account",
+            MailTemplate::PasswordChanged { .. } => "password_changed",
+            MailTemplate::RoleChanged { .. } => "role_changed",
+            MailTemplate::Notification { .. } => "notification",
+        }
+    }
+
+    pub fn render(&self, app_name: &str) -> RenderedMail {
+        let app = escape_html(app_name);
+        let (subject, text, body) = match self {
+            MailTemplate::ConfirmRegistration {
+                confirm_url,
+                expires_in_minutes,
+            } => (
+                format!("Confirm your {app_name} account"),
+                format!(
+                    "Finish creating your {app_name} account by opening this link:\n\n\
+                     {confirm_url}\n\n\
+                     The link expires in {expires_in_minutes} minutes. \
+                     If you did not sign up, you can ignore this email.\n"
+                ),
+                format!(
+                    "<p>Finish creating your {app} account:</p>\
+                     {button}\
+                     <p>The link expires in {expires_in_minutes} minutes. \
+                     If you did not sign up, you can ignore this email.</p>",
+                    button = button(confirm_url, "Confirm account"),
+                ),
+            ),
+            MailTemplate::ExistingAccount { login_url } => (
+                format!("Sign-up attempt for your {app_name} account"),
+                format!(
+                    "Someone tried to create a {app_name} account with this email address, \
+                     which already has one.\n\n\
+                     If it was you, log in instead: {login_url}\n\n\
+                     If it wasn't, no action is needed.\n"
+                ),
+                format!(
+                    "<p>Someone tried to create a {app} account with this email address, \
+                     which already has one.</p>\
+                     <p>If it was you, log in instead:</p>\
+                     {button}\
+                     <p>If it wasn't, no action is needed.</p>",
+                    button = button(login_url, "Log in"),
+                ),
+            ),
+            MailTemplate::PasswordChanged { login_url } => (
+                format!("Your {app_name} password was changed"),
+                format!(
+                    "The password for your {app_name} account was just changed and all \
+                     sessions were signed out.\n\n\
+                     If you did not do this, reset your password and contact support. \
+                     Log in: {login_url}\n"
+                ),
+                format!(
+                    "<p>The password for your {app} account was just changed and all \
+                     sessions were signed out.</p>\
+                     <p>If you did not do this, reset your password and contact support.</p>\
+                     {button}",
+                    button = button(login_url, "Log in"),
+                ),
+            ),
+            MailTemplate::RoleChanged { role, login_url } => (
+                format!("Your {app_name} role changed"),
+                format!(
+                    "An administrator changed your {app_name} role to {role}. \
+                     Log in again to continue: {login_url}\n"
+                ),
+                format!(
+                    "<p>An administrator changed your {app} role to <strong>{role}</strong>. \
+                     Log in again to continue.</p>\
+                     {button}",
+                    role = escape_html(role),
+                    button = button(login_url, "Log in"),
+                ),
+            ),
+            MailTemplate::Notification { title, body, url } => (
+                title.clone(),
+                format!("{body}\n\nOpen {app_name}: {url}\n"),
+                format!(
+                    "<p><strong>{title}</strong></p><p>{body}</p>{button}",
+                    title = escape_html(title),
+                    body = escape_html(body),
+                    button = button(url, &format!("Open {app_name}")),
+                ),
+            ),
+        };
+        RenderedMail {
+            html: layout(&subject, &body),
+            subject,
+            text,
+        }
+    }
+}
+
+fn layout(title: &str, body: &str) -> String {
+    format!(
+        r#"<!doctype html>
+<html lang="en">
+  <head>
+    <meta charset="utf-8">
+    <title>{title}</title>
+  </head>
+  <body style="margin:0;padding:24px;background:#f8fafc;font-family:system-ui,sans-serif;color:#0f172a">
+    <div style="max-width:560px;margin:0 auto;padding:24px;background:#ffffff;border-radius:8px">
+      {body}
+    </div>
+  </body>
+</html>"#,
+        title = escape_html(title),
+    )
+}
+
+fn button(url: &str, label: &str) -> String {
+    format!(
+        r#"<p><a href="{url}" style="display:inline-block;padding:10px 16px;background:#10b981;color:#ffffff;border-radius:6px;text-decoration:none">{label}</a></p><p style="font-size:12px;color:#64748b">{url}</p>"#,
+        url = escape_html(url),
+        label = escape_html(label),
+    )
+}
+
+pub fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
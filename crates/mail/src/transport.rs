@@ -0,0 +1,118 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use lettre::message::{Mailbox, MultiPart};
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use shared::config::MailConfig;
+use shared::error::{AppError, Result};
+use shared::types::MailTransportKind;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+/// A rendered message ready for delivery.
+#[derive(Debug, Clone)]
+pub struct Mail {
+    pub id: Uuid,
+    pub from: String,
+    pub to: String,
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[async_trait]
+pub trait MailTransport: Send + Sync {
+    async fn send(&self, mail: &Mail) -> Result<()>;
+}
+
+pub struct SmtpTransport {
+    inner: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpTransport {
+    pub fn from_url(url: &str) -> Result<Self> {
+        let inner = AsyncSmtpTransport::<Tokio1Executor>::from_url(url)
+            .map_err(|e| AppError::config(format!("invalid smtp url: {e}")))?
+            .build();
+        Ok(Self { inner })
+    }
+}
+
+#[async_trait]
+impl MailTransport for SmtpTransport {
+    async fn send(&self, mail: &Mail) -> Result<()> {
+        let parse = |address: &str| {
+            address
+                .parse::<Mailbox>()
+                .map_err(|e| AppError::Validation(format!("invalid address {address}: {e}")))
+        };
+        let message = Message::builder()
+            .from(parse(&mail.from)?)
+            .to(parse(&mail.to)?)
+            .subject(&mail.subject)
+            .multipart(MultiPart::alternative_plain_html(
+                mail.text.clone(),
+                mail.html.clone(),
+            ))
+            .map_err(|e| AppError::internal(format!("failed to build email: {e}")))?;
+        self.inner
+            .send(message)
+            .await
+            .map_err(|e| AppError::internal(format!("smtp delivery failed: {e}")))?;
+        Ok(())
+    }
+}
+
+const MEMORY_CAPACITY: usize = 100;
+
+/// Keeps the last hundred messages in memory instead of sending them. Clones share one mailbox.
+#[derive(Clone, Default)]
+pub struct MemoryTransport {
+    messages: Arc<Mutex<VecDeque<Mail>>>,
+}
+
+impl MemoryTransport {
+    /// Captured messages, newest first.
+    pub fn messages(&self) -> Vec<Mail> {
+        let messages = self.messages.lock().expect("mailbox lock poisoned");
+        messages.iter().rev().cloned().collect()
+    }
+
+    pub fn clear(&self) {
+        self.messages.lock().expect("mailbox lock poisoned").clear();
+    }
+}
+
+#[async_trait]
+impl MailTransport for MemoryTransport {
+    async fn send(&self, mail: &Mail) -> Result<()> {
+        let mut messages = self.messages.lock().expect("mailbox lock poisoned");
+        if messages.len() == MEMORY_CAPACITY {
+            messages.pop_front();
+        }
+        messages.push_back(mail.clone());
+        tracing::info!(to = %mail.to, subject = %mail.subject, "captured email");
+        Ok(())
+    }
+}
+
+/// Builds the configured transport. The in-memory mailbox is returned as well when
+/// messages are captured rather than sent, so it can be shown on `/dev/mail`.
+pub fn transport_from_config(
+    config: &MailConfig,
+) -> Result<(Arc<dyn MailTransport>, Option<MemoryTransport>)> {
+    match config.transport {
+        MailTransportKind::Memory => {
+            let mailbox = MemoryTransport::default();
+            Ok((Arc::new(mailbox.clone()), Some(mailbox)))
+        }
+        MailTransportKind::Smtp => {
+            let url = config
+                .smtp_url
+                .as_deref()
+                .ok_or_else(|| AppError::config("SMTP_URL is required for the smtp transport"))?;
+            Ok((Arc::new(SmtpTransport::from_url(url)?), None))
+        }
+    }
+}
@@ -0,0 +1,27 @@
+use db::Database;
+use domain::audit_chain::{self, CheckpointSigner};
+use shared::config::AuditConfig;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Periodically signs checkpoints for the audit chain heads, when a secret is configured.
+pub fn spawn_checkpoints(db: Database, config: &AuditConfig) {
+    let Some(secret) = config.checkpoint_secret.as_deref() else {
+        warn!("AUDIT_CHECKPOINT_SECRET is not set; audit checkpoints are disabled");
+        return;
+    };
+    let signer = CheckpointSigner::new(secret);
+    let period = Duration::from_secs(config.checkpoint_interval_minutes.max(1) * 60);
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(period);
+        loop {
+            ticker.tick().await;
+            match audit_chain::write_checkpoints(&db, &signer).await {
+                Ok(0) => {}
+                Ok(written) => info!(written, "wrote audit checkpoints"),
+                Err(err) => warn!(error = %err, "failed to write audit checkpoints"),
+            }
+        }
+    });
+}
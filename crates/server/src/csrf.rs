@@ -0,0 +1,207 @@
+use crate::handlers::error_response;
+use crate::security;
+use crate::state::AppState;
+use app::CsrfToken;
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, HeaderValue, Method, Request},
+    middleware::Next,
+    response::{IntoResponse, Redirect, Response},
+};
+use axum_extra::extract::CookieJar;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha256;
+use shared::config::AppConfig;
+use shared::error::AppError;
+use shared::types::RequestId;
+
+/// Shared with the app, which sends the header and renders the form field.
+pub use app::{CSRF_FORM_FIELD, CSRF_HEADER};
+
+const MAX_FORM_BYTES: usize = 64 * 1024;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Issues a token of the form `nonce.signature`, where the signature covers the nonce and
+/// the session it belongs to. Anonymous visitors share the empty session, so tokens minted
+/// before login stop verifying once a refresh cookie exists.
+pub fn issue_token(secret: &str, session: &str) -> String {
+    let mut nonce = [0u8; 24];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    let nonce = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(nonce);
+    let signature = sign(secret, session, &nonce).finalize().into_bytes();
+    format!(
+        "{nonce}.{}",
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(signature)
+    )
+}
+
+pub fn verify_token(secret: &str, session: &str, token: &str) -> bool {
+    let Some((nonce, signature)) = token.split_once('.') else {
+        return false;
+    };
+    let Ok(signature) = base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(signature) else {
+        return false;
+    };
+    sign(secret, session, nonce)
+        .verify_slice(&signature)
+        .is_ok()
+}
+
+fn sign(secret: &str, session: &str, nonce: &str) -> HmacSha256 {
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("hmac accepts keys of any length");
+    mac.update(b"csrf:");
+    mac.update(session.as_bytes());
+    mac.update(b":");
+    mac.update(nonce.as_bytes());
+    mac
+}
+
+/// The session a token is bound to: the refresh cookie when logged in, otherwise empty.
+pub fn session_binding(jar: &CookieJar, config: &AppConfig) -> String {
+    jar.get(&config.auth.refresh_cookie_name)
+        .map(|c| c.value().to_string())
+        .unwrap_or_default()
+}
+
+/// Checks every unsafe request for a token matching the CSRF cookie and the current session,
+/// taken from the `x-csrf-token` header or the `csrf_token` form field. Requests carrying a
+/// valid bearer token are exempt because browsers never attach one on their own.
+///
+/// Safe requests get a fresh token when the cookie is missing or bound to another session;
+/// it is exposed to handlers as a `CsrfToken` extension so pages can render it into forms.
+pub async fn protect(
+    State(state): State<AppState>,
+    mut req: Request<Body>,
+    next: Next,
+) -> Response {
+    let config = &state.config;
+    let jar = CookieJar::from_headers(req.headers());
+    let session = session_binding(&jar, config);
+    let cookie_token = jar
+        .get(&config.auth.csrf_cookie_name)
+        .map(|c| c.value().to_string())
+        .filter(|token| verify_token(&config.auth.csrf_secret, &session, token));
+
+    if is_unsafe(req.method()) && !is_exempt(&req, config) {
+        let (submitted, rebuilt) = submitted_token(req).await;
+        req = match rebuilt {
+            Ok(req) => req,
+            Err(response) => return response,
+        };
+        let valid = matches!(
+            (&submitted, &cookie_token),
+            (Some(submitted), Some(cookie)) if submitted == cookie
+        );
+        if !valid {
+            return reject(&req, config);
+        }
+    }
+
+    let (token, issued) = match cookie_token {
+        Some(token) => (token, false),
+        None => (issue_token(&config.auth.csrf_secret, &session), true),
+    };
+    req.extensions_mut().insert(CsrfToken(token.clone()));
+
+    let skip_cookie = is_static_asset(req.uri().path());
+    let mut res = next.run(req).await;
+    if issued && !skip_cookie && !sets_cookie(&res, &config.auth.csrf_cookie_name) {
+        let cookie = security::build_csrf_cookie(&token, config);
+        if let Ok(value) = HeaderValue::from_str(&cookie.to_string()) {
+            res.headers_mut().append(header::SET_COOKIE, value);
+        }
+    }
+    res
+}
+
+fn is_unsafe(method: &Method) -> bool {
+    !matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE
+    )
+}
+
+fn is_exempt(req: &Request<Body>, config: &AppConfig) -> bool {
+    req.uri().path().starts_with("/api/")
+        && security::bearer_token(req.headers())
+            .is_some_and(|token| security::decode_access_token(&token, &config.auth).is_ok())
+}
+
+fn is_static_asset(path: &str) -> bool {
+    path.starts_with("/pkg/") || path.starts_with("/assets/")
+}
+
+async fn submitted_token(req: Request<Body>) -> (Option<String>, Result<Request<Body>, Response>) {
+    if let Some(token) = req.headers().get(CSRF_HEADER).and_then(|v| v.to_str().ok()) {
+        return (Some(token.to_string()), Ok(req));
+    }
+
+    let is_form = req
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|ct| ct.starts_with("application/x-www-form-urlencoded"));
+    if !is_form {
+        return (None, Ok(req));
+    }
+
+    // The form body has to be read to find the field; hand the handler an identical copy.
+    let (parts, body) = req.into_parts();
+    let bytes = match axum::body::to_bytes(body, MAX_FORM_BYTES).await {
+        Ok(bytes) => bytes,
+        Err(_) => {
+            return (
+                None,
+                Err(axum::http::StatusCode::PAYLOAD_TOO_LARGE.into_response()),
+            )
+        }
+    };
+    let token = serde_urlencoded::from_bytes::<Vec<(String, String)>>(&bytes)
+        .ok()
+        .and_then(|fields| {
+            fields
+                .into_iter()
+                .find(|(name, _)| name == CSRF_FORM_FIELD)
+                .map(|(_, value)| value)
+        });
+    (token, Ok(Request::from_parts(parts, Body::from(bytes))))
+}
+
+fn reject(req: &Request<Body>, config: &AppConfig) -> Response {
+    let path = req.uri().path();
+    if path.starts_with("/api/") {
+        let request_id = req
+            .extensions()
+            .get::<RequestId>()
+            .cloned()
+            .unwrap_or_default();
+        return error_response(AppError::Forbidden, &request_id).into_response();
+    }
+
+    // Browser form posts go back to the page they came from with an explanation.
+    let back = if path.starts_with("/app/") {
+        path.to_string()
+    } else {
+        "/app".to_string()
+    };
+    let jar = CookieJar::new().add(security::build_flash_error_cookie(
+        "Your form expired. Please try again.",
+        config,
+        15,
+    ));
+    (jar, Redirect::to(&back)).into_response()
+}
+
+fn sets_cookie(res: &Response, name: &str) -> bool {
+    let prefix = format!("{name}=");
+    res.headers()
+        .get_all(header::SET_COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .any(|v| v.starts_with(&prefix))
+}
@@ -0,0 +1,133 @@
+use crate::handlers::public;
+use crate::state::AppState;
+use axum::extract::{Request, State};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use db::Database;
+use redis::aio::ConnectionManager;
+use shared::config::DatabaseConfig;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tracing::{info, warn};
+
+const MAX_RECONNECT_DELAY_SECS: u64 = 30;
+/// How often a healthy database is pinged to notice it going away.
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Whether the database answers with its schema up to date. While it does not, page routes
+/// serve a maintenance page and `/api/ready` fails.
+#[derive(Clone, Default)]
+pub struct Readiness {
+    ready: Arc<AtomicBool>,
+}
+
+impl Readiness {
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Relaxed)
+    }
+
+    pub fn set(&self, ready: bool) {
+        if self.ready.swap(ready, Ordering::Relaxed) != ready {
+            if ready {
+                info!("database available");
+            } else {
+                warn!("database unavailable; serving the maintenance page");
+            }
+        }
+    }
+}
+
+/// Keeps `readiness` current by pinging the database. Until `migrated`, it instead applies
+/// pending migrations, or with `auto_migrate` off waits for someone else to, as after a
+/// degraded start.
+pub fn spawn_database_monitor(
+    db: Database,
+    config: &DatabaseConfig,
+    readiness: Readiness,
+    mut migrated: bool,
+) {
+    let auto_migrate = config.auto_migrate;
+    tokio::spawn(async move {
+        let mut delay = 1;
+        loop {
+            let result = if migrated {
+                db.ping().await
+            } else if auto_migrate {
+                db.migrate().await
+            } else {
+                db.ensure_migrated().await
+            };
+            match result {
+                Ok(()) => {
+                    migrated = true;
+                    readiness.set(true);
+                    delay = 1;
+                    tokio::time::sleep(CHECK_INTERVAL).await;
+                }
+                Err(err) => {
+                    warn!(error = %err, "database not ready");
+                    readiness.set(false);
+                    tokio::time::sleep(Duration::from_secs(delay)).await;
+                    delay = (delay * 2).min(MAX_RECONNECT_DELAY_SECS);
+                }
+            }
+        }
+    });
+}
+
+/// Serves the maintenance page instead of page routes while the database is not ready.
+/// API routes, metrics and static assets are left to answer for themselves.
+pub async fn maintenance(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let path = req.uri().path();
+    let page = !["/api/", "/pkg/", "/assets/"]
+        .iter()
+        .any(|prefix| path.starts_with(prefix))
+        && path != "/metrics";
+    if page && !state.readiness.is_ready() {
+        return public::maintenance().into_response();
+    }
+    next.run(req).await
+}
+
+/// The shared Redis connection, once one has been made. The connection manager reconnects
+/// by itself after that.
+#[derive(Clone, Default)]
+pub struct Redis {
+    conn: Arc<OnceLock<ConnectionManager>>,
+}
+
+impl Redis {
+    /// Connects to `client`, retrying in the background with backoff if Redis is not up yet.
+    pub async fn connect(client: redis::Client) -> Self {
+        let redis = Self::default();
+        if let Err(err) = redis.try_connect(&client).await {
+            warn!(error = %err, "redis unavailable; retrying in the background");
+            let redis = redis.clone();
+            tokio::spawn(async move {
+                let mut delay = 1;
+                loop {
+                    tokio::time::sleep(Duration::from_secs(delay)).await;
+                    match redis.try_connect(&client).await {
+                        Ok(()) => return,
+                        Err(err) => warn!(error = %err, "redis still unavailable"),
+                    }
+                    delay = (delay * 2).min(MAX_RECONNECT_DELAY_SECS);
+                }
+            });
+        }
+        redis
+    }
+
+    async fn try_connect(&self, client: &redis::Client) -> redis::RedisResult<()> {
+        let conn = client.get_connection_manager().await?;
+        info!("connected to redis");
+        let _ = self.conn.set(conn);
+        Ok(())
+    }
+
+    /// The connection, or `None` without Redis or until it first answers.
+    pub fn get(&self) -> Option<ConnectionManager> {
+        self.conn.get().cloned()
+    }
+}
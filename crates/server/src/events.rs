@@ -0,0 +1,83 @@
+use async_trait::async_trait;
+use db::Database;
+use domain::events::{EventBus, EventEnvelope, EventSubscriber, OutboxPublisher};
+use domain::ports::EventPublisher;
+use shared::error::Result;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{watch, Notify};
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+const DISPATCH_BATCH: i64 = 100;
+/// Catches events published by other processes, such as the CLI, and due retries.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Appends to the outbox, then wakes the dispatcher so subscribers run right away.
+pub struct WakingPublisher {
+    outbox: OutboxPublisher<Database>,
+    wake: Arc<Notify>,
+}
+
+impl WakingPublisher {
+    pub fn new(db: Arc<Database>, wake: Arc<Notify>) -> Self {
+        Self {
+            outbox: OutboxPublisher::new(db),
+            wake,
+        }
+    }
+}
+
+#[async_trait]
+impl EventPublisher for WakingPublisher {
+    async fn publish(&self, envelope: EventEnvelope) -> Result<()> {
+        self.outbox.publish(envelope).await?;
+        self.wake.notify_one();
+        Ok(())
+    }
+
+    fn committed(&self) {
+        self.wake.notify_one();
+    }
+}
+
+/// Counts domain events by type.
+pub struct MetricsSubscriber;
+
+#[async_trait]
+impl EventSubscriber for MetricsSubscriber {
+    fn name(&self) -> &'static str {
+        "metrics"
+    }
+
+    async fn handle(&self, envelope: &EventEnvelope) -> Result<()> {
+        metrics::increment_counter!(
+            "domain_events_total",
+            "type" => envelope.event.audit_type().as_str()
+        );
+        Ok(())
+    }
+}
+
+/// Hands outbox events to the subscribers until `shutdown` turns true.
+pub fn spawn_dispatcher(
+    db: Database,
+    bus: EventBus,
+    wake: Arc<Notify>,
+    mut shutdown: watch::Receiver<bool>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        while !*shutdown.borrow() {
+            match bus.dispatch_batch(&db, DISPATCH_BATCH).await {
+                Ok(claimed) if claimed as i64 == DISPATCH_BATCH => continue,
+                Ok(_) => {}
+                Err(err) => warn!(error = %err, "failed to dispatch outbox events"),
+            }
+            tokio::select! {
+                _ = wake.notified() => {}
+                _ = tokio::time::sleep(POLL_INTERVAL) => {}
+                _ = shutdown.changed() => {}
+            }
+        }
+    })
+}
@@ -0,0 +1,225 @@
+use crate::handlers::{error_response, public};
+use crate::security;
+use crate::state::AppState;
+use axum::extract::{ConnectInfo, FromRequestParts};
+use axum::http::{header, request::Parts, Extensions, HeaderMap};
+use axum::response::{IntoResponse, Redirect, Response};
+use axum_extra::extract::CookieJar;
+use domain::models::User;
+use domain::ports::UserRepository;
+use shared::config::ServerConfig;
+use shared::error::AppError;
+use shared::types::{RequestContext, RequestId, UserRole};
+use std::marker::PhantomData;
+use std::net::SocketAddr;
+use uuid::Uuid;
+
+const LOGIN_PAGE: &str = "/app/login";
+const MAX_USER_AGENT_LEN: usize = 512;
+
+/// The authenticated user for this request. Rejects with 401 on API routes
+/// and redirects to the login page elsewhere.
+#[derive(Clone, Debug)]
+pub struct AuthUser(pub User);
+
+/// Like [`AuthUser`], but never rejects when there is no valid session.
+#[derive(Clone, Debug)]
+pub struct OptionalAuthUser(pub Option<User>);
+
+/// The refresh session the caller's access token was issued for, when signed in with one
+/// that names it. Like [`OptionalAuthUser`], never rejects when there is no valid session.
+#[derive(Clone, Copy, Debug)]
+pub struct SessionId(pub Option<Uuid>);
+
+/// An authenticated user holding role `R`. Rejects with 403 when the role does not match.
+#[derive(Clone, Debug)]
+pub struct RequireRole<R: RoleGuard>(pub User, PhantomData<R>);
+
+pub trait RoleGuard: Send + Sync + 'static {
+    const ROLE: UserRole;
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Admin;
+
+impl RoleGuard for Admin {
+    const ROLE: UserRole = UserRole::Admin;
+}
+
+/// Cached in request extensions so the principal is only looked up once.
+#[derive(Clone)]
+struct ResolvedPrincipal(Option<User>);
+
+#[derive(Debug)]
+pub struct AuthRejection {
+    error: AppError,
+    api: bool,
+    request_id: RequestId,
+}
+
+impl AuthRejection {
+    fn new(error: AppError, parts: &Parts) -> Self {
+        Self {
+            error,
+            api: parts.uri.path().starts_with("/api/"),
+            request_id: parts
+                .extensions
+                .get::<RequestId>()
+                .cloned()
+                .unwrap_or_else(|| Uuid::new_v4().to_string()),
+        }
+    }
+}
+
+impl IntoResponse for AuthRejection {
+    fn into_response(self) -> Response {
+        if self.api {
+            return error_response(self.error, &self.request_id).into_response();
+        }
+        match self.error {
+            AppError::Forbidden => public::forbidden().into_response(),
+            _ => Redirect::to(LOGIN_PAGE).into_response(),
+        }
+    }
+}
+
+async fn resolve_principal(parts: &mut Parts, state: &AppState) -> Result<Option<User>, AppError> {
+    if let Some(resolved) = parts.extensions.get::<ResolvedPrincipal>() {
+        return Ok(resolved.0.clone());
+    }
+
+    let token = security::bearer_token(&parts.headers).or_else(|| {
+        CookieJar::from_headers(&parts.headers)
+            .get(&state.config.auth.access_cookie_name)
+            .map(|c| c.value().to_string())
+    });
+
+    let (user, session_id) = match token {
+        Some(token) => match security::decode_access_token(&token, &state.config.auth) {
+            Ok(claims) => (state.db.find_by_id(claims.sub).await?, claims.sid),
+            Err(_) => (None, None),
+        },
+        None => (None, None),
+    };
+
+    parts.extensions.insert(ResolvedPrincipal(user.clone()));
+    if let Some(user) = &user {
+        parts.extensions.insert(AuthUser(user.clone()));
+        parts.extensions.insert(SessionId(session_id));
+    }
+    Ok(user)
+}
+
+impl FromRequestParts<AppState> for OptionalAuthUser {
+    type Rejection = AuthRejection;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        resolve_principal(parts, state)
+            .await
+            .map(OptionalAuthUser)
+            .map_err(|err| AuthRejection::new(err, parts))
+    }
+}
+
+impl FromRequestParts<AppState> for AuthUser {
+    type Rejection = AuthRejection;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        match resolve_principal(parts, state).await {
+            Ok(Some(user)) => Ok(AuthUser(user)),
+            Ok(None) => Err(AuthRejection::new(AppError::Unauthorized, parts)),
+            Err(err) => Err(AuthRejection::new(err, parts)),
+        }
+    }
+}
+
+impl FromRequestParts<AppState> for SessionId {
+    type Rejection = AuthRejection;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        resolve_principal(parts, state)
+            .await
+            .map_err(|err| AuthRejection::new(err, parts))?;
+        Ok(parts
+            .extensions
+            .get::<SessionId>()
+            .copied()
+            .unwrap_or(SessionId(None)))
+    }
+}
+
+impl<R: RoleGuard> FromRequestParts<AppState> for RequireRole<R> {
+    type Rejection = AuthRejection;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let AuthUser(user) = AuthUser::from_request_parts(parts, state).await?;
+        if user.role != R::ROLE {
+            return Err(AuthRejection::new(AppError::Forbidden, parts));
+        }
+        Ok(RequireRole(user, PhantomData))
+    }
+}
+
+/// Client IP, user agent and request id, for the audit events a handler causes.
+#[derive(Clone, Debug)]
+pub struct RequestContextExtractor(pub RequestContext);
+
+impl FromRequestParts<AppState> for RequestContextExtractor {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        Ok(RequestContextExtractor(request_context(
+            &parts.headers,
+            &parts.extensions,
+            &state.config.server,
+        )))
+    }
+}
+
+pub fn request_context(
+    headers: &HeaderMap,
+    extensions: &Extensions,
+    config: &ServerConfig,
+) -> RequestContext {
+    let forwarded = config
+        .trust_forwarded_for
+        .then(|| {
+            headers
+                .get("x-forwarded-for")
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.split(',').next())
+                .map(|ip| ip.trim().to_string())
+                .filter(|ip| !ip.is_empty())
+        })
+        .flatten();
+    let ip = forwarded.or_else(|| {
+        extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|info| info.0.ip().to_string())
+    });
+    let user_agent = headers
+        .get(header::USER_AGENT)
+        .and_then(|v| v.to_str().ok())
+        .map(|ua| ua.chars().take(MAX_USER_AGENT_LEN).collect());
+
+    RequestContext {
+        ip,
+        user_agent,
+        request_id: extensions.get::<RequestId>().cloned(),
+    }
+}
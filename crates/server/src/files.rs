@@ -0,0 +1,262 @@
+use axum::extract::multipart::{Field, Multipart, MultipartError};
+use axum::extract::DefaultBodyLimit;
+use base64::Engine;
+use chrono::{DateTime, Duration, Utc};
+use db::Database;
+use domain::files::{NewFile, StoredFile};
+use domain::ports::FileRepository;
+use futures::StreamExt;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use shared::dto::FileResponse;
+use shared::error::{AppError, Result};
+use std::path::PathBuf;
+use std::sync::Arc;
+use storage::{sniff_content_type, ObjectStorage, StoredObject, SNIFF_LEN};
+use tokio::io::AsyncWriteExt;
+use tokio_util::io::ReaderStream;
+use tracing::warn;
+use uuid::Uuid;
+
+pub const IMAGE_TYPES: &[&str] = &["image/png", "image/jpeg", "image/gif", "image/webp"];
+pub const ATTACHMENT_TYPES: &[&str] = &[
+    "image/png",
+    "image/jpeg",
+    "image/gif",
+    "image/webp",
+    "application/pdf",
+    "text/plain",
+];
+
+/// Room for multipart boundaries and headers on top of the file itself.
+const MULTIPART_OVERHEAD: u64 = 64 * 1024;
+const MAX_FILENAME_LEN: usize = 255;
+
+/// Request body limit for an upload route accepting files of up to `max_bytes`. The upload
+/// itself is checked against `max_bytes` while it streams; this only stops oversized bodies.
+pub fn body_limit(max_bytes: u64) -> DefaultBodyLimit {
+    let limit = max_bytes.saturating_add(MULTIPART_OVERHEAD);
+    DefaultBodyLimit::max(usize::try_from(limit).unwrap_or(usize::MAX))
+}
+
+/// An upload written to a temporary file as it streamed in. The file is removed on drop.
+pub struct StagedUpload {
+    path: PathBuf,
+    pub size: u64,
+    /// Sniffed from the content.
+    pub content_type: &'static str,
+    pub filename: Option<String>,
+}
+
+impl Drop for StagedUpload {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Streams the `file` field of a multipart body to disk, failing as soon as it grows past
+/// `max_bytes`, and accepts it only if its sniffed type is one of `allowed`.
+pub async fn stage_upload(
+    multipart: &mut Multipart,
+    max_bytes: u64,
+    allowed: &[&str],
+) -> Result<StagedUpload> {
+    while let Some(field) = multipart.next_field().await.map_err(multipart_error)? {
+        if field.name() == Some("file") {
+            return stage_field(field, max_bytes, allowed).await;
+        }
+    }
+    Err(AppError::Validation("missing `file` field".into()))
+}
+
+async fn stage_field(
+    mut field: Field<'_>,
+    max_bytes: u64,
+    allowed: &[&str],
+) -> Result<StagedUpload> {
+    let mut upload = StagedUpload {
+        path: std::env::temp_dir().join(format!("upload-{}", Uuid::new_v4())),
+        size: 0,
+        content_type: "",
+        filename: field.file_name().and_then(clean_filename),
+    };
+    let mut file = tokio::fs::File::create(&upload.path)
+        .await
+        .map_err(|e| AppError::internal(format!("failed to stage upload: {e}")))?;
+    let mut head = Vec::with_capacity(SNIFF_LEN);
+    while let Some(chunk) = field.chunk().await.map_err(multipart_error)? {
+        upload.size += chunk.len() as u64;
+        if upload.size > max_bytes {
+            return Err(AppError::Validation(format!(
+                "file is larger than {max_bytes} bytes"
+            )));
+        }
+        let wanted = SNIFF_LEN.saturating_sub(head.len()).min(chunk.len());
+        head.extend_from_slice(&chunk[..wanted]);
+        file.write_all(&chunk)
+            .await
+            .map_err(|e| AppError::internal(format!("failed to stage upload: {e}")))?;
+    }
+    file.flush()
+        .await
+        .map_err(|e| AppError::internal(format!("failed to stage upload: {e}")))?;
+
+    upload.content_type = sniff_content_type(&head)
+        .filter(|content_type| allowed.contains(content_type))
+        .ok_or_else(|| {
+            AppError::Validation(format!(
+                "unsupported file type; expected one of {}",
+                allowed.join(", ")
+            ))
+        })?;
+    Ok(upload)
+}
+
+fn multipart_error(err: MultipartError) -> AppError {
+    AppError::Validation(format!("invalid upload: {}", err.body_text()))
+}
+
+/// Keeps the last path segment of a client-supplied name, without control characters or quotes.
+fn clean_filename(name: &str) -> Option<String> {
+    let name: String = name
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| !c.is_control() && *c != '"')
+        .take(MAX_FILENAME_LEN)
+        .collect();
+    let name = name.trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+/// Keeps uploaded files in object storage and records them in the `files` table. Downloads go
+/// through the app with short-lived signed URLs, so files are served with safe headers whatever
+/// the backend.
+#[derive(Clone)]
+pub struct Files {
+    db: Arc<Database>,
+    storage: Arc<dyn ObjectStorage>,
+    url_secret: Arc<str>,
+    url_ttl: Duration,
+}
+
+impl Files {
+    pub fn new(
+        db: Arc<Database>,
+        storage: Arc<dyn ObjectStorage>,
+        url_secret: &str,
+        url_ttl_seconds: u64,
+    ) -> Result<Self> {
+        // Checked here too, as the configuration's own bound only holds once it is validated.
+        let url_ttl = i64::try_from(url_ttl_seconds)
+            .ok()
+            .and_then(Duration::try_seconds)
+            .filter(|ttl| Utc::now().checked_add_signed(*ttl).is_some())
+            .ok_or_else(|| AppError::config("download_url_ttl_seconds is out of range"))?;
+        Ok(Self {
+            db,
+            storage,
+            url_secret: url_secret.into(),
+            url_ttl,
+        })
+    }
+
+    /// Uploads the staged file under `{prefix}/{owner_id}/{file id}` and records it.
+    pub async fn store(
+        &self,
+        owner_id: Uuid,
+        prefix: &str,
+        upload: StagedUpload,
+    ) -> Result<StoredFile> {
+        let id = Uuid::new_v4();
+        let storage_key = format!("{prefix}/{owner_id}/{id}");
+        let staged = tokio::fs::File::open(&upload.path)
+            .await
+            .map_err(|e| AppError::internal(format!("failed to read staged upload: {e}")))?;
+        self.storage
+            .put(
+                &storage_key,
+                upload.content_type,
+                upload.size,
+                ReaderStream::new(staged).boxed(),
+            )
+            .await?;
+
+        let record = NewFile {
+            id,
+            owner_id,
+            storage_key: storage_key.clone(),
+            content_type: upload.content_type.to_string(),
+            size_bytes: i64::try_from(upload.size).unwrap_or(i64::MAX),
+            filename: upload.filename.clone(),
+        };
+        match self.db.create_file(record).await {
+            Ok(file) => Ok(file),
+            Err(err) => {
+                let _ = self.storage.delete(&storage_key).await;
+                Err(err)
+            }
+        }
+    }
+
+    pub async fn open(&self, file: &StoredFile) -> Result<Option<StoredObject>> {
+        self.storage.get(&file.storage_key).await
+    }
+
+    /// Removes the record first, so a failure to delete the bytes only leaves an
+    /// unreachable object behind.
+    pub async fn delete(&self, file: &StoredFile) -> Result<()> {
+        self.db.delete_file(file.id).await?;
+        if let Err(err) = self.storage.delete(&file.storage_key).await {
+            warn!(error = %err, key = %file.storage_key, "failed to delete stored object");
+        }
+        Ok(())
+    }
+
+    pub fn response(&self, file: &StoredFile) -> FileResponse {
+        let (url, url_expires_at) = self.signed_url(file.id);
+        FileResponse {
+            id: file.id,
+            content_type: file.content_type.clone(),
+            size_bytes: file.size_bytes,
+            filename: file.filename.clone(),
+            created_at: file.created_at,
+            url,
+            url_expires_at,
+        }
+    }
+
+    /// An app-relative download URL for the file, valid until the returned time.
+    pub fn signed_url(&self, file_id: Uuid) -> (String, DateTime<Utc>) {
+        let expires_at = Utc::now() + self.url_ttl;
+        let expires = expires_at.timestamp();
+        let signature = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .encode(self.mac(file_id, expires).finalize().into_bytes());
+        (
+            format!("/files/{file_id}?expires={expires}&signature={signature}"),
+            expires_at,
+        )
+    }
+
+    pub fn verify_url(&self, file_id: Uuid, expires: i64, signature: &str) -> bool {
+        if expires < Utc::now().timestamp() {
+            return false;
+        }
+        let Ok(signature) = base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(signature)
+        else {
+            return false;
+        };
+        self.mac(file_id, expires).verify_slice(&signature).is_ok()
+    }
+
+    fn mac(&self, file_id: Uuid, expires: i64) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.url_secret.as_bytes())
+            .expect("hmac accepts keys of any length");
+        mac.update(b"file:");
+        mac.update(file_id.as_bytes());
+        mac.update(b":");
+        mac.update(expires.to_string().as_bytes());
+        mac
+    }
+}
@@ -0,0 +1,108 @@
+use db::Database;
+use domain::flags::{FeatureFlag, FlagContext};
+use domain::ports::FeatureFlagRepository;
+use shared::error::Result;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::warn;
+
+const MAX_RECONNECT_DELAY_SECS: u64 = 30;
+
+/// In-memory copy of the `feature_flags` table for evaluating flags without a query.
+/// Kept current by listening for the table's change notifications.
+#[derive(Clone, Default)]
+pub struct FeatureFlags {
+    flags: Arc<RwLock<HashMap<String, FeatureFlag>>>,
+}
+
+impl FeatureFlags {
+    pub async fn load(db: &Database) -> Result<Self> {
+        let flags = Self::default();
+        flags.reload(db).await?;
+        Ok(flags)
+    }
+
+    /// Unknown flags are off.
+    pub fn is_enabled(&self, key: &str, ctx: &FlagContext) -> bool {
+        self.flags
+            .read()
+            .expect("feature flag cache poisoned")
+            .get(key)
+            .is_some_and(|flag| flag.evaluate(ctx))
+    }
+
+    /// Keys of every flag that is on for `ctx`.
+    pub fn enabled_for(&self, ctx: &FlagContext) -> BTreeSet<String> {
+        self.flags
+            .read()
+            .expect("feature flag cache poisoned")
+            .values()
+            .filter(|flag| flag.evaluate(ctx))
+            .map(|flag| flag.key.clone())
+            .collect()
+    }
+
+    /// Applies a change made by this instance right away, ahead of its notification.
+    pub fn store(&self, flag: FeatureFlag) {
+        self.flags
+            .write()
+            .expect("feature flag cache poisoned")
+            .insert(flag.key.clone(), flag);
+    }
+
+    pub fn forget(&self, key: &str) {
+        self.flags
+            .write()
+            .expect("feature flag cache poisoned")
+            .remove(key);
+    }
+
+    async fn reload(&self, db: &Database) -> Result<()> {
+        let flags = db
+            .list_flags()
+            .await?
+            .into_iter()
+            .map(|flag| (flag.key.clone(), flag))
+            .collect();
+        *self.flags.write().expect("feature flag cache poisoned") = flags;
+        Ok(())
+    }
+
+    /// Reloads the cache whenever any process changes a flag. Reloads after every
+    /// reconnect too, since notifications sent while disconnected are lost.
+    pub fn spawn_listener(&self, db: Database) {
+        let flags = self.clone();
+        tokio::spawn(async move {
+            let mut delay = 1;
+            loop {
+                match flags.listen(&db).await {
+                    Ok(()) => delay = 1,
+                    Err(err) => warn!(error = %err, "feature flag listener failed"),
+                }
+                tokio::time::sleep(Duration::from_secs(delay)).await;
+                delay = (delay * 2).min(MAX_RECONNECT_DELAY_SECS);
+            }
+        });
+    }
+
+    async fn listen(&self, db: &Database) -> Result<()> {
+        let mut changes = db.listen_for_flag_changes().await?;
+        self.reload(db).await?;
+        loop {
+            match changes.recv().await {
+                Ok(Some(_)) => self.reload(db).await?,
+                // Changes may have been missed, e.g. while the connection was down; the
+                // next `recv` reconnects.
+                Ok(None) => {
+                    warn!("feature flag listener reconnecting");
+                    self.reload(db).await?;
+                }
+                Err(err) => {
+                    warn!(error = %err, "feature flag listener lost its connection");
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use crate::extractors::{Admin, RequireRole};
+use crate::handlers::{error_response, RequestIdExtractor};
+use crate::state::AppState;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use domain::models::{AuditCursor, AuditEventType, AuditQuery};
+use domain::ports::AuditLogRepository;
+use serde::Deserialize;
+use shared::dto::CursorPage;
+use shared::error::AppError;
+use tracing::instrument;
+use uuid::Uuid;
+
+const MAX_AUDIT_PAGE: i64 = 200;
+
+#[derive(Debug, Deserialize)]
+pub struct AuditLogParams {
+    pub user_id: Option<Uuid>,
+    pub event_type: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub cursor: Option<String>,
+    #[serde(default = "default_limit")]
+    pub limit: i64,
+}
+
+fn default_limit() -> i64 {
+    50
+}
+
+impl AuditLogParams {
+    fn into_query(self) -> Result<AuditQuery, AppError> {
+        let event_type = match self.event_type.as_deref() {
+            Some(value) => Some(
+                AuditEventType::parse(value)
+                    .ok_or_else(|| AppError::Validation(format!("unknown event type {value}")))?,
+            ),
+            None => None,
+        };
+        let after = self
+            .cursor
+            .as_deref()
+            .map(AuditCursor::decode)
+            .transpose()?;
+
+        Ok(AuditQuery {
+            user_id: self.user_id,
+            event_type,
+            from: self.from,
+            to: self.to,
+            after,
+            limit: self.limit.clamp(1, MAX_AUDIT_PAGE),
+        })
+    }
+}
+
+#[instrument(skip(state, admin), fields(admin_id = %admin.0.id))]
+pub async fn list_audit_events(
+    State(state): State<AppState>,
+    request_id: RequestIdExtractor,
+    admin: RequireRole<Admin>,
+    Query(params): Query<AuditLogParams>,
+) -> impl IntoResponse {
+    let mut query = match params.into_query() {
+        Ok(query) => query,
+        Err(err) => return error_response(err, &request_id.0).into_response(),
+    };
+
+    // Ask for one extra row to learn whether another page exists.
+    let limit = query.limit;
+    query.limit += 1;
+    let mut events = match state.db.list_events(&query).await {
+        Ok(events) => events,
+        Err(err) => return error_response(err, &request_id.0).into_response(),
+    };
+
+    let next_cursor = if events.len() as i64 > limit {
+        events.truncate(limit as usize);
+        events
+            .last()
+            .map(|event| AuditCursor::from_event(event).encode())
+    } else {
+        None
+    };
+
+    (
+        StatusCode::OK,
+        Json(CursorPage {
+            items: events,
+            next_cursor,
+            prev_cursor: None,
+            total: None,
+        }),
+    )
+        .into_response()
+}
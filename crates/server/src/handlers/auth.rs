@@ -1,29 +1,54 @@
+use crate::csrf;
+use crate::extractors::{AuthUser, RequestContextExtractor};
 use crate::handlers::{error_response, RequestIdExtractor};
 use crate::security;
 use crate::state::AppState;
 use axum::{
     extract::Form,
-    extract::State,
-    http::{HeaderMap, StatusCode},
+    extract::{Query, State},
+    http::StatusCode,
     response::{IntoResponse, Redirect},
     Json,
 };
 use axum_extra::extract::CookieJar;
 use domain::models::User;
-use domain::ports::UserRepository;
-use shared::dto::{LoginRequest, RegisterRequest, TokenResponse, UserResponse};
+use serde::Deserialize;
+use shared::dto::{
+    ChangePasswordRequest, ConfirmRegistrationRequest, LoginRequest, RegisterRequest,
+    RegistrationAccepted, TokenResponse, UserResponse,
+};
 use shared::error::AppError;
+use shared::types::{RegistrationMode, RequestContext};
 use time::Duration as TimeDuration;
 use tracing::instrument;
+use uuid::Uuid;
+use validator::Validate;
+
+const REGISTRATION_PENDING_MESSAGE: &str = "Check your inbox to finish creating your account.";
 
-#[instrument(skip(state, jar, payload))]
+#[instrument(skip(state, ctx, jar, payload))]
 pub async fn register(
     State(state): State<AppState>,
     request_id: RequestIdExtractor,
+    RequestContextExtractor(ctx): RequestContextExtractor,
     jar: CookieJar,
     Json(payload): Json<RegisterRequest>,
 ) -> impl IntoResponse {
-    match state.auth.register(payload, None).await {
+    if state.config.auth.registration_mode == RegistrationMode::EmailConfirmation {
+        let ttl = state.config.auth.signup_token_ttl_minutes as i64;
+        return match state.auth.request_registration(payload, ttl, &ctx).await {
+            Ok(()) => (
+                StatusCode::ACCEPTED,
+                Json(RegistrationAccepted {
+                    message: REGISTRATION_PENDING_MESSAGE.into(),
+                }),
+            )
+                .into_response(),
+            Err(err) => error_response(err, &request_id.0).into_response(),
+        };
+    }
+
+    match state.auth.register(payload, None, &ctx).await {
         Ok(user) => match issue_session(&state, jar, user).await {
             Ok((jar, tokens)) => (jar, (StatusCode::CREATED, Json(tokens))).into_response(),
             Err(err) => error_response(err, &request_id.0).into_response(),
@@ -32,14 +57,15 @@ pub async fn register(
     }
 }
 
-#[instrument(skip(state, jar, payload))]
+#[instrument(skip(state, ctx, jar, payload))]
 pub async fn login(
     State(state): State<AppState>,
     request_id: RequestIdExtractor,
+    RequestContextExtractor(ctx): RequestContextExtractor,
     jar: CookieJar,
     Json(payload): Json<LoginRequest>,
 ) -> impl IntoResponse {
-    match state.auth.login(payload).await {
+    match state.auth.login(payload, &ctx).await {
         Ok(user) => match issue_session(&state, jar, user).await {
             Ok((jar, tokens)) => (jar, (StatusCode::OK, Json(tokens))).into_response(),
             Err(err) => error_response(err, &request_id.0).into_response(),
@@ -48,14 +74,15 @@ pub async fn login(
     }
 }
 
-#[instrument(skip(state, jar, payload))]
+#[instrument(skip(state, ctx, jar, payload))]
 pub async fn login_form(
     State(state): State<AppState>,
     _request_id: RequestIdExtractor,
+    RequestContextExtractor(ctx): RequestContextExtractor,
     jar: CookieJar,
     Form(payload): Form<LoginRequest>,
 ) -> impl IntoResponse {
-    match state.auth.login(payload).await {
+    match state.auth.login(payload, &ctx).await {
         Ok(user) => {
             let jar_for_err = jar.clone();
             match issue_session(&state, jar, user).await {
@@ -81,14 +108,30 @@ pub async fn login_form(
     }
 }
 
-#[instrument(skip(state, jar, payload))]
+#[instrument(skip(state, ctx, jar, payload))]
 pub async fn register_form(
     State(state): State<AppState>,
     _request_id: RequestIdExtractor,
+    RequestContextExtractor(ctx): RequestContextExtractor,
     jar: CookieJar,
     Form(payload): Form<RegisterRequest>,
 ) -> impl IntoResponse {
-    match state.auth.register(payload, None).await {
+    if state.config.auth.registration_mode == RegistrationMode::EmailConfirmation {
+        let ttl = state.config.auth.signup_token_ttl_minutes as i64;
+        return match state.auth.request_registration(payload, ttl, &ctx).await {
+            Ok(()) => (jar, Redirect::to("/app/register/pending")).into_response(),
+            Err(err) => {
+                let jar = jar.add(security::build_flash_error_cookie(
+                    &err.to_string(),
+                    &state.config,
+                    15,
+                ));
+                (jar, Redirect::to("/app/register")).into_response()
+            }
+        };
+    }
+
+    match state.auth.register(payload, None, &ctx).await {
         Ok(user) => {
             let jar_for_err = jar.clone();
             match issue_session(&state, jar, user).await {
@@ -114,125 +157,153 @@ pub async fn register_form(
     }
 }
 
-#[instrument(skip(state, jar, headers))]
-pub async fn refresh(
+#[instrument(skip(state, ctx, jar, payload))]
+pub async fn confirm_registration(
     State(state): State<AppState>,
     request_id: RequestIdExtractor,
+    RequestContextExtractor(ctx): RequestContextExtractor,
     jar: CookieJar,
-    headers: HeaderMap,
+    Json(payload): Json<ConfirmRegistrationRequest>,
 ) -> impl IntoResponse {
-    let refresh_cookie = match jar.get(&state.config.auth.refresh_cookie_name) {
-        Some(c) => c.value().to_string(),
-        None => return error_response(AppError::Unauthorized, &request_id.0).into_response(),
-    };
-
-    let csrf_cookie = jar
-        .get(&state.config.auth.csrf_cookie_name)
-        .map(|c| c.value().to_string());
-    let csrf_header = headers
-        .get("x-csrf-token")
-        .and_then(|h| h.to_str().ok())
-        .map(|s| s.to_string());
+    if let Err(err) = payload.validate() {
+        return error_response(err.into(), &request_id.0).into_response();
+    }
 
-    if let Err(err) = security::verify_csrf(csrf_header.as_deref(), csrf_cookie.as_deref()) {
-        return error_response(err, &request_id.0).into_response();
+    match state.auth.confirm_registration(&payload.token, &ctx).await {
+        Ok(user) => match issue_session(&state, jar, user).await {
+            Ok((jar, tokens)) => (jar, (StatusCode::CREATED, Json(tokens))).into_response(),
+            Err(err) => error_response(err, &request_id.0).into_response(),
+        },
+        Err(err) => error_response(err, &request_id.0).into_response(),
     }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConfirmLinkQuery {
+    pub token: String,
+}
 
-    let token = match state.auth.validate_refresh_token(&refresh_cookie).await {
-        Ok(token) => token,
-        Err(err) => return error_response(err, &request_id.0).into_response(),
+#[instrument(skip(state, ctx, jar, query))]
+pub async fn confirm_registration_link(
+    State(state): State<AppState>,
+    _request_id: RequestIdExtractor,
+    RequestContextExtractor(ctx): RequestContextExtractor,
+    jar: CookieJar,
+    Query(query): Query<ConfirmLinkQuery>,
+) -> impl IntoResponse {
+    let result = match state.auth.confirm_registration(&query.token, &ctx).await {
+        Ok(user) => issue_session(&state, jar.clone(), user).await,
+        Err(err) => Err(err),
     };
 
-    // rotate token
-    let _ = state.auth.logout(&refresh_cookie).await;
+    match result {
+        Ok((jar, _tokens)) => (jar, Redirect::to("/app")).into_response(),
+        Err(err) => {
+            let message = match err {
+                AppError::Unauthorized => "This sign-up link is invalid or has expired.".into(),
+                other => other.to_string(),
+            };
+            let jar = jar.add(security::build_flash_error_cookie(
+                &message,
+                &state.config,
+                15,
+            ));
+            (jar, Redirect::to("/app/register")).into_response()
+        }
+    }
+}
 
-    let user = match state.db.find_by_id(token.user_id).await {
-        Ok(Some(user)) => user,
-        Ok(None) => return error_response(AppError::Unauthorized, &request_id.0).into_response(),
-        Err(err) => return error_response(err, &request_id.0).into_response(),
+#[instrument(skip(state, ctx, jar))]
+pub async fn refresh(
+    State(state): State<AppState>,
+    request_id: RequestIdExtractor,
+    RequestContextExtractor(ctx): RequestContextExtractor,
+    jar: CookieJar,
+) -> impl IntoResponse {
+    let refresh_cookie = match jar.get(&state.config.auth.refresh_cookie_name) {
+        Some(c) => c.value().to_string(),
+        None => return error_response(AppError::Unauthorized, &request_id.0).into_response(),
     };
 
-    match issue_session(&state, jar, user).await {
+    match rotate_session(&state, jar, &refresh_cookie, &ctx).await {
         Ok((jar, tokens)) => (jar, (StatusCode::OK, Json(tokens))).into_response(),
         Err(err) => error_response(err, &request_id.0).into_response(),
     }
 }
 
-#[instrument(skip(state, jar, headers))]
+#[instrument(skip(state, ctx, jar))]
 pub async fn logout(
     State(state): State<AppState>,
-    request_id: RequestIdExtractor,
+    RequestContextExtractor(ctx): RequestContextExtractor,
     jar: CookieJar,
-    headers: HeaderMap,
 ) -> impl IntoResponse {
     let refresh_cookie = match jar.get(&state.config.auth.refresh_cookie_name) {
         Some(c) => c.value().to_string(),
         None => return (jar, StatusCode::NO_CONTENT).into_response(),
     };
 
-    let csrf_cookie = jar
-        .get(&state.config.auth.csrf_cookie_name)
-        .map(|c| c.value().to_string());
-    let csrf_header = headers
-        .get("x-csrf-token")
-        .and_then(|h| h.to_str().ok())
-        .map(|s| s.to_string());
-
-    if let Err(err) = security::verify_csrf(csrf_header.as_deref(), csrf_cookie.as_deref()) {
-        return error_response(err, &request_id.0).into_response();
-    }
-
-    let _ = state.auth.logout(&refresh_cookie).await;
+    let _ = state.auth.logout(&refresh_cookie, &ctx).await;
     let cleared = clear_session(jar, &state.config);
     (cleared, StatusCode::NO_CONTENT).into_response()
 }
 
-pub async fn logout_get(
+#[instrument(skip(state, ctx, jar))]
+pub async fn logout_form(
     State(state): State<AppState>,
+    RequestContextExtractor(ctx): RequestContextExtractor,
     jar: CookieJar,
 ) -> impl IntoResponse {
+    if let Some(c) = jar.get(&state.config.auth.refresh_cookie_name) {
+        let _ = state.auth.logout(c.value(), &ctx).await;
+    }
     let cleared = clear_session(jar, &state.config);
     (cleared, Redirect::to("/")).into_response()
 }
 
-#[instrument(skip(state))]
-pub async fn me(
+/// Ends every session of the caller, on every device, this one included.
+#[instrument(skip(state, ctx, user, jar))]
+pub async fn logout_all(
     State(state): State<AppState>,
     request_id: RequestIdExtractor,
-    headers: HeaderMap,
+    RequestContextExtractor(ctx): RequestContextExtractor,
+    AuthUser(user): AuthUser,
     jar: CookieJar,
 ) -> impl IntoResponse {
-    let token = security::bearer_token(&headers).or_else(|| {
-        jar.get(&state.config.auth.access_cookie_name)
-            .map(|c| c.value().to_string())
-    });
-    let Some(token) = token else {
-        return error_response(AppError::Unauthorized, &request_id.0).into_response();
-    };
-
-    let claims = match security::decode_access_token(&token, &state.config.auth) {
-        Ok(c) => c,
-        Err(err) => return error_response(err, &request_id.0).into_response(),
-    };
-
-    let user = match state.db.find_by_id(claims.sub).await {
-        Ok(Some(user)) => user,
-        Ok(None) => return error_response(AppError::Unauthorized, &request_id.0).into_response(),
-        Err(err) => return error_response(err, &request_id.0).into_response(),
-    };
+    match state.auth.revoke_all(user.id, &ctx).await {
+        Ok(()) => (clear_session(jar, &state.config), StatusCode::NO_CONTENT).into_response(),
+        Err(err) => error_response(err, &request_id.0).into_response(),
+    }
+}
 
+#[instrument(skip_all)]
+pub async fn me(AuthUser(user): AuthUser) -> impl IntoResponse {
     let body = to_user_response(&user);
     (StatusCode::OK, Json(body)).into_response()
 }
 
+/// Changes the caller's password. Every session is revoked, including this one.
+#[instrument(skip(state, ctx, user, jar, payload))]
+pub async fn change_password(
+    State(state): State<AppState>,
+    request_id: RequestIdExtractor,
+    RequestContextExtractor(ctx): RequestContextExtractor,
+    AuthUser(user): AuthUser,
+    jar: CookieJar,
+    Json(payload): Json<ChangePasswordRequest>,
+) -> impl IntoResponse {
+    match state.auth.change_password(&user, payload, &ctx).await {
+        Ok(()) => (clear_session(jar, &state.config), StatusCode::NO_CONTENT).into_response(),
+        Err(err) => error_response(err, &request_id.0).into_response(),
+    }
+}
+
 async fn issue_session(
     state: &AppState,
     jar: CookieJar,
     user: User,
 ) -> Result<(CookieJar, TokenResponse), AppError> {
-    let access_token = security::sign_access_token(user.id, user.role, &state.config.auth)?;
     let refresh_raw = domain::auth::generate_refresh_token();
-    let _stored = state
+    let stored = state
         .auth
         .store_refresh_token(
             user.id,
@@ -241,12 +312,42 @@ async fn issue_session(
         )
         .await?;
 
-    let csrf_token = security::generate_csrf_token();
-    let jar =
-        attach_session_cookies(jar, &state.config, &access_token, &refresh_raw, &csrf_token);
+    session_tokens(state, jar, &user, &refresh_raw, stored.session_id)
+}
+
+/// Rotates the refresh token and issues a matching access token and CSRF token.
+pub(crate) async fn rotate_session(
+    state: &AppState,
+    jar: CookieJar,
+    refresh_raw: &str,
+    ctx: &RequestContext,
+) -> Result<(CookieJar, TokenResponse), AppError> {
+    let (user, refresh_raw, session_id) = state
+        .auth
+        .rotate_refresh_token(
+            refresh_raw,
+            state.config.auth.refresh_token_ttl_days as i64,
+            ctx,
+        )
+        .await?;
+
+    session_tokens(state, jar, &user, &refresh_raw, session_id)
+}
+
+fn session_tokens(
+    state: &AppState,
+    jar: CookieJar,
+    user: &User,
+    refresh_raw: &str,
+    session_id: Uuid,
+) -> Result<(CookieJar, TokenResponse), AppError> {
+    let access_token =
+        security::sign_access_token(user.id, user.role, session_id, &state.config.auth)?;
+    let csrf_token = csrf::issue_token(&state.config.auth.csrf_secret, refresh_raw);
+    let jar = attach_session_cookies(jar, &state.config, &access_token, refresh_raw, &csrf_token);
     let tokens = TokenResponse {
         access_token,
-        user: to_user_response(&user),
+        user: to_user_response(user),
         csrf_token,
     };
 
@@ -0,0 +1,70 @@
+use crate::handlers::public;
+use crate::state::AppState;
+use axum::extract::State;
+use axum::response::{Html, IntoResponse, Response};
+use http::header;
+use mail::escape_html;
+
+/// Lists mail captured by the memory transport. Only routed outside production.
+pub async fn mail_outbox(State(state): State<AppState>) -> Response {
+    let Some(mailbox) = state.mailbox else {
+        return public::not_found(Default::default()).await.into_response();
+    };
+
+    let messages = mailbox.messages();
+    let items = if messages.is_empty() {
+        r#"<p class="text-slate-400">No mail captured yet. Messages appear here once the job worker has sent them.</p>"#
+            .to_string()
+    } else {
+        messages
+            .iter()
+            .map(|mail| {
+                format!(
+                    r#"<details class="rounded-lg border border-slate-800 bg-slate-900 p-4">
+          <summary class="cursor-pointer">
+            <span class="font-semibold">{subject}</span>
+            <span class="text-slate-400"> to {to} at {sent}</span>
+          </summary>
+          <iframe class="mt-4 w-full h-96 rounded bg-white" sandbox srcdoc="{html}"></iframe>
+          <pre class="mt-4 whitespace-pre-wrap text-sm text-slate-300">{text}</pre>
+        </details>"#,
+                    subject = escape_html(&mail.subject),
+                    to = escape_html(&mail.to),
+                    sent = mail.created_at.format("%Y-%m-%d %H:%M:%S UTC"),
+                    html = escape_html(&mail.html),
+                    text = escape_html(&mail.text),
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n        ")
+    };
+
+    let html = format!(
+        r#"<!doctype html>
+<html lang="en">
+  <head>
+    <meta charset="utf-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+    <title>Captured mail</title>
+    <link rel="stylesheet" href="/pkg/app.css">
+  </head>
+  <body>
+    <main class="min-h-screen bg-slate-950 text-slate-100">
+      <section class="max-w-4xl mx-auto px-6 py-12 space-y-6">
+        <h1 class="text-3xl font-bold">Captured mail</h1>
+        {items}
+      </section>
+    </main>
+  </body>
+</html>"#
+    );
+
+    (
+        [
+            (header::CONTENT_TYPE, "text/html; charset=utf-8"),
+            (header::CACHE_CONTROL, "no-store"),
+        ],
+        Html(html),
+    )
+        .into_response()
+}
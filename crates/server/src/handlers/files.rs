@@ -0,0 +1,240 @@
+use crate::extractors::AuthUser;
+use crate::files::{stage_upload, ATTACHMENT_TYPES, IMAGE_TYPES};
+use crate::handlers::{error_response, RequestIdExtractor};
+use crate::state::AppState;
+use axum::{
+    body::Body,
+    extract::{Multipart, Path, Query, State},
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::Utc;
+use domain::files::StoredFile;
+use domain::models::User;
+use domain::ports::FileRepository;
+use serde::Deserialize;
+use shared::error::AppError;
+use shared::types::UserRole;
+use tracing::{instrument, warn};
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize)]
+pub struct DownloadQuery {
+    pub expires: i64,
+    pub signature: String,
+}
+
+/// Uploads an attachment from the `file` field of a multipart body.
+#[instrument(skip(state, user, multipart), fields(user_id = %user.id))]
+pub async fn upload_file(
+    State(state): State<AppState>,
+    request_id: RequestIdExtractor,
+    AuthUser(user): AuthUser,
+    mut multipart: Multipart,
+) -> impl IntoResponse {
+    let max_bytes = state.config.storage.max_attachment_bytes;
+    let upload = match stage_upload(&mut multipart, max_bytes, ATTACHMENT_TYPES).await {
+        Ok(upload) => upload,
+        Err(err) => return error_response(err, &request_id.0).into_response(),
+    };
+    match state.files.store(user.id, "attachments", upload).await {
+        Ok(file) => (StatusCode::CREATED, Json(state.files.response(&file))).into_response(),
+        Err(err) => error_response(err, &request_id.0).into_response(),
+    }
+}
+
+/// The file's details with a fresh download link.
+#[instrument(skip(state, user), fields(user_id = %user.id))]
+pub async fn get_file(
+    State(state): State<AppState>,
+    request_id: RequestIdExtractor,
+    AuthUser(user): AuthUser,
+    Path(id): Path<Uuid>,
+) -> impl IntoResponse {
+    match visible_file(&state, &user, id).await {
+        Ok(file) => (StatusCode::OK, Json(state.files.response(&file))).into_response(),
+        Err(err) => error_response(err, &request_id.0).into_response(),
+    }
+}
+
+#[instrument(skip(state, user), fields(user_id = %user.id))]
+pub async fn delete_file(
+    State(state): State<AppState>,
+    request_id: RequestIdExtractor,
+    AuthUser(user): AuthUser,
+    Path(id): Path<Uuid>,
+) -> impl IntoResponse {
+    let result = match visible_file(&state, &user, id).await {
+        Ok(file) => state.files.delete(&file).await,
+        Err(err) => Err(err),
+    };
+    match result {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(err) => error_response(err, &request_id.0).into_response(),
+    }
+}
+
+/// Files belong to their uploader; admins can see everyone's. Others get `NotFound` so ids
+/// cannot be probed.
+async fn visible_file(state: &AppState, user: &User, id: Uuid) -> Result<StoredFile, AppError> {
+    match state.db.find_file(id).await? {
+        Some(file) if file.owner_id == user.id || user.role == UserRole::Admin => Ok(file),
+        _ => Err(AppError::NotFound),
+    }
+}
+
+/// Serves a file to anyone holding a valid signed URL from [`crate::files::Files::signed_url`].
+pub async fn download(
+    State(state): State<AppState>,
+    request_id: RequestIdExtractor,
+    Path(id): Path<Uuid>,
+    Query(query): Query<DownloadQuery>,
+) -> Response {
+    if !state.files.verify_url(id, query.expires, &query.signature) {
+        return error_response(AppError::Forbidden, &request_id.0).into_response();
+    }
+    let file = match state.db.find_file(id).await {
+        Ok(Some(file)) => file,
+        Ok(None) => return error_response(AppError::NotFound, &request_id.0).into_response(),
+        Err(err) => return error_response(err, &request_id.0).into_response(),
+    };
+    let object = match state.files.open(&file).await {
+        Ok(Some(object)) => object,
+        Ok(None) => {
+            warn!(file_id = %file.id, key = %file.storage_key, "stored object is missing");
+            return error_response(AppError::NotFound, &request_id.0).into_response();
+        }
+        Err(err) => return error_response(err, &request_id.0).into_response(),
+    };
+
+    let max_age = (query.expires - Utc::now().timestamp()).max(0);
+    let mut response = Body::from_stream(object.body).into_response();
+    let headers = response.headers_mut();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_str(&file.content_type)
+            .unwrap_or(HeaderValue::from_static("application/octet-stream")),
+    );
+    headers.insert(header::CONTENT_LENGTH, HeaderValue::from(object.size));
+    headers.insert(
+        header::CONTENT_DISPOSITION,
+        HeaderValue::from_str(&content_disposition(&file))
+            .unwrap_or(HeaderValue::from_static("attachment")),
+    );
+    headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_str(&format!("private, max-age={max_age}"))
+            .unwrap_or(HeaderValue::from_static("no-store")),
+    );
+    // Uploaded content must never be interpreted as anything but its sniffed type, nor run
+    // with the app's origin if opened directly.
+    headers.insert(
+        header::X_CONTENT_TYPE_OPTIONS,
+        HeaderValue::from_static("nosniff"),
+    );
+    headers.insert(
+        header::CONTENT_SECURITY_POLICY,
+        HeaderValue::from_static("sandbox"),
+    );
+    response
+}
+
+/// Images display in place; anything else downloads under its original name.
+fn content_disposition(file: &StoredFile) -> String {
+    if file.is_image() {
+        return "inline".to_string();
+    }
+    let Some(name) = &file.filename else {
+        return "attachment".to_string();
+    };
+    let fallback: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_graphic() || c == ' ' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let encoded: String = name
+        .bytes()
+        .map(|b| {
+            if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+                (b as char).to_string()
+            } else {
+                format!("%{b:02X}")
+            }
+        })
+        .collect();
+    format!("attachment; filename=\"{fallback}\"; filename*=UTF-8''{encoded}")
+}
+
+#[instrument(skip(state, user), fields(user_id = %user.id))]
+pub async fn avatar(
+    State(state): State<AppState>,
+    request_id: RequestIdExtractor,
+    AuthUser(user): AuthUser,
+) -> impl IntoResponse {
+    match state.db.find_avatar(user.id).await {
+        Ok(Some(file)) => (StatusCode::OK, Json(state.files.response(&file))).into_response(),
+        Ok(None) => error_response(AppError::NotFound, &request_id.0).into_response(),
+        Err(err) => error_response(err, &request_id.0).into_response(),
+    }
+}
+
+/// Replaces the caller's avatar with the image in the `file` field of a multipart body.
+#[instrument(skip(state, user, multipart), fields(user_id = %user.id))]
+pub async fn upload_avatar(
+    State(state): State<AppState>,
+    request_id: RequestIdExtractor,
+    AuthUser(user): AuthUser,
+    mut multipart: Multipart,
+) -> impl IntoResponse {
+    let max_bytes = state.config.storage.max_avatar_bytes;
+    let upload = match stage_upload(&mut multipart, max_bytes, IMAGE_TYPES).await {
+        Ok(upload) => upload,
+        Err(err) => return error_response(err, &request_id.0).into_response(),
+    };
+    let file = match state.files.store(user.id, "avatars", upload).await {
+        Ok(file) => file,
+        Err(err) => return error_response(err, &request_id.0).into_response(),
+    };
+    match replace_avatar(&state, &user, Some(file.id)).await {
+        Ok(()) => (StatusCode::OK, Json(state.files.response(&file))).into_response(),
+        Err(err) => {
+            let _ = state.files.delete(&file).await;
+            error_response(err, &request_id.0).into_response()
+        }
+    }
+}
+
+#[instrument(skip(state, user), fields(user_id = %user.id))]
+pub async fn delete_avatar(
+    State(state): State<AppState>,
+    request_id: RequestIdExtractor,
+    AuthUser(user): AuthUser,
+) -> impl IntoResponse {
+    match replace_avatar(&state, &user, None).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(err) => error_response(err, &request_id.0).into_response(),
+    }
+}
+
+/// Points the avatar at `file_id` and deletes the file it replaces.
+async fn replace_avatar(
+    state: &AppState,
+    user: &User,
+    file_id: Option<Uuid>,
+) -> Result<(), AppError> {
+    let Some(previous) = state.db.set_avatar(user.id, file_id).await? else {
+        return Ok(());
+    };
+    if let Some(file) = state.db.find_file(previous).await? {
+        if let Err(err) = state.files.delete(&file).await {
+            warn!(error = %err, file_id = %previous, "failed to delete replaced avatar");
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,87 @@
+use crate::extractors::{Admin, OptionalAuthUser, RequestContextExtractor, RequireRole};
+use crate::handlers::{error_response, RequestIdExtractor};
+use crate::state::AppState;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use domain::flags::FlagContext;
+use shared::dto::UpsertFeatureFlagRequest;
+use shared::error::AppError;
+use tracing::instrument;
+use validator::Validate;
+
+/// Keys of the flags that are on for the caller, signed in or not.
+pub async fn enabled_flags(
+    State(state): State<AppState>,
+    OptionalAuthUser(user): OptionalAuthUser,
+) -> impl IntoResponse {
+    let ctx = user
+        .as_ref()
+        .map_or_else(FlagContext::anonymous, FlagContext::for_user);
+    Json(serde_json::json!({ "enabled": state.flags.enabled_for(&ctx) }))
+}
+
+#[instrument(skip(state, admin), fields(admin_id = %admin.0.id))]
+pub async fn list_flags(
+    State(state): State<AppState>,
+    request_id: RequestIdExtractor,
+    admin: RequireRole<Admin>,
+) -> impl IntoResponse {
+    match state.flag_admin.list().await {
+        Ok(flags) => (StatusCode::OK, Json(flags)).into_response(),
+        Err(err) => error_response(err, &request_id.0).into_response(),
+    }
+}
+
+#[instrument(skip(state, ctx, admin, req), fields(admin_id = %admin.0.id))]
+pub async fn upsert_flag(
+    State(state): State<AppState>,
+    request_id: RequestIdExtractor,
+    RequestContextExtractor(ctx): RequestContextExtractor,
+    admin: RequireRole<Admin>,
+    Path(key): Path<String>,
+    Json(req): Json<UpsertFeatureFlagRequest>,
+) -> impl IntoResponse {
+    if let Err(err) = req.validate() {
+        return error_response(err.into(), &request_id.0).into_response();
+    }
+    let result = state
+        .flag_admin
+        .update(&key, Some(admin.0.id), &ctx, |flag| {
+            flag.description = req.description;
+            flag.enabled = req.enabled;
+            flag.rollout_percentage = req.rollout_percentage;
+            flag.users = req.users;
+            flag.roles = req.roles;
+            flag.orgs = req.orgs;
+        })
+        .await;
+    match result {
+        Ok(flag) => {
+            state.flags.store(flag.clone());
+            (StatusCode::OK, Json(flag)).into_response()
+        }
+        Err(err) => error_response(err, &request_id.0).into_response(),
+    }
+}
+
+#[instrument(skip(state, ctx, admin), fields(admin_id = %admin.0.id))]
+pub async fn delete_flag(
+    State(state): State<AppState>,
+    request_id: RequestIdExtractor,
+    RequestContextExtractor(ctx): RequestContextExtractor,
+    admin: RequireRole<Admin>,
+    Path(key): Path<String>,
+) -> impl IntoResponse {
+    match state.flag_admin.delete(&key, Some(admin.0.id), &ctx).await {
+        Ok(true) => {
+            state.flags.forget(&key);
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(false) => error_response(AppError::NotFound, &request_id.0).into_response(),
+        Err(err) => error_response(err, &request_id.0).into_response(),
+    }
+}
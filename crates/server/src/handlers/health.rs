@@ -11,7 +11,8 @@ pub async fn health(
     let body = HealthStatus {
         status: "ok".into(),
         db: false,
-        redis: state.redis.is_some(),
+        db_replica: None,
+        redis: state.redis.get().is_some(),
         version: env!("CARGO_PKG_VERSION").into(),
     };
     (StatusCode::OK, Json(body))
@@ -21,9 +22,11 @@ pub async fn ready(
     State(state): State<AppState>,
     _request_id: RequestIdExtractor,
 ) -> impl axum::response::IntoResponse {
-    let db_ok = state.db.ping().await.is_ok();
-    let redis_ok = if let Some(client) = &state.redis {
-        let mut conn = client.clone();
+    // Not ready while migrations are pending either, as after a degraded start.
+    let db_ok = state.readiness.is_ready() && state.db.ping().await.is_ok();
+    // Reads fall back to the primary while the replica is down, so it doesn't fail readiness.
+    let replica_ok = state.db.ping_replica().await.map(|result| result.is_ok());
+    let redis_ok = if let Some(mut conn) = state.redis.get() {
         redis::cmd("PING")
             .query_async::<_, String>(&mut conn)
             .await
@@ -44,6 +47,7 @@ pub async fn ready(
             "degraded".into()
         },
         db: db_ok,
+        db_replica: replica_ok,
         redis: redis_ok,
         version: env!("CARGO_PKG_VERSION").into(),
     };
@@ -63,8 +67,7 @@ where
     fn from_request_parts(
         parts: &mut axum::http::request::Parts,
         _state: &S,
-    ) -> impl std::future::Future<Output = Result<Self, Self::Rejection>> + Send
-    {
+    ) -> impl std::future::Future<Output = Result<Self, Self::Rejection>> + Send {
         let request_id = parts
             .extensions
             .get::<RequestId>()
@@ -0,0 +1,76 @@
+use crate::extractors::{AuthUser, SessionId};
+use crate::live::LiveMessage;
+use crate::state::AppState;
+use axum::{
+    extract::{Query, State},
+    http::{HeaderMap, HeaderName, HeaderValue},
+    response::sse::{Event, KeepAlive, Sse},
+    response::IntoResponse,
+};
+use futures::{stream, StreamExt};
+use serde::Deserialize;
+use shared::dto::LiveEvent;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use uuid::Uuid;
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Deserialize)]
+pub struct EventsQuery {
+    /// Same as the `Last-Event-ID` header, for clients that open a new `EventSource`.
+    pub last_event_id: Option<Uuid>,
+}
+
+/// Streams the caller's [`LiveEvent`]s as server-sent events, resuming after `Last-Event-ID`.
+pub async fn events(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    SessionId(session_id): SessionId,
+    headers: HeaderMap,
+    Query(query): Query<EventsQuery>,
+) -> impl IntoResponse {
+    let last_event_id = headers
+        .get("last-event-id")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| Uuid::parse_str(v).ok())
+        .or(query.last_event_id);
+    let (replay, receiver) = state.live.subscribe(user.id, last_event_id);
+
+    let live = stream::unfold(receiver, |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(message) => return Some((message, receiver)),
+                // A slow client misses events rather than holding the others back.
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+    let messages = stream::iter(replay)
+        .chain(live)
+        .filter(move |message| futures::future::ready(message.reaches(session_id)))
+        .boxed();
+    // Ends right after a revocation, rather than when the next message comes.
+    let events = stream::unfold(Some(messages), |messages| async move {
+        let mut messages = messages?;
+        let message: LiveMessage = messages.next().await?;
+        let revoked = matches!(message.event, LiveEvent::SessionRevoked { .. });
+        Some((message, (!revoked).then_some(messages)))
+    })
+    .map(|message| {
+        Event::default()
+            .id(message.id.to_string())
+            .retry(RECONNECT_DELAY)
+            .json_data(&message.event)
+    });
+
+    (
+        // Keeps buffering proxies such as nginx from holding events back.
+        [(
+            HeaderName::from_static("x-accel-buffering"),
+            HeaderValue::from_static("no"),
+        )],
+        Sse::new(events).keep_alive(KeepAlive::default()),
+    )
+}
@@ -1,8 +1,16 @@
+pub mod admin;
 pub mod auth;
+pub mod dev;
+pub mod files;
+pub mod flags;
 pub mod health;
+pub mod live;
+pub mod notifications;
 pub mod pages;
 pub mod public;
 pub mod users;
+pub mod webhooks;
+pub mod ws;
 
 use axum::{http::StatusCode, Json};
 use shared::error::{AppError, ErrorResponse};
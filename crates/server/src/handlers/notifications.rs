@@ -0,0 +1,154 @@
+use crate::extractors::{Admin, AuthUser, RequireRole};
+use crate::handlers::{error_response, RequestIdExtractor};
+use crate::state::AppState;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use domain::notifications::{effective_preferences, NewNotification};
+use domain::ports::{NotificationRepository, UserRepository};
+use serde::Deserialize;
+use shared::dto::{
+    CreateNotificationRequest, NotificationPreferenceRequest, PaginatedResponse, UnreadCount,
+};
+use shared::error::AppError;
+use tracing::instrument;
+use uuid::Uuid;
+use validator::Validate;
+
+const MAX_PER_PAGE: i64 = 100;
+
+#[derive(Debug, Deserialize)]
+pub struct Pagination {
+    #[serde(default = "default_page")]
+    pub page: i64,
+    #[serde(default = "default_per_page")]
+    pub per_page: i64,
+}
+
+fn default_page() -> i64 {
+    1
+}
+
+fn default_per_page() -> i64 {
+    20
+}
+
+#[instrument(skip(state, user), fields(user_id = %user.id))]
+pub async fn list_notifications(
+    State(state): State<AppState>,
+    request_id: RequestIdExtractor,
+    AuthUser(user): AuthUser,
+    Query(pagination): Query<Pagination>,
+) -> impl IntoResponse {
+    let page = pagination.page.max(1);
+    let per_page = pagination.per_page.clamp(1, MAX_PER_PAGE);
+    match state.db.list_notifications(user.id, page, per_page).await {
+        Ok((items, total)) => (
+            StatusCode::OK,
+            Json(PaginatedResponse {
+                items,
+                total,
+                page,
+                per_page,
+            }),
+        )
+            .into_response(),
+        Err(err) => error_response(err, &request_id.0).into_response(),
+    }
+}
+
+#[instrument(skip(state, user), fields(user_id = %user.id))]
+pub async fn unread_count(
+    State(state): State<AppState>,
+    request_id: RequestIdExtractor,
+    AuthUser(user): AuthUser,
+) -> impl IntoResponse {
+    match state.db.count_unread_notifications(user.id).await {
+        Ok(unread) => (StatusCode::OK, Json(UnreadCount { unread })).into_response(),
+        Err(err) => error_response(err, &request_id.0).into_response(),
+    }
+}
+
+#[instrument(skip(state, user), fields(user_id = %user.id))]
+pub async fn mark_read(
+    State(state): State<AppState>,
+    request_id: RequestIdExtractor,
+    AuthUser(user): AuthUser,
+    Path(id): Path<Uuid>,
+) -> impl IntoResponse {
+    match state.db.mark_notification_read(user.id, id).await {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => error_response(AppError::NotFound, &request_id.0).into_response(),
+        Err(err) => error_response(err, &request_id.0).into_response(),
+    }
+}
+
+#[instrument(skip(state, user), fields(user_id = %user.id))]
+pub async fn mark_all_read(
+    State(state): State<AppState>,
+    request_id: RequestIdExtractor,
+    AuthUser(user): AuthUser,
+) -> impl IntoResponse {
+    match state.db.mark_all_notifications_read(user.id).await {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(err) => error_response(err, &request_id.0).into_response(),
+    }
+}
+
+#[instrument(skip(state, user), fields(user_id = %user.id))]
+pub async fn preferences(
+    State(state): State<AppState>,
+    request_id: RequestIdExtractor,
+    AuthUser(user): AuthUser,
+) -> impl IntoResponse {
+    match state.db.notification_preferences(user.id).await {
+        Ok(stored) => (StatusCode::OK, Json(effective_preferences(&stored))).into_response(),
+        Err(err) => error_response(err, &request_id.0).into_response(),
+    }
+}
+
+#[instrument(skip(state, user, req), fields(user_id = %user.id))]
+pub async fn set_preference(
+    State(state): State<AppState>,
+    request_id: RequestIdExtractor,
+    AuthUser(user): AuthUser,
+    Json(req): Json<NotificationPreferenceRequest>,
+) -> impl IntoResponse {
+    let result = state
+        .db
+        .set_notification_preference(user.id, req.category, req.channel)
+        .await;
+    match result {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(err) => error_response(err, &request_id.0).into_response(),
+    }
+}
+
+#[instrument(skip(state, admin, req), fields(admin_id = %admin.0.id))]
+pub async fn create_notification(
+    State(state): State<AppState>,
+    request_id: RequestIdExtractor,
+    admin: RequireRole<Admin>,
+    Json(req): Json<CreateNotificationRequest>,
+) -> impl IntoResponse {
+    if let Err(err) = req.validate() {
+        return error_response(err.into(), &request_id.0).into_response();
+    }
+    match state.db.find_by_id(req.user_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return error_response(AppError::NotFound, &request_id.0).into_response(),
+        Err(err) => return error_response(err, &request_id.0).into_response(),
+    }
+
+    let mut notification = NewNotification::new(req.user_id, req.category, req.title, req.body);
+    if let Some(link) = req.link {
+        notification = notification.link(link);
+    }
+    match state.notifier.notify(notification, None).await {
+        Ok(notification) => (StatusCode::CREATED, Json(notification)).into_response(),
+        Err(err) => error_response(err, &request_id.0).into_response(),
+    }
+}
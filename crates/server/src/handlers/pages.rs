@@ -1,21 +1,29 @@
-use crate::handlers::RequestIdExtractor;
+use crate::extractors::{AuthUser, OptionalAuthUser};
 use crate::security;
 use crate::state::AppState;
 use axum::{
     body::Body,
     extract::State,
-    http::{HeaderMap, Request},
+    http::Request,
     response::{IntoResponse, Redirect, Response},
 };
 use axum_extra::extract::CookieJar;
-use domain::ports::UserRepository;
+use domain::flags::FlagContext;
+use domain::models::User;
+use domain::ports::FileRepository;
 use leptos::prelude::provide_context;
 
-fn bearer_or_cookie_token(state: &AppState, headers: &HeaderMap, jar: &CookieJar) -> Option<String> {
-    security::bearer_token(headers).or_else(|| {
-        jar.get(&state.config.auth.access_cookie_name)
-            .map(|c| c.value().to_string())
-    })
+fn request_csrf_token(req: &Request<Body>) -> app::CsrfToken {
+    req.extensions()
+        .get::<app::CsrfToken>()
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Flags are evaluated once per render; pages read them with `app::feature_enabled`.
+fn enabled_features(state: &AppState, user: Option<&User>) -> app::EnabledFeatures {
+    let ctx = user.map_or_else(FlagContext::anonymous, FlagContext::for_user);
+    app::EnabledFeatures(state.flags.enabled_for(&ctx))
 }
 
 fn take_flash_error_cookie(state: &AppState, jar: CookieJar) -> (CookieJar, Option<String>) {
@@ -31,15 +39,24 @@ fn take_flash_error_cookie(state: &AppState, jar: CookieJar) -> (CookieJar, Opti
 
 pub async fn app_login_page(
     State(state): State<AppState>,
+    OptionalAuthUser(current_user): OptionalAuthUser,
     jar: CookieJar,
     req: Request<Body>,
-) -> impl IntoResponse {
+) -> Response {
+    if current_user.is_some() {
+        return Redirect::to("/app").into_response();
+    }
+
     let (jar, flash_error) = take_flash_error_cookie(&state, jar);
     let leptos_options = state.leptos_options.clone();
+    let csrf_token = request_csrf_token(&req);
     let state_for_ctx = state.clone();
+    let features = enabled_features(&state, None);
     let handler = leptos_axum::render_app_to_stream_with_context(
         move || {
             provide_context(state_for_ctx.clone());
+            provide_context(features.clone());
+            provide_context(csrf_token.clone());
         },
         move || {
             let flash_error = flash_error.clone();
@@ -51,20 +68,29 @@ pub async fn app_login_page(
         },
     );
 
-    (jar, handler(req).await)
+    (jar, handler(req).await).into_response()
 }
 
 pub async fn app_register_page(
     State(state): State<AppState>,
+    OptionalAuthUser(current_user): OptionalAuthUser,
     jar: CookieJar,
     req: Request<Body>,
-) -> impl IntoResponse {
+) -> Response {
+    if current_user.is_some() {
+        return Redirect::to("/app").into_response();
+    }
+
     let (jar, flash_error) = take_flash_error_cookie(&state, jar);
     let leptos_options = state.leptos_options.clone();
+    let csrf_token = request_csrf_token(&req);
     let state_for_ctx = state.clone();
+    let features = enabled_features(&state, None);
     let handler = leptos_axum::render_app_to_stream_with_context(
         move || {
             provide_context(state_for_ctx.clone());
+            provide_context(features.clone());
+            provide_context(csrf_token.clone());
         },
         move || {
             let flash_error = flash_error.clone();
@@ -76,43 +102,64 @@ pub async fn app_register_page(
         },
     );
 
-    (jar, handler(req).await)
+    (jar, handler(req).await).into_response()
 }
 
-pub async fn app_dashboard(
+pub async fn app_register_pending_page(
     State(state): State<AppState>,
-    _request_id: RequestIdExtractor,
-    headers: HeaderMap,
-    jar: CookieJar,
     req: Request<Body>,
 ) -> Response {
-    let Some(token) = bearer_or_cookie_token(&state, &headers, &jar) else {
-        return Redirect::to("/app/login").into_response();
-    };
-
-    let claims = match security::decode_access_token(&token, &state.config.auth) {
-        Ok(c) => c,
-        Err(_) => return Redirect::to("/app/login").into_response(),
-    };
+    let leptos_options = state.leptos_options.clone();
+    let state_for_ctx = state.clone();
+    let features = enabled_features(&state, None);
+    let handler = leptos_axum::render_app_to_stream_with_context(
+        move || {
+            provide_context(state_for_ctx.clone());
+            provide_context(features.clone());
+        },
+        move || {
+            leptos::prelude::view! {
+                <app::PageShell title="Check your inbox" options=leptos_options.clone() client_scripts=false>
+                    <app::RegistrationPendingPage/>
+                </app::PageShell>
+            }
+        },
+    );
 
-    let user = match state.db.find_by_id(claims.sub).await {
-        Ok(Some(user)) => user,
-        _ => return Redirect::to("/app/login").into_response(),
-    };
+    handler(req).await
+}
 
+pub async fn app_dashboard(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    req: Request<Body>,
+) -> Response {
     let leptos_options = state.leptos_options.clone();
+    let csrf_token = request_csrf_token(&req);
     let state_for_ctx = state.clone();
-    let email = user.email.clone();
+    let features = enabled_features(&state, Some(&auth_user.0));
+    let email = auth_user.0.email.clone();
+    let avatar_url = match state.db.find_avatar(auth_user.0.id).await {
+        Ok(file) => file.map(|file| state.files.signed_url(file.id).0),
+        Err(err) => {
+            tracing::warn!(error = %err, "failed to load avatar");
+            None
+        }
+    };
 
     let handler = leptos_axum::render_app_to_stream_with_context(
         move || {
             provide_context(state_for_ctx.clone());
+            provide_context(features.clone());
+            provide_context(csrf_token.clone());
+            provide_context(auth_user.clone());
         },
         move || {
             let email = email.clone();
+            let avatar_url = avatar_url.clone();
             leptos::prelude::view! {
-                <app::PageShell title="Dashboard" options=leptos_options.clone() client_scripts=false>
-                    <app::DashboardPage email/>
+                <app::PageShell title="Dashboard" options=leptos_options.clone() client_scripts=true>
+                    <app::DashboardPage email avatar_url/>
                 </app::PageShell>
             }
         },
@@ -124,9 +171,11 @@ pub async fn app_dashboard(
 pub async fn app_not_found(State(state): State<AppState>, req: Request<Body>) -> Response {
     let leptos_options = state.leptos_options.clone();
     let state_for_ctx = state.clone();
+    let features = enabled_features(&state, None);
     let handler = leptos_axum::render_app_to_stream_with_context(
         move || {
             provide_context(state_for_ctx.clone());
+            provide_context(features.clone());
         },
         move || {
             leptos::prelude::view! {
@@ -68,3 +68,63 @@ pub async fn not_found(_uri: Uri) -> impl IntoResponse {
         Html(html),
     )
 }
+
+pub fn forbidden() -> impl IntoResponse {
+    let html = r#"<!doctype html>
+<html lang="en">
+  <head>
+    <meta charset="utf-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+    <title>403 - Forbidden</title>
+    <link rel="stylesheet" href="/pkg/app.css">
+  </head>
+  <body>
+    <main class="min-h-screen bg-slate-950 text-slate-100 flex items-center justify-center">
+      <div class="text-center space-y-4">
+        <p class="text-sm text-emerald-300 uppercase tracking-widest">403</p>
+        <h1 class="text-3xl font-bold">You don't have access to this page</h1>
+        <a href="/app" class="btn-primary">Back to dashboard</a>
+      </div>
+    </main>
+  </body>
+</html>"#
+        .to_string();
+
+    (
+        StatusCode::FORBIDDEN,
+        [(header::CONTENT_TYPE, "text/html; charset=utf-8")],
+        Html(html),
+    )
+}
+
+/// Served for pages while the database is unavailable; see `dependencies::maintenance`.
+pub fn maintenance() -> impl IntoResponse {
+    let html = r#"<!doctype html>
+<html lang="en">
+  <head>
+    <meta charset="utf-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+    <title>503 - Down for maintenance</title>
+    <link rel="stylesheet" href="/pkg/app.css">
+  </head>
+  <body>
+    <main class="min-h-screen bg-slate-950 text-slate-100 flex items-center justify-center">
+      <div class="text-center space-y-4">
+        <p class="text-sm text-emerald-300 uppercase tracking-widest">503</p>
+        <h1 class="text-3xl font-bold">We'll be right back</h1>
+        <p class="text-slate-400">The site is temporarily unavailable. Please try again in a minute.</p>
+      </div>
+    </main>
+  </body>
+</html>"#
+        .to_string();
+
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        [
+            (header::CONTENT_TYPE, "text/html; charset=utf-8"),
+            (header::RETRY_AFTER, "30"),
+        ],
+        Html(html),
+    )
+}
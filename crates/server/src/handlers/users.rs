@@ -1,74 +1,243 @@
+use crate::extractors::{Admin, RequestContextExtractor, RequireRole};
 use crate::handlers::{error_response, RequestIdExtractor};
-use crate::security;
 use crate::state::AppState;
 use axum::{
-    extract::{Query, State},
-    http::HeaderMap,
-    http::StatusCode,
+    extract::{Path, Query, State},
+    http::{header, HeaderValue, StatusCode},
     response::IntoResponse,
     Json,
 };
-use domain::models::User;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use domain::models::{User, UserCursor, UserFilter, UserQuery, UserSort};
 use domain::ports::UserRepository;
-use serde::Deserialize;
-use shared::dto::{PaginatedResponse, UserResponse};
+use serde::{Deserialize, Serialize};
+use shared::dto::{ChangeRoleRequest, CursorPage, UserResponse};
 use shared::error::AppError;
 use shared::types::UserRole;
 use tracing::instrument;
+use uuid::Uuid;
 
-#[derive(Debug, Deserialize)]
-pub struct Pagination {
-    #[serde(default = "default_page")]
-    pub page: i64,
-    #[serde(default = "default_per_page")]
-    pub per_page: i64,
+const DEFAULT_USER_PAGE: i64 = 20;
+const MAX_USER_PAGE: i64 = 100;
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct UserListParams {
+    /// Part of the email, in any case.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub role: Option<UserRole>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_from: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_to: Option<DateTime<Utc>>,
+    /// `created_at` or `email`, prefixed with `-` for descending order. Newest first when
+    /// absent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sort: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<i64>,
+    /// Also count every matching user, which costs a scan the page alone does not.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub include_total: bool,
 }
 
-fn default_page() -> i64 {
-    1
+impl UserListParams {
+    fn sort(&self) -> Result<(UserSort, bool), AppError> {
+        let value = self.sort.as_deref().unwrap_or("-created_at");
+        let (field, descending) = match value.strip_prefix('-') {
+            Some(field) => (field, true),
+            None => (value, false),
+        };
+        let sort = UserSort::parse(field)
+            .ok_or_else(|| AppError::Validation(format!("cannot sort users by {field}")))?;
+        Ok((sort, descending))
+    }
+
+    fn filter(&self) -> UserFilter {
+        UserFilter {
+            email: self.email.clone().filter(|email| !email.is_empty()),
+            role: self.role,
+            created_from: self.created_from,
+            created_to: self.created_to,
+        }
+    }
+
+    /// This listing again, from `cursor`.
+    fn link(&self, cursor: String, rel: &str) -> String {
+        let params = UserListParams {
+            cursor: Some(cursor),
+            ..self.clone()
+        };
+        let query = serde_urlencoded::to_string(&params).unwrap_or_default();
+        format!("</api/users?{query}>; rel=\"{rel}\"")
+    }
 }
 
-fn default_per_page() -> i64 {
-    20
+/// The opaque `cursor` of `/api/users`: where a page starts, the order it belongs to, and
+/// whether it pages backwards from there.
+struct PageCursor {
+    position: UserCursor,
+    descending: bool,
+    backward: bool,
 }
 
-#[instrument(skip(state, headers))]
+impl PageCursor {
+    fn encode(&self) -> String {
+        let key = match &self.position {
+            UserCursor::CreatedAt(created_at, _) => created_at.timestamp_micros().to_string(),
+            UserCursor::Email(email, _) => email.clone(),
+        };
+        let raw = format!(
+            "{}:{}:{}:{}:{}",
+            self.position.sort().as_str(),
+            if self.descending { "desc" } else { "asc" },
+            if self.backward { "prev" } else { "next" },
+            self.position.id(),
+            key
+        );
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(raw)
+    }
+
+    fn decode(value: &str) -> Result<Self, AppError> {
+        let invalid = || AppError::Validation("invalid cursor".into());
+        let raw = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(value)
+            .map_err(|_| invalid())?;
+        let raw = String::from_utf8(raw).map_err(|_| invalid())?;
+        // The key goes last because an email may contain `:`.
+        let mut parts = raw.splitn(5, ':');
+        let mut next = || parts.next().ok_or_else(invalid);
+        let sort = UserSort::parse(next()?).ok_or_else(invalid)?;
+        let descending = match next()? {
+            "asc" => false,
+            "desc" => true,
+            _ => return Err(invalid()),
+        };
+        let backward = match next()? {
+            "next" => false,
+            "prev" => true,
+            _ => return Err(invalid()),
+        };
+        let id = Uuid::parse_str(next()?).map_err(|_| invalid())?;
+        let key = next()?;
+        let position = match sort {
+            UserSort::CreatedAt => {
+                let micros = key.parse::<i64>().map_err(|_| invalid())?;
+                let created_at = DateTime::from_timestamp_micros(micros).ok_or_else(invalid)?;
+                UserCursor::CreatedAt(created_at, id)
+            }
+            UserSort::Email => UserCursor::Email(key.to_string(), id),
+        };
+        Ok(Self {
+            position,
+            descending,
+            backward,
+        })
+    }
+}
+
+/// Lists users a page at a time, in keyset order. The response carries `next_cursor` and
+/// `prev_cursor`, and the same pages as `Link` headers.
+#[instrument(skip(state, admin), fields(admin_id = %admin.0.id))]
 pub async fn list_users(
     State(state): State<AppState>,
     request_id: RequestIdExtractor,
-    headers: HeaderMap,
-    Query(pagination): Query<Pagination>,
+    admin: RequireRole<Admin>,
+    Query(params): Query<UserListParams>,
 ) -> impl IntoResponse {
-    let Some(token) = security::bearer_token(&headers) else {
-        return error_response(AppError::Unauthorized, &request_id.0).into_response();
+    let (sort, descending) = match params.sort() {
+        Ok(sort) => sort,
+        Err(err) => return error_response(err, &request_id.0).into_response(),
     };
-
-    let claims = match security::decode_access_token(&token, &state.config.auth) {
-        Ok(c) => c,
+    let cursor = match params.cursor.as_deref().map(PageCursor::decode).transpose() {
+        Ok(cursor) => cursor,
         Err(err) => return error_response(err, &request_id.0).into_response(),
     };
-
-    if claims.role != UserRole::Admin {
-        return error_response(AppError::Forbidden, &request_id.0).into_response();
+    if cursor
+        .as_ref()
+        .is_some_and(|c| c.position.sort() != sort || c.descending != descending)
+    {
+        let err = AppError::Validation("cursor belongs to a different sort".into());
+        return error_response(err, &request_id.0).into_response();
     }
 
-    let (users, total) = match state
-        .db
-        .list_users(pagination.page, pagination.per_page)
-        .await
-    {
-        Ok(data) => data,
+    // A backward page is read in reverse from the cursor, then flipped. Ask for one extra
+    // row to learn whether there is more in the direction being paged.
+    let backward = cursor.as_ref().is_some_and(|c| c.backward);
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_USER_PAGE)
+        .clamp(1, MAX_USER_PAGE);
+    let filter = params.filter();
+    let query = UserQuery {
+        filter: filter.clone(),
+        sort,
+        descending: descending != backward,
+        after: cursor.map(|c| c.position),
+        limit: limit + 1,
+    };
+    let mut users = match state.db.list_users(&query).await {
+        Ok(users) => users,
         Err(err) => return error_response(err, &request_id.0).into_response(),
     };
+    let more = users.len() as i64 > limit;
+    users.truncate(limit as usize);
+    if backward {
+        users.reverse();
+    }
+    // The page the cursor came from lies on its other side.
+    let (has_next, has_prev) = if backward {
+        (true, more)
+    } else {
+        (more, query.after.is_some())
+    };
+    let page_cursor = |user: &User, backward| {
+        PageCursor {
+            position: UserCursor::from_user(user, sort),
+            descending,
+            backward,
+        }
+        .encode()
+    };
+    let next_cursor = users
+        .last()
+        .filter(|_| has_next)
+        .map(|user| page_cursor(user, false));
+    let prev_cursor = users
+        .first()
+        .filter(|_| has_prev)
+        .map(|user| page_cursor(user, true));
 
-    let resp = PaginatedResponse {
+    let total = if params.include_total {
+        match state.db.count_users(&filter).await {
+            Ok(total) => Some(total),
+            Err(err) => return error_response(err, &request_id.0).into_response(),
+        }
+    } else {
+        None
+    };
+
+    let links: Vec<String> = [(&next_cursor, "next"), (&prev_cursor, "prev")]
+        .into_iter()
+        .filter_map(|(cursor, rel)| Some(params.link(cursor.clone()?, rel)))
+        .collect();
+    let page = CursorPage {
         items: users.into_iter().map(to_user_response).collect(),
+        next_cursor,
+        prev_cursor,
         total,
-        page: pagination.page,
-        per_page: pagination.per_page,
     };
-
-    (StatusCode::OK, Json(resp)).into_response()
+    let mut response = (StatusCode::OK, Json(page)).into_response();
+    if !links.is_empty() {
+        if let Ok(value) = HeaderValue::from_str(&links.join(", ")) {
+            response.headers_mut().insert(header::LINK, value);
+        }
+    }
+    response
 }
 
 fn to_user_response(user: User) -> UserResponse {
@@ -79,3 +248,22 @@ fn to_user_response(user: User) -> UserResponse {
         created_at: user.created_at,
     }
 }
+
+#[instrument(skip(state, ctx, admin, req), fields(admin_id = %admin.0.id))]
+pub async fn change_role(
+    State(state): State<AppState>,
+    request_id: RequestIdExtractor,
+    RequestContextExtractor(ctx): RequestContextExtractor,
+    admin: RequireRole<Admin>,
+    Path(user_id): Path<Uuid>,
+    Json(req): Json<ChangeRoleRequest>,
+) -> impl IntoResponse {
+    match state
+        .auth
+        .change_role(&admin.0, user_id, req.role, &ctx)
+        .await
+    {
+        Ok(user) => (StatusCode::OK, Json(to_user_response(user))).into_response(),
+        Err(err) => error_response(err, &request_id.0).into_response(),
+    }
+}
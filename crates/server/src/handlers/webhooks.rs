@@ -0,0 +1,173 @@
+use crate::extractors::{Admin, RequireRole};
+use crate::handlers::{error_response, RequestIdExtractor};
+use crate::state::AppState;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use domain::ports::WebhookRepository;
+use domain::webhooks::{event_catalogue, NewWebhookEndpoint, WebhookEndpoint};
+use serde::{Deserialize, Serialize};
+use shared::dto::CreateWebhookRequest;
+use shared::error::AppError;
+use tracing::instrument;
+use uuid::Uuid;
+use validator::Validate;
+
+const MAX_DELIVERY_PAGE: i64 = 200;
+
+/// The endpoint as stored, plus its signing secret. Only returned once, on creation.
+#[derive(Serialize)]
+pub struct CreatedWebhook {
+    #[serde(flatten)]
+    pub endpoint: WebhookEndpoint,
+    pub secret: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeliveryParams {
+    #[serde(default = "default_limit")]
+    pub limit: i64,
+}
+
+fn default_limit() -> i64 {
+    50
+}
+
+pub async fn list_webhook_events(_admin: RequireRole<Admin>) -> impl IntoResponse {
+    Json(serde_json::json!({ "events": event_catalogue() }))
+}
+
+#[instrument(skip(state, admin), fields(admin_id = %admin.0.id))]
+pub async fn list_webhooks(
+    State(state): State<AppState>,
+    request_id: RequestIdExtractor,
+    admin: RequireRole<Admin>,
+) -> impl IntoResponse {
+    match state.db.list_webhook_endpoints(admin.0.id).await {
+        Ok(endpoints) => (StatusCode::OK, Json(endpoints)).into_response(),
+        Err(err) => error_response(err, &request_id.0).into_response(),
+    }
+}
+
+#[instrument(skip(state, admin, req), fields(admin_id = %admin.0.id))]
+pub async fn create_webhook(
+    State(state): State<AppState>,
+    request_id: RequestIdExtractor,
+    admin: RequireRole<Admin>,
+    Json(req): Json<CreateWebhookRequest>,
+) -> impl IntoResponse {
+    if let Err(err) = validate_webhook(&req) {
+        return error_response(err, &request_id.0).into_response();
+    }
+
+    let secret = NewWebhookEndpoint::generate_secret();
+    let new_endpoint = NewWebhookEndpoint {
+        owner_id: admin.0.id,
+        url: req.url,
+        description: req.description,
+        events: req.events,
+        secret: secret.clone(),
+    };
+    match state.db.create_webhook_endpoint(new_endpoint).await {
+        Ok(endpoint) => (
+            StatusCode::CREATED,
+            Json(CreatedWebhook { endpoint, secret }),
+        )
+            .into_response(),
+        Err(err) => error_response(err, &request_id.0).into_response(),
+    }
+}
+
+#[instrument(skip(state, admin), fields(admin_id = %admin.0.id))]
+pub async fn delete_webhook(
+    State(state): State<AppState>,
+    request_id: RequestIdExtractor,
+    admin: RequireRole<Admin>,
+    Path(id): Path<Uuid>,
+) -> impl IntoResponse {
+    match state.db.delete_webhook_endpoint(id, admin.0.id).await {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => error_response(AppError::NotFound, &request_id.0).into_response(),
+        Err(err) => error_response(err, &request_id.0).into_response(),
+    }
+}
+
+#[instrument(skip(state, admin), fields(admin_id = %admin.0.id))]
+pub async fn list_webhook_deliveries(
+    State(state): State<AppState>,
+    request_id: RequestIdExtractor,
+    admin: RequireRole<Admin>,
+    Path(id): Path<Uuid>,
+    Query(params): Query<DeliveryParams>,
+) -> impl IntoResponse {
+    if let Err(err) = owned_endpoint(&state, id, admin.0.id).await {
+        return error_response(err, &request_id.0).into_response();
+    }
+    let limit = params.limit.clamp(1, MAX_DELIVERY_PAGE);
+    match state.db.list_webhook_deliveries(id, limit).await {
+        Ok(deliveries) => (StatusCode::OK, Json(deliveries)).into_response(),
+        Err(err) => error_response(err, &request_id.0).into_response(),
+    }
+}
+
+#[instrument(skip(state, admin), fields(admin_id = %admin.0.id))]
+pub async fn redeliver_webhook(
+    State(state): State<AppState>,
+    request_id: RequestIdExtractor,
+    admin: RequireRole<Admin>,
+    Path(id): Path<Uuid>,
+) -> impl IntoResponse {
+    let result = async {
+        let delivery = state
+            .db
+            .find_webhook_delivery(id)
+            .await?
+            .ok_or(AppError::NotFound)?;
+        owned_endpoint(&state, delivery.endpoint_id, admin.0.id).await?;
+        if !state.db.redeliver_webhook(id).await? {
+            return Err(AppError::NotFound);
+        }
+        Ok(())
+    }
+    .await;
+
+    match result {
+        Ok(()) => StatusCode::ACCEPTED.into_response(),
+        Err(err) => error_response(err, &request_id.0).into_response(),
+    }
+}
+
+/// Other admins' endpoints look the same as missing ones.
+async fn owned_endpoint(
+    state: &AppState,
+    id: Uuid,
+    owner_id: Uuid,
+) -> Result<WebhookEndpoint, AppError> {
+    match state.db.find_webhook_endpoint(id).await? {
+        Some(endpoint) if endpoint.owner_id == owner_id => Ok(endpoint),
+        _ => Err(AppError::NotFound),
+    }
+}
+
+fn validate_webhook(req: &CreateWebhookRequest) -> Result<(), AppError> {
+    req.validate()?;
+    if !(req.url.starts_with("https://") || req.url.starts_with("http://")) {
+        return Err(AppError::Validation(
+            "webhook url must use http or https".into(),
+        ));
+    }
+    let catalogue = event_catalogue();
+    if let Some(unknown) = req
+        .events
+        .iter()
+        .find(|event| !catalogue.contains(&event.as_str()))
+    {
+        return Err(AppError::Validation(format!(
+            "unknown event type {unknown}"
+        )));
+    }
+    Ok(())
+}
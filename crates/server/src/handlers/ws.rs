@@ -0,0 +1,42 @@
+use crate::extractors::{AuthUser, SessionId};
+use crate::handlers::{error_response, RequestIdExtractor};
+use crate::security;
+use crate::state::AppState;
+use crate::ws::{self, MAX_MESSAGE_BYTES};
+use axum::{
+    extract::{ws::WebSocketUpgrade, State},
+    http::{header, HeaderMap},
+    response::{IntoResponse, Response},
+};
+use shared::error::AppError;
+
+/// Upgrades to a WebSocket speaking the `ClientMessage`/`ServerMessage` protocol.
+/// Authenticates like any API route, with a bearer token or the access cookie.
+pub async fn connect(
+    State(state): State<AppState>,
+    request_id: RequestIdExtractor,
+    AuthUser(user): AuthUser,
+    SessionId(session_id): SessionId,
+    headers: HeaderMap,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    // Browsers attach cookies to cross-site WebSocket handshakes, and CSRF tokens cannot be
+    // sent with them, so cookie-authenticated sockets must come from our own origin.
+    if security::bearer_token(&headers).is_none() && !same_origin(&headers, &state) {
+        return error_response(AppError::Forbidden, &request_id.0).into_response();
+    }
+
+    let rooms = state.rooms.clone();
+    let live = state.live.clone();
+    upgrade
+        .max_message_size(MAX_MESSAGE_BYTES)
+        .on_upgrade(move |socket| ws::serve_socket(socket, user, session_id, rooms, live))
+}
+
+fn same_origin(headers: &HeaderMap, state: &AppState) -> bool {
+    match headers.get(header::ORIGIN).and_then(|v| v.to_str().ok()) {
+        Some(origin) => origin == state.config.server.base_url.trim_end_matches('/'),
+        // Non-browser clients send no `Origin`.
+        None => true,
+    }
+}
@@ -0,0 +1,518 @@
+#![recursion_limit = "256"]
+
+mod audit;
+mod csrf;
+mod dependencies;
+mod events;
+mod extractors;
+mod files;
+mod flags;
+mod handlers;
+mod live;
+mod notifications;
+mod security;
+mod session;
+mod signup;
+mod state;
+mod telemetry;
+#[cfg(feature = "test-support")]
+pub mod test_support;
+mod worker;
+mod ws;
+
+use crate::handlers::{
+    admin, auth, dev, files as file_routes, flags as flag_routes, health, live as live_events,
+    notifications as notification_routes, pages, users, webhooks, ws as websocket,
+};
+use crate::handlers::public;
+use crate::state::AppState;
+use anyhow::Context;
+use domain::events::EventBus;
+use axum::{
+    http,
+    http::{HeaderValue, StatusCode},
+    response::Redirect,
+    middleware,
+    routing::{delete, get, post, put},
+    Router,
+};
+use metrics_exporter_prometheus::PrometheusHandle;
+use shared::config::AppConfig;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::future::IntoFuture;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::task::LocalSet;
+use tower_http::cors::CorsLayer;
+use tower_http::services::ServeDir;
+use tower_http::trace::TraceLayer;
+use tracing::info;
+use uuid::Uuid;
+
+/// Reads the configuration from the environment and serves the app until shut down.
+pub async fn run() -> anyhow::Result<()> {
+    let config = AppConfig::from_env()?;
+    telemetry::init_tracing(&config.tracing)?;
+
+    // Leptos/ServerFn uses `any_spawner` for background tasks; initialize it for Tokio.
+    let _ = any_spawner::Executor::init_tokio();
+
+    // When running the server directly (e.g. `cargo run -p server --release`),
+    // cargo-leptos runtime env vars (like `LEPTOS_OUTPUT_NAME`) may be absent.
+    // Read workspace metadata from the workspace `Cargo.toml`.
+    let workspace_cargo_toml = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("../..")
+        .join("Cargo.toml");
+    let workspace_cargo_toml = workspace_cargo_toml
+        .to_str()
+        .unwrap_or("Cargo.toml");
+    let leptos_config = leptos_config::get_configuration(Some(workspace_cargo_toml))?;
+    let mut leptos_options = leptos_config.leptos_options;
+
+    let addr: SocketAddr = config.addr().context("invalid server addr")?;
+    leptos_options.site_addr = addr;
+
+    let metrics_handle = metrics_exporter_prometheus::PrometheusBuilder::new()
+        .set_buckets_for_metric(
+            metrics_exporter_prometheus::Matcher::Prefix("db_".into()),
+            db::LATENCY_BUCKETS,
+        )
+        .expect("db latency buckets are not empty")
+        .install_recorder()
+        .expect("failed to install prometheus recorder");
+
+    let readiness = dependencies::Readiness::default();
+    let db = if config.server.degraded_start {
+        // Start without the database; the monitor migrates once it answers and pages show
+        // the maintenance page until then.
+        db::Database::connect_lazy(&config.database).await?
+    } else {
+        let db = db::Database::connect(&config.database).await?;
+        if config.database.auto_migrate {
+            db.migrate().await?;
+        } else {
+            db.ensure_migrated().await?;
+        }
+        readiness.set(true);
+        db
+    };
+    if db.is_memory() && config.server.env.is_prod() {
+        tracing::warn!("DATABASE_URL is memory:// in production; all data is lost on restart");
+    }
+    dependencies::spawn_database_monitor(
+        db.clone(),
+        &config.database,
+        readiness.clone(),
+        readiness.is_ready(),
+    );
+
+    audit::spawn_checkpoints(db.clone(), &config.audit);
+
+    let (mail_transport, mailbox) = mail::transport_from_config(&config.mail)?;
+    if mailbox.is_some() && config.server.env.is_prod() {
+        tracing::warn!("MAIL_TRANSPORT is memory in production; emails will not be delivered");
+    }
+
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let job_worker =
+        worker::spawn_worker(db.clone(), &config, mail_transport, shutdown_rx.clone())?;
+
+    let redis_client = config
+        .redis
+        .as_ref()
+        .map(|redis_cfg| redis::Client::open(redis_cfg.url.as_str()))
+        .transpose()
+        .context("invalid REDIS_URL")?;
+    let redis = match &redis_client {
+        Some(client) => dependencies::Redis::connect(client.clone()).await,
+        None => dependencies::Redis::default(),
+    };
+
+    let feature_flags = match flags::FeatureFlags::load(&db).await {
+        Ok(flags) => flags,
+        // The listener loads them once the database answers.
+        Err(err) if config.server.degraded_start => {
+            tracing::warn!(error = %err, "feature flags unavailable; starting with all off");
+            flags::FeatureFlags::default()
+        }
+        Err(err) => return Err(err.into()),
+    };
+    feature_flags.spawn_listener(db.clone());
+
+    let (state, event_bus, wake_dispatcher) = build_state(
+        &config,
+        db.clone(),
+        Runtime {
+            readiness,
+            redis,
+            flags: feature_flags,
+            leptos_options: leptos_options.clone(),
+            metrics: metrics_handle.clone(),
+            mailbox,
+        },
+    )?;
+    let live = state.live.clone();
+    if let Some(client) = redis_client {
+        live.spawn_redis_listener(client);
+    }
+    let dispatcher = events::spawn_dispatcher(db.clone(), event_bus, wake_dispatcher, shutdown_rx);
+
+    let app = build_router(state.clone(), leptos_options, metrics_handle);
+
+    info!("listening on http://{}", addr);
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+
+    // Leptos uses !Send futures in some cases; wrap server in LocalSet to allow spawn_local.
+    let local = LocalSet::new();
+    local
+        .run_until(async move {
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .with_graceful_shutdown(async move {
+                worker::shutdown_signal().await;
+                // Open event streams would otherwise keep the server from stopping.
+                live.close();
+            })
+            .into_future()
+            .await?;
+            Ok::<(), anyhow::Error>(())
+        })
+        .await?;
+
+    // Stop dispatching events and claiming jobs; running jobs get the configured grace period.
+    let _ = shutdown_tx.send(true);
+    let _ = dispatcher.await;
+    if let Some(job_worker) = job_worker {
+        let _ = job_worker.await;
+    }
+
+    opentelemetry::global::shutdown_tracer_provider();
+
+    Ok(())
+}
+
+/// What [`build_state`] takes from `run` besides the configuration and database: the
+/// connections it made and the handles it installed.
+struct Runtime {
+    readiness: dependencies::Readiness,
+    redis: dependencies::Redis,
+    flags: flags::FeatureFlags,
+    leptos_options: leptos_config::LeptosOptions,
+    metrics: PrometheusHandle,
+    mailbox: Option<mail::MemoryTransport>,
+}
+
+/// Wires the services the handlers use. Also returns the event bus for the dispatcher and
+/// the notify that wakes it whenever a service records an event.
+fn build_state(
+    config: &AppConfig,
+    db: db::Database,
+    runtime: Runtime,
+) -> anyhow::Result<(AppState, EventBus, Arc<Notify>)> {
+    let live = live::LiveHub::new(runtime.redis.clone());
+    let repo = Arc::new(db.clone());
+    let mailer = mail::Mailer::new(repo.clone());
+    let notifier = notifications::Notifier::new(
+        repo.clone(),
+        mailer.clone(),
+        live.clone(),
+        config.server.base_url.clone(),
+    );
+    let event_bus = EventBus::new()
+        .subscribe(Arc::new(domain::events::AuditSubscriber::new(repo.clone())))
+        .subscribe(Arc::new(events::MetricsSubscriber))
+        .subscribe(Arc::new(live::LiveSubscriber::new(live.clone())))
+        .subscribe(Arc::new(notifications::NotificationSubscriber::new(
+            notifier.clone(),
+        )))
+        .subscribe(Arc::new(::webhooks::WebhookSubscriber::new(repo.clone())));
+    let wake_dispatcher = Arc::new(Notify::new());
+
+    let files = files::Files::new(
+        repo.clone(),
+        storage::storage_from_config(&config.storage)?,
+        &config.auth.jwt_secret,
+        config.storage.download_url_ttl_seconds,
+    )?;
+
+    let flag_admin = domain::flags::FeatureFlagService::new(repo.clone()).with_publisher(Arc::new(
+        events::WakingPublisher::new(repo.clone(), wake_dispatcher.clone()),
+    ));
+
+    let auth = domain::AuthService::new(
+        repo.clone(),
+        domain::RefreshTokenHasher::from_config(&config.auth),
+    )
+    .with_rotation_grace(Duration::from_secs(
+        config.auth.refresh_rotation_grace_seconds,
+    ))
+    .with_publisher(Arc::new(events::WakingPublisher::new(
+        repo,
+        wake_dispatcher.clone(),
+    )))
+    .with_notifier(Arc::new(signup::MailRegistrationNotifier::new(
+        mailer,
+        config.server.base_url.clone(),
+        config.auth.signup_token_ttl_minutes,
+    )));
+
+    let state = AppState {
+        config: config.clone(),
+        db,
+        readiness: runtime.readiness,
+        auth,
+        leptos_options: runtime.leptos_options,
+        metrics: runtime.metrics,
+        redis: runtime.redis,
+        live,
+        rooms: ws::Rooms::default(),
+        notifier,
+        files,
+        flags: runtime.flags,
+        flag_admin: Arc::new(flag_admin),
+        // Captured mail is only ever shown outside production.
+        mailbox: runtime.mailbox.filter(|_| !config.server.env.is_prod()),
+    };
+    Ok((state, event_bus, wake_dispatcher))
+}
+
+fn build_router(
+    state: AppState,
+    leptos_options: leptos_config::LeptosOptions,
+    metrics_handle: PrometheusHandle,
+) -> Router<()> {
+    let site_root: PathBuf = PathBuf::from(leptos_options.site_root.as_ref());
+    let pkg_dir = site_root.join("pkg");
+
+    let auth_routes = Router::<AppState>::new()
+        .route("/api/auth/register", post(auth::register))
+        .route(
+            "/api/auth/register/confirm",
+            post(auth::confirm_registration),
+        )
+        .route("/api/auth/login", post(auth::login))
+        .route("/api/auth/logout", post(auth::logout))
+        .route("/api/auth/logout-all", post(auth::logout_all))
+        .route("/api/auth/refresh", post(auth::refresh))
+        .route("/api/me", get(auth::me))
+        .route("/api/events", get(live_events::events))
+        .route("/api/ws", get(websocket::connect))
+        .route("/api/flags", get(flag_routes::enabled_flags))
+        .route(
+            "/api/notifications",
+            get(notification_routes::list_notifications),
+        )
+        .route(
+            "/api/notifications/unread-count",
+            get(notification_routes::unread_count),
+        )
+        .route(
+            "/api/notifications/read-all",
+            post(notification_routes::mark_all_read),
+        )
+        .route(
+            "/api/notifications/{id}/read",
+            post(notification_routes::mark_read),
+        )
+        .route(
+            "/api/notifications/preferences",
+            get(notification_routes::preferences).put(notification_routes::set_preference),
+        )
+        .route("/api/me/password", post(auth::change_password))
+        .route(
+            "/api/me/avatar",
+            get(file_routes::avatar)
+                .put(file_routes::upload_avatar)
+                .delete(file_routes::delete_avatar)
+                .layer(files::body_limit(state.config.storage.max_avatar_bytes)),
+        )
+        .route(
+            "/api/files",
+            post(file_routes::upload_file)
+                .layer(files::body_limit(state.config.storage.max_attachment_bytes)),
+        )
+        .route(
+            "/api/files/{id}",
+            get(file_routes::get_file).delete(file_routes::delete_file),
+        );
+
+    let api_routes = Router::<AppState>::new()
+        .route("/api/health", get(health::health))
+        .route("/api/ready", get(health::ready))
+        .route("/api/users", get(users::list_users))
+        .route("/api/admin/audit", get(admin::list_audit_events))
+        .route("/api/admin/users/{id}/role", put(users::change_role))
+        .route("/api/admin/flags", get(flag_routes::list_flags))
+        .route(
+            "/api/admin/flags/{key}",
+            put(flag_routes::upsert_flag).delete(flag_routes::delete_flag),
+        )
+        .route(
+            "/api/admin/notifications",
+            post(notification_routes::create_notification),
+        )
+        .route(
+            "/api/admin/webhooks",
+            get(webhooks::list_webhooks).post(webhooks::create_webhook),
+        )
+        .route("/api/admin/webhooks/events", get(webhooks::list_webhook_events))
+        .route("/api/admin/webhooks/{id}", delete(webhooks::delete_webhook))
+        .route(
+            "/api/admin/webhooks/{id}/deliveries",
+            get(webhooks::list_webhook_deliveries),
+        )
+        .route(
+            "/api/admin/webhooks/deliveries/{id}/redeliver",
+            post(webhooks::redeliver_webhook),
+        )
+        .merge(auth_routes);
+
+    let trace_layer = TraceLayer::new_for_http();
+
+    let metrics_route = Router::<AppState>::new().route(
+        "/metrics",
+        get({
+            move |axum::extract::State(state): axum::extract::State<AppState>| {
+                let handle = metrics_handle.clone();
+                async move {
+                    state.db.record_pool_metrics();
+                    (
+                        StatusCode::OK,
+                        [(
+                            http::header::CONTENT_TYPE,
+                            HeaderValue::from_static("text/plain; version=0.0.4"),
+                        )],
+                        handle.render(),
+                    )
+                }
+            }
+        }),
+    );
+
+    let mut router = Router::<AppState>::new()
+        .route("/", get(public::landing))
+        .route("/login", get(|| async { Redirect::temporary("/app/login") }))
+        .route("/register", get(|| async { Redirect::temporary("/app/register") }))
+        .route(
+            "/logout",
+            get(|| async { Redirect::temporary("/app") }).post(auth::logout_form),
+        )
+        .route("/app", get(pages::app_dashboard))
+        .route("/app/", get(|| async { Redirect::temporary("/app") }))
+        .route("/app/login", get(pages::app_login_page).post(auth::login_form))
+        .route(
+            "/app/register",
+            get(pages::app_register_page).post(auth::register_form),
+        )
+        .route(
+            "/app/register/pending",
+            get(pages::app_register_pending_page),
+        )
+        .route(
+            "/app/register/confirm",
+            get(auth::confirm_registration_link),
+        )
+        .route("/app/{*path}", get(pages::app_not_found))
+        .route("/files/{id}", get(file_routes::download))
+        // wasm-bindgen's JS glue sometimes expects `app_bg.wasm`, while cargo-leptos outputs `app.wasm`.
+        // Redirect keeps the app working and lets ServeDir handle precompressed variants for `app.wasm`.
+        .route(
+            "/pkg/app_bg.wasm",
+            get(|| async { Redirect::temporary("/pkg/app.wasm") }),
+        )
+        .merge(api_routes)
+        .merge(metrics_route);
+
+    if !state.config.server.env.is_prod() {
+        router = router.route("/dev/mail", get(dev::mail_outbox));
+    }
+
+    // In development `cargo-leptos watch` typically doesn't regenerate `.br/.gz`.
+    // If we serve stale precompressed assets, browsers may load mismatched JS/WASM and crash.
+    let serve_precompressed = state.config.server.env.is_prod();
+    let mut pkg_service = ServeDir::new(pkg_dir);
+    let mut assets_service = ServeDir::new(&site_root);
+    if serve_precompressed {
+        pkg_service = pkg_service.precompressed_br().precompressed_gzip();
+        assets_service = assets_service.precompressed_br().precompressed_gzip();
+    }
+
+    router
+        .nest_service("/pkg", pkg_service)
+        .nest_service("/assets", assets_service)
+        .layer(middleware::from_fn_with_state(state.clone(), csrf::protect))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            session::refresh_page_session,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            dependencies::maintenance,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            dev_no_cache_pkg_assets,
+        ))
+        .layer(middleware::from_fn(read_your_writes))
+        .layer(middleware::from_fn(inject_request_id))
+        .layer(trace_layer)
+        .layer(CorsLayer::permissive())
+        .with_state(state)
+        .fallback(public::not_found)
+}
+
+async fn dev_no_cache_pkg_assets(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    req: axum::http::Request<axum::body::Body>,
+    next: middleware::Next,
+) -> axum::response::Response {
+    let path = req.uri().path().to_string();
+    let mut res = next.run(req).await;
+
+    if !state.config.server.env.is_prod() && (path.starts_with("/pkg/") || path.starts_with("/assets/")) {
+        res.headers_mut().insert(
+            http::header::CACHE_CONTROL,
+            HeaderValue::from_static("no-store, max-age=0"),
+        );
+        res.headers_mut().insert(
+            http::header::PRAGMA,
+            HeaderValue::from_static("no-cache"),
+        );
+    }
+
+    res
+}
+
+/// Lets the request read from the database replica until it writes.
+async fn read_your_writes(
+    req: axum::http::Request<axum::body::Body>,
+    next: middleware::Next,
+) -> axum::response::Response {
+    db::read_your_writes(next.run(req)).await
+}
+
+async fn inject_request_id(
+    mut req: axum::http::Request<axum::body::Body>,
+    next: middleware::Next,
+) -> axum::response::Response {
+    let request_id = req
+        .headers()
+        .get("x-request-id")
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.to_string())
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+    req.extensions_mut().insert(request_id.clone());
+
+    let mut res = next.run(req).await;
+    if !res.headers().contains_key("x-request-id") {
+        if let Ok(value) = HeaderValue::from_str(&request_id) {
+            res.headers_mut()
+                .insert(http::header::HeaderName::from_static("x-request-id"), value);
+        }
+    }
+    res
+}
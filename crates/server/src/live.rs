@@ -0,0 +1,249 @@
+use crate::dependencies::Redis;
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use domain::events::{DomainEvent, EventEnvelope, EventSubscriber};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use shared::dto::LiveEvent;
+use shared::error::Result;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+const REDIS_CHANNEL: &str = "live_events";
+const CHANNEL_CAPACITY: usize = 64;
+/// How many recent events per user a reconnecting page can resume from.
+const REPLAY_CAPACITY: usize = 32;
+const REPLAY_WINDOW_MINUTES: i64 = 5;
+const MAX_RECONNECT_DELAY_SECS: u64 = 30;
+
+/// One event for one user, or only for one of their sessions. `id` is what SSE clients
+/// send back as `Last-Event-ID`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LiveMessage {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    #[serde(default)]
+    pub session_id: Option<Uuid>,
+    pub at: DateTime<Utc>,
+    pub event: LiveEvent,
+}
+
+impl LiveMessage {
+    /// Whether a stream opened in `session_id` gets this message. Streams whose session is
+    /// unknown get every message for their user.
+    pub fn reaches(&self, session_id: Option<Uuid>) -> bool {
+        self.session_id.is_none() || session_id.is_none() || self.session_id == session_id
+    }
+}
+
+/// Per-user broadcast of [`LiveEvent`]s to open `/api/events` streams. With Redis configured,
+/// events go through pub/sub so every instance sees them, whichever one published.
+#[derive(Clone)]
+pub struct LiveHub {
+    users: Arc<Mutex<HashMap<Uuid, UserChannel>>>,
+    redis: Redis,
+}
+
+struct UserChannel {
+    sender: broadcast::Sender<LiveMessage>,
+    recent: VecDeque<LiveMessage>,
+}
+
+impl UserChannel {
+    fn new() -> Self {
+        Self {
+            sender: broadcast::channel(CHANNEL_CAPACITY).0,
+            recent: VecDeque::with_capacity(REPLAY_CAPACITY),
+        }
+    }
+}
+
+impl LiveHub {
+    pub fn new(redis: Redis) -> Self {
+        Self {
+            users: Arc::new(Mutex::new(HashMap::new())),
+            redis,
+        }
+    }
+
+    pub async fn publish(&self, user_id: Uuid, event: LiveEvent) {
+        self.publish_to(user_id, None, event).await;
+    }
+
+    /// Publishes to the streams of one of the user's sessions, or all of them when
+    /// `session_id` is `None`.
+    pub async fn publish_to(&self, user_id: Uuid, session_id: Option<Uuid>, event: LiveEvent) {
+        let message = LiveMessage {
+            id: Uuid::new_v4(),
+            user_id,
+            session_id,
+            at: Utc::now(),
+            event,
+        };
+        if let Some(mut conn) = self.redis.get() {
+            let payload = serde_json::to_string(&message).expect("live messages serialize");
+            match redis::cmd("PUBLISH")
+                .arg(REDIS_CHANNEL)
+                .arg(payload)
+                .query_async::<_, i64>(&mut conn)
+                .await
+            {
+                // The Redis listener delivers it here too.
+                Ok(_) => return,
+                Err(err) => warn!(error = %err, "failed to publish live event to redis"),
+            }
+        }
+        self.deliver(message);
+    }
+
+    /// Subscribes to `user_id`'s events. With `last_event_id`, also returns the buffered
+    /// events that came after it, or all of them if it has already been dropped.
+    pub fn subscribe(
+        &self,
+        user_id: Uuid,
+        last_event_id: Option<Uuid>,
+    ) -> (Vec<LiveMessage>, broadcast::Receiver<LiveMessage>) {
+        let mut users = self.users.lock().expect("live hub lock poisoned");
+        let channel = users.entry(user_id).or_insert_with(UserChannel::new);
+        let receiver = channel.sender.subscribe();
+        let replay = match last_event_id {
+            Some(last) => {
+                let start = channel
+                    .recent
+                    .iter()
+                    .position(|m| m.id == last)
+                    .map_or(0, |i| i + 1);
+                channel.recent.iter().skip(start).cloned().collect()
+            }
+            None => Vec::new(),
+        };
+        (replay, receiver)
+    }
+
+    /// Ends every open stream so graceful shutdown is not held up by them.
+    pub fn close(&self) {
+        self.users.lock().expect("live hub lock poisoned").clear();
+    }
+
+    fn deliver(&self, message: LiveMessage) {
+        let now = Utc::now();
+        let window = Duration::minutes(REPLAY_WINDOW_MINUTES);
+        let mut users = self.users.lock().expect("live hub lock poisoned");
+        // Forget users nobody is listening for once their replay window has passed.
+        users.retain(|_, channel| {
+            channel.sender.receiver_count() > 0
+                || channel.recent.back().is_some_and(|m| now - m.at < window)
+        });
+        let channel = users
+            .entry(message.user_id)
+            .or_insert_with(UserChannel::new);
+        channel.recent.retain(|m| now - m.at < window);
+        if channel.recent.len() == REPLAY_CAPACITY {
+            channel.recent.pop_front();
+        }
+        channel.recent.push_back(message.clone());
+        let _ = channel.sender.send(message);
+    }
+
+    /// Delivers events published by any instance, reconnecting with backoff when Redis
+    /// goes away.
+    pub fn spawn_redis_listener(&self, client: redis::Client) {
+        let hub = self.clone();
+        tokio::spawn(async move {
+            let mut delay = 1;
+            loop {
+                match hub.listen(&client).await {
+                    Ok(()) => delay = 1,
+                    Err(err) => warn!(error = %err, "live event subscription failed"),
+                }
+                tokio::time::sleep(std::time::Duration::from_secs(delay)).await;
+                delay = (delay * 2).min(MAX_RECONNECT_DELAY_SECS);
+            }
+        });
+    }
+
+    async fn listen(&self, client: &redis::Client) -> redis::RedisResult<()> {
+        let mut pubsub = client.get_async_connection().await?.into_pubsub();
+        pubsub.subscribe(REDIS_CHANNEL).await?;
+        info!(channel = REDIS_CHANNEL, "subscribed to live events");
+        let mut messages = pubsub.on_message();
+        while let Some(msg) = messages.next().await {
+            let payload: String = msg.get_payload()?;
+            match serde_json::from_str::<LiveMessage>(&payload) {
+                Ok(message) => self.deliver(message),
+                Err(err) => warn!(error = %err, "ignoring malformed live event"),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Turns domain events into live events for the user they concern.
+pub struct LiveSubscriber {
+    hub: LiveHub,
+}
+
+impl LiveSubscriber {
+    pub fn new(hub: LiveHub) -> Self {
+        Self { hub }
+    }
+}
+
+#[async_trait]
+impl EventSubscriber for LiveSubscriber {
+    fn name(&self) -> &'static str {
+        "live"
+    }
+
+    async fn handle(&self, envelope: &EventEnvelope) -> Result<()> {
+        let (user_id, session_id, event) = match &envelope.event {
+            DomainEvent::LoggedIn { user_id } => (
+                *user_id,
+                None,
+                LiveEvent::NewSignIn {
+                    ip: envelope.context.ip.clone(),
+                    user_agent: envelope.context.user_agent.clone(),
+                    at: envelope.occurred_at,
+                },
+            ),
+            // Only the session that logged out ends; the user's others stay signed in.
+            DomainEvent::LoggedOut {
+                user_id,
+                session_id,
+            } => (
+                *user_id,
+                *session_id,
+                LiveEvent::SessionRevoked {
+                    reason: "logged_out".into(),
+                },
+            ),
+            DomainEvent::SessionsRevoked { user_id } => (
+                *user_id,
+                None,
+                LiveEvent::SessionRevoked {
+                    reason: "sessions_revoked".into(),
+                },
+            ),
+            DomainEvent::PasswordChanged { user_id, .. } => (
+                *user_id,
+                None,
+                LiveEvent::SessionRevoked {
+                    reason: "password_changed".into(),
+                },
+            ),
+            DomainEvent::RoleChanged { user_id, .. } => (
+                *user_id,
+                None,
+                LiveEvent::SessionRevoked {
+                    reason: "role_changed".into(),
+                },
+            ),
+            _ => return Ok(()),
+        };
+        self.hub.publish_to(user_id, session_id, event).await;
+        Ok(())
+    }
+}
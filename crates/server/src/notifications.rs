@@ -0,0 +1,159 @@
+use crate::live::LiveHub;
+use async_trait::async_trait;
+use db::Database;
+use domain::events::{DomainEvent, EventEnvelope, EventSubscriber};
+use domain::notifications::{effective_preferences, NewNotification, Notification};
+use domain::ports::{NotificationRepository, Transaction, UnitOfWork, UserRepository};
+use mail::{MailTemplate, Mailer};
+use shared::dto::LiveEvent;
+use shared::error::{AppError, Result};
+use shared::types::NotificationChannel;
+use std::sync::Arc;
+
+/// Stores notifications, pushes them to the user's open pages and emails them when the
+/// user's preference for the category asks for it.
+#[derive(Clone)]
+pub struct Notifier {
+    db: Arc<Database>,
+    mailer: Mailer,
+    live: LiveHub,
+    base_url: String,
+}
+
+impl Notifier {
+    pub fn new(db: Arc<Database>, mailer: Mailer, live: LiveHub, base_url: String) -> Self {
+        Self {
+            db,
+            mailer,
+            live,
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// `email` replaces the generic notification email with a dedicated template. The
+    /// notification is stored together with its queued email, so one that already exists
+    /// has had its email queued as well.
+    pub async fn notify(
+        &self,
+        notification: NewNotification,
+        email: Option<MailTemplate>,
+    ) -> Result<Notification> {
+        let recipient = match self.channel(&notification).await? {
+            NotificationChannel::InAppAndEmail => self.db.find_by_id(notification.user_id).await?,
+            NotificationChannel::InApp => None,
+        };
+
+        let tx = self.db.begin().await?;
+        let notification = tx.create_notification(notification).await?;
+        if let Some(user) = recipient {
+            let template = email.unwrap_or_else(|| MailTemplate::Notification {
+                title: notification.title.clone(),
+                body: notification.body.clone(),
+                url: self.url(notification.link.as_deref().unwrap_or("/app")),
+            });
+            self.mailer.send_in(&tx, &user.email, template).await?;
+        }
+        tx.commit().await?;
+
+        self.live
+            .publish(
+                notification.user_id,
+                LiveEvent::Notification {
+                    title: notification.title.clone(),
+                    body: notification.body.clone(),
+                },
+            )
+            .await;
+        Ok(notification)
+    }
+
+    async fn channel(&self, notification: &NewNotification) -> Result<NotificationChannel> {
+        let stored = self
+            .db
+            .notification_preferences(notification.user_id)
+            .await?;
+        Ok(effective_preferences(&stored)
+            .into_iter()
+            .find(|p| p.category == notification.category)
+            .map_or_else(|| notification.category.default_channel(), |p| p.channel))
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{path}", self.base_url)
+    }
+}
+
+/// Notifies users about security events on their account.
+pub struct NotificationSubscriber {
+    notifier: Notifier,
+}
+
+impl NotificationSubscriber {
+    pub fn new(notifier: Notifier) -> Self {
+        Self { notifier }
+    }
+}
+
+#[async_trait]
+impl EventSubscriber for NotificationSubscriber {
+    fn name(&self) -> &'static str {
+        "notifications"
+    }
+
+    async fn handle(&self, envelope: &EventEnvelope) -> Result<()> {
+        use shared::types::NotificationCategory::Security;
+
+        let login_url = self.notifier.url("/app/login");
+        let (notification, email) = match &envelope.event {
+            DomainEvent::LoggedIn { user_id } => {
+                let from = match (&envelope.context.user_agent, &envelope.context.ip) {
+                    (Some(agent), Some(ip)) => format!(" from {agent} ({ip})"),
+                    (Some(agent), None) => format!(" from {agent}"),
+                    (None, Some(ip)) => format!(" from {ip}"),
+                    (None, None) => String::new(),
+                };
+                (
+                    NewNotification::new(
+                        *user_id,
+                        Security,
+                        "New sign-in",
+                        format!("Your account was signed in to{from}. If this was not you, change your password."),
+                    ),
+                    None,
+                )
+            }
+            DomainEvent::PasswordChanged { user_id, .. } => (
+                NewNotification::new(
+                    *user_id,
+                    Security,
+                    "Password changed",
+                    "Your password was changed and every session was signed out.",
+                ),
+                Some(MailTemplate::PasswordChanged { login_url }),
+            ),
+            DomainEvent::RoleChanged { user_id, to, .. } => (
+                NewNotification::new(
+                    *user_id,
+                    Security,
+                    "Role changed",
+                    format!("An administrator changed your role to {}.", to.as_str()),
+                ),
+                Some(MailTemplate::RoleChanged {
+                    role: to.as_str().to_string(),
+                    login_url,
+                }),
+            ),
+            _ => return Ok(()),
+        };
+
+        // One notification per event: a redelivered event finds it already there.
+        match self
+            .notifier
+            .notify(notification.id(envelope.id), email)
+            .await
+        {
+            Ok(_) | Err(AppError::Conflict(_)) => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+}
@@ -1,7 +1,6 @@
 use axum_extra::extract::cookie::{Cookie, SameSite};
 use base64::Engine;
 use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation};
-use rand::RngCore;
 use shared::config::AppConfig;
 use shared::error::{AppError, Result};
 use shared::types::Claims;
@@ -21,6 +20,7 @@ fn should_set_cookie_domain(config: &AppConfig) -> bool {
 pub fn sign_access_token(
     user_id: uuid::Uuid,
     role: shared::types::UserRole,
+    session_id: uuid::Uuid,
     cfg: &shared::config::AuthConfig,
 ) -> Result<String> {
     let now = SystemTime::now()
@@ -31,6 +31,7 @@ pub fn sign_access_token(
     let claims = Claims {
         sub: user_id,
         role,
+        sid: Some(session_id),
         exp: exp.as_secs() as usize,
         iat: now.as_secs() as usize,
     };
@@ -117,19 +118,6 @@ pub fn decode_flash_error(value: &str) -> Option<String> {
     String::from_utf8(bytes).ok()
 }
 
-pub fn generate_csrf_token() -> String {
-    let mut bytes = [0u8; 32];
-    rand::thread_rng().fill_bytes(&mut bytes);
-    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes)
-}
-
-pub fn verify_csrf(csrf_header: Option<&str>, csrf_cookie: Option<&str>) -> Result<()> {
-    match (csrf_header, csrf_cookie) {
-        (Some(header), Some(cookie)) if header == cookie => Ok(()),
-        _ => Err(AppError::Forbidden),
-    }
-}
-
 pub fn bearer_token(headers: &http::HeaderMap) -> Option<String> {
     headers
         .get(http::header::AUTHORIZATION)
@@ -0,0 +1,78 @@
+use crate::extractors;
+use crate::handlers::auth;
+use crate::security;
+use crate::state::AppState;
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, HeaderValue, Method, Request},
+    middleware::Next,
+    response::Response,
+};
+use axum_extra::extract::CookieJar;
+use tracing::debug;
+
+/// Silently renews the session for page requests whose access cookie is missing or expired
+/// but whose refresh cookie is still valid. The rotated cookies are written back into the
+/// request so extractors and the CSRF layer see the new session, and set on the response.
+pub async fn refresh_page_session(
+    State(state): State<AppState>,
+    mut req: Request<Body>,
+    next: Next,
+) -> Response {
+    if !is_page_request(&req) {
+        return next.run(req).await;
+    }
+
+    let config = &state.config;
+    let jar = CookieJar::from_headers(req.headers());
+    let access_valid = jar
+        .get(&config.auth.access_cookie_name)
+        .is_some_and(|c| security::decode_access_token(c.value(), &config.auth).is_ok());
+    if access_valid {
+        return next.run(req).await;
+    }
+    let Some(refresh_raw) = jar
+        .get(&config.auth.refresh_cookie_name)
+        .map(|c| c.value().to_string())
+    else {
+        return next.run(req).await;
+    };
+
+    let ctx = extractors::request_context(req.headers(), req.extensions(), &config.server);
+    let issued = match auth::rotate_session(&state, CookieJar::new(), &refresh_raw, &ctx).await {
+        Ok((issued, _tokens)) => issued,
+        Err(err) => {
+            debug!(error = %err, "page session refresh failed");
+            return next.run(req).await;
+        }
+    };
+
+    let mut merged = jar;
+    for cookie in issued.iter() {
+        merged = merged.add(cookie.clone());
+    }
+    let cookie_header = merged
+        .iter()
+        .map(|c| format!("{}={}", c.name(), c.value()))
+        .collect::<Vec<_>>()
+        .join("; ");
+    req.headers_mut().remove(header::COOKIE);
+    if let Ok(value) = HeaderValue::from_str(&cookie_header) {
+        req.headers_mut().insert(header::COOKIE, value);
+    }
+
+    let mut res = next.run(req).await;
+    for cookie in issued.iter() {
+        if let Ok(value) = HeaderValue::from_str(&cookie.to_string()) {
+            res.headers_mut().append(header::SET_COOKIE, value);
+        }
+    }
+    res
+}
+
+fn is_page_request(req: &Request<Body>) -> bool {
+    let path = req.uri().path();
+    matches!(*req.method(), Method::GET | Method::HEAD)
+        && (path == "/app" || path.starts_with("/app/"))
+}
@@ -0,0 +1,49 @@
+use async_trait::async_trait;
+use domain::ports::RegistrationNotifier;
+use mail::{MailTemplate, Mailer};
+use shared::error::Result;
+
+/// Queues the sign-up emails for background delivery through the configured mail transport.
+pub struct MailRegistrationNotifier {
+    mailer: Mailer,
+    base_url: String,
+    link_ttl_minutes: u64,
+}
+
+impl MailRegistrationNotifier {
+    pub fn new(mailer: Mailer, base_url: impl Into<String>, link_ttl_minutes: u64) -> Self {
+        Self {
+            mailer,
+            base_url: base_url.into(),
+            link_ttl_minutes,
+        }
+    }
+}
+
+#[async_trait]
+impl RegistrationNotifier for MailRegistrationNotifier {
+    async fn send_signup_link(&self, email: &str, token: &str) -> Result<()> {
+        let template = MailTemplate::ConfirmRegistration {
+            confirm_url: confirm_link(&self.base_url, token),
+            expires_in_minutes: self.link_ttl_minutes,
+        };
+        self.mailer.send(email, template).await?;
+        Ok(())
+    }
+
+    async fn send_existing_account_notice(&self, email: &str) -> Result<()> {
+        let template = MailTemplate::ExistingAccount {
+            login_url: format!("{}/app/login", self.base_url.trim_end_matches('/')),
+        };
+        self.mailer.send(email, template).await?;
+        Ok(())
+    }
+}
+
+pub fn confirm_link(base_url: &str, token: &str) -> String {
+    format!(
+        "{}/app/register/confirm?token={}",
+        base_url.trim_end_matches('/'),
+        token
+    )
+}
@@ -1,19 +1,37 @@
+use crate::dependencies::{Readiness, Redis};
+use crate::files::Files;
+use crate::flags::FeatureFlags;
+use crate::live::LiveHub;
+use crate::notifications::Notifier;
+use crate::ws::Rooms;
 use axum::extract::FromRef;
 use db::Database;
+use domain::flags::FeatureFlagService;
 use domain::AuthService;
 use leptos_config::LeptosOptions;
+use mail::MemoryTransport;
 use metrics_exporter_prometheus::PrometheusHandle;
-use redis::aio::ConnectionManager;
 use shared::config::AppConfig;
+use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct AppState {
     pub config: AppConfig,
     pub db: Database,
+    pub readiness: Readiness,
     pub auth: AuthService<Database>,
     pub leptos_options: LeptosOptions,
     pub metrics: PrometheusHandle,
-    pub redis: Option<ConnectionManager>,
+    pub redis: Redis,
+    pub live: LiveHub,
+    pub rooms: Rooms,
+    pub notifier: Notifier,
+    pub files: Files,
+    /// Evaluates flags from memory; see `flag_admin` for changing them.
+    pub flags: FeatureFlags,
+    pub flag_admin: Arc<FeatureFlagService<Database>>,
+    /// Mail captured by the memory transport, for `/dev/mail`. Always `None` in production.
+    pub mailbox: Option<MemoryTransport>,
 }
 
 impl FromRef<AppState> for LeptosOptions {
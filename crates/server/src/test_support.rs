@@ -0,0 +1,371 @@
+//! Drives the full router in tests, without binding a port or starting background tasks.
+//!
+//! Built with the `test-support` feature. [`TestApp`] wires the same state and router as
+//! [`crate::run`], against an in-memory store unless given another [`Database`], and
+//! [`TestClient`] sends requests through it with `oneshot`, keeping cookies between calls
+//! the way a browser would.
+
+use crate::dependencies::{Readiness, Redis};
+use crate::flags::FeatureFlags;
+use crate::{build_router, build_state, Runtime};
+use axum::body::{Body, Bytes};
+use axum::http::{header, HeaderMap, HeaderValue, Method, Request, Response, StatusCode};
+use axum::Router;
+use cookie::Cookie;
+use db::Database;
+use domain::events::EventBus;
+use metrics_exporter_prometheus::PrometheusBuilder;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use shared::config::{
+    AppConfig, AuditConfig, AuthConfig, DatabaseConfig, JobsConfig, MailConfig, ServerConfig,
+    StorageConfig, TracingConfig,
+};
+use shared::dto::{LoginRequest, RegisterRequest, TokenResponse};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use tower::ServiceExt;
+
+/// A configuration that validates, with fixed secrets, the in-memory store, memory mail and
+/// uploads under a fresh temporary directory.
+pub fn test_config() -> AppConfig {
+    let uploads = std::env::temp_dir().join(format!("server-test-{}", uuid::Uuid::new_v4()));
+    AppConfig {
+        server: ServerConfig::default(),
+        database: DatabaseConfig {
+            url: "memory://".into(),
+            ..DatabaseConfig::default()
+        },
+        auth: AuthConfig {
+            jwt_secret: "test-jwt-secret-that-is-at-least-32-chars".into(),
+            refresh_secret: "test-refresh-secret-that-is-at-least-32-chars".into(),
+            csrf_secret: "test-csrf-secret-at-least-16".into(),
+            ..AuthConfig::default()
+        },
+        tracing: TracingConfig::default(),
+        redis: None,
+        audit: AuditConfig::default(),
+        jobs: JobsConfig::default(),
+        mail: MailConfig::default(),
+        storage: StorageConfig {
+            local_root: uploads.to_string_lossy().into_owned(),
+            ..StorageConfig::default()
+        },
+    }
+}
+
+/// The app's router and what it serves from.
+pub struct TestApp {
+    router: Router,
+    event_bus: EventBus,
+    pub config: AppConfig,
+    pub db: Database,
+}
+
+impl TestApp {
+    /// An app on [`test_config`] and a fresh in-memory store.
+    pub async fn new() -> Self {
+        Self::builder().build().await
+    }
+
+    pub fn builder() -> TestAppBuilder {
+        TestAppBuilder {
+            config: test_config(),
+            db: None,
+        }
+    }
+
+    /// Hands the outbox events published so far to the subscribers, as the dispatcher
+    /// `run` starts would.
+    pub async fn dispatch_events(&self) {
+        while self
+            .event_bus
+            .dispatch_batch(&self.db, 100)
+            .await
+            .expect("dispatch outbox events")
+            > 0
+        {}
+    }
+
+    /// A client with no cookies yet.
+    pub fn client(&self) -> TestClient {
+        TestClient {
+            router: self.router.clone(),
+            config: self.config.clone(),
+            cookies: Arc::default(),
+        }
+    }
+}
+
+pub struct TestAppBuilder {
+    config: AppConfig,
+    db: Option<Database>,
+}
+
+impl TestAppBuilder {
+    /// Adjusts the configuration, which starts out as [`test_config`].
+    pub fn config(mut self, configure: impl FnOnce(&mut AppConfig)) -> Self {
+        configure(&mut self.config);
+        self
+    }
+
+    /// Serves from `db`, such as a Postgres or SQLite store, instead of connecting to
+    /// `config.database`. It is migrated on build.
+    pub fn database(mut self, db: Database) -> Self {
+        self.db = Some(db);
+        self
+    }
+
+    /// Panics when the app cannot be set up, as a test cannot go on without it.
+    pub async fn build(self) -> TestApp {
+        let config = self.config;
+        let db = match self.db {
+            Some(db) => db,
+            None => Database::connect(&config.database)
+                .await
+                .expect("connect to the test database"),
+        };
+        db.migrate().await.expect("migrate the test database");
+
+        let (_transport, mailbox) =
+            mail::transport_from_config(&config.mail).expect("set up the mail transport");
+        let readiness = Readiness::default();
+        readiness.set(true);
+        let leptos_options = leptos_config::LeptosOptions::builder()
+            .output_name("app")
+            .build();
+        let metrics = PrometheusBuilder::new().build_recorder().handle();
+        let flags = FeatureFlags::load(&db).await.expect("load feature flags");
+
+        let (state, event_bus, _wake_dispatcher) = build_state(
+            &config,
+            db.clone(),
+            Runtime {
+                readiness,
+                redis: Redis::default(),
+                flags,
+                leptos_options: leptos_options.clone(),
+                metrics: metrics.clone(),
+                mailbox,
+            },
+        )
+        .expect("wire the app state");
+        let router = build_router(state, leptos_options, metrics);
+
+        TestApp {
+            router,
+            event_bus,
+            config,
+            db,
+        }
+    }
+}
+
+/// Sends requests through the router, carrying the cookies responses set into later
+/// requests. Unsafe requests also get the CSRF cookie's token in the `x-csrf-token` header,
+/// as the frontend sends it. Clones share their cookies.
+#[derive(Clone)]
+pub struct TestClient {
+    router: Router,
+    config: AppConfig,
+    cookies: Arc<Mutex<BTreeMap<String, String>>>,
+}
+
+impl TestClient {
+    pub async fn get(&self, uri: &str) -> TestResponse {
+        self.send(Request::get(uri).body(Body::empty()).unwrap())
+            .await
+    }
+
+    pub async fn post(&self, uri: &str) -> TestResponse {
+        self.send(Request::post(uri).body(Body::empty()).unwrap())
+            .await
+    }
+
+    pub async fn post_json(&self, uri: &str, body: &impl Serialize) -> TestResponse {
+        let body = serde_json::to_vec(body).expect("serialize the request body");
+        self.send(
+            Request::post(uri)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body))
+                .unwrap(),
+        )
+        .await
+    }
+
+    /// Sends `request` with the client's cookies and keeps those the response sets.
+    pub async fn send(&self, request: Request<Body>) -> TestResponse {
+        let (parts, body) = self.dispatch(request).await.into_parts();
+        let body = axum::body::to_bytes(body, usize::MAX)
+            .await
+            .expect("read the response body");
+        let response = TestResponse {
+            status: parts.status,
+            headers: parts.headers,
+            body,
+        };
+        self.store_cookies(&response);
+        response
+    }
+
+    /// Gets `uri` without waiting for the body, for streams such as `/api/events` that only
+    /// end when the server closes them. Cookies the response sets are not kept.
+    pub async fn open(&self, uri: &str) -> Response<Body> {
+        self.dispatch(Request::get(uri).body(Body::empty()).unwrap())
+            .await
+    }
+
+    async fn dispatch(&self, mut request: Request<Body>) -> Response<Body> {
+        let unsafe_method = !matches!(
+            *request.method(),
+            Method::GET | Method::HEAD | Method::OPTIONS
+        );
+        let csrf_header = crate::csrf::CSRF_HEADER;
+        if unsafe_method && !request.headers().contains_key(csrf_header) {
+            if let Some(token) = self.cookie(&self.config.auth.csrf_cookie_name) {
+                request
+                    .headers_mut()
+                    .insert(csrf_header, HeaderValue::from_str(&token).unwrap());
+            }
+        }
+        if let Some(cookies) = self.cookie_header() {
+            request.headers_mut().insert(header::COOKIE, cookies);
+        }
+
+        self.router
+            .clone()
+            .oneshot(request)
+            .await
+            .expect("the router is infallible")
+    }
+
+    /// The value of the cookie `name`, if the client holds it.
+    pub fn cookie(&self, name: &str) -> Option<String> {
+        self.cookies.lock().unwrap().get(name).cloned()
+    }
+
+    /// Sets a cookie by hand, as when replaying an old one.
+    pub fn set_cookie(&self, name: &str, value: &str) {
+        self.cookies
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), value.to_string());
+    }
+
+    /// Drops a cookie by hand, as when it has expired in the browser.
+    pub fn remove_cookie(&self, name: &str) {
+        self.cookies.lock().unwrap().remove(name);
+    }
+
+    /// Gets a CSRF cookie, as loading any page would, unless the client already has one.
+    pub async fn fetch_csrf_token(&self) -> String {
+        let name = &self.config.auth.csrf_cookie_name;
+        if self.cookie(name).is_none() {
+            self.get("/api/health").await.assert_status(StatusCode::OK);
+        }
+        self.cookie(name)
+            .expect("a page visit sets the CSRF cookie")
+    }
+
+    /// Registers `email` and keeps the session. Expects registration to be open.
+    pub async fn register(&self, email: &str, password: &str) -> TokenResponse {
+        self.fetch_csrf_token().await;
+        let request = RegisterRequest {
+            email: email.to_string(),
+            password: password.to_string(),
+        };
+        self.post_json("/api/auth/register", &request)
+            .await
+            .assert_status(StatusCode::CREATED)
+            .json()
+    }
+
+    /// Logs in as `email` and keeps the session.
+    pub async fn login(&self, email: &str, password: &str) -> TokenResponse {
+        self.fetch_csrf_token().await;
+        let request = LoginRequest {
+            email: email.to_string(),
+            password: password.to_string(),
+        };
+        self.post_json("/api/auth/login", &request)
+            .await
+            .assert_status(StatusCode::OK)
+            .json()
+    }
+
+    fn cookie_header(&self) -> Option<HeaderValue> {
+        let cookies = self.cookies.lock().unwrap();
+        if cookies.is_empty() {
+            return None;
+        }
+        let header = cookies
+            .iter()
+            .map(|(name, value)| format!("{name}={value}"))
+            .collect::<Vec<_>>()
+            .join("; ");
+        Some(HeaderValue::from_str(&header).expect("cookies are valid header values"))
+    }
+
+    /// Cookies set empty or already expired are removed, as browsers do on logout.
+    fn store_cookies(&self, response: &TestResponse) {
+        let mut cookies = self.cookies.lock().unwrap();
+        for cookie in response.set_cookies() {
+            let expired =
+                cookie.value().is_empty() || cookie.max_age().is_some_and(|age| age.is_zero());
+            if expired {
+                cookies.remove(cookie.name());
+            } else {
+                cookies.insert(cookie.name().to_string(), cookie.value().to_string());
+            }
+        }
+    }
+}
+
+/// A response with its body read.
+#[derive(Debug)]
+pub struct TestResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+impl TestResponse {
+    /// Panics with the body when the status is not `status`.
+    #[track_caller]
+    pub fn assert_status(self, status: StatusCode) -> Self {
+        assert_eq!(
+            self.status,
+            status,
+            "unexpected status; body: {}",
+            self.text()
+        );
+        self
+    }
+
+    #[track_caller]
+    pub fn json<T: DeserializeOwned>(&self) -> T {
+        serde_json::from_slice(&self.body)
+            .unwrap_or_else(|err| panic!("invalid JSON ({err}): {}", self.text()))
+    }
+
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+
+    /// Every cookie the response sets, including those it clears.
+    pub fn set_cookies(&self) -> Vec<Cookie<'static>> {
+        self.headers
+            .get_all(header::SET_COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .filter_map(|value| Cookie::parse(value.to_string()).ok())
+            .collect()
+    }
+
+    /// The cookie `name` the response sets, if any.
+    pub fn set_cookie(&self, name: &str) -> Option<Cookie<'static>> {
+        self.set_cookies()
+            .into_iter()
+            .find(|cookie| cookie.name() == name)
+    }
+}
@@ -0,0 +1,54 @@
+use db::Database;
+use mail::MailTransport;
+use shared::config::AppConfig;
+use std::sync::Arc;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tracing::info;
+
+/// Starts the background job worker with the built-in jobs registered, unless disabled.
+pub fn spawn_worker(
+    db: Database,
+    config: &AppConfig,
+    mail_transport: Arc<dyn MailTransport>,
+    shutdown: watch::Receiver<bool>,
+) -> anyhow::Result<Option<JoinHandle<()>>> {
+    if !config.jobs.enabled {
+        info!("job worker disabled");
+        return Ok(None);
+    }
+    let repo = Arc::new(db);
+    let registry = jobs::JobRegistry::new().register(mail::SendMailHandler::new(
+        mail_transport,
+        config.mail.from.clone(),
+        config.server.app_name.clone(),
+    ));
+    let registry = registry.register(webhooks::DeliverWebhookHandler::new(repo.clone()));
+    let registry = jobs::builtin::register(registry, repo.clone(), config)?;
+    let worker = jobs::Worker::new(repo, registry, config.jobs.clone());
+    Ok(Some(tokio::spawn(worker.run(shutdown))))
+}
+
+/// Resolves on Ctrl-C or, on Unix, SIGTERM.
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending::<()>().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+    info!("shutdown signal received");
+}
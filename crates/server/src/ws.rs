@@ -0,0 +1,299 @@
+use crate::live::LiveHub;
+use axum::extract::ws::{CloseFrame, Message, WebSocket};
+use chrono::Utc;
+use domain::models::User;
+use futures::{SinkExt, StreamExt};
+use shared::dto::{ClientMessage, LiveEvent, PresenceMember, ServerMessage};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::sync::Notify;
+use uuid::Uuid;
+
+pub const MAX_MESSAGE_BYTES: usize = 64 * 1024;
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(20);
+/// Sockets that send nothing, not even a pong, for this long are dropped.
+const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(60);
+/// Messages queued for one socket before it counts as too slow and is disconnected.
+const OUTBOUND_CAPACITY: usize = 64;
+const MAX_ROOMS_PER_CONNECTION: usize = 16;
+const MAX_ROOM_NAME_LEN: usize = 64;
+
+const CLOSE_GOING_AWAY: u16 = 1001;
+const CLOSE_TRY_AGAIN_LATER: u16 = 1013;
+/// Application close code: the socket's session was revoked.
+const CLOSE_SESSION_REVOKED: u16 = 4001;
+
+/// Room membership for this instance's sockets. Rooms are open to every signed-in user and
+/// exist while someone is in them.
+#[derive(Clone, Default)]
+pub struct Rooms {
+    rooms: Arc<Mutex<HashMap<String, HashMap<Uuid, Member>>>>,
+}
+
+#[derive(Clone)]
+struct Member {
+    presence: PresenceMember,
+    outbound: mpsc::Sender<ServerMessage>,
+    /// Wakes the socket's task when its outbound queue overflows.
+    kick: Arc<Notify>,
+}
+
+impl Rooms {
+    /// Adds the connection to `room` and returns the users already there.
+    fn join(&self, room: &str, connection_id: Uuid, member: Member) -> Vec<PresenceMember> {
+        let mut rooms = self.rooms.lock().expect("rooms lock poisoned");
+        let members = rooms.entry(room.to_string()).or_default();
+        if !has_user(members, member.presence.user_id) {
+            broadcast(
+                members,
+                None,
+                ServerMessage::PresenceJoined {
+                    room: room.to_string(),
+                    member: member.presence.clone(),
+                },
+            );
+        }
+        members.insert(connection_id, member);
+        present_users(members)
+    }
+
+    fn leave(&self, room: &str, connection_id: Uuid) {
+        let mut rooms = self.rooms.lock().expect("rooms lock poisoned");
+        let Some(members) = rooms.get_mut(room) else {
+            return;
+        };
+        let Some(member) = members.remove(&connection_id) else {
+            return;
+        };
+        if members.is_empty() {
+            rooms.remove(room);
+        } else if !has_user(members, member.presence.user_id) {
+            broadcast(
+                members,
+                None,
+                ServerMessage::PresenceLeft {
+                    room: room.to_string(),
+                    user_id: member.presence.user_id,
+                },
+            );
+        }
+    }
+
+    /// Relays a message to everyone in `room` except the sending connection.
+    fn relay(&self, room: &str, connection_id: Uuid, message: ServerMessage) {
+        let rooms = self.rooms.lock().expect("rooms lock poisoned");
+        if let Some(members) = rooms.get(room) {
+            broadcast(members, Some(connection_id), message);
+        }
+    }
+}
+
+fn has_user(members: &HashMap<Uuid, Member>, user_id: Uuid) -> bool {
+    members.values().any(|m| m.presence.user_id == user_id)
+}
+
+fn present_users(members: &HashMap<Uuid, Member>) -> Vec<PresenceMember> {
+    let mut seen = HashSet::new();
+    members
+        .values()
+        .filter(|m| seen.insert(m.presence.user_id))
+        .map(|m| m.presence.clone())
+        .collect()
+}
+
+/// Never waits on a socket: one whose queue is full is kicked instead of slowing the room.
+fn broadcast(members: &HashMap<Uuid, Member>, except: Option<Uuid>, message: ServerMessage) {
+    for (id, member) in members {
+        if Some(*id) == except {
+            continue;
+        }
+        if let Err(TrySendError::Full(_)) = member.outbound.try_send(message.clone()) {
+            member.kick.notify_one();
+        }
+    }
+}
+
+/// Runs one authenticated socket until either side closes it, the heartbeat times out, the
+/// socket falls too far behind, or the session it was opened in is revoked.
+pub async fn serve_socket(
+    socket: WebSocket,
+    user: User,
+    session_id: Option<Uuid>,
+    rooms: Rooms,
+    live: LiveHub,
+) {
+    let connection_id = Uuid::new_v4();
+    let presence = PresenceMember {
+        user_id: user.id,
+        email: user.email.clone(),
+    };
+    let (outbound, mut queued) = mpsc::channel(OUTBOUND_CAPACITY);
+    let kick = Arc::new(Notify::new());
+    let mut connection = Connection {
+        id: connection_id,
+        member: Member {
+            presence,
+            outbound,
+            kick: kick.clone(),
+        },
+        rooms,
+        joined: HashSet::new(),
+    };
+    let (_, mut revoked) = live.subscribe(user.id, None);
+    let (mut sink, mut stream) = socket.split();
+
+    metrics::increment_gauge!("websocket_connections", 1.0);
+    connection.send(ServerMessage::Welcome {
+        user_id: user.id,
+        heartbeat_seconds: HEARTBEAT_INTERVAL.as_secs(),
+    });
+
+    let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+    let mut last_seen = Instant::now();
+    let close: Option<CloseFrame> = loop {
+        tokio::select! {
+            incoming = stream.next() => {
+                let Some(Ok(message)) = incoming else {
+                    break None;
+                };
+                last_seen = Instant::now();
+                match message {
+                    Message::Text(text) => connection.handle_text(text.as_str()),
+                    Message::Binary(_) => {
+                        connection.error("unsupported", "send JSON text frames");
+                    }
+                    Message::Close(_) => break None,
+                    Message::Ping(_) | Message::Pong(_) => {}
+                }
+            }
+            Some(message) = queued.recv() => {
+                let text = serde_json::to_string(&message).expect("server messages serialize");
+                if sink.send(Message::Text(text.into())).await.is_err() {
+                    break None;
+                }
+            }
+            _ = heartbeat.tick() => {
+                if last_seen.elapsed() > HEARTBEAT_TIMEOUT {
+                    break Some(close_frame(CLOSE_GOING_AWAY, "heartbeat timeout"));
+                }
+                if sink.send(Message::Ping(Default::default())).await.is_err() {
+                    break None;
+                }
+            }
+            _ = kick.notified() => {
+                break Some(close_frame(CLOSE_TRY_AGAIN_LATER, "too slow to keep up"));
+            }
+            event = revoked.recv() => match event {
+                Ok(message) if message.reaches(session_id) => {
+                    if let LiveEvent::SessionRevoked { reason } = message.event {
+                        let text = serde_json::to_string(&ServerMessage::SessionRevoked { reason })
+                            .expect("server messages serialize");
+                        let _ = sink.send(Message::Text(text.into())).await;
+                        break Some(close_frame(CLOSE_SESSION_REVOKED, "session revoked"));
+                    }
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(_)) => {}
+                // The hub closes its channels when the server shuts down.
+                Err(RecvError::Closed) => break Some(close_frame(CLOSE_GOING_AWAY, "server shutting down")),
+            }
+        }
+    };
+
+    connection.leave_all();
+    if let Some(frame) = close {
+        let _ = sink.send(Message::Close(Some(frame))).await;
+    }
+    metrics::decrement_gauge!("websocket_connections", 1.0);
+}
+
+struct Connection {
+    id: Uuid,
+    member: Member,
+    rooms: Rooms,
+    joined: HashSet<String>,
+}
+
+impl Connection {
+    fn handle_text(&mut self, text: &str) {
+        let message = match serde_json::from_str::<ClientMessage>(text) {
+            Ok(message) => message,
+            Err(err) => return self.error("invalid_message", &err.to_string()),
+        };
+        match message {
+            ClientMessage::Join { room } => {
+                if !valid_room_name(&room) {
+                    return self.error(
+                        "invalid_room",
+                        "room names are 1-64 letters, digits, `-`, `_`, `.` or `:`",
+                    );
+                }
+                if self.joined.contains(&room) {
+                    return;
+                }
+                if self.joined.len() >= MAX_ROOMS_PER_CONNECTION {
+                    return self.error("too_many_rooms", "leave a room before joining another");
+                }
+                let members = self.rooms.join(&room, self.id, self.member.clone());
+                self.joined.insert(room.clone());
+                self.send(ServerMessage::Joined { room, members });
+            }
+            ClientMessage::Leave { room } => {
+                if self.joined.remove(&room) {
+                    self.rooms.leave(&room, self.id);
+                    self.send(ServerMessage::Left { room });
+                }
+            }
+            ClientMessage::Send { room, body } => {
+                if !self.joined.contains(&room) {
+                    return self.error("not_joined", "join the room before sending to it");
+                }
+                let message = ServerMessage::Message {
+                    room: room.clone(),
+                    from: self.member.presence.clone(),
+                    body,
+                    sent_at: Utc::now(),
+                };
+                self.rooms.relay(&room, self.id, message);
+            }
+            ClientMessage::Ping => self.send(ServerMessage::Pong),
+        }
+    }
+
+    fn send(&self, message: ServerMessage) {
+        if let Err(TrySendError::Full(_)) = self.member.outbound.try_send(message) {
+            self.member.kick.notify_one();
+        }
+    }
+
+    fn error(&self, code: &str, message: &str) {
+        self.send(ServerMessage::Error {
+            code: code.to_string(),
+            message: message.to_string(),
+        });
+    }
+
+    fn leave_all(&mut self) {
+        for room in self.joined.drain() {
+            self.rooms.leave(&room, self.id);
+        }
+    }
+}
+
+fn valid_room_name(room: &str) -> bool {
+    !room.is_empty()
+        && room.len() <= MAX_ROOM_NAME_LEN
+        && room
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'))
+}
+
+fn close_frame(code: u16, reason: &'static str) -> CloseFrame {
+    CloseFrame {
+        code,
+        reason: reason.into(),
+    }
+}
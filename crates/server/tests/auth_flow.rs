@@ -0,0 +1,232 @@
+//! The cookie session end to end, through the full router and its middleware.
+
+use axum::http::{header, StatusCode};
+use server::test_support::{TestApp, TestClient};
+use shared::dto::{TokenResponse, UserResponse};
+
+const PASSWORD: &str = "correct horse battery";
+
+fn session_cookies(client: &TestClient) -> [Option<String>; 3] {
+    ["access_token", "refresh_token", "csrf_token"].map(|name| client.cookie(name))
+}
+
+#[tokio::test]
+async fn register_refresh_logout() {
+    let app = TestApp::new().await;
+    let client = app.client();
+
+    let registered = client.register("ada@example.com", PASSWORD).await;
+    assert_eq!(registered.user.email, "ada@example.com");
+    let [access, refresh, csrf] = session_cookies(&client);
+    assert_eq!(access.as_deref(), Some(registered.access_token.as_str()));
+    assert_eq!(csrf.as_deref(), Some(registered.csrf_token.as_str()));
+    let refresh = refresh.expect("registering sets the refresh cookie");
+
+    let me: UserResponse = client
+        .get("/api/me")
+        .await
+        .assert_status(StatusCode::OK)
+        .json();
+    assert_eq!(me.id, registered.user.id);
+
+    // Refreshing rotates the refresh token and rebinds the CSRF token to it.
+    let response = client
+        .post("/api/auth/refresh")
+        .await
+        .assert_status(StatusCode::OK);
+    let refreshed: TokenResponse = response.json();
+    let rotated = response
+        .set_cookie("refresh_token")
+        .expect("refreshing sets the refresh cookie");
+    assert_ne!(rotated.value(), refresh);
+    assert_eq!(refreshed.user.id, registered.user.id);
+    assert_ne!(refreshed.csrf_token, registered.csrf_token);
+    assert_eq!(
+        client.cookie("csrf_token").as_deref(),
+        Some(refreshed.csrf_token.as_str())
+    );
+    client.get("/api/me").await.assert_status(StatusCode::OK);
+
+    let response = client
+        .post("/api/auth/logout")
+        .await
+        .assert_status(StatusCode::NO_CONTENT);
+    for name in ["access_token", "refresh_token", "csrf_token"] {
+        let cleared = response.set_cookie(name).expect("logout clears the cookie");
+        assert_eq!(cleared.value(), "", "{name}");
+    }
+    assert_eq!(session_cookies(&client), [None, None, None]);
+
+    client
+        .get("/api/me")
+        .await
+        .assert_status(StatusCode::UNAUTHORIZED);
+    client.fetch_csrf_token().await;
+    client
+        .post("/api/auth/refresh")
+        .await
+        .assert_status(StatusCode::UNAUTHORIZED);
+
+    // The account outlives the session.
+    let again = client.login("ada@example.com", PASSWORD).await;
+    assert_eq!(again.user.id, registered.user.id);
+    client.get("/api/me").await.assert_status(StatusCode::OK);
+}
+
+#[tokio::test]
+async fn rotated_refresh_token_is_not_accepted_again() {
+    let app = TestApp::new().await;
+    let client = app.client();
+    client.register("ada@example.com", PASSWORD).await;
+    let [_, old_refresh, old_csrf] = session_cookies(&client);
+
+    client
+        .post("/api/auth/refresh")
+        .await
+        .assert_status(StatusCode::OK);
+
+    // Replays the old session as a whole, so that the CSRF check passes.
+    client.set_cookie("refresh_token", &old_refresh.unwrap());
+    client.set_cookie("csrf_token", &old_csrf.unwrap());
+    client
+        .post("/api/auth/refresh")
+        .await
+        .assert_status(StatusCode::UNAUTHORIZED);
+}
+
+/// With a rotation grace, parallel requests renewing one session, such as page loads with
+/// an expired access cookie, all get the session the first of them rotated to.
+#[tokio::test]
+async fn racing_refreshes_share_the_successor() {
+    let app = TestApp::builder()
+        .config(|config| config.auth.refresh_rotation_grace_seconds = 10)
+        .build()
+        .await;
+    let first = app.client();
+    first.register("ada@example.com", PASSWORD).await;
+    let [_, old_refresh, old_csrf] = session_cookies(&first);
+    let replay = || {
+        let client = app.client();
+        client.set_cookie("refresh_token", old_refresh.as_deref().unwrap());
+        client.set_cookie("csrf_token", old_csrf.as_deref().unwrap());
+        client
+    };
+    let second = replay();
+
+    first
+        .post("/api/auth/refresh")
+        .await
+        .assert_status(StatusCode::OK);
+    second
+        .post("/api/auth/refresh")
+        .await
+        .assert_status(StatusCode::OK);
+    assert_eq!(
+        second.cookie("refresh_token"),
+        first.cookie("refresh_token")
+    );
+    second.get("/api/me").await.assert_status(StatusCode::OK);
+
+    // The old token only stands in for its successor, so logging out ends both.
+    first
+        .post("/api/auth/logout")
+        .await
+        .assert_status(StatusCode::NO_CONTENT);
+    replay()
+        .post("/api/auth/refresh")
+        .await
+        .assert_status(StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn session_requests_need_the_csrf_token() {
+    let app = TestApp::new().await;
+    let client = app.client();
+
+    // Without a page visit first there is no CSRF cookie to echo.
+    let body = serde_json::json!({ "email": "ada@example.com", "password": PASSWORD });
+    client
+        .post_json("/api/auth/register", &body)
+        .await
+        .assert_status(StatusCode::FORBIDDEN);
+
+    client.register("ada@example.com", PASSWORD).await;
+    let forged = axum::http::Request::post("/api/auth/logout")
+        .header("x-csrf-token", "forged.token")
+        .body(axum::body::Body::empty())
+        .unwrap();
+    client
+        .send(forged)
+        .await
+        .assert_status(StatusCode::FORBIDDEN);
+    client.get("/api/me").await.assert_status(StatusCode::OK);
+}
+
+#[tokio::test]
+async fn clients_keep_separate_sessions() {
+    let app = TestApp::new().await;
+    let ada = app.client();
+    let grace = app.client();
+    ada.register("ada@example.com", PASSWORD).await;
+    grace.register("grace@example.com", PASSWORD).await;
+
+    ada.post("/api/auth/logout")
+        .await
+        .assert_status(StatusCode::NO_CONTENT);
+
+    ada.get("/api/me")
+        .await
+        .assert_status(StatusCode::UNAUTHORIZED);
+    let me: UserResponse = grace
+        .get("/api/me")
+        .await
+        .assert_status(StatusCode::OK)
+        .json();
+    assert_eq!(me.email, "grace@example.com");
+}
+
+#[tokio::test]
+async fn page_loads_renew_a_lapsed_access_cookie() {
+    let app = TestApp::new().await;
+    let client = app.client();
+    client.register("ada@example.com", PASSWORD).await;
+
+    let lapse: [fn(&TestClient); 2] = [
+        |client| client.remove_cookie("access_token"),
+        |client| client.set_cookie("access_token", "expired"),
+    ];
+    for lapse in lapse {
+        lapse(&client);
+        let refresh = client.cookie("refresh_token").unwrap();
+
+        let response = client.get("/app").await.assert_status(StatusCode::OK);
+        let rotated = response
+            .set_cookie("refresh_token")
+            .expect("the page rotates the refresh cookie");
+        assert_ne!(rotated.value(), refresh);
+        for name in ["access_token", "csrf_token"] {
+            assert!(response.set_cookie(name).is_some(), "{name}");
+        }
+        client.get("/api/me").await.assert_status(StatusCode::OK);
+    }
+}
+
+#[tokio::test]
+async fn page_loads_with_an_ended_session_go_to_login() {
+    let app = TestApp::new().await;
+    let client = app.client();
+    client.register("ada@example.com", PASSWORD).await;
+    let refresh = client.cookie("refresh_token").unwrap();
+    client
+        .post("/api/auth/logout")
+        .await
+        .assert_status(StatusCode::NO_CONTENT);
+
+    client.set_cookie("refresh_token", &refresh);
+    let response = client
+        .get("/app")
+        .await
+        .assert_status(StatusCode::SEE_OTHER);
+    assert_eq!(response.headers[header::LOCATION], "/app/login");
+    assert!(response.set_cookie("access_token").is_none());
+}
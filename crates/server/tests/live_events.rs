@@ -0,0 +1,98 @@
+//! Live event streams end along with the session they were opened in.
+
+use axum::body::{Body, Bytes};
+use axum::http::{Response, StatusCode};
+use server::test_support::TestApp;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+const PASSWORD: &str = "correct horse battery";
+
+/// Reads the stream in the background, finishing once the server closes it.
+fn read_to_end(stream: Response<Body>) -> JoinHandle<Bytes> {
+    assert_eq!(stream.status(), StatusCode::OK);
+    tokio::spawn(async move {
+        axum::body::to_bytes(stream.into_body(), usize::MAX)
+            .await
+            .expect("read the event stream")
+    })
+}
+
+async fn closed(stream: JoinHandle<Bytes>) -> String {
+    let body = tokio::time::timeout(Duration::from_secs(5), stream)
+        .await
+        .expect("the stream closes")
+        .unwrap();
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+#[tokio::test]
+async fn streams_close_with_their_session() {
+    let app = TestApp::new().await;
+    let laptop = app.client();
+    laptop.register("ada@example.com", PASSWORD).await;
+    let phone = app.client();
+    phone.login("ada@example.com", PASSWORD).await;
+    app.dispatch_events().await;
+
+    let laptop_events = read_to_end(laptop.open("/api/events").await);
+    let phone_events = read_to_end(phone.open("/api/events").await);
+
+    laptop
+        .post("/api/auth/logout")
+        .await
+        .assert_status(StatusCode::NO_CONTENT);
+    app.dispatch_events().await;
+    let body = closed(laptop_events).await;
+    assert!(body.contains(r#""reason":"logged_out""#), "{body}");
+
+    // Logging out on one device leaves the others signed in and streaming.
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(!phone_events.is_finished());
+    phone.get("/api/me").await.assert_status(StatusCode::OK);
+
+    phone
+        .post("/api/auth/logout-all")
+        .await
+        .assert_status(StatusCode::NO_CONTENT);
+    app.dispatch_events().await;
+    let body = closed(phone_events).await;
+    assert!(body.contains(r#""reason":"sessions_revoked""#), "{body}");
+    phone
+        .get("/api/me")
+        .await
+        .assert_status(StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn logging_out_everywhere_closes_every_stream() {
+    let app = TestApp::new().await;
+    let laptop = app.client();
+    laptop.register("ada@example.com", PASSWORD).await;
+    let phone = app.client();
+    phone.login("ada@example.com", PASSWORD).await;
+    let refresh = phone.cookie("refresh_token").unwrap();
+    app.dispatch_events().await;
+
+    let streams = [
+        read_to_end(laptop.open("/api/events").await),
+        read_to_end(phone.open("/api/events").await),
+    ];
+    laptop
+        .post("/api/auth/logout-all")
+        .await
+        .assert_status(StatusCode::NO_CONTENT);
+    app.dispatch_events().await;
+    for stream in streams {
+        let body = closed(stream).await;
+        assert!(body.contains(r#""reason":"sessions_revoked""#), "{body}");
+    }
+
+    // The other device's refresh token is gone too.
+    phone.remove_cookie("access_token");
+    phone.set_cookie("refresh_token", &refresh);
+    phone
+        .post("/api/auth/refresh")
+        .await
+        .assert_status(StatusCode::UNAUTHORIZED);
+}
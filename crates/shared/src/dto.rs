@@ -1,4 +1,4 @@
-use crate::types::UserRole;
+use crate::types::{NotificationCategory, NotificationChannel, UserRole};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
@@ -20,6 +20,17 @@ pub struct LoginRequest {
     pub password: String,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct ConfirmRegistrationRequest {
+    #[validate(length(min = 1, max = 256))]
+    pub token: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegistrationAccepted {
+    pub message: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TokenResponse {
     pub access_token: String,
@@ -35,6 +46,98 @@ pub struct UserResponse {
     pub created_at: DateTime<Utc>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct ChangePasswordRequest {
+    #[validate(length(min = 1, max = 128))]
+    pub current_password: String,
+    #[validate(length(min = 8, max = 128))]
+    pub new_password: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChangeRoleRequest {
+    pub role: UserRole,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct CreateWebhookRequest {
+    #[validate(url)]
+    pub url: String,
+    #[validate(length(max = 256))]
+    pub description: Option<String>,
+    /// Event types from `GET /api/admin/webhooks/events`.
+    #[validate(length(min = 1))]
+    pub events: Vec<String>,
+}
+
+/// Replaces a feature flag's settings, creating the flag if needed.
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct UpsertFeatureFlagRequest {
+    #[validate(length(max = 256))]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    #[validate(range(max = 100))]
+    pub rollout_percentage: u8,
+    #[serde(default)]
+    pub users: Vec<Uuid>,
+    #[serde(default)]
+    pub roles: Vec<UserRole>,
+    #[serde(default)]
+    pub orgs: Vec<String>,
+}
+
+/// An admin notifying a user.
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct CreateNotificationRequest {
+    pub user_id: Uuid,
+    #[serde(default = "default_notification_category")]
+    pub category: NotificationCategory,
+    #[validate(length(min = 1, max = 120))]
+    pub title: String,
+    #[validate(length(min = 1, max = 2000))]
+    pub body: String,
+    /// A path inside the app, such as `/app/settings`.
+    #[validate(length(max = 512), custom = "validate_app_path")]
+    pub link: Option<String>,
+}
+
+fn default_notification_category() -> NotificationCategory {
+    NotificationCategory::General
+}
+
+fn validate_app_path(link: &str) -> Result<(), validator::ValidationError> {
+    if link.starts_with('/') && !link.starts_with("//") {
+        Ok(())
+    } else {
+        Err(validator::ValidationError::new("app_path"))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationPreferenceRequest {
+    pub category: NotificationCategory,
+    pub channel: NotificationChannel,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnreadCount {
+    pub unread: i64,
+}
+
+/// An uploaded file with an app-relative download link that stops working at `url_expires_at`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileResponse {
+    pub id: Uuid,
+    pub content_type: String,
+    pub size_bytes: i64,
+    pub filename: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub url: String,
+    pub url_expires_at: DateTime<Utc>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PaginatedResponse<T> {
     pub items: Vec<T>,
@@ -43,10 +146,115 @@ pub struct PaginatedResponse<T> {
     pub per_page: i64,
 }
 
+/// A page of results ordered by a stable key; pass `next_cursor` back to get the next page.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CursorPage<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+    /// Set by listings that can also page backwards.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prev_cursor: Option<String>,
+    /// Every matching item, when the request asked for the count.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total: Option<i64>,
+}
+
+/// Pushed to a user's open pages over `GET /api/events`, tagged by `type`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LiveEvent {
+    /// The account signed in somewhere, seen by the pages already open elsewhere.
+    NewSignIn {
+        ip: Option<String>,
+        user_agent: Option<String>,
+        at: DateTime<Utc>,
+    },
+    /// The stream's session logged out, or all the account's sessions were ended. The stream
+    /// closes after this event.
+    SessionRevoked {
+        reason: String,
+    },
+    Notification {
+        title: String,
+        body: String,
+    },
+}
+
+/// Sent by WebSocket clients on `GET /api/ws`, as JSON text frames tagged by `type`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    Join {
+        room: String,
+    },
+    Leave {
+        room: String,
+    },
+    /// Relays `body` to the room's other connections.
+    Send {
+        room: String,
+        body: serde_json::Value,
+    },
+    /// Application-level heartbeat for clients that cannot see WebSocket pings.
+    Ping,
+}
+
+/// Sent by the server on `GET /api/ws`, as JSON text frames tagged by `type`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    Welcome {
+        user_id: Uuid,
+        heartbeat_seconds: u64,
+    },
+    /// Reply to `join`, with everyone already in the room.
+    Joined {
+        room: String,
+        members: Vec<PresenceMember>,
+    },
+    Left {
+        room: String,
+    },
+    PresenceJoined {
+        room: String,
+        member: PresenceMember,
+    },
+    PresenceLeft {
+        room: String,
+        user_id: Uuid,
+    },
+    Message {
+        room: String,
+        from: PresenceMember,
+        body: serde_json::Value,
+        sent_at: DateTime<Utc>,
+    },
+    Pong,
+    Error {
+        code: String,
+        message: String,
+    },
+    /// The socket's session logged out, or all the account's sessions were ended; the socket
+    /// closes right after.
+    SessionRevoked {
+        reason: String,
+    },
+}
+
+/// A user present in a room. Several connections of one user count once.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PresenceMember {
+    pub user_id: Uuid,
+    pub email: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HealthStatus {
     pub status: String,
     pub db: bool,
+    /// Whether the read replica answers, when one is configured.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub db_replica: Option<bool>,
     pub redis: bool,
     pub version: String,
 }
@@ -2,9 +2,10 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
 #[serde(rename_all = "lowercase")]
 pub enum Environment {
+    #[default]
     Development,
     Production,
     Test,
@@ -16,12 +17,6 @@ impl Environment {
     }
 }
 
-impl Default for Environment {
-    fn default() -> Self {
-        Environment::Development
-    }
-}
-
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
 #[serde(rename_all = "lowercase")]
 pub enum UserRole {
@@ -30,22 +25,175 @@ pub enum UserRole {
     Admin,
 }
 
+impl UserRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UserRole::User => "user",
+            UserRole::Admin => "admin",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "user" => Some(UserRole::User),
+            "admin" => Some(UserRole::Admin),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationCategory {
+    /// Sign-ins and changes to the account's credentials or role.
+    Security,
+    General,
+}
+
+impl NotificationCategory {
+    pub const ALL: [NotificationCategory; 2] = [
+        NotificationCategory::Security,
+        NotificationCategory::General,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotificationCategory::Security => "security",
+            NotificationCategory::General => "general",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "security" => Some(NotificationCategory::Security),
+            "general" => Some(NotificationCategory::General),
+            _ => None,
+        }
+    }
+
+    /// Used until the user picks a channel for the category.
+    pub fn default_channel(&self) -> NotificationChannel {
+        match self {
+            NotificationCategory::Security => NotificationChannel::InAppAndEmail,
+            NotificationCategory::General => NotificationChannel::InApp,
+        }
+    }
+}
+
+/// Where a category's notifications go. They always show up in the app.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationChannel {
+    InApp,
+    InAppAndEmail,
+}
+
+impl NotificationChannel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotificationChannel::InApp => "in_app",
+            NotificationChannel::InAppAndEmail => "in_app_and_email",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "in_app" => Some(NotificationChannel::InApp),
+            "in_app_and_email" => Some(NotificationChannel::InAppAndEmail),
+            _ => None,
+        }
+    }
+}
+
+/// `EmailConfirmation` answers every sign-up the same way and finishes it by email,
+/// so the response never reveals whether an account already exists.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RegistrationMode {
+    #[default]
+    Open,
+    EmailConfirmation,
+}
+
+/// `Memory` keeps outgoing mail in process for the `/dev/mail` page instead of sending it.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum MailTransportKind {
+    #[default]
+    Memory,
+    Smtp,
+}
+
+/// `Local` keeps uploads on the server's disk; `S3` uses any S3-compatible object store.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackendKind {
+    #[default]
+    Local,
+    S3,
+}
+
 pub type RequestId = String;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: Uuid,
     pub role: UserRole,
+    /// The refresh session the token was issued for. Missing from tokens issued before
+    /// sessions had ids.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<Uuid>,
     pub exp: usize,
     pub iat: usize,
 }
 
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditSeverity {
+    #[default]
+    Info,
+    Warning,
+    Critical,
+}
+
+impl AuditSeverity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditSeverity::Info => "info",
+            AuditSeverity::Warning => "warning",
+            AuditSeverity::Critical => "critical",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "info" => Some(AuditSeverity::Info),
+            "warning" => Some(AuditSeverity::Warning),
+            "critical" => Some(AuditSeverity::Critical),
+            _ => None,
+        }
+    }
+}
+
+/// `actor_id` is who performed the action, `subject_id` the account it was performed on.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AuditEvent {
     pub id: Uuid,
-    pub user_id: Option<Uuid>,
+    pub actor_id: Option<Uuid>,
+    pub subject_id: Option<Uuid>,
     pub event_type: String,
+    pub severity: AuditSeverity,
+    pub metadata: serde_json::Value,
     pub ip: Option<String>,
     pub user_agent: Option<String>,
+    pub request_id: Option<RequestId>,
     pub created_at: DateTime<Utc>,
 }
+
+/// Where a request came from, attached to the audit events it causes.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RequestContext {
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub request_id: Option<RequestId>,
+}
@@ -0,0 +1,70 @@
+mod local;
+mod s3;
+mod sigv4;
+mod sniff;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use shared::config::StorageConfig;
+use shared::error::{AppError, Result};
+use shared::types::StorageBackendKind;
+use std::sync::Arc;
+
+pub use local::LocalStorage;
+pub use s3::S3Storage;
+pub use sigv4::{SigV4Signer, UNSIGNED_PAYLOAD};
+pub use sniff::{sniff_content_type, SNIFF_LEN};
+
+const MAX_KEY_LEN: usize = 512;
+
+pub type ByteStream = BoxStream<'static, std::io::Result<Bytes>>;
+
+/// An object read back from storage.
+pub struct StoredObject {
+    pub size: u64,
+    pub body: ByteStream,
+}
+
+/// Where uploaded bytes live. Metadata such as the owner and content type is kept in the
+/// database; backends only map keys to bytes.
+#[async_trait]
+pub trait ObjectStorage: Send + Sync {
+    /// Stores exactly `size` bytes from `body` under `key`, replacing any object already there.
+    async fn put(&self, key: &str, content_type: &str, size: u64, body: ByteStream) -> Result<()>;
+    async fn get(&self, key: &str) -> Result<Option<StoredObject>>;
+    /// Deleting a missing object is not an error.
+    async fn delete(&self, key: &str) -> Result<()>;
+}
+
+/// Keys are `/`-separated segments of ASCII letters, digits, `-`, `_` and `.`, so they are
+/// safe as both file paths and URL paths.
+pub fn validate_key(key: &str) -> Result<()> {
+    let valid = !key.is_empty()
+        && key.len() <= MAX_KEY_LEN
+        && key.split('/').all(|segment| {
+            !segment.is_empty()
+                && segment != "."
+                && segment != ".."
+                && segment
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        });
+    if valid {
+        Ok(())
+    } else {
+        Err(AppError::Validation(format!("invalid storage key: {key}")))
+    }
+}
+
+pub fn storage_from_config(config: &StorageConfig) -> Result<Arc<dyn ObjectStorage>> {
+    match config.backend {
+        StorageBackendKind::Local => Ok(Arc::new(LocalStorage::new(&config.local_root))),
+        StorageBackendKind::S3 => {
+            let s3 = config.s3.as_ref().ok_or_else(|| {
+                AppError::config("STORAGE__S3__* settings are required for the s3 backend")
+            })?;
+            Ok(Arc::new(S3Storage::new(s3)?))
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use crate::{validate_key, ByteStream, ObjectStorage, StoredObject};
+use async_trait::async_trait;
+use futures::StreamExt;
+use shared::error::{AppError, Result};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tokio_util::io::ReaderStream;
+use uuid::Uuid;
+
+/// Stores objects as files under a root directory. Suits a single server, or several sharing
+/// a network volume.
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf> {
+        validate_key(key)?;
+        Ok(self.root.join(key))
+    }
+}
+
+#[async_trait]
+impl ObjectStorage for LocalStorage {
+    async fn put(
+        &self,
+        key: &str,
+        _content_type: &str,
+        size: u64,
+        mut body: ByteStream,
+    ) -> Result<()> {
+        let path = self.path(key)?;
+        let dir = path.parent().unwrap_or(&self.root);
+        fs::create_dir_all(dir)
+            .await
+            .map_err(|e| io_error("create storage directory", e))?;
+
+        // Written beside the target and renamed into place, so readers never see half a file.
+        let partial = dir.join(format!(".{}.partial", Uuid::new_v4()));
+        let result = write_file(&partial, size, &mut body).await;
+        let result = match result {
+            Ok(()) => fs::rename(&partial, &path)
+                .await
+                .map_err(|e| io_error("store object", e)),
+            Err(err) => Err(err),
+        };
+        if result.is_err() {
+            let _ = fs::remove_file(&partial).await;
+        }
+        result
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<StoredObject>> {
+        let path = self.path(key)?;
+        let file = match fs::File::open(&path).await {
+            Ok(file) => file,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(io_error("open object", err)),
+        };
+        let size = file
+            .metadata()
+            .await
+            .map_err(|e| io_error("read object metadata", e))?
+            .len();
+        Ok(Some(StoredObject {
+            size,
+            body: ReaderStream::new(file).boxed(),
+        }))
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        match fs::remove_file(self.path(key)?).await {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(io_error("delete object", err)),
+        }
+    }
+}
+
+async fn write_file(path: &Path, size: u64, body: &mut ByteStream) -> Result<()> {
+    let mut file = fs::File::create(path)
+        .await
+        .map_err(|e| io_error("create object", e))?;
+    let mut written = 0u64;
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk.map_err(|e| io_error("read upload", e))?;
+        written += chunk.len() as u64;
+        if written > size {
+            break;
+        }
+        file.write_all(&chunk)
+            .await
+            .map_err(|e| io_error("write object", e))?;
+    }
+    if written != size {
+        return Err(AppError::Validation(format!(
+            "expected {size} bytes but received {written}"
+        )));
+    }
+    file.sync_all()
+        .await
+        .map_err(|e| io_error("flush object", e))
+}
+
+fn io_error(action: &str, err: std::io::Error) -> AppError {
+    AppError::internal(format!("failed to {action}: {err}"))
+}
@@ -0,0 +1,157 @@
+use crate::sigv4::{amz_date, encode_path, SigV4Signer, UNSIGNED_PAYLOAD};
+use crate::{validate_key, ByteStream, ObjectStorage, StoredObject};
+use async_trait::async_trait;
+use chrono::Utc;
+use futures::{StreamExt, TryStreamExt};
+use reqwest::header::{AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE};
+use reqwest::{Body, Method, RequestBuilder, Response, StatusCode, Url};
+use shared::config::S3Config;
+use shared::error::{AppError, Result};
+use std::time::Duration;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Stores objects in an S3-compatible bucket over its REST API.
+pub struct S3Storage {
+    client: reqwest::Client,
+    base: Url,
+    /// Path prefix before the key: `/{bucket}` in path style, empty otherwise.
+    prefix: String,
+    signer: SigV4Signer,
+}
+
+impl S3Storage {
+    pub fn new(config: &S3Config) -> Result<Self> {
+        let mut base = Url::parse(&config.endpoint)
+            .map_err(|e| AppError::config(format!("invalid s3 endpoint: {e}")))?;
+        let prefix = if config.path_style {
+            format!("/{}", config.bucket)
+        } else {
+            let host = base
+                .host_str()
+                .ok_or_else(|| AppError::config("s3 endpoint has no host"))?;
+            let host = format!("{}.{host}", config.bucket);
+            base.set_host(Some(&host))
+                .map_err(|e| AppError::config(format!("invalid s3 bucket host: {e}")))?;
+            String::new()
+        };
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .map_err(|e| AppError::config(format!("failed to build s3 client: {e}")))?;
+        Ok(Self {
+            client,
+            base,
+            prefix,
+            signer: SigV4Signer::new(
+                &config.access_key_id,
+                &config.secret_access_key,
+                &config.region,
+                "s3",
+            ),
+        })
+    }
+
+    fn request(&self, method: Method, key: &str) -> Result<RequestBuilder> {
+        validate_key(key)?;
+        let path = encode_path(&format!(
+            "{}{}/{key}",
+            self.base.path().trim_end_matches('/'),
+            self.prefix
+        ));
+        let mut url = self.base.clone();
+        url.set_path(&path);
+
+        let host = match url.port() {
+            Some(port) => format!("{}:{port}", url.host_str().unwrap_or_default()),
+            None => url.host_str().unwrap_or_default().to_string(),
+        };
+        let now = Utc::now();
+        let date = amz_date(now);
+        let authorization = self.signer.authorization(
+            method.as_str(),
+            &path,
+            "",
+            &[
+                ("host", &host),
+                ("x-amz-content-sha256", UNSIGNED_PAYLOAD),
+                ("x-amz-date", &date),
+            ],
+            UNSIGNED_PAYLOAD,
+            now,
+        );
+        Ok(self
+            .client
+            .request(method, url)
+            .header("x-amz-content-sha256", UNSIGNED_PAYLOAD)
+            .header("x-amz-date", date)
+            .header(AUTHORIZATION, authorization))
+    }
+}
+
+#[async_trait]
+impl ObjectStorage for S3Storage {
+    async fn put(&self, key: &str, content_type: &str, size: u64, body: ByteStream) -> Result<()> {
+        let response = self
+            .request(Method::PUT, key)?
+            .header(CONTENT_TYPE, content_type)
+            .header(CONTENT_LENGTH, size)
+            .body(Body::wrap_stream(body))
+            .send()
+            .await
+            .map_err(unreachable)?;
+        check(response, "store object").await.map(drop)
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<StoredObject>> {
+        let response = self
+            .request(Method::GET, key)?
+            .send()
+            .await
+            .map_err(unreachable)?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let response = check(response, "read object").await?;
+        let size = response.content_length().unwrap_or_default();
+        let body = response
+            .bytes_stream()
+            .map_err(std::io::Error::other)
+            .boxed();
+        Ok(Some(StoredObject { size, body }))
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        let response = self
+            .request(Method::DELETE, key)?
+            .send()
+            .await
+            .map_err(unreachable)?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(());
+        }
+        check(response, "delete object").await.map(drop)
+    }
+}
+
+fn unreachable(err: reqwest::Error) -> AppError {
+    AppError::Unavailable(format!("object storage unreachable: {err}"))
+}
+
+async fn check(response: Response, action: &str) -> Result<Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().await.unwrap_or_default();
+    tracing::warn!(%status, body = %body, "object storage failed to {action}");
+    if status.is_server_error() {
+        Err(AppError::Unavailable(format!(
+            "object storage failed to {action}: {status}"
+        )))
+    } else {
+        Err(AppError::internal(format!(
+            "object storage failed to {action}: {status}"
+        )))
+    }
+}
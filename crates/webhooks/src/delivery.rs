@@ -0,0 +1,150 @@
+use crate::signature::{sign, EVENT_HEADER, ID_HEADER, SIGNATURE_HEADER, TIMESTAMP_HEADER};
+use async_trait::async_trait;
+use chrono::Utc;
+use domain::jobs::Job;
+use domain::ports::WebhookRepository;
+use domain::webhooks::{DeliverWebhook, DeliveryAttempt, DeliveryStatus};
+use jobs::JobHandler;
+use shared::error::{AppError, Result};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Receivers' error bodies are kept in the delivery log up to this many bytes.
+const MAX_ERROR_BODY: usize = 512;
+
+/// Posts a delivery's payload to its endpoint and records the attempt. Failed attempts
+/// return an error so the job queue retries them with backoff; the last one marks the
+/// delivery failed.
+pub struct DeliverWebhookHandler<R> {
+    repo: Arc<R>,
+    client: reqwest::Client,
+}
+
+impl<R> DeliverWebhookHandler<R> {
+    pub fn new(repo: Arc<R>) -> Self {
+        // A redirect would send the signed payload to a URL nobody registered, possibly
+        // one on the internal network, so it counts as a failed delivery instead.
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .redirect(reqwest::redirect::Policy::none())
+            .user_agent("leptos-app-webhooks/1")
+            .build()
+            .expect("default reqwest client builds");
+        Self { repo, client }
+    }
+}
+
+#[async_trait]
+impl<R> JobHandler<DeliverWebhook> for DeliverWebhookHandler<R>
+where
+    R: WebhookRepository + 'static,
+{
+    async fn handle(&self, payload: DeliverWebhook, job: &Job) -> Result<()> {
+        // Deliveries go away with their endpoint; nothing left to do.
+        let Some(delivery) = self.repo.find_webhook_delivery(payload.delivery_id).await? else {
+            return Ok(());
+        };
+        let endpoint = match self
+            .repo
+            .find_webhook_endpoint(delivery.endpoint_id)
+            .await?
+        {
+            Some(endpoint) if endpoint.enabled => endpoint,
+            _ => {
+                let attempt = DeliveryAttempt {
+                    status: DeliveryStatus::Failed,
+                    response_status: None,
+                    error: Some("endpoint disabled".into()),
+                };
+                return self
+                    .repo
+                    .record_delivery_attempt(delivery.id, &attempt)
+                    .await;
+            }
+        };
+
+        let body = serde_json::to_vec(&delivery.payload)
+            .map_err(|e| AppError::internal(format!("failed to encode webhook payload: {e}")))?;
+        let timestamp = Utc::now().timestamp();
+        let response = self
+            .client
+            .post(&endpoint.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(ID_HEADER, delivery.id.to_string())
+            .header(EVENT_HEADER, &delivery.event_type)
+            .header(TIMESTAMP_HEADER, timestamp.to_string())
+            .header(SIGNATURE_HEADER, sign(&endpoint.secret, timestamp, &body))
+            .body(body)
+            .send()
+            .await;
+
+        let (response_status, error) = match response {
+            Ok(response) if response.status().is_success() => {
+                let attempt = DeliveryAttempt {
+                    status: DeliveryStatus::Succeeded,
+                    response_status: Some(i32::from(response.status().as_u16())),
+                    error: None,
+                };
+                self.repo
+                    .record_delivery_attempt(delivery.id, &attempt)
+                    .await?;
+                info!(delivery_id = %delivery.id, endpoint_id = %endpoint.id, "webhook delivered");
+                return Ok(());
+            }
+            Ok(response) if response.status().is_redirection() => {
+                let status = response.status();
+                let location = response
+                    .headers()
+                    .get(reqwest::header::LOCATION)
+                    .and_then(|value| value.to_str().ok())
+                    .unwrap_or("nowhere");
+                (
+                    Some(i32::from(status.as_u16())),
+                    format!(
+                        "endpoint redirected ({status}) to {location}; redirects are not followed"
+                    ),
+                )
+            }
+            Ok(response) => {
+                let status = response.status();
+                let mut text = response.text().await.unwrap_or_default();
+                if text.len() > MAX_ERROR_BODY {
+                    let mut end = MAX_ERROR_BODY;
+                    while !text.is_char_boundary(end) {
+                        end -= 1;
+                    }
+                    text.truncate(end);
+                }
+                (
+                    Some(i32::from(status.as_u16())),
+                    format!("endpoint responded {status}: {text}"),
+                )
+            }
+            Err(err) => (None, format!("request failed: {err}")),
+        };
+
+        let status = if job.attempts >= job.max_attempts {
+            DeliveryStatus::Failed
+        } else {
+            DeliveryStatus::Retrying
+        };
+        let attempt = DeliveryAttempt {
+            status,
+            response_status,
+            error: Some(error.clone()),
+        };
+        self.repo
+            .record_delivery_attempt(delivery.id, &attempt)
+            .await?;
+        warn!(
+            delivery_id = %delivery.id,
+            endpoint_id = %endpoint.id,
+            attempt = job.attempts,
+            error = %error,
+            "webhook delivery failed"
+        );
+        Err(AppError::internal(error))
+    }
+}
@@ -0,0 +1,9 @@
+mod delivery;
+mod signature;
+mod subscriber;
+
+pub use delivery::DeliverWebhookHandler;
+pub use signature::{
+    sign, verify_signature, EVENT_HEADER, ID_HEADER, SIGNATURE_HEADER, TIMESTAMP_HEADER,
+};
+pub use subscriber::WebhookSubscriber;
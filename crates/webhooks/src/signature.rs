@@ -0,0 +1,48 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+/// Unique per delivery and stable across retries, so receivers can drop duplicates.
+pub const ID_HEADER: &str = "webhook-id";
+pub const EVENT_HEADER: &str = "webhook-event";
+/// Unix seconds at which the attempt was signed.
+pub const TIMESTAMP_HEADER: &str = "webhook-timestamp";
+/// `v1=` followed by the hex HMAC-SHA256 of `"{timestamp}.{body}"` keyed with the endpoint secret.
+pub const SIGNATURE_HEADER: &str = "webhook-signature";
+
+pub fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mac = signing_mac(secret, timestamp, body);
+    format!("v1={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Checks a received delivery the way a receiver should: the signature must match and
+/// the timestamp must be within `tolerance_secs` of `now`, which stops replays.
+pub fn verify_signature(
+    secret: &str,
+    timestamp: i64,
+    signature: &str,
+    body: &[u8],
+    now: i64,
+    tolerance_secs: i64,
+) -> bool {
+    if (now - timestamp).abs() > tolerance_secs {
+        return false;
+    }
+    let Some(expected) = signature
+        .strip_prefix("v1=")
+        .and_then(|hex_sig| hex::decode(hex_sig).ok())
+    else {
+        return false;
+    };
+    signing_mac(secret, timestamp, body)
+        .verify_slice(&expected)
+        .is_ok()
+}
+
+fn signing_mac(secret: &str, timestamp: i64, body: &[u8]) -> Hmac<Sha256> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    mac
+}
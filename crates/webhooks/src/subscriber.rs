@@ -0,0 +1,36 @@
+use async_trait::async_trait;
+use domain::events::{EventEnvelope, EventSubscriber};
+use domain::ports::WebhookRepository;
+use shared::error::Result;
+use std::sync::Arc;
+
+/// Queues a delivery to every endpoint subscribed to the event's type.
+pub struct WebhookSubscriber<R> {
+    repo: Arc<R>,
+}
+
+impl<R> WebhookSubscriber<R> {
+    pub fn new(repo: Arc<R>) -> Self {
+        Self { repo }
+    }
+}
+
+#[async_trait]
+impl<R> EventSubscriber for WebhookSubscriber<R>
+where
+    R: WebhookRepository + 'static,
+{
+    fn name(&self) -> &'static str {
+        "webhooks"
+    }
+
+    async fn handle(&self, envelope: &EventEnvelope) -> Result<()> {
+        if !envelope.event.audit_type().is_webhook_event() {
+            return Ok(());
+        }
+        self.repo
+            .queue_webhook_deliveries(&envelope.audit_event())
+            .await?;
+        Ok(())
+    }
+}
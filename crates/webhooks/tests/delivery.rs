@@ -0,0 +1,386 @@
+use async_trait::async_trait;
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
+use axum::routing::post;
+use axum::Router;
+use chrono::Utc;
+use domain::jobs::{Job, JobStatus};
+use domain::ports::WebhookRepository;
+use domain::webhooks::{
+    DeliverWebhook, DeliveryAttempt, DeliveryStatus, NewWebhookEndpoint, WebhookDelivery,
+    WebhookEndpoint,
+};
+use jobs::JobHandler;
+use shared::error::Result;
+use shared::types::AuditEvent;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+use webhooks::{
+    verify_signature, DeliverWebhookHandler, EVENT_HEADER, ID_HEADER, SIGNATURE_HEADER,
+    TIMESTAMP_HEADER,
+};
+
+const SECRET: &str = "whsec_test_secret";
+
+#[derive(Default)]
+struct FakeRepo {
+    endpoints: Mutex<Vec<WebhookEndpoint>>,
+    deliveries: Mutex<Vec<WebhookDelivery>>,
+}
+
+#[async_trait]
+impl WebhookRepository for FakeRepo {
+    async fn create_webhook_endpoint(
+        &self,
+        endpoint: NewWebhookEndpoint,
+    ) -> Result<WebhookEndpoint> {
+        let endpoint = WebhookEndpoint {
+            id: Uuid::new_v4(),
+            owner_id: endpoint.owner_id,
+            url: endpoint.url,
+            description: endpoint.description,
+            events: endpoint.events,
+            secret: endpoint.secret,
+            enabled: true,
+            created_at: Utc::now(),
+        };
+        self.endpoints.lock().unwrap().push(endpoint.clone());
+        Ok(endpoint)
+    }
+
+    async fn list_webhook_endpoints(&self, owner_id: Uuid) -> Result<Vec<WebhookEndpoint>> {
+        let endpoints = self.endpoints.lock().unwrap();
+        Ok(endpoints
+            .iter()
+            .filter(|e| e.owner_id == owner_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn find_webhook_endpoint(&self, id: Uuid) -> Result<Option<WebhookEndpoint>> {
+        let endpoints = self.endpoints.lock().unwrap();
+        Ok(endpoints.iter().find(|e| e.id == id).cloned())
+    }
+
+    async fn delete_webhook_endpoint(&self, id: Uuid, owner_id: Uuid) -> Result<bool> {
+        let mut endpoints = self.endpoints.lock().unwrap();
+        let before = endpoints.len();
+        endpoints.retain(|e| !(e.id == id && e.owner_id == owner_id));
+        Ok(endpoints.len() < before)
+    }
+
+    async fn find_webhook_delivery(&self, id: Uuid) -> Result<Option<WebhookDelivery>> {
+        let deliveries = self.deliveries.lock().unwrap();
+        Ok(deliveries.iter().find(|d| d.id == id).cloned())
+    }
+
+    async fn list_webhook_deliveries(
+        &self,
+        endpoint_id: Uuid,
+        _limit: i64,
+    ) -> Result<Vec<WebhookDelivery>> {
+        let deliveries = self.deliveries.lock().unwrap();
+        Ok(deliveries
+            .iter()
+            .filter(|d| d.endpoint_id == endpoint_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn queue_webhook_deliveries(&self, _event: &AuditEvent) -> Result<usize> {
+        Ok(0)
+    }
+
+    async fn record_delivery_attempt(&self, id: Uuid, attempt: &DeliveryAttempt) -> Result<()> {
+        let mut deliveries = self.deliveries.lock().unwrap();
+        if let Some(delivery) = deliveries.iter_mut().find(|d| d.id == id) {
+            delivery.status = attempt.status;
+            delivery.attempts += 1;
+            delivery.response_status = attempt.response_status;
+            delivery.last_error = attempt.error.clone();
+            if attempt.status == DeliveryStatus::Succeeded {
+                delivery.delivered_at = Some(Utc::now());
+            }
+        }
+        Ok(())
+    }
+
+    async fn redeliver_webhook(&self, id: Uuid) -> Result<bool> {
+        let mut deliveries = self.deliveries.lock().unwrap();
+        Ok(deliveries
+            .iter_mut()
+            .find(|d| d.id == id)
+            .map(|d| d.status = DeliveryStatus::Pending)
+            .is_some())
+    }
+}
+
+impl FakeRepo {
+    async fn endpoint_with_delivery(&self, url: String) -> WebhookDelivery {
+        let endpoint = self
+            .create_webhook_endpoint(NewWebhookEndpoint {
+                owner_id: Uuid::new_v4(),
+                url,
+                description: None,
+                events: vec!["auth.login".into()],
+                secret: SECRET.into(),
+            })
+            .await
+            .unwrap();
+        let delivery = WebhookDelivery {
+            id: Uuid::new_v4(),
+            endpoint_id: endpoint.id,
+            event_id: Uuid::new_v4(),
+            event_type: "auth.login".into(),
+            payload: serde_json::json!({ "type": "auth.login", "data": { "actor_id": null } }),
+            status: DeliveryStatus::Pending,
+            attempts: 0,
+            response_status: None,
+            last_error: None,
+            created_at: Utc::now(),
+            delivered_at: None,
+        };
+        self.deliveries.lock().unwrap().push(delivery.clone());
+        delivery
+    }
+
+    fn delivery(&self, id: Uuid) -> WebhookDelivery {
+        let deliveries = self.deliveries.lock().unwrap();
+        deliveries.iter().find(|d| d.id == id).cloned().unwrap()
+    }
+}
+
+#[derive(Clone)]
+struct Receiver {
+    status: StatusCode,
+    requests: Arc<Mutex<Vec<(HeaderMap, Bytes)>>>,
+}
+
+/// Starts a local receiver that records every request and answers with `status`.
+async fn start_receiver(status: StatusCode) -> (String, Receiver) {
+    let receiver = Receiver {
+        status,
+        requests: Arc::new(Mutex::new(Vec::new())),
+    };
+    let app = Router::new()
+        .route(
+            "/hook",
+            post(
+                |State(receiver): State<Receiver>, headers: HeaderMap, body: Bytes| async move {
+                    receiver.requests.lock().unwrap().push((headers, body));
+                    receiver.status
+                },
+            ),
+        )
+        .with_state(receiver.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    (format!("http://{addr}/hook"), receiver)
+}
+
+fn job(delivery_id: Uuid, attempts: i32) -> Job {
+    Job {
+        id: Uuid::new_v4(),
+        kind: "webhooks.deliver".into(),
+        payload: serde_json::json!({ "delivery_id": delivery_id }),
+        status: JobStatus::Running,
+        attempts,
+        max_attempts: 3,
+        run_at: Utc::now(),
+        locked_by: Some("test".into()),
+        locked_at: Some(Utc::now()),
+        last_error: None,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+    }
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> &'a str {
+    headers.get(name).unwrap().to_str().unwrap()
+}
+
+#[tokio::test]
+async fn delivers_signed_payload_to_receiver() {
+    let (url, receiver) = start_receiver(StatusCode::OK).await;
+    let repo = Arc::new(FakeRepo::default());
+    let delivery = repo.endpoint_with_delivery(url).await;
+    let handler = DeliverWebhookHandler::new(repo.clone());
+
+    handler
+        .handle(
+            DeliverWebhook {
+                delivery_id: delivery.id,
+            },
+            &job(delivery.id, 1),
+        )
+        .await
+        .unwrap();
+
+    let requests = receiver.requests.lock().unwrap();
+    assert_eq!(requests.len(), 1);
+    let (headers, body) = &requests[0];
+    assert_eq!(header(headers, ID_HEADER), delivery.id.to_string());
+    assert_eq!(header(headers, EVENT_HEADER), "auth.login");
+    let timestamp: i64 = header(headers, TIMESTAMP_HEADER).parse().unwrap();
+    assert!(verify_signature(
+        SECRET,
+        timestamp,
+        header(headers, SIGNATURE_HEADER),
+        body,
+        Utc::now().timestamp(),
+        300,
+    ));
+    let sent: serde_json::Value = serde_json::from_slice(body).unwrap();
+    assert_eq!(sent, delivery.payload);
+
+    let recorded = repo.delivery(delivery.id);
+    assert_eq!(recorded.status, DeliveryStatus::Succeeded);
+    assert_eq!(recorded.response_status, Some(200));
+    assert_eq!(recorded.attempts, 1);
+    assert!(recorded.delivered_at.is_some());
+}
+
+#[tokio::test]
+async fn failed_attempt_is_logged_and_retried() {
+    let (url, receiver) = start_receiver(StatusCode::INTERNAL_SERVER_ERROR).await;
+    let repo = Arc::new(FakeRepo::default());
+    let delivery = repo.endpoint_with_delivery(url).await;
+    let handler = DeliverWebhookHandler::new(repo.clone());
+    let payload = || DeliverWebhook {
+        delivery_id: delivery.id,
+    };
+
+    // An error tells the job queue to retry with backoff.
+    assert!(handler
+        .handle(payload(), &job(delivery.id, 1))
+        .await
+        .is_err());
+    let recorded = repo.delivery(delivery.id);
+    assert_eq!(recorded.status, DeliveryStatus::Retrying);
+    assert_eq!(recorded.response_status, Some(500));
+    assert!(recorded.last_error.is_some());
+
+    // The final attempt marks the delivery failed.
+    assert!(handler
+        .handle(payload(), &job(delivery.id, 3))
+        .await
+        .is_err());
+    let recorded = repo.delivery(delivery.id);
+    assert_eq!(recorded.status, DeliveryStatus::Failed);
+    assert_eq!(recorded.attempts, 2);
+    assert_eq!(receiver.requests.lock().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn unreachable_endpoint_is_retried() {
+    // Bind and drop a listener to get a port nothing is listening on.
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    drop(listener);
+
+    let repo = Arc::new(FakeRepo::default());
+    let delivery = repo
+        .endpoint_with_delivery(format!("http://{addr}/hook"))
+        .await;
+    let handler = DeliverWebhookHandler::new(repo.clone());
+
+    let result = handler
+        .handle(
+            DeliverWebhook {
+                delivery_id: delivery.id,
+            },
+            &job(delivery.id, 1),
+        )
+        .await;
+    assert!(result.is_err());
+    let recorded = repo.delivery(delivery.id);
+    assert_eq!(recorded.status, DeliveryStatus::Retrying);
+    assert_eq!(recorded.response_status, None);
+}
+
+#[tokio::test]
+async fn redirects_are_not_followed() {
+    let followed = Arc::new(Mutex::new(0));
+    let app = Router::new()
+        .route(
+            "/hook",
+            post(|| async {
+                (
+                    StatusCode::TEMPORARY_REDIRECT,
+                    [(axum::http::header::LOCATION, "/elsewhere")],
+                )
+            }),
+        )
+        .route(
+            "/elsewhere",
+            post(|State(followed): State<Arc<Mutex<i32>>>| async move {
+                *followed.lock().unwrap() += 1;
+                StatusCode::OK
+            }),
+        )
+        .with_state(followed.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+
+    let repo = Arc::new(FakeRepo::default());
+    let delivery = repo
+        .endpoint_with_delivery(format!("http://{addr}/hook"))
+        .await;
+    let handler = DeliverWebhookHandler::new(repo.clone());
+    let result = handler
+        .handle(
+            DeliverWebhook {
+                delivery_id: delivery.id,
+            },
+            &job(delivery.id, 1),
+        )
+        .await;
+
+    assert!(result.is_err());
+    assert_eq!(*followed.lock().unwrap(), 0);
+    let recorded = repo.delivery(delivery.id);
+    assert_eq!(recorded.status, DeliveryStatus::Retrying);
+    assert_eq!(recorded.response_status, Some(307));
+    assert!(recorded.delivered_at.is_none());
+}
+
+#[test]
+fn signature_rejects_tampering_and_stale_timestamps() {
+    let body = br#"{"type":"auth.login"}"#;
+    let now = Utc::now().timestamp();
+    let signature = webhooks::sign(SECRET, now, body);
+
+    assert!(verify_signature(SECRET, now, &signature, body, now, 300));
+    assert!(!verify_signature(
+        SECRET,
+        now,
+        &signature,
+        br#"{"type":"auth.logout"}"#,
+        now,
+        300
+    ));
+    assert!(!verify_signature(
+        "whsec_other",
+        now,
+        &signature,
+        body,
+        now,
+        300
+    ));
+    assert!(!verify_signature(
+        SECRET,
+        now,
+        &signature,
+        body,
+        now + 301,
+        300
+    ));
+    assert!(!verify_signature(SECRET, now, "v1=zz", body, now, 300));
+}
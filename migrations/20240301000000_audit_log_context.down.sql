@@ -0,0 +1,14 @@
+-- actor/subject split, severity and request context for the audit log
+-- Events where someone acted on another account keep only their actor.
+DROP INDEX IF EXISTS idx_audit_log_event_type;
+DROP INDEX IF EXISTS idx_audit_log_created_at_id;
+DROP INDEX IF EXISTS idx_audit_log_subject_id;
+
+ALTER TABLE audit_log
+    DROP COLUMN IF EXISTS request_id,
+    DROP COLUMN IF EXISTS metadata,
+    DROP COLUMN IF EXISTS severity,
+    DROP COLUMN IF EXISTS subject_id;
+
+ALTER INDEX IF EXISTS idx_audit_log_actor_id RENAME TO idx_audit_log_user_id;
+ALTER TABLE audit_log RENAME COLUMN actor_id TO user_id;
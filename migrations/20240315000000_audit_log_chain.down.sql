@@ -0,0 +1,9 @@
+-- per-day hash chain over audit_log plus signed checkpoints of chain heads
+DROP TABLE IF EXISTS audit_checkpoints;
+DROP INDEX IF EXISTS idx_audit_log_chain;
+
+ALTER TABLE audit_log
+    DROP COLUMN IF EXISTS hash,
+    DROP COLUMN IF EXISTS prev_hash,
+    DROP COLUMN IF EXISTS seq,
+    DROP COLUMN IF EXISTS chain_day;
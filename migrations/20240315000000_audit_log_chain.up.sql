@@ -0,0 +1,19 @@
+-- per-day hash chain over audit_log plus signed checkpoints of chain heads
+ALTER TABLE audit_log
+    ADD COLUMN chain_day DATE,
+    ADD COLUMN seq BIGINT,
+    ADD COLUMN prev_hash TEXT,
+    ADD COLUMN hash TEXT;
+
+-- Rows written before this migration stay unchained; verification reports them separately.
+CREATE UNIQUE INDEX IF NOT EXISTS idx_audit_log_chain ON audit_log(chain_day, seq);
+
+CREATE TABLE IF NOT EXISTS audit_checkpoints (
+    id UUID PRIMARY KEY,
+    chain_day DATE NOT NULL,
+    seq BIGINT NOT NULL,
+    hash TEXT NOT NULL,
+    signature TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE (chain_day, seq)
+);
@@ -0,0 +1,3 @@
+-- background job queue and recurring schedules
+DROP TABLE IF EXISTS job_schedules;
+DROP TABLE IF EXISTS jobs;
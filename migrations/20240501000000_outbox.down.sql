@@ -0,0 +1,3 @@
+-- transactional outbox for domain events, with per-subscriber progress
+DROP TABLE IF EXISTS outbox_deliveries;
+DROP TABLE IF EXISTS outbox_events;
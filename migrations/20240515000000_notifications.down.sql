@@ -0,0 +1,3 @@
+-- in-app notifications and each user's choice of channel per category
+DROP TABLE IF EXISTS notification_preferences;
+DROP TABLE IF EXISTS notifications;
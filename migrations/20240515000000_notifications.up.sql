@@ -0,0 +1,23 @@
+-- in-app notifications and each user's choice of channel per category
+CREATE TABLE IF NOT EXISTS notifications (
+    id UUID PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    category TEXT NOT NULL CHECK (category IN ('security', 'general')),
+    title TEXT NOT NULL,
+    body TEXT NOT NULL,
+    link TEXT,
+    read_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- unread first, newest first
+CREATE INDEX IF NOT EXISTS idx_notifications_user
+    ON notifications(user_id, (read_at IS NOT NULL), created_at DESC);
+
+CREATE TABLE IF NOT EXISTS notification_preferences (
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    category TEXT NOT NULL CHECK (category IN ('security', 'general')),
+    channel TEXT NOT NULL CHECK (channel IN ('in_app', 'in_app_and_email')),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (user_id, category)
+);
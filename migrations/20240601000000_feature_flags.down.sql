@@ -0,0 +1,3 @@
+-- feature flags, cached by each server and reloaded when a row changes
+DROP TABLE IF EXISTS feature_flags;
+DROP FUNCTION IF EXISTS notify_feature_flags_changed();
@@ -0,0 +1,25 @@
+-- feature flags, cached by each server and reloaded when a row changes
+CREATE TABLE IF NOT EXISTS feature_flags (
+    key TEXT PRIMARY KEY,
+    description TEXT,
+    enabled BOOLEAN NOT NULL DEFAULT false,
+    rollout_percentage SMALLINT NOT NULL DEFAULT 0
+        CHECK (rollout_percentage BETWEEN 0 AND 100),
+    users UUID[] NOT NULL DEFAULT '{}',
+    roles TEXT[] NOT NULL DEFAULT '{}',
+    orgs TEXT[] NOT NULL DEFAULT '{}',
+    updated_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE OR REPLACE FUNCTION notify_feature_flags_changed() RETURNS trigger AS $$
+BEGIN
+    PERFORM pg_notify('feature_flags_changed', COALESCE(NEW.key, OLD.key));
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS feature_flags_changed ON feature_flags;
+CREATE TRIGGER feature_flags_changed
+    AFTER INSERT OR UPDATE OR DELETE ON feature_flags
+    FOR EACH ROW EXECUTE FUNCTION notify_feature_flags_changed();
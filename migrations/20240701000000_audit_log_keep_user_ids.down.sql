@@ -0,0 +1,12 @@
+-- audit_log keeps the ids of deleted users
+-- Events of users deleted since lose their ids, as they would have with the constraints.
+UPDATE audit_log SET actor_id = NULL
+    WHERE actor_id IS NOT NULL AND NOT EXISTS (SELECT 1 FROM users WHERE users.id = actor_id);
+UPDATE audit_log SET subject_id = NULL
+    WHERE subject_id IS NOT NULL AND NOT EXISTS (SELECT 1 FROM users WHERE users.id = subject_id);
+
+ALTER TABLE audit_log
+    ADD CONSTRAINT audit_log_user_id_fkey
+        FOREIGN KEY (actor_id) REFERENCES users(id) ON DELETE SET NULL,
+    ADD CONSTRAINT audit_log_subject_id_fkey
+        FOREIGN KEY (subject_id) REFERENCES users(id) ON DELETE SET NULL;
@@ -0,0 +1,6 @@
+-- audit_log keeps the ids of deleted users
+-- actor_id and subject_id are hashed into the audit chain, so nulling them when a user is
+-- deleted would make every chained event of theirs fail verification.
+ALTER TABLE audit_log
+    DROP CONSTRAINT IF EXISTS audit_log_user_id_fkey,
+    DROP CONSTRAINT IF EXISTS audit_log_subject_id_fkey;
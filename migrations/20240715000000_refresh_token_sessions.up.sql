@@ -0,0 +1,6 @@
+-- refresh tokens remember the sign-in they descend from
+-- Rotation copies session_id to the new token, so one session can be ended on its own.
+-- Tokens issued before this each start a session of their own.
+ALTER TABLE refresh_tokens ADD COLUMN IF NOT EXISTS session_id UUID;
+UPDATE refresh_tokens SET session_id = id WHERE session_id IS NULL;
+ALTER TABLE refresh_tokens ALTER COLUMN session_id SET NOT NULL;
@@ -0,0 +1,2 @@
+-- sign-ups waiting for email confirmation
+DROP TABLE IF EXISTS pending_registrations;
@@ -0,0 +1,14 @@
+-- actor/subject split, severity and request context for the audit log
+-- Events where someone acted on another account keep only their actor.
+DROP INDEX IF EXISTS idx_audit_log_event_type;
+DROP INDEX IF EXISTS idx_audit_log_created_at_id;
+DROP INDEX IF EXISTS idx_audit_log_subject_id;
+
+ALTER TABLE audit_log DROP COLUMN request_id;
+ALTER TABLE audit_log DROP COLUMN metadata;
+ALTER TABLE audit_log DROP COLUMN severity;
+ALTER TABLE audit_log DROP COLUMN subject_id;
+
+DROP INDEX IF EXISTS idx_audit_log_actor_id;
+ALTER TABLE audit_log RENAME COLUMN actor_id TO user_id;
+CREATE INDEX IF NOT EXISTS idx_audit_log_user_id ON audit_log(user_id);
@@ -0,0 +1,17 @@
+-- actor/subject split, severity and request context for the audit log
+ALTER TABLE audit_log RENAME COLUMN user_id TO actor_id;
+DROP INDEX IF EXISTS idx_audit_log_user_id;
+CREATE INDEX IF NOT EXISTS idx_audit_log_actor_id ON audit_log(actor_id);
+
+ALTER TABLE audit_log ADD COLUMN subject_id BLOB REFERENCES users(id) ON DELETE SET NULL;
+ALTER TABLE audit_log ADD COLUMN severity TEXT NOT NULL DEFAULT 'info'
+    CHECK (severity IN ('info', 'warning', 'critical'));
+ALTER TABLE audit_log ADD COLUMN metadata TEXT NOT NULL DEFAULT '{}';
+ALTER TABLE audit_log ADD COLUMN request_id TEXT;
+
+-- Every event logged so far was a user acting on their own account.
+UPDATE audit_log SET subject_id = actor_id;
+
+CREATE INDEX IF NOT EXISTS idx_audit_log_subject_id ON audit_log(subject_id);
+CREATE INDEX IF NOT EXISTS idx_audit_log_created_at_id ON audit_log(created_at DESC, id DESC);
+CREATE INDEX IF NOT EXISTS idx_audit_log_event_type ON audit_log(event_type, created_at DESC);
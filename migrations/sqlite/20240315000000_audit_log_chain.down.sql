@@ -0,0 +1,8 @@
+-- per-day hash chain over audit_log plus signed checkpoints of chain heads
+DROP TABLE IF EXISTS audit_checkpoints;
+DROP INDEX IF EXISTS idx_audit_log_chain;
+
+ALTER TABLE audit_log DROP COLUMN hash;
+ALTER TABLE audit_log DROP COLUMN prev_hash;
+ALTER TABLE audit_log DROP COLUMN seq;
+ALTER TABLE audit_log DROP COLUMN chain_day;
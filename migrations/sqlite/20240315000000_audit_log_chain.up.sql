@@ -0,0 +1,18 @@
+-- per-day hash chain over audit_log plus signed checkpoints of chain heads
+ALTER TABLE audit_log ADD COLUMN chain_day TEXT;
+ALTER TABLE audit_log ADD COLUMN seq INTEGER;
+ALTER TABLE audit_log ADD COLUMN prev_hash TEXT;
+ALTER TABLE audit_log ADD COLUMN hash TEXT;
+
+-- Rows written before this migration stay unchained; verification reports them separately.
+CREATE UNIQUE INDEX IF NOT EXISTS idx_audit_log_chain ON audit_log(chain_day, seq);
+
+CREATE TABLE IF NOT EXISTS audit_checkpoints (
+    id BLOB PRIMARY KEY,
+    chain_day TEXT NOT NULL,
+    seq INTEGER NOT NULL,
+    hash TEXT NOT NULL,
+    signature TEXT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now')),
+    UNIQUE (chain_day, seq)
+);
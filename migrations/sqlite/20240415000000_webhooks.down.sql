@@ -0,0 +1,3 @@
+-- outgoing webhooks: endpoints registered by admins and a log of every delivery
+DROP TABLE IF EXISTS webhook_deliveries;
+DROP TABLE IF EXISTS webhook_endpoints;
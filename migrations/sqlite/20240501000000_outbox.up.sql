@@ -0,0 +1,23 @@
+-- transactional outbox for domain events, with per-subscriber progress
+CREATE TABLE IF NOT EXISTS outbox_events (
+    id BLOB PRIMARY KEY,
+    event_type TEXT NOT NULL,
+    envelope TEXT NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    available_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now')),
+    locked_until TEXT,
+    last_error TEXT,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now')),
+    processed_at TEXT,
+    failed_at TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_outbox_events_due ON outbox_events(available_at)
+    WHERE processed_at IS NULL AND failed_at IS NULL;
+
+CREATE TABLE IF NOT EXISTS outbox_deliveries (
+    event_id BLOB NOT NULL REFERENCES outbox_events(id) ON DELETE CASCADE,
+    subscriber TEXT NOT NULL,
+    delivered_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now')),
+    PRIMARY KEY (event_id, subscriber)
+);
@@ -0,0 +1,23 @@
+-- in-app notifications and each user's choice of channel per category
+CREATE TABLE IF NOT EXISTS notifications (
+    id BLOB PRIMARY KEY,
+    user_id BLOB NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    category TEXT NOT NULL CHECK (category IN ('security', 'general')),
+    title TEXT NOT NULL,
+    body TEXT NOT NULL,
+    link TEXT,
+    read_at TEXT,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now'))
+);
+
+-- unread first, newest first
+CREATE INDEX IF NOT EXISTS idx_notifications_user
+    ON notifications(user_id, (read_at IS NOT NULL), created_at DESC);
+
+CREATE TABLE IF NOT EXISTS notification_preferences (
+    user_id BLOB NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    category TEXT NOT NULL CHECK (category IN ('security', 'general')),
+    channel TEXT NOT NULL CHECK (channel IN ('in_app', 'in_app_and_email')),
+    updated_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now')),
+    PRIMARY KEY (user_id, category)
+);
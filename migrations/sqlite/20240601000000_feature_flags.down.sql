@@ -0,0 +1,2 @@
+-- feature flags, cached by each server and reloaded when a row changes
+DROP TABLE IF EXISTS feature_flags;
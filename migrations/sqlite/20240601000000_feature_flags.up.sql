@@ -0,0 +1,15 @@
+-- feature flags, cached by each server and reloaded when a row changes
+-- users, roles and orgs are JSON arrays. SQLite has no LISTEN/NOTIFY, so the store announces
+-- changes to the process that made them instead of a trigger.
+CREATE TABLE IF NOT EXISTS feature_flags (
+    key TEXT PRIMARY KEY,
+    description TEXT,
+    enabled BOOLEAN NOT NULL DEFAULT 0,
+    rollout_percentage INTEGER NOT NULL DEFAULT 0
+        CHECK (rollout_percentage BETWEEN 0 AND 100),
+    users TEXT NOT NULL DEFAULT '[]',
+    roles TEXT NOT NULL DEFAULT '[]',
+    orgs TEXT NOT NULL DEFAULT '[]',
+    updated_by BLOB REFERENCES users(id) ON DELETE SET NULL,
+    updated_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now'))
+);
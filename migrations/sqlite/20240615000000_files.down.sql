@@ -0,0 +1,4 @@
+-- uploaded files; the bytes live in object storage under storage_key
+-- The stored objects are left in place.
+ALTER TABLE users DROP COLUMN avatar_file_id;
+DROP TABLE IF EXISTS files;
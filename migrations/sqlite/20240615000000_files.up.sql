@@ -0,0 +1,14 @@
+-- uploaded files; the bytes live in object storage under storage_key
+CREATE TABLE IF NOT EXISTS files (
+    id BLOB PRIMARY KEY,
+    owner_id BLOB NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    storage_key TEXT NOT NULL UNIQUE,
+    content_type TEXT NOT NULL,
+    size_bytes INTEGER NOT NULL CHECK (size_bytes >= 0),
+    filename TEXT,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now'))
+);
+
+CREATE INDEX IF NOT EXISTS idx_files_owner ON files(owner_id, created_at DESC);
+
+ALTER TABLE users ADD COLUMN avatar_file_id BLOB REFERENCES files(id) ON DELETE SET NULL;
@@ -0,0 +1,36 @@
+-- audit_log keeps the ids of deleted users
+-- Events of users deleted since lose their ids, as they would have with the constraints.
+UPDATE audit_log SET actor_id = NULL
+    WHERE actor_id IS NOT NULL AND NOT EXISTS (SELECT 1 FROM users WHERE users.id = actor_id);
+UPDATE audit_log SET subject_id = NULL
+    WHERE subject_id IS NOT NULL AND NOT EXISTS (SELECT 1 FROM users WHERE users.id = subject_id);
+
+CREATE TABLE audit_log_rebuilt (
+    id BLOB PRIMARY KEY,
+    actor_id BLOB REFERENCES users(id) ON DELETE SET NULL,
+    event_type TEXT NOT NULL,
+    ip TEXT,
+    user_agent TEXT,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now')),
+    subject_id BLOB REFERENCES users(id) ON DELETE SET NULL,
+    severity TEXT NOT NULL DEFAULT 'info'
+        CHECK (severity IN ('info', 'warning', 'critical')),
+    metadata TEXT NOT NULL DEFAULT '{}',
+    request_id TEXT,
+    chain_day TEXT,
+    seq INTEGER,
+    prev_hash TEXT,
+    hash TEXT
+);
+INSERT INTO audit_log_rebuilt
+    SELECT id, actor_id, event_type, ip, user_agent, created_at, subject_id, severity,
+        metadata, request_id, chain_day, seq, prev_hash, hash
+    FROM audit_log;
+DROP TABLE audit_log;
+ALTER TABLE audit_log_rebuilt RENAME TO audit_log;
+
+CREATE INDEX IF NOT EXISTS idx_audit_log_actor_id ON audit_log(actor_id);
+CREATE INDEX IF NOT EXISTS idx_audit_log_subject_id ON audit_log(subject_id);
+CREATE INDEX IF NOT EXISTS idx_audit_log_created_at_id ON audit_log(created_at DESC, id DESC);
+CREATE INDEX IF NOT EXISTS idx_audit_log_event_type ON audit_log(event_type, created_at DESC);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_audit_log_chain ON audit_log(chain_day, seq);
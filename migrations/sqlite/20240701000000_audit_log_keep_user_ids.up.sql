@@ -0,0 +1,33 @@
+-- audit_log keeps the ids of deleted users
+-- actor_id and subject_id are hashed into the audit chain, so nulling them when a user is
+-- deleted would make every chained event of theirs fail verification. SQLite cannot drop a
+-- constraint, so the table is rebuilt without them.
+CREATE TABLE audit_log_rebuilt (
+    id BLOB PRIMARY KEY,
+    actor_id BLOB,
+    event_type TEXT NOT NULL,
+    ip TEXT,
+    user_agent TEXT,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now')),
+    subject_id BLOB,
+    severity TEXT NOT NULL DEFAULT 'info'
+        CHECK (severity IN ('info', 'warning', 'critical')),
+    metadata TEXT NOT NULL DEFAULT '{}',
+    request_id TEXT,
+    chain_day TEXT,
+    seq INTEGER,
+    prev_hash TEXT,
+    hash TEXT
+);
+INSERT INTO audit_log_rebuilt
+    SELECT id, actor_id, event_type, ip, user_agent, created_at, subject_id, severity,
+        metadata, request_id, chain_day, seq, prev_hash, hash
+    FROM audit_log;
+DROP TABLE audit_log;
+ALTER TABLE audit_log_rebuilt RENAME TO audit_log;
+
+CREATE INDEX IF NOT EXISTS idx_audit_log_actor_id ON audit_log(actor_id);
+CREATE INDEX IF NOT EXISTS idx_audit_log_subject_id ON audit_log(subject_id);
+CREATE INDEX IF NOT EXISTS idx_audit_log_created_at_id ON audit_log(created_at DESC, id DESC);
+CREATE INDEX IF NOT EXISTS idx_audit_log_event_type ON audit_log(event_type, created_at DESC);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_audit_log_chain ON audit_log(chain_day, seq);
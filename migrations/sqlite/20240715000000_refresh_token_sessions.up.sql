@@ -0,0 +1,5 @@
+-- refresh tokens remember the sign-in they descend from
+-- Rotation copies session_id to the new token, so one session can be ended on its own.
+-- Tokens issued before this each start a session of their own.
+ALTER TABLE refresh_tokens ADD COLUMN session_id BLOB;
+UPDATE refresh_tokens SET session_id = id WHERE session_id IS NULL;